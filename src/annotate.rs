@@ -0,0 +1,368 @@
+use std::{fmt, sync::atomic::AtomicBool};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    board::Board,
+    board_builder::BoardBuilder,
+    move_generation::{Move, MoveGenerator},
+    pgn::{Line, PgnGame, PgnMove},
+    piece::Color,
+    score::Score,
+    search::{Search, SearchLimits, SearchResult},
+};
+
+// How deep every position of a game is searched by default
+pub const DEFAULT_ANNOTATION_DEPTH: u32 = 10;
+// How many centipawns a move may lose against the engine's best move before it's a blunder
+pub const DEFAULT_BLUNDER_THRESHOLD: i32 = 200;
+const MATE_CENTIPAWNS: i32 = 10_000;
+// "??"
+const BLUNDER_NAG: u8 = 4;
+
+// A position of a game's main line, with what the search made of it
+pub struct AnalyzedPosition {
+    pub board: Board,
+    // The move the game went on with, None after the last one
+    pub played: Option<Move>,
+    // None once the game is over and there is nothing to search
+    pub result: Option<SearchResult>,
+}
+
+// Searches every position of the game's main line, the one after the last move included
+pub fn analyze(
+    game: &PgnGame,
+    searcher: &mut dyn Search,
+    limits: &SearchLimits,
+) -> Result<Vec<AnalyzedPosition>> {
+    let board = match game.tag("FEN") {
+        Some(fen) => BoardBuilder::try_from_fen(fen)?,
+        None => Board::starting_position(),
+    };
+    // All moves are read first, so a game that can't be read fails before any searching
+    let mut move_generator = MoveGenerator::new(board.without_history());
+    let mut moves = Vec::new();
+    for (ply, mv) in game.main_line.moves.iter().enumerate() {
+        let mv = Move::try_from_san(&mv.san, &mut move_generator)
+            .map_err(|err| anyhow!("ply {} ({}): {err}", ply + 1, mv.san))?;
+        move_generator.board.move_piece(&mv);
+        moves.push(mv);
+    }
+
+    let mut board = board;
+    let mut positions = Vec::new();
+    for played in moves.into_iter().map(Some).chain([None]) {
+        let result = if MoveGenerator::new(board.without_history())
+            .generate_moves()
+            .is_empty()
+        {
+            None
+        } else {
+            Some(searcher.search(&board, limits, &AtomicBool::new(false), &mut |_| {}))
+        };
+        positions.push(AnalyzedPosition {
+            board: board.without_history(),
+            played: played.clone(),
+            result,
+        });
+        if let Some(mv) = played {
+            board.move_piece(&mv);
+        }
+    }
+
+    Ok(positions)
+}
+
+// Adds the engine's evaluation after every move as an "[%eval ...]" comment, and its best line
+// as a variation wherever the game went another way
+pub fn annotate(
+    game: &PgnGame,
+    searcher: &mut dyn Search,
+    limits: &SearchLimits,
+) -> Result<PgnGame> {
+    let positions = analyze(game, searcher, limits)?;
+    let mut annotated = game.clone();
+    for (mv, analysis) in annotated
+        .main_line
+        .moves
+        .iter_mut()
+        .zip(positions.windows(2))
+    {
+        let [before, after] = analysis else {
+            unreachable!()
+        };
+        if let Some(result) = &after.result {
+            mv.comments
+                .push(eval_comment(result.score, after.board.to_move));
+        }
+        if let Some(best) = &before.result {
+            if before.played.as_ref() != Some(&best.best_move) {
+                mv.variations.push(best_line(&before.board, best));
+            }
+        }
+    }
+
+    Ok(annotated)
+}
+
+// The search's principal variation in SAN, evaluated on its first move
+pub fn best_line(board: &Board, result: &SearchResult) -> Line {
+    let mut line = pv_line(board, result);
+    line.moves[0]
+        .comments
+        .push(eval_comment(result.score, board.to_move));
+
+    line
+}
+
+fn pv_line(board: &Board, result: &SearchResult) -> Line {
+    let pv = if result.pv.is_empty() {
+        std::slice::from_ref(&result.best_move)
+    } else {
+        &result.pv[..]
+    };
+    let mut move_generator = MoveGenerator::new(board.without_history());
+    let mut line = Line::default();
+    for mv in pv {
+        line.moves
+            .push(PgnMove::new(&mv.to_san(&mut move_generator)));
+        move_generator.board.move_piece(mv);
+    }
+
+    line
+}
+
+// A move that gave away at least the threshold compared to the engine's best move
+pub struct Blunder {
+    // Index of the move in the game's main line
+    pub ply: usize,
+    pub san: String,
+    pub full_move_number: u32,
+    pub color: Color,
+    // Both from the side that blundered's perspective
+    pub best_score: Score,
+    pub played_score: Score,
+    pub best_line: Line,
+    // The engine's best line after the blunder, empty if the game was over
+    pub refutation: Line,
+}
+
+// Like "3... Nf6?? (-0.25 -> #1), best was 3... Qe7 4. Nc3, refuted by 4. Qxf7#"
+impl fmt::Display for Blunder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let white_to_move = self.color == Color::White;
+        let dots = if white_to_move { "." } else { "..." };
+        write!(
+            f,
+            "{}{dots} {}?? ({} -> {}), best was {}",
+            self.full_move_number,
+            self.san,
+            white_eval(self.best_score, self.color),
+            white_eval(self.played_score, self.color),
+            self.best_line
+                .to_movetext(self.full_move_number, white_to_move)
+        )?;
+        if let Some(refutation) = self.refutation_movetext() {
+            write!(f, ", refuted by {refutation}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Blunder {
+    fn refutation_movetext(&self) -> Option<String> {
+        if self.refutation.moves.is_empty() {
+            return None;
+        }
+        let number = match self.color {
+            Color::White => self.full_move_number,
+            Color::Black => self.full_move_number + 1,
+        };
+        Some(
+            self.refutation
+                .to_movetext(number, self.color == Color::Black),
+        )
+    }
+}
+
+// The moves that lost at least threshold centipawns against the engine's best move. Mates count
+// as MATE_CENTIPAWNS, so missing a mate or walking into one is always a blunder.
+pub fn find_blunders(positions: &[AnalyzedPosition], threshold: i32) -> Vec<Blunder> {
+    let mut blunders = Vec::new();
+    for (ply, analysis) in positions.windows(2).enumerate() {
+        let [before, after] = analysis else {
+            unreachable!()
+        };
+        let (Some(played), Some(best)) = (&before.played, &before.result) else {
+            continue;
+        };
+        if *played == best.best_move {
+            continue;
+        }
+        let played_score = match &after.result {
+            Some(result) => -result.score,
+            // Checkmate or stalemate
+            None if MoveGenerator::new(after.board.without_history())
+                .is_in_check(after.board.to_move) =>
+            {
+                Score::Mate(1)
+            }
+            None => Score::Cp(0),
+        };
+        if centipawns(best.score) - centipawns(played_score) < threshold {
+            continue;
+        }
+
+        let mut move_generator = MoveGenerator::new(before.board.without_history());
+        blunders.push(Blunder {
+            ply,
+            san: played.to_san(&mut move_generator),
+            full_move_number: before.board.full_move_number,
+            color: before.board.to_move,
+            best_score: best.score,
+            played_score,
+            best_line: pv_line(&before.board, best),
+            refutation: after
+                .result
+                .as_ref()
+                .map(|result| pv_line(&after.board, result))
+                .unwrap_or_default(),
+        });
+    }
+
+    blunders
+}
+
+// Marks the blunders with "??", the engine's best line as a variation and a comment with the
+// evaluations and the refutation
+pub fn annotate_blunders(game: &PgnGame, blunders: &[Blunder]) -> PgnGame {
+    let mut annotated = game.clone();
+    for blunder in blunders {
+        let mv = &mut annotated.main_line.moves[blunder.ply];
+        if !mv.nags.contains(&BLUNDER_NAG) {
+            mv.nags.push(BLUNDER_NAG);
+        }
+        let mut comment = format!(
+            "Blunder ({} -> {})",
+            white_eval(blunder.best_score, blunder.color),
+            white_eval(blunder.played_score, blunder.color)
+        );
+        if let Some(refutation) = blunder.refutation_movetext() {
+            comment.push_str(&format!(", refuted by {refutation}"));
+        }
+        mv.comments.push(comment);
+        let mut best_line = blunder.best_line.clone();
+        best_line.moves[0]
+            .comments
+            .push(eval_comment(blunder.best_score, blunder.color));
+        mv.variations.push(best_line);
+    }
+
+    annotated
+}
+
+fn centipawns(score: Score) -> i32 {
+    match score {
+        Score::Cp(cp) => cp,
+        Score::Mate(moves) if moves > 0 => MATE_CENTIPAWNS,
+        Score::Mate(_) => -MATE_CENTIPAWNS,
+    }
+}
+
+// Like "[%eval 0.45]" or "[%eval #-3]"
+pub fn eval_comment(score: Score, to_move: Color) -> String {
+    format!("[%eval {}]", white_eval(score, to_move))
+}
+
+// In pawns from white's side, like "0.45", or moves to mate, like "#-3"
+fn white_eval(score: Score, to_move: Color) -> String {
+    let score = match to_move {
+        Color::White => score,
+        Color::Black => -score,
+    };
+    match score {
+        Score::Cp(cp) => format!("{:.2}", cp as f64 / 100.0),
+        Score::Mate(moves) => format!("#{moves}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        pgn::parse_games,
+        piece::Color,
+        score::Score,
+        search::{AlphaBeta, SearchLimits},
+    };
+
+    use super::{
+        analyze, annotate, annotate_blunders, eval_comment, find_blunders,
+        DEFAULT_BLUNDER_THRESHOLD,
+    };
+
+    #[test]
+    fn test_eval_comment() {
+        assert_eq!(eval_comment(Score::Cp(45), Color::White), "[%eval 0.45]");
+        assert_eq!(eval_comment(Score::Cp(45), Color::Black), "[%eval -0.45]");
+        assert_eq!(eval_comment(Score::Cp(-300), Color::Black), "[%eval 3.00]");
+        assert_eq!(eval_comment(Score::Mate(2), Color::Black), "[%eval #-2]");
+    }
+
+    #[test]
+    fn test_annotate() {
+        let game = parse_games("1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 {a blunder} 4. Qxf7# 1-0")
+            .remove(0)
+            .unwrap();
+        let limits = SearchLimits {
+            depth: Some(2),
+            ..SearchLimits::default()
+        };
+
+        let annotated = annotate(&game, &mut AlphaBeta::new(), &limits).unwrap();
+        let moves = &annotated.main_line.moves;
+
+        // The game is over after the last move, so there is nothing to evaluate
+        assert!(moves[..6]
+            .iter()
+            .all(|mv| mv.comments.last().unwrap().starts_with("[%eval ")));
+        assert_eq!(moves[6].comments, Vec::<String>::new());
+        assert_eq!(moves[5].comments, ["a blunder", "[%eval #1]"]);
+        // Nf6 walks into mate, the engine defends f7
+        let defence = &moves[5].variations[0];
+        assert_ne!(defence.moves[0].san, "Nf6");
+        assert!(moves[6].variations.is_empty());
+        assert_eq!(parse_games(&annotated.to_string()).remove(0).unwrap(), annotated);
+    }
+
+    #[test]
+    fn test_find_blunders() {
+        let game = parse_games("1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0")
+            .remove(0)
+            .unwrap();
+        let limits = SearchLimits {
+            depth: Some(2),
+            ..SearchLimits::default()
+        };
+        let positions = analyze(&game, &mut AlphaBeta::new(), &limits).unwrap();
+
+        let blunders = find_blunders(&positions, DEFAULT_BLUNDER_THRESHOLD);
+        assert_eq!(blunders.len(), 1);
+        let blunder = &blunders[0];
+        assert_eq!(blunder.ply, 5);
+        assert_eq!(blunder.played_score, Score::Mate(-1));
+        assert!(blunder
+            .to_string()
+            .starts_with("3... Nf6?? (-0.05 -> #1), best was 3... "));
+        assert!(blunder.to_string().ends_with(", refuted by 4. Qxf7#"));
+
+        let annotated = annotate_blunders(&game, &blunders);
+        let nf6 = &annotated.main_line.moves[5];
+        assert_eq!(nf6.nags, [4]);
+        assert_eq!(nf6.comments, ["Blunder (-0.05 -> #1), refuted by 4. Qxf7#"]);
+        assert_eq!(nf6.variations[0].moves[0].comments, ["[%eval -0.05]"]);
+        // Nothing else is touched
+        assert!(annotated.main_line.moves[..5]
+            .iter()
+            .all(|mv| mv.comments.is_empty() && mv.nags.is_empty()));
+    }
+}
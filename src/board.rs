@@ -1,9 +1,13 @@
 use crate::board_builder::BoardBuilder;
-use crate::move_generation::{Flag, Move};
+use crate::move_generation::{Flag, Move, MoveGenerator};
+use crate::move_list::MoveList;
+use crate::nnue::Accumulator;
 use crate::piece::{Color, Piece};
-use crate::square::Square;
-use anyhow::{anyhow, Result};
-use std::fmt;
+use crate::piece_square_table::{Tapered, MAX_PHASE, PHASE_WEIGHTS};
+use crate::square::{Square, SquareColor};
+use crate::zobrist::{hash_board, hash_pawns, piece_square_key, state_key};
+use anyhow::{anyhow, bail, Result};
+use std::{fmt, sync::Arc};
 
 #[derive(PartialEq, Eq, Clone)]
 pub struct Board {
@@ -12,9 +16,70 @@ pub struct Board {
     pub to_move: Color,
     pub full_move_number: u32,
     pub board_state: BoardState,
+    // The moves of the game played with move_piece, and what undo_last_move needs to take them
+    // back. Searches play with make_move instead and keep their undo state on a stack of their
+    // own, so these only grow with the game. Whoever keeps a board around for a whole game, or
+    // copies one to search it, should start them over with clear_history or without_history.
     pub board_state_history: Vec<BoardState>,
+    pub move_history: Vec<Move>,
+    // Files of the rooks each side castles with, indexed by color. These never change during a
+    // game, standard chess always uses the a- and h-files.
+    pub castling_rook_files: [CastlingRookFiles; 2],
+    // In Chess960 mode castling is written as the king capturing its own rook, both in UCI
+    // notation and in the castling rights of the FEN (Shredder-FEN style)
+    pub chess960: bool,
+    // Piece square table values of each side's pieces, indexed by color. Kept up to date as
+    // pieces are put on and taken off the board, so the evaluation doesn't have to add them up
+    // at every leaf.
+    pub(crate) position: [Tapered; 2],
+    // Material and number of pieces of each kind, indexed by color and then piece, kept up to
+    // date the same way
+    pub(crate) material: [i32; 2],
+    pub(crate) piece_counts: [[u32; 6]; 2],
+    // Where each side's king is, indexed by color, so finding it doesn't take a search of the
+    // board. None for the deliberately odd boards without one.
+    pub(crate) king_squares: [Option<usize>; 2],
+    // Zobrist keys of all the pieces and of just the pawns, kept up to date the same way. The
+    // rest of the position's key is added by zobrist_key.
+    pub(crate) piece_key: u64,
+    pub(crate) pawn_key: u64,
+    // The hidden layers of the NNUE evaluation, once an NnueEvaluator has prepared the board
+    pub(crate) nnue: Option<Box<Accumulator>>,
+    pub(crate) listeners: Listeners,
 }
 
+// What happened on a board, for the listeners added with Board::add_listener. A move comes with
+// one MovePlayed, followed by what else it did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoardEvent<'a> {
+    MovePlayed(&'a Move),
+    Capture(&'a Move, Piece),
+    Promotion(&'a Move, Piece),
+    Castling(&'a Move),
+    MoveUndone(&'a Move),
+}
+
+type Listener = dyn Fn(&Board, &BoardEvent) + Send + Sync;
+
+// The listeners belong to the board they were added to. Copies, like the ones searches and move
+// generators play on, start without any, and they don't make boards unequal.
+#[derive(Default)]
+pub(crate) struct Listeners(Vec<Arc<Listener>>);
+
+impl Clone for Listeners {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl PartialEq for Listeners {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Listeners {}
+
 impl Default for Board {
     fn default() -> Self {
         Self {
@@ -24,6 +89,17 @@ impl Default for Board {
             full_move_number: 1,
             board_state: BoardState::default(),
             board_state_history: Vec::new(),
+            move_history: Vec::new(),
+            castling_rook_files: [CastlingRookFiles::default(); 2],
+            chess960: false,
+            position: [Tapered::default(); 2],
+            material: [0; 2],
+            piece_counts: [[0; 6]; 2],
+            king_squares: [None; 2],
+            piece_key: 0,
+            pawn_key: 0,
+            nnue: None,
+            listeners: Listeners::default(),
         }
     }
 }
@@ -99,12 +175,146 @@ impl fmt::Debug for Board {
     }
 }
 
+// The parts of a FEN that have more than one convention
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FenFormat {
+    // X-FEN instead of Shredder-FEN castling rights for Chess960
+    pub x_fen: bool,
+    // The en passant square after every double pawn push, like the original FEN spec, instead of
+    // only when a pawn can capture en passant
+    pub always_en_passant: bool,
+}
+
+// How a board is shown in the terminal, Board's Display being the plain one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DisplayFormat {
+    // Chess symbols like ♞ instead of FEN letters
+    pub unicode: bool,
+    // Light and dark squares in ANSI colors
+    pub colored: bool,
+    // Marks the squares the last move went from and to
+    pub highlight_last_move: bool,
+    // From black's side, with the eighth rank at the bottom
+    pub flipped: bool,
+}
+
+// ANSI 256 color backgrounds of the light and dark squares, then of those the last move
+// touched
+const LIGHT_SQUARE_BACKGROUND: u8 = 180;
+const DARK_SQUARE_BACKGROUND: u8 = 137;
+const LIGHT_HIGHLIGHT_BACKGROUND: u8 = 186;
+const DARK_HIGHLIGHT_BACKGROUND: u8 = 143;
+
 impl Board {
     pub fn starting_position() -> Self {
         BoardBuilder::try_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
             .expect("failed to construct default board config")
     }
+
+    // Plays the moves, given in UCI notation, from the FEN, or from the starting position without
+    // one. Fails at the first move that isn't legal.
+    pub fn from_start_and_moves(fen: Option<&str>, moves: &[&str]) -> Result<Self> {
+        let board = match fen {
+            Some(fen) => BoardBuilder::try_from_fen(fen)?,
+            None => Self::starting_position(),
+        };
+
+        Ok(board.play_uci_moves(moves)?.0)
+    }
+
+    // Like from_start_and_moves, but from this position, which decides e.g. whether castling is
+    // written the Chess960 way. Also hands back the moves that were played.
+    pub fn play_uci_moves(mut self, moves: &[&str]) -> Result<(Self, Vec<Move>)> {
+        let mut played_moves = Vec::with_capacity(moves.len());
+        for mv in moves {
+            let mv = Move::try_from_uci(mv, &self)
+                .map_err(|err| anyhow!("rejected position at move {mv}: {err}"))?;
+            self.move_piece(&mv);
+            played_moves.push(mv);
+        }
+
+        Ok((self, played_moves))
+    }
+
+    // The board a rank per line, with the side to move under it
+    pub fn display_with(&self, format: DisplayFormat) -> String {
+        let last_move = self
+            .move_history
+            .last()
+            .filter(|_| format.highlight_last_move);
+        let (ranks, files): (Vec<usize>, Vec<usize>) = if format.flipped {
+            ((0..8).collect(), (0..8).rev().collect())
+        } else {
+            ((0..8).rev().collect(), (0..8).collect())
+        };
+        let mut output = String::new();
+        for &rank in &ranks {
+            output.push_str(&format!("{} ", rank + 1));
+            for &file in &files {
+                let square = rank * 8 + file;
+                let highlighted = last_move
+                    .is_some_and(|mv| square == mv.starting_square || square == mv.target_square);
+                let symbol = match (self.squares[square], self.colors[square]) {
+                    // With colors, the foreground tells the sides apart
+                    (Some(piece), Some(_)) if format.unicode && format.colored => {
+                        piece.to_unicode_symbol(Color::Black)
+                    }
+                    (Some(piece), Some(color)) if format.unicode => piece.to_unicode_symbol(color),
+                    (Some(piece), Some(color)) => piece.to_symbol(color),
+                    _ => ' ',
+                };
+
+                if format.colored {
+                    let is_light = Square::from_index(square).color() == SquareColor::Light;
+                    let background = match (is_light, highlighted) {
+                        (true, false) => LIGHT_SQUARE_BACKGROUND,
+                        (false, false) => DARK_SQUARE_BACKGROUND,
+                        (true, true) => LIGHT_HIGHLIGHT_BACKGROUND,
+                        (false, true) => DARK_HIGHLIGHT_BACKGROUND,
+                    };
+                    let foreground = match self.colors[square] {
+                        Some(Color::White) => 97,
+                        _ => 30,
+                    };
+                    output.push_str(&format!("\x1b[48;5;{background};{foreground}m {symbol} "));
+                } else if highlighted {
+                    output.push_str(&format!("[{symbol}]"));
+                } else {
+                    output.push_str(&format!(" {symbol} "));
+                }
+            }
+            if format.colored {
+                output.push_str("\x1b[0m");
+            }
+            output.push('\n');
+        }
+        output.push(' ');
+        for &file in &files {
+            output.push_str(&format!("  {}", (b'a' + file as u8) as char));
+        }
+        output.push('\n');
+        output.push_str(&format!("{:?} to move.\n", self.to_move));
+
+        output
+    }
+
+    // Chess960 castling rights are written as the files of the castling rooks (Shredder-FEN),
+    // and the en passant square only when a pawn can capture there
     pub fn to_fen(&self) -> String {
+        self.to_fen_with(FenFormat::default())
+    }
+
+    // Like to_fen, but Chess960 castling rights are written as KQkq unless another rook is
+    // farther out on that side than the castling rook (X-FEN), which some GUIs expect
+    pub fn to_xfen(&self) -> String {
+        self.to_fen_with(FenFormat {
+            x_fen: true,
+            ..FenFormat::default()
+        })
+    }
+
+    pub fn to_fen_with(&self, format: FenFormat) -> String {
+        let x_fen = format.x_fen;
         let mut fen = String::new();
 
         for rank in (0..8).rev() {
@@ -139,17 +349,23 @@ impl Board {
         };
 
         fen.push(' ');
+        let castling_symbols = [
+            self.castling_symbol(Color::White, true, x_fen),
+            self.castling_symbol(Color::White, false, x_fen),
+            self.castling_symbol(Color::Black, true, x_fen),
+            self.castling_symbol(Color::Black, false, x_fen),
+        ];
         if self.board_state.white_kingside_castling_priviledge {
-            fen.push('K');
+            fen.push(castling_symbols[0]);
         }
         if self.board_state.white_queenside_castling_priviledge {
-            fen.push('Q');
+            fen.push(castling_symbols[1]);
         }
         if self.board_state.black_kingside_castling_priviledge {
-            fen.push('k');
+            fen.push(castling_symbols[2]);
         }
         if self.board_state.black_queenside_castling_priviledge {
-            fen.push('q');
+            fen.push(castling_symbols[3]);
         }
         if !(self.board_state.white_kingside_castling_priviledge
             || self.board_state.white_queenside_castling_priviledge
@@ -159,20 +375,15 @@ impl Board {
             fen.push('-')
         }
 
-        // TODO: Should Talia support the newer FEN spec where en passant squares are only listed
-        // if a opposite-color pawn is there to actually capture it?
         fen.push(' ');
-        match self.board_state.en_passant_square {
+        let en_passant_square = self
+            .board_state
+            .en_passant_square
+            .filter(|_| format.always_en_passant || self.can_capture_en_passant());
+        match en_passant_square {
             None => fen.push('-'),
             Some(square) => {
-                let square_names = [
-                    "a1", "b1", "c1", "d1", "e1", "f1", "g1", "h1", "a2", "b2", "c2", "d2", "e2",
-                    "f2", "g2", "h2", "a3", "b3", "c3", "d3", "e3", "f3", "g3", "h3", "a4", "b4",
-                    "c4", "d4", "e4", "f4", "g4", "h4", "a5", "b5", "c5", "d5", "e5", "f5", "g5",
-                    "h5", "a6", "b6", "c6", "d6", "e6", "f6", "g6", "h6", "a7", "b7", "c7", "d7",
-                    "e7", "f7", "g7", "h7", "a8", "b8", "c8", "d8", "e8", "f8", "g8", "h8",
-                ];
-                fen.push_str(square_names[square]);
+                fen.push_str(&Square::from_index(square).to_string());
             }
         }
 
@@ -185,12 +396,216 @@ impl Board {
         fen
     }
 
+    // Whether both boards are in the same position, however they got there: the same pieces on
+    // the same squares, the same side to move and the same castling and en passant rights. Unlike
+    // ==, this ignores the history and the move counters.
+    pub fn same_position(&self, other: &Board) -> bool {
+        // An en passant square nobody can capture on is no right at all
+        let en_passant = |board: &Board| {
+            board
+                .board_state
+                .en_passant_square
+                .filter(|_| board.can_capture_en_passant())
+        };
+
+        self.squares == other.squares
+            && self.colors == other.colors
+            && self.to_move == other.to_move
+            && self.castling_rights() == other.castling_rights()
+            && self.castling_rook_files == other.castling_rook_files
+            && (self.board_state.en_passant_square == other.board_state.en_passant_square
+                || en_passant(self) == en_passant(other))
+    }
+
+    // A Zobrist key of the position, the same for any two boards that are the same_position.
+    // The keys are fixed, so it's the same on every run and every build and can be stored, e.g.
+    // to key a cache or find duplicate positions in a data set. Different positions can share a
+    // key, if very rarely.
+    pub fn position_key(&self) -> u64 {
+        if self.board_state.en_passant_square.is_some() && !self.can_capture_en_passant() {
+            let mut board = self.without_history();
+            board.board_state.en_passant_square = None;
+            return board.zobrist_key();
+        }
+
+        self.zobrist_key()
+    }
+
+    // The Zobrist key the search uses, with the en passant square whether or not a capture is
+    // possible. Only the side to move, castling and en passant rights are added up here, the
+    // pieces' part is kept up to date as they move.
+    pub fn zobrist_key(&self) -> u64 {
+        let key = self.piece_key ^ state_key(self);
+        debug_assert_eq!(key, hash_board(self));
+
+        key
+    }
+
+    // Zobrist key of the pawns alone, e.g. for a pawn structure cache
+    pub fn pawn_key(&self) -> u64 {
+        debug_assert_eq!(self.pawn_key, hash_pawns(self));
+
+        self.pawn_key
+    }
+
+    fn castling_rights(&self) -> [bool; 4] {
+        [
+            self.board_state.white_kingside_castling_priviledge,
+            self.board_state.white_queenside_castling_priviledge,
+            self.board_state.black_kingside_castling_priviledge,
+            self.board_state.black_queenside_castling_priviledge,
+        ]
+    }
+
+    pub fn is_legal(&self, mv: &Move) -> bool {
+        MoveGenerator::new(self.clone()).is_legal(mv)
+    }
+
+    // The legal moves of the piece on the square, e.g. for a GUI to highlight where a clicked
+    // piece can go
+    pub fn moves_from(&self, square: Square) -> MoveList {
+        MoveGenerator::new(self.clone()).generate_moves_from(square.as_index())
+    }
+
+    // Whether one of the legal moves is an en passant capture
+    pub fn can_capture_en_passant(&self) -> bool {
+        self.board_state.en_passant_square.is_some()
+            && MoveGenerator::new(self.clone())
+                .generate_moves()
+                .iter()
+                .any(|mv| mv.flag == Flag::EnPassantCapture)
+    }
+
+    fn castling_symbol(&self, color: Color, kingside: bool, x_fen: bool) -> char {
+        let rook_files = self.castling_rook_files[color as usize];
+        let (symbol, rook_file, outer_files) = if kingside {
+            ('k', rook_files.kingside, rook_files.kingside + 1..8)
+        } else {
+            ('q', rook_files.queenside, 0..rook_files.queenside)
+        };
+        let back_rank_start = if color == Color::White { 0 } else { 56 };
+        let is_outermost_rook = !outer_files
+            .into_iter()
+            .any(|file| self.is_piece_at_square(back_rank_start + file, Piece::Rook, color));
+        let symbol = if !self.chess960 || (x_fen && is_outermost_rook) {
+            symbol
+        } else {
+            (b'a' + rook_file as u8) as char
+        };
+
+        match color {
+            Color::White => symbol.to_ascii_uppercase(),
+            Color::Black => symbol,
+        }
+    }
+
+    // The UCI notation of a move in this position
+    pub fn move_to_uci(&self, mv: &Move) -> String {
+        match mv.flag {
+            Flag::KingsideCastle | Flag::QueensideCastle if self.chess960 => {
+                let rook_square = self.castling_rook_square(mv);
+                format!(
+                    "{}{}",
+                    Square::from_index(mv.starting_square),
+                    Square::from_index(rook_square)
+                )
+            }
+            _ => mv.to_string(),
+        }
+    }
+
+    // In standard algebraic notation, e.g. for PGN export and logs
+    pub fn move_to_san(&self, mv: &Move) -> String {
+        mv.to_san(&mut MoveGenerator::new(self.clone()))
+    }
+
+    // Square the castling rook starts on for a castling move
+    pub fn castling_rook_square(&self, mv: &Move) -> usize {
+        let (color, back_rank_start) = Self::back_rank_of(mv.starting_square);
+        let rook_files = self.castling_rook_files[color as usize];
+        match mv.flag {
+            Flag::KingsideCastle => back_rank_start + rook_files.kingside,
+            Flag::QueensideCastle => back_rank_start + rook_files.queenside,
+            _ => panic!("castling rook square requested for a non castling move"),
+        }
+    }
+
+    // Castling always puts the king on the g- or c-file and the rook right next to it on the
+    // f- or d-file, no matter where they started (this matters in Chess960)
+    pub fn castling_target_squares(mv: &Move) -> (usize, usize) {
+        let (_, back_rank_start) = Self::back_rank_of(mv.starting_square);
+        match mv.flag {
+            Flag::KingsideCastle => (back_rank_start + 6, back_rank_start + 5),
+            Flag::QueensideCastle => (back_rank_start + 2, back_rank_start + 3),
+            _ => panic!("castling target squares requested for a non castling move"),
+        }
+    }
+
+    fn back_rank_of(king_square: usize) -> (Color, usize) {
+        if king_square < 8 {
+            (Color::White, 0)
+        } else {
+            (Color::Black, 56)
+        }
+    }
+
+    // Called after every move played or undone on this board, with the board as it is afterwards
+    pub fn add_listener(&mut self, listener: impl Fn(&Board, &BoardEvent) + Send + Sync + 'static) {
+        self.listeners.0.push(Arc::new(listener));
+    }
+
     pub fn move_piece(&mut self, mv: &Move) {
-        self.board_state_history.push(self.board_state.clone());
+        let previous_state = self.make_move(mv);
+        self.board_state_history.push(previous_state);
+        self.move_history.push(mv.clone());
+        if self.listeners.0.is_empty() {
+            return;
+        }
+
+        self.notify(&BoardEvent::MovePlayed(mv));
+        if let Some(piece) = self.board_state.captured_piece {
+            self.notify(&BoardEvent::Capture(mv, piece));
+        }
+        match mv.flag {
+            Flag::PromoteTo(piece) | Flag::CaptureWithPromotion(piece) => {
+                self.notify(&BoardEvent::Promotion(mv, piece))
+            }
+            Flag::KingsideCastle | Flag::QueensideCastle => self.notify(&BoardEvent::Castling(mv)),
+            _ => {}
+        }
+    }
+
+    fn notify(&self, event: &BoardEvent) {
+        for listener in &self.listeners.0 {
+            listener(self, event);
+        }
+    }
+
+    // Plays the move without keeping it in the game history or telling the listeners, and hands
+    // back the state unmake_move needs to take it back again
+    pub fn make_move(&mut self, mv: &Move) -> BoardState {
+        let previous_state = self.board_state.clone();
+        self.play_move(mv);
+
+        previous_state
+    }
+
+    fn play_move(&mut self, mv: &Move) {
+        // The move doesn't know what it captures, so unmake_move finds it here
+        self.board_state.captured_piece = match mv.flag {
+            Flag::Capture | Flag::CaptureWithPromotion(_) => self.squares[mv.target_square],
+            Flag::EnPassantCapture => Some(Piece::Pawn),
+            _ => None,
+        };
         // With every move, the ability to en passant expires until a double pawn push
         let saved_en_passant_square = self.board_state.en_passant_square;
         self.board_state.en_passant_square = None;
 
+        if mv.flag == Flag::NullMove {
+            self.board_state.half_move_clock += 1;
+            self.pass_turn();
+            return;
+        }
         if self.is_fifty_move_rule_resetting_move(mv) {
             self.board_state.half_move_clock = 0;
         } else {
@@ -214,15 +629,10 @@ impl Board {
                     en_passant_square + 8
                 };
 
-                self.squares[captured_pawn_index] = None;
-                self.colors[captured_pawn_index] = None;
-            }
-            Flag::KingsideCastle => {
-                self.make_kingside_castling_move(mv);
-                return;
+                self.remove_piece(captured_pawn_index);
             }
-            Flag::QueensideCastle => {
-                self.make_queenside_castling_move(mv);
+            Flag::KingsideCastle | Flag::QueensideCastle => {
+                self.make_castling_move(mv);
                 return;
             }
             _ => (),
@@ -244,76 +654,29 @@ impl Board {
 
         // The the rook moves, castling rights to that particular side is lost
         if self.squares[mv.starting_square].is_some_and(|piece| piece == Piece::Rook) {
-            let is_from_starting_kingside_room_square = if self.to_move == Color::White {
-                mv.starting_square == Square::H1.as_index()
-            } else {
-                mv.starting_square == Square::H8.as_index()
-            };
-            let is_from_starting_queenside_room_square = if self.to_move == Color::White {
-                mv.starting_square == Square::A1.as_index()
-            } else {
-                mv.starting_square == Square::A8.as_index()
-            };
-
-            match self.to_move {
-                Color::White => {
-                    if is_from_starting_kingside_room_square {
-                        self.board_state.white_kingside_castling_priviledge = false;
-                    } else if is_from_starting_queenside_room_square {
-                        self.board_state.white_queenside_castling_priviledge = false;
-                    }
-                }
-                Color::Black => {
-                    if is_from_starting_kingside_room_square {
-                        self.board_state.black_kingside_castling_priviledge = false;
-                    } else if is_from_starting_queenside_room_square {
-                        self.board_state.black_queenside_castling_priviledge = false;
-                    }
-                }
-            }
+            self.remove_castling_priviledge_of_rook(mv.starting_square, self.to_move);
         }
 
         // The the rook is captured, castling rights to that particular side is lost
         if self.squares[mv.target_square].is_some_and(|piece| piece == Piece::Rook) {
-            let is_to_starting_kingside_room_square = if self.to_move == Color::White {
-                mv.target_square == Square::H8.as_index()
-            } else {
-                mv.target_square == Square::H1.as_index()
-            };
-            let is_to_starting_queenside_room_square = if self.to_move == Color::White {
-                mv.target_square == Square::A8.as_index()
-            } else {
-                mv.target_square == Square::A1.as_index()
-            };
-
-            match self.to_move {
-                Color::White => {
-                    if is_to_starting_kingside_room_square {
-                        self.board_state.black_kingside_castling_priviledge = false;
-                    } else if is_to_starting_queenside_room_square {
-                        self.board_state.black_queenside_castling_priviledge = false;
-                    }
-                }
-                Color::Black => {
-                    if is_to_starting_kingside_room_square {
-                        self.board_state.white_kingside_castling_priviledge = false;
-                    } else if is_to_starting_queenside_room_square {
-                        self.board_state.white_queenside_castling_priviledge = false;
-                    }
-                }
-            }
+            self.remove_castling_priviledge_of_rook(
+                mv.target_square,
+                self.to_move.opposite_color(),
+            );
         }
 
-        match mv.flag {
-            Flag::PromoteTo(piece) | Flag::CaptureWithPromotion(_, piece) => {
-                self.squares[mv.target_square] = Some(piece);
-            }
-            _ => self.squares[mv.target_square] = self.squares[mv.starting_square],
-        }
-        self.colors[mv.target_square] = self.colors[mv.starting_square];
-        self.squares[mv.starting_square] = None;
-        self.colors[mv.starting_square] = None;
+        let piece = match mv.flag {
+            Flag::PromoteTo(piece) | Flag::CaptureWithPromotion(piece) => piece,
+            _ => self.squares[mv.starting_square].expect("cannot make a move from empty square"),
+        };
+        let color = self.colors[mv.starting_square].expect("cannot make a move from empty square");
+        self.remove_piece(mv.starting_square);
+        self.put_piece(mv.target_square, piece, color);
+
+        self.pass_turn();
+    }
 
+    fn pass_turn(&mut self) {
         if self.to_move == Color::White {
             self.to_move = Color::Black;
         } else {
@@ -322,14 +685,65 @@ impl Board {
         }
     }
 
-    pub fn unmake_move(&mut self, mv: &Move) -> Result<()> {
-        self.board_state = self
-            .board_state_history
-            .pop()
-            .ok_or(anyhow!("Already at oldest move"))?;
+    // The moves played so far can no longer be taken back, the position stays as it is
+    pub fn clear_history(&mut self) {
+        self.board_state_history = Vec::new();
+        self.move_history = Vec::new();
+    }
+
+    // A copy of the position whose history starts here, without copying the old one
+    pub fn without_history(&self) -> Board {
+        Board {
+            squares: self.squares,
+            colors: self.colors,
+            to_move: self.to_move,
+            full_move_number: self.full_move_number,
+            board_state: self.board_state.clone(),
+            board_state_history: Vec::new(),
+            move_history: Vec::new(),
+            castling_rook_files: self.castling_rook_files,
+            chess960: self.chess960,
+            position: self.position,
+            material: self.material,
+            piece_counts: self.piece_counts,
+            king_squares: self.king_squares,
+            piece_key: self.piece_key,
+            pawn_key: self.pawn_key,
+            nnue: self.nnue.clone(),
+            listeners: Listeners::default(),
+        }
+    }
+
+    // Takes back the last move played, and hands it back
+    pub fn undo_last_move(&mut self) -> Result<Move> {
+        let (Some(mv), Some(previous_state)) =
+            (self.move_history.pop(), self.board_state_history.pop())
+        else {
+            bail!("Already at oldest move");
+        };
+        self.unmake_move(&mv, previous_state)?;
+        self.notify(&BoardEvent::MoveUndone(&mv));
+
+        Ok(mv)
+    }
+
+    // Takes back a move played with make_move, given the state make_move handed back for it
+    pub fn unmake_move(&mut self, mv: &Move, previous_state: BoardState) -> Result<()> {
+        let captured_piece = self.board_state.captured_piece;
+        self.board_state = previous_state;
 
         self.to_move = self.to_move.opposite_color();
 
+        if let Flag::KingsideCastle | Flag::QueensideCastle | Flag::NullMove = mv.flag {
+            if mv.flag != Flag::NullMove {
+                self.unmake_castling_move(mv);
+            }
+            if self.to_move == Color::Black {
+                self.full_move_number -= 1;
+            }
+            return Ok(());
+        }
+
         let error_message = "Tried to unmake move, but could not find piece";
         // First move the piece back to its starting square
         let piece = self.squares[mv.target_square].ok_or(anyhow!(error_message))?;
@@ -337,9 +751,9 @@ impl Board {
         self.put_piece(mv.starting_square, piece, color);
 
         match mv.flag {
-            Flag::Capture(piece) => {
-                self.squares[mv.target_square] = Some(piece);
-                self.colors[mv.target_square] = Some(self.to_move.opposite_color());
+            Flag::Capture => {
+                let captured_piece = captured_piece.ok_or(anyhow!(error_message))?;
+                self.put_piece(mv.target_square, captured_piece, self.to_move.opposite_color());
             }
             Flag::EnPassantCapture => {
                 let captured_pawn_index = if self.to_move == Color::White {
@@ -348,73 +762,20 @@ impl Board {
                     mv.target_square + 8
                 };
 
-                self.squares[captured_pawn_index] = Some(Piece::Pawn);
-                self.colors[captured_pawn_index] = Some(self.to_move.opposite_color());
-                self.squares[mv.target_square] = None;
-                self.colors[mv.target_square] = None;
-                self.squares[mv.starting_square] = Some(Piece::Pawn);
-                self.colors[mv.starting_square] = Some(self.to_move);
+                self.put_piece(captured_pawn_index, Piece::Pawn, self.to_move.opposite_color());
+                self.remove_piece(mv.target_square);
+                self.put_piece(mv.starting_square, Piece::Pawn, self.to_move);
             }
             Flag::PromoteTo(_) => {
-                self.squares[mv.starting_square] = Some(Piece::Pawn);
-                self.colors[mv.starting_square] = Some(self.to_move);
-                self.squares[mv.target_square] = None;
-                self.colors[mv.target_square] = None;
+                self.put_piece(mv.starting_square, Piece::Pawn, self.to_move);
+                self.remove_piece(mv.target_square);
             }
-            Flag::KingsideCastle => match self.to_move {
-                Color::White => {
-                    self.squares[Square::H1.as_index()] = Some(Piece::Rook);
-                    self.colors[Square::H1.as_index()] = Some(Color::White);
-                    self.squares[Square::E1.as_index()] = Some(Piece::King);
-                    self.colors[Square::E1.as_index()] = Some(Color::White);
-                    self.squares[Square::F1.as_index()] = None;
-                    self.colors[Square::F1.as_index()] = None;
-                    self.squares[Square::G1.as_index()] = None;
-                    self.colors[Square::G1.as_index()] = None;
-                }
-                Color::Black => {
-                    self.squares[Square::H8.as_index()] = Some(Piece::Rook);
-                    self.colors[Square::H8.as_index()] = Some(Color::Black);
-                    self.squares[Square::E8.as_index()] = Some(Piece::King);
-                    self.colors[Square::E8.as_index()] = Some(Color::Black);
-                    self.squares[Square::F8.as_index()] = None;
-                    self.colors[Square::F8.as_index()] = None;
-                    self.squares[Square::G8.as_index()] = None;
-                    self.colors[Square::G8.as_index()] = None;
-                }
-            },
-            Flag::QueensideCastle => match self.to_move {
-                Color::White => {
-                    self.squares[Square::A1.as_index()] = Some(Piece::Rook);
-                    self.colors[Square::A1.as_index()] = Some(Color::White);
-                    self.squares[Square::E1.as_index()] = Some(Piece::King);
-                    self.colors[Square::E1.as_index()] = Some(Color::White);
-                    self.squares[Square::C1.as_index()] = None;
-                    self.colors[Square::C1.as_index()] = None;
-                    self.squares[Square::D1.as_index()] = None;
-                    self.colors[Square::D1.as_index()] = None;
-                }
-                Color::Black => {
-                    self.squares[Square::A8.as_index()] = Some(Piece::Rook);
-                    self.colors[Square::A8.as_index()] = Some(Color::Black);
-                    self.squares[Square::E8.as_index()] = Some(Piece::King);
-                    self.colors[Square::E8.as_index()] = Some(Color::Black);
-                    self.squares[Square::C8.as_index()] = None;
-                    self.colors[Square::C8.as_index()] = None;
-                    self.squares[Square::D8.as_index()] = None;
-                    self.colors[Square::D8.as_index()] = None;
-                }
-            },
-            Flag::CaptureWithPromotion(captured_piece, _) => {
-                self.squares[mv.target_square] = Some(captured_piece);
-                self.colors[mv.target_square] = Some(self.to_move.opposite_color());
-                self.squares[mv.starting_square] = Some(Piece::Pawn);
-                self.colors[mv.starting_square] = Some(self.to_move);
-            }
-            _ => {
-                self.squares[mv.target_square] = None;
-                self.colors[mv.target_square] = None;
+            Flag::CaptureWithPromotion(_) => {
+                let captured_piece = captured_piece.ok_or(anyhow!(error_message))?;
+                self.put_piece(mv.target_square, captured_piece, self.to_move.opposite_color());
+                self.put_piece(mv.starting_square, Piece::Pawn, self.to_move);
             }
+            _ => self.remove_piece(mv.target_square),
         }
 
         if self.to_move == Color::Black {
@@ -424,9 +785,93 @@ impl Board {
         Ok(())
     }
 
+    // Replaces whatever stood on the square
     pub fn put_piece(&mut self, square: usize, piece: Piece, color: Color) {
+        self.remove_piece(square);
         self.squares[square] = Some(piece);
         self.colors[square] = Some(color);
+        self.position[color as usize] += piece.position_value(square, color);
+        self.material[color as usize] += piece.piece_value();
+        self.piece_counts[color as usize][piece as usize] += 1;
+        if piece == Piece::King {
+            self.king_squares[color as usize] = Some(square);
+        }
+        let key = piece_square_key(piece, color, square);
+        self.piece_key ^= key;
+        if piece == Piece::Pawn {
+            self.pawn_key ^= key;
+        }
+        if let Some(mut accumulator) = self.nnue.take() {
+            accumulator.put_piece(self, square, piece, color);
+            self.nnue = Some(accumulator);
+        }
+    }
+
+    // Takes every piece off and starts over like an empty Board::default(), except that the
+    // board stays in Chess960 mode and keeps its NNUE accumulator up to date
+    pub fn clear(&mut self) {
+        for square in 0..64 {
+            self.remove_piece(square);
+        }
+        self.to_move = Color::White;
+        self.full_move_number = 1;
+        self.board_state = BoardState::default();
+        self.castling_rook_files = [CastlingRookFiles::default(); 2];
+        self.clear_history();
+    }
+
+    // Sets up the position of the FEN on this board. The board is left as it was if the FEN
+    // isn't valid.
+    pub fn set_from_fen(&mut self, fen: &str) -> Result<()> {
+        let board = BoardBuilder::try_from_fen(fen)?;
+        self.clear();
+        for square in 0..64 {
+            if let (Some(piece), Some(color)) = (board.squares[square], board.colors[square]) {
+                self.put_piece(square, piece, color);
+            }
+        }
+        self.to_move = board.to_move;
+        self.full_move_number = board.full_move_number;
+        self.board_state = board.board_state;
+        self.castling_rook_files = board.castling_rook_files;
+        self.chess960 |= board.chess960;
+
+        Ok(())
+    }
+
+    // The piece square table values of the side's pieces, for the current game phase
+    pub fn position(&self, color: Color) -> i32 {
+        self.position[color as usize].blend(self.phase())
+    }
+
+    // From MAX_PHASE in the opening down to 0 once only kings and pawns are left
+    pub fn phase(&self) -> i32 {
+        let phase: i32 = self
+            .piece_counts
+            .iter()
+            .flat_map(|counts| counts.iter().zip(PHASE_WEIGHTS))
+            .map(|(&count, weight)| count as i32 * weight)
+            .sum();
+
+        phase.min(MAX_PHASE)
+    }
+
+    // Kings are not counted
+    pub fn material(&self, color: Color) -> i32 {
+        self.material[color as usize]
+    }
+
+    pub fn piece_count(&self, piece: Piece, color: Color) -> u32 {
+        self.piece_counts[color as usize][piece as usize]
+    }
+
+    // Kings included
+    pub fn total_piece_count(&self) -> u32 {
+        self.piece_counts.iter().flatten().sum()
+    }
+
+    pub fn king_square(&self, color: Color) -> Option<usize> {
+        self.king_squares[color as usize]
     }
 
     pub fn is_piece_at_square(&self, index: usize, piece: Piece, color: Color) -> bool {
@@ -450,36 +895,26 @@ impl Board {
         is_pawn_move || is_non_en_passant_capture
     }
 
-    // TODO: Refactor how the board stores castling priviledges so we can clean this up
-    fn make_kingside_castling_move(&mut self, mv: &Move) {
-        if let Color::White = self.to_move {
-            // Move the king
-            self.squares[Square::G1.as_index()] = self.squares[mv.starting_square];
-            self.colors[Square::G1.as_index()] = self.colors[mv.starting_square];
-            self.squares[mv.starting_square] = None;
-            self.colors[mv.starting_square] = None;
-            // Move the rook
-            self.squares[Square::F1.as_index()] = self.squares[Square::H1.as_index()];
-            self.colors[Square::F1.as_index()] = self.colors[Square::H1.as_index()];
-            self.squares[Square::H1.as_index()] = None;
-            self.colors[Square::H1.as_index()] = None;
-
-            self.board_state.white_kingside_castling_priviledge = false;
-            self.board_state.white_queenside_castling_priviledge = false;
-        } else {
-            // Move the king
-            self.squares[Square::G8.as_index()] = self.squares[mv.starting_square];
-            self.colors[Square::G8.as_index()] = self.colors[mv.starting_square];
-            self.squares[mv.starting_square] = None;
-            self.colors[mv.starting_square] = None;
-            // Move the rook
-            self.squares[Square::F8.as_index()] = self.squares[Square::H8.as_index()];
-            self.colors[Square::F8.as_index()] = self.colors[Square::H8.as_index()];
-            self.squares[Square::H8.as_index()] = None;
-            self.colors[Square::H8.as_index()] = None;
-
-            self.board_state.black_kingside_castling_priviledge = false;
-            self.board_state.black_queenside_castling_priviledge = false;
+    fn make_castling_move(&mut self, mv: &Move) {
+        let rook_square = self.castling_rook_square(mv);
+        let (king_target, rook_target) = Self::castling_target_squares(mv);
+
+        // In Chess960 the king and rook may land on each other's starting squares, so take both
+        // off the board before putting them back
+        self.remove_piece(mv.starting_square);
+        self.remove_piece(rook_square);
+        self.put_piece(king_target, Piece::King, self.to_move);
+        self.put_piece(rook_target, Piece::Rook, self.to_move);
+
+        match self.to_move {
+            Color::White => {
+                self.board_state.white_kingside_castling_priviledge = false;
+                self.board_state.white_queenside_castling_priviledge = false;
+            }
+            Color::Black => {
+                self.board_state.black_kingside_castling_priviledge = false;
+                self.board_state.black_queenside_castling_priviledge = false;
+            }
         }
 
         if self.to_move == Color::White {
@@ -490,42 +925,167 @@ impl Board {
         }
     }
 
-    fn make_queenside_castling_move(&mut self, mv: &Move) {
-        if let Color::White = self.to_move {
-            // Move the king
-            self.squares[Square::C1.as_index()] = self.squares[mv.starting_square];
-            self.colors[Square::C1.as_index()] = self.colors[mv.starting_square];
-            self.squares[mv.starting_square] = None;
-            self.colors[mv.starting_square] = None;
-            // Move the rook
-            self.squares[Square::D1.as_index()] = self.squares[Square::A1.as_index()];
-            self.colors[Square::D1.as_index()] = self.colors[Square::A1.as_index()];
-            self.squares[Square::A1.as_index()] = None;
-            self.colors[Square::A1.as_index()] = None;
-
-            self.board_state.white_kingside_castling_priviledge = false;
-            self.board_state.white_queenside_castling_priviledge = false;
-        } else {
-            // Move the king
-            self.squares[Square::C8.as_index()] = self.squares[mv.starting_square];
-            self.colors[Square::C8.as_index()] = self.colors[mv.starting_square];
-            self.squares[mv.starting_square] = None;
-            self.colors[mv.starting_square] = None;
-            // Move the rook
-            self.squares[Square::D8.as_index()] = self.squares[Square::A8.as_index()];
-            self.colors[Square::D8.as_index()] = self.colors[Square::A8.as_index()];
-            self.squares[Square::A8.as_index()] = None;
-            self.colors[Square::A8.as_index()] = None;
-
-            self.board_state.black_kingside_castling_priviledge = false;
-            self.board_state.black_queenside_castling_priviledge = false;
+    fn unmake_castling_move(&mut self, mv: &Move) {
+        let rook_square = self.castling_rook_square(mv);
+        let (king_target, rook_target) = Self::castling_target_squares(mv);
+
+        self.remove_piece(king_target);
+        self.remove_piece(rook_target);
+        self.put_piece(mv.starting_square, Piece::King, self.to_move);
+        self.put_piece(rook_square, Piece::Rook, self.to_move);
+    }
+
+    fn remove_castling_priviledge_of_rook(&mut self, rook_square: usize, rook_color: Color) {
+        let (back_rank_start, rook_files) = match rook_color {
+            Color::White => (0, self.castling_rook_files[Color::White as usize]),
+            Color::Black => (56, self.castling_rook_files[Color::Black as usize]),
+        };
+
+        if rook_square == back_rank_start + rook_files.kingside {
+            match rook_color {
+                Color::White => self.board_state.white_kingside_castling_priviledge = false,
+                Color::Black => self.board_state.black_kingside_castling_priviledge = false,
+            }
+        } else if rook_square == back_rank_start + rook_files.queenside {
+            match rook_color {
+                Color::White => self.board_state.white_queenside_castling_priviledge = false,
+                Color::Black => self.board_state.black_queenside_castling_priviledge = false,
+            }
         }
+    }
 
-        if self.to_move == Color::White {
-            self.to_move = Color::Black;
-        } else {
-            self.to_move = Color::White;
-            self.full_move_number += 1;
+    // Leaves the square empty, if it wasn't already
+    pub fn remove_piece(&mut self, square: usize) {
+        if let (Some(piece), Some(color)) = (self.squares[square], self.colors[square]) {
+            self.position[color as usize] -= piece.position_value(square, color);
+            self.material[color as usize] -= piece.piece_value();
+            self.piece_counts[color as usize][piece as usize] -= 1;
+            if piece == Piece::King && self.king_squares[color as usize] == Some(square) {
+                self.king_squares[color as usize] = None;
+            }
+            let key = piece_square_key(piece, color, square);
+            self.piece_key ^= key;
+            if piece == Piece::Pawn {
+                self.pawn_key ^= key;
+            }
+            if let Some(accumulator) = &mut self.nnue {
+                accumulator.remove_piece(square, piece, color);
+            }
+        }
+        self.squares[square] = None;
+        self.colors[square] = None;
+    }
+
+    // The same position with the colors swapped and the board flipped top to bottom, which is
+    // exactly as good for the side to move. The move history is left behind.
+    pub fn mirror(&self) -> Board {
+        let state = &self.board_state;
+        let mut mirrored = Board {
+            to_move: self.to_move.opposite_color(),
+            full_move_number: self.full_move_number,
+            chess960: self.chess960,
+            castling_rook_files: [self.castling_rook_files[1], self.castling_rook_files[0]],
+            board_state: BoardState {
+                captured_piece: None,
+                en_passant_square: state.en_passant_square.map(|square| square ^ 56),
+                half_move_clock: state.half_move_clock,
+                white_kingside_castling_priviledge: state.black_kingside_castling_priviledge,
+                black_kingside_castling_priviledge: state.white_kingside_castling_priviledge,
+                white_queenside_castling_priviledge: state.black_queenside_castling_priviledge,
+                black_queenside_castling_priviledge: state.white_queenside_castling_priviledge,
+            },
+            ..Board::default()
+        };
+        for square in 0..64 {
+            if let (Some(piece), Some(color)) = (self.squares[square], self.colors[square]) {
+                mirrored.put_piece(square ^ 56, piece, color.opposite_color());
+            }
+        }
+
+        mirrored
+    }
+
+    // The board flipped top to bottom with the pieces keeping their colors, e.g. to get more
+    // training data out of a position. Castling and en passant rights don't survive the flip.
+    pub fn flip_vertical(&self) -> Board {
+        let mut flipped = Board {
+            to_move: self.to_move,
+            full_move_number: self.full_move_number,
+            chess960: self.chess960,
+            board_state: BoardState {
+                half_move_clock: self.board_state.half_move_clock,
+                ..BoardState::default()
+            },
+            ..Board::default()
+        };
+        for square in 0..64 {
+            if let (Some(piece), Some(color)) = (self.squares[square], self.colors[square]) {
+                flipped.put_piece(square ^ 56, piece, color);
+            }
+        }
+
+        flipped
+    }
+
+    // The piece square table values of the side's pieces, added up from scratch
+    pub fn compute_position(&self, color: Color) -> Tapered {
+        (0..64)
+            .filter_map(|square| match self.squares[square] {
+                Some(piece) if self.colors[square] == Some(color) => {
+                    Some(piece.position_value(square, color))
+                }
+                _ => None,
+            })
+            .sum()
+    }
+}
+
+// Boards are stored as their FEN, with every en passant square so nothing about the position is
+// lost. The history isn't stored, so a deserialized board can't take back the moves before it.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedBoard {
+    fen: String,
+    chess960: bool,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Board {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedBoard {
+            fen: self.to_fen_with(FenFormat {
+                x_fen: false,
+                always_en_passant: true,
+            }),
+            chess960: self.chess960,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Board {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let serialized = SerializedBoard::deserialize(deserializer)?;
+        let mut board = BoardBuilder::try_from_fen(&serialized.fen)
+            .map_err(<D::Error as serde::de::Error>::custom)?;
+        board.chess960 = serialized.chess960;
+
+        Ok(board)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CastlingRookFiles {
+    pub kingside: usize,
+    pub queenside: usize,
+}
+
+impl Default for CastlingRookFiles {
+    fn default() -> Self {
+        Self {
+            kingside: 7,
+            queenside: 0,
         }
     }
 }
@@ -533,6 +1093,7 @@ impl Board {
 // Structure that stores misc information on the board state
 // that unmake_move does not have enough information to compute
 #[derive(Default, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BoardState {
     pub captured_piece: Option<Piece>,
     pub en_passant_square: Option<usize>,
@@ -545,11 +1106,14 @@ pub struct BoardState {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::{Arc, Mutex};
+
     use crate::{
-        board::Board,
+        board::{Board, BoardEvent, DisplayFormat, FenFormat},
         board_builder::BoardBuilder,
-        move_generation::{Flag, Move},
+        move_generation::{Flag, Move, MoveGenerator},
         piece::{Color::*, Piece::*},
+        piece_square_table::MAX_PHASE,
         square::Square::*,
     };
     use anyhow::Result;
@@ -602,7 +1166,8 @@ mod tests {
     #[test]
     fn test_from_fen_empty_board() -> Result<()> {
         let empty_board = Board::default();
-        let empty_board_from_fen = BoardBuilder::try_from_fen("8/8/8/8/8/8/8/8 w - - 0 1")?;
+        let empty_board_from_fen =
+            BoardBuilder::try_from_fen_unchecked("8/8/8/8/8/8/8/8 w - - 0 1")?;
 
         assert_eq!(empty_board, empty_board_from_fen);
 
@@ -611,22 +1176,174 @@ mod tests {
 
     #[test]
     fn test_from_fen_sicilian_defense() -> Result<()> {
-        let mut starting_board: Board = BoardBuilder::from_starting_position()
+        let starting_board: Board = BoardBuilder::from_starting_position()
             .make_move(Move::from_square(E2, E4, Flag::PawnDoublePush))
             .make_move(Move::from_square(C7, C5, Flag::PawnDoublePush))
             .make_move(Move::from_square(G1, F3, Flag::None))
             .try_into()?;
 
-        // TODO: Currently two boards are considered to be equal only if they
-        // also have the same board history, should this be the case?
-        starting_board.board_state_history.clear();
-
         // Position after 1. e4, c5 => 2. Nf3
         let created_board = BoardBuilder::try_from_fen(
             "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2",
         )?;
 
-        assert_eq!(starting_board, created_board);
+        assert!(starting_board.same_position(&created_board));
+        assert_eq!(starting_board.to_fen(), created_board.to_fen());
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_position() -> Result<()> {
+        let start = || Board::starting_position();
+        let (board, _) = start().play_uci_moves(&["g1f3", "g8f6", "b1c3"])?;
+        let (transposed, _) = start().play_uci_moves(&["b1c3", "g8f6", "g1f3"])?;
+        assert!(board != transposed);
+        assert!(board.same_position(&transposed));
+
+        // Only the move counters differ
+        let (knights_back, _) = start().play_uci_moves(&["g1f3", "g8f6", "f3g1", "f6g8"])?;
+        assert!(knights_back.same_position(&start()));
+
+        // The rooks are back, but castling isn't
+        let (rooks_back, _) = start().play_uci_moves(&[
+            "g1f3", "g8f6", "h1g1", "h8g8", "g1h1", "g8h8", "f3g1", "f6g8",
+        ])?;
+        assert!(!rooks_back.same_position(&start()));
+
+        // Nothing can take on e3, so the en passant square makes no difference
+        let (e4, _) = start().play_uci_moves(&["e2e4"])?;
+        let without_en_passant = BoardBuilder::try_from_fen(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+        )?;
+        assert!(e4.same_position(&without_en_passant));
+
+        let (d5, _) = start().play_uci_moves(&["e2e4", "a7a6", "e4e5", "d7d5"])?;
+        let mut d5_without_en_passant = d5.clone();
+        d5_without_en_passant.board_state.en_passant_square = None;
+        assert!(!d5.same_position(&d5_without_en_passant));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_listeners() -> Result<()> {
+        let mut board = BoardBuilder::try_from_fen("3rk3/2P5/8/8/8/8/8/4K2R w K - 0 1")?;
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        board.add_listener(move |board, event| {
+            let event = match event {
+                BoardEvent::MovePlayed(mv) => format!("played {mv}"),
+                BoardEvent::Capture(_, piece) => format!("captured {piece:?}"),
+                BoardEvent::Promotion(_, piece) => format!("promoted to {piece:?}"),
+                BoardEvent::Castling(_) => String::from("castled"),
+                BoardEvent::MoveUndone(mv) => format!("undid {mv}"),
+            };
+            recorded
+                .lock()
+                .unwrap()
+                .push(format!("{event}, {:?} to move", board.to_move));
+        });
+
+        let (mut board, _) = board.play_uci_moves(&["e1g1", "e8e7", "c7d8n"])?;
+        // Copies don't tell anyone
+        board.clone().undo_last_move()?;
+        board.undo_last_move()?;
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "played e1g1, Black to move",
+                "castled, Black to move",
+                "played e8e7, White to move",
+                "played c7d8n, Black to move",
+                "captured Rook, Black to move",
+                "promoted to Knight, Black to move",
+                "undid c7d8n, White to move",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_display_with() -> Result<()> {
+        let (board, _) = Board::starting_position().play_uci_moves(&["e2e4"])?;
+
+        let plain = board.display_with(DisplayFormat::default());
+        assert!(plain.starts_with("8  r  n  b  q  k  b  n  r \n"));
+        assert!(plain.ends_with("   a  b  c  d  e  f  g  h\nBlack to move.\n"));
+
+        let unicode = board.display_with(DisplayFormat {
+            unicode: true,
+            highlight_last_move: true,
+            ..DisplayFormat::default()
+        });
+        assert!(unicode.contains("4             [♙]         \n"));
+        assert!(unicode.contains("2  ♙  ♙  ♙  ♙ [ ] ♙  ♙  ♙ \n"));
+
+        let colored = board.display_with(DisplayFormat {
+            unicode: true,
+            colored: true,
+            highlight_last_move: true,
+            flipped: false,
+        });
+        // a1 is dark, h1 light with a white rook on it
+        assert!(colored.contains("1 \x1b[48;5;137;97m ♜ "));
+        assert!(colored.contains("\x1b[48;5;180;97m ♜ \x1b[0m\n"));
+        // e4 is light, and the pawn just got there
+        assert!(colored.contains("\x1b[48;5;186;97m ♟ "));
+
+        let flipped = board.display_with(DisplayFormat {
+            flipped: true,
+            ..DisplayFormat::default()
+        });
+        assert!(flipped.starts_with("1  R  N  B  K  Q  B  N  R \n"));
+        assert!(flipped.ends_with("   h  g  f  e  d  c  b  a\nBlack to move.\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_from_fen() -> Result<()> {
+        let (mut board, _) = Board::starting_position().play_uci_moves(&["e2e4", "e7e5"])?;
+        let fen = "r3k2r/8/8/8/4p3/8/8/R3K2R b KQq - 3 20";
+
+        board.set_from_fen(fen)?;
+        assert!(board == BoardBuilder::try_from_fen(fen)?);
+
+        assert!(board.set_from_fen("8/8/8/8/8/8/8/8 w - - 0 1").is_err());
+        assert!(board.set_from_fen("4k3/8/8/8/8/8/8/4K3 w").is_err());
+        assert!(board
+            .set_from_fen("4k3/8/8/8/8/8/8/8/4K3 w - - 0 1")
+            .is_err());
+        assert_eq!(board.to_fen(), fen);
+
+        board.remove_piece(E4.as_index());
+        assert_eq!(board.to_fen(), "r3k2r/8/8/8/8/8/8/R3K2R b KQq - 3 20");
+
+        board.clear();
+        assert!(board == Board::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_position_key() -> Result<()> {
+        // Stored keys have to stay valid, so this must never change
+        assert_eq!(Board::starting_position().position_key(), 0x8069_f048_ce50_9f9c);
+
+        let start = || Board::starting_position();
+        let (board, _) = start().play_uci_moves(&["g1f3", "g8f6", "b1c3"])?;
+        let (transposed, _) = start().play_uci_moves(&["b1c3", "g8f6", "g1f3"])?;
+        assert_eq!(board.position_key(), transposed.position_key());
+        assert_ne!(board.position_key(), start().position_key());
+
+        let (e4, _) = start().play_uci_moves(&["e2e4"])?;
+        let without_en_passant = BoardBuilder::try_from_fen(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+        )?;
+        assert_eq!(e4.position_key(), without_en_passant.position_key());
+
         Ok(())
     }
 
@@ -673,6 +1390,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_shredder_fen_castling_rights_round_trip() -> Result<()> {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1";
+        let board = BoardBuilder::try_from_fen(fen)?;
+
+        assert!(board.chess960);
+        assert!(board.board_state.white_kingside_castling_priviledge);
+        assert!(board.board_state.black_queenside_castling_priviledge);
+        assert_eq!(board.to_fen(), fen);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_x_fen_castling_rights_round_trip() -> Result<()> {
+        // The g1 rook castles, so the h1 rook is in the way of writing it as K
+        let fen = "r3k2r/8/8/8/8/8/8/1R2K1RR w GQkq - 0 1";
+        let board = BoardBuilder::try_from_fen(fen)?;
+
+        assert!(board.chess960);
+        assert_eq!(board.castling_rook_files[White as usize].kingside, 6);
+        assert_eq!(board.castling_rook_files[White as usize].queenside, 1);
+        assert_eq!(board.to_xfen(), fen);
+        assert_eq!(board.to_fen(), "r3k2r/8/8/8/8/8/8/1R2K1RR w GBha - 0 1");
+        assert_eq!(
+            Board::starting_position().to_xfen(),
+            Board::starting_position().to_fen()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chess960_castling_with_king_and_rook_swapping_squares() -> Result<()> {
+        let fen = "4k3/8/8/8/8/8/8/5KR1 w G - 0 1";
+        let mut board = BoardBuilder::try_from_fen(fen)?;
+        let castling_move = Move::from_square(F1, G1, Flag::KingsideCastle);
+
+        board.move_piece(&castling_move);
+        assert!(board.is_piece_at_square(G1.as_index(), King, White));
+        assert!(board.is_piece_at_square(F1.as_index(), Rook, White));
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+
+        board.undo_last_move()?;
+        assert_eq!(board.to_fen(), fen);
+
+        Ok(())
+    }
+
     #[test]
     fn test_to_fen_italian_game() -> Result<()> {
         let board: Board = BoardBuilder::from_starting_position()
@@ -734,13 +1500,55 @@ mod tests {
             .make_move(Move::from_square(D7, D5, Flag::PawnDoublePush))
             .try_into()?;
 
+        // No white pawn can take on d6
         assert_eq!(
             board.to_fen(),
+            "r1bq1rk1/2p1bppp/p1n2n2/1p1pp3/4P3/1BP2N2/PP1P1PPP/RNBQR1K1 w - - 0 9"
+        );
+        assert_eq!(
+            board.to_fen_with(FenFormat {
+                always_en_passant: true,
+                ..FenFormat::default()
+            }),
             "r1bq1rk1/2p1bppp/p1n2n2/1p1pp3/4P3/1BP2N2/PP1P1PPP/RNBQR1K1 w - d6 0 9"
         );
         Ok(())
     }
 
+    #[test]
+    fn test_from_start_and_moves() -> Result<()> {
+        let board = Board::from_start_and_moves(None, &["e2e4", "c7c5", "g1f3"])?;
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+
+        let board = Board::from_start_and_moves(Some("4k3/8/8/8/8/8/8/4K2R w K - 0 1"), &["e1g1"])?;
+        assert!(board.is_piece_at_square(G1.as_index(), King, White));
+
+        assert_eq!(
+            Board::from_start_and_moves(None, &["e2e4", "e2e4"])
+                .unwrap_err()
+                .to_string(),
+            "rejected position at move e2e4: Not a legal move"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_fen_en_passant_square_only_when_it_can_be_captured() -> Result<()> {
+        let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        assert_eq!(BoardBuilder::try_from_fen(fen)?.to_fen(), fen);
+
+        // The e5 pawn is pinned along the rank once the d5 pawn is gone
+        let board = BoardBuilder::try_from_fen("4k3/8/8/K2pP2r/8/8/8/8 w - d6 0 1")?;
+        assert!(!board.can_capture_en_passant());
+        assert_eq!(board.to_fen(), "4k3/8/8/K2pP2r/8/8/8/8 w - - 0 1");
+
+        Ok(())
+    }
+
     #[test]
     fn test_pawn_double_push_registers_en_passant_square() {
         let mut board = Board::starting_position();
@@ -1153,6 +1961,85 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_undo_last_move() -> Result<()> {
+        let starting_board = Board::starting_position();
+        let (mut board, moves) = starting_board
+            .clone()
+            .play_uci_moves(&["e2e4", "d7d5", "e4d5"])?;
+        assert_eq!(board.move_history, moves);
+
+        for mv in moves.iter().rev() {
+            assert_eq!(&board.undo_last_move()?, mv);
+        }
+        assert!(board == starting_board);
+        assert!(board.undo_last_move().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_make_move_leaves_the_game_history_alone() -> Result<()> {
+        let (mut board, moves) = Board::starting_position().play_uci_moves(&["e2e4", "d7d5"])?;
+        let original = board.clone();
+
+        let mv = Move::try_from_uci("e4d5", &board)?;
+        let previous_state = board.make_move(&mv);
+        assert_eq!(board.move_history, moves);
+        assert!(board.squares[D5.as_index()] == Some(Pawn));
+
+        board.unmake_move(&mv, previous_state)?;
+        assert!(board == original);
+        assert_eq!(&board.undo_last_move()?, &moves[1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_without_history() -> Result<()> {
+        let (mut board, _) =
+            Board::starting_position().play_uci_moves(&["e2e4", "d7d5", "e4d5"])?;
+
+        let mut copy = board.without_history();
+        assert!(copy.same_position(&board));
+        assert!(copy.undo_last_move().is_err());
+
+        board.clear_history();
+        assert!(board == copy);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() -> Result<()> {
+        let (board, moves) =
+            Board::starting_position().play_uci_moves(&["e2e4", "d7d5", "e4d5", "c7c5"])?;
+
+        let json = serde_json::to_string(&board)?;
+        assert_eq!(
+            json,
+            r#"{"fen":"rnbqkbnr/pp2pppp/8/2pP4/8/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 3","chess960":false}"#
+        );
+        assert!(serde_json::from_str::<Board>(&json)? == board.without_history());
+        assert!(serde_json::from_str::<Board>(
+            r#"{"fen":"8/8/8/8/8/8/8/8 w - - 0 1","chess960":false}"#
+        )
+        .is_err());
+        // A malformed FEN is an error too, not a panic
+        let error =
+            serde_json::from_str::<Board>(r#"{"fen":"8/8/8/8/8/8/8/8 w","chess960":false}"#)
+                .unwrap_err();
+        assert!(error.to_string().contains("FEN has 2 fields instead of 6"));
+
+        let json = serde_json::to_string(&moves)?;
+        assert_eq!(serde_json::from_str::<Vec<Move>>(&json)?, moves);
+        let json = serde_json::to_string(&board.board_state)?;
+        assert_eq!(serde_json::from_str::<super::BoardState>(&json)?, board.board_state);
+
+        Ok(())
+    }
+
     #[test]
     fn test_unmake_simple_piece_move_white() -> Result<()> {
         let mut board: Board = BoardBuilder::from_starting_position()
@@ -1160,7 +2047,7 @@ mod tests {
             .try_into()?;
 
         let expected_board: Board = BoardBuilder::from_starting_position().try_into()?;
-        board.unmake_move(&Move::from_square(E2, E4, Flag::PawnDoublePush))?;
+        board.undo_last_move()?;
 
         assert!(board == expected_board);
 
@@ -1178,7 +2065,7 @@ mod tests {
             .make_move(Move::from_square(E2, E4, Flag::PawnDoublePush))
             .try_into()?;
 
-        board.unmake_move(&Move::from_square(E7, E5, Flag::PawnDoublePush))?;
+        board.undo_last_move()?;
 
         assert!(board == expected_board);
 
@@ -1192,7 +2079,7 @@ mod tests {
             .make_move(Move::from_square(E7, E5, Flag::PawnDoublePush))
             .make_move(Move::from_square(G1, F3, Flag::None))
             .make_move(Move::from_square(B8, C6, Flag::None))
-            .make_move(Move::from_square(F3, E5, Flag::Capture(Pawn)))
+            .make_move(Move::from_square(F3, E5, Flag::Capture))
             .try_into()?;
 
         let expected_board: Board = BoardBuilder::from_starting_position()
@@ -1202,7 +2089,7 @@ mod tests {
             .make_move(Move::from_square(B8, C6, Flag::None))
             .try_into()?;
 
-        board.unmake_move(&Move::from_square(F3, E5, Flag::Capture(Pawn)))?;
+        board.undo_last_move()?;
 
         assert!(board == expected_board);
 
@@ -1216,8 +2103,8 @@ mod tests {
             .make_move(Move::from_square(E7, E5, Flag::PawnDoublePush))
             .make_move(Move::from_square(G1, F3, Flag::None))
             .make_move(Move::from_square(B8, C6, Flag::None))
-            .make_move(Move::from_square(F3, E5, Flag::Capture(Pawn)))
-            .make_move(Move::from_square(C6, E5, Flag::Capture(Knight)))
+            .make_move(Move::from_square(F3, E5, Flag::Capture))
+            .make_move(Move::from_square(C6, E5, Flag::Capture))
             .try_into()?;
 
         let expected_board: Board = BoardBuilder::from_starting_position()
@@ -1225,10 +2112,10 @@ mod tests {
             .make_move(Move::from_square(E7, E5, Flag::PawnDoublePush))
             .make_move(Move::from_square(G1, F3, Flag::None))
             .make_move(Move::from_square(B8, C6, Flag::None))
-            .make_move(Move::from_square(F3, E5, Flag::Capture(Pawn)))
+            .make_move(Move::from_square(F3, E5, Flag::Capture))
             .try_into()?;
 
-        board.unmake_move(&Move::from_square(C6, E5, Flag::Capture(Knight)))?;
+        board.undo_last_move()?;
 
         assert!(board == expected_board);
 
@@ -1252,7 +2139,7 @@ mod tests {
             .make_move(Move::from_square(D7, D5, Flag::PawnDoublePush))
             .try_into()?;
 
-        board.unmake_move(&Move::from_square(E5, D6, Flag::EnPassantCapture))?;
+        board.undo_last_move()?;
 
         assert!(board == expected_board);
 
@@ -1278,7 +2165,7 @@ mod tests {
             .make_move(Move::from_square(D2, D4, Flag::PawnDoublePush))
             .try_into()?;
 
-        board.unmake_move(&Move::from_square(E4, D3, Flag::EnPassantCapture))?;
+        board.undo_last_move()?;
 
         assert!(board == expected_board);
 
@@ -1292,7 +2179,7 @@ mod tests {
             .piece(E7, Pawn, White)
             .piece(G8, King, Black)
             .piece(F8, Knight, Black)
-            .make_move(Move::from_square(E7, F8, Flag::CaptureWithPromotion(Knight, Queen)))
+            .make_move(Move::from_square(E7, F8, Flag::CaptureWithPromotion(Queen)))
             .try_into()?;
 
         let expected_board: Board = BoardBuilder::default()
@@ -1302,7 +2189,7 @@ mod tests {
             .piece(F8, Knight, Black)
             .try_into()?;
 
-        board.unmake_move(&Move::from_square(E7, F8, Flag::CaptureWithPromotion(Knight, Queen)))?;
+        board.undo_last_move()?;
 
         assert!(board == expected_board);
 
@@ -1324,7 +2211,7 @@ mod tests {
             .piece(G8, King, Black)
             .try_into()?;
 
-        board.unmake_move(&Move::from_square(E7, E8, Flag::PromoteTo(Queen)))?;
+        board.undo_last_move()?;
 
         assert!(board == expected_board);
 
@@ -1348,7 +2235,7 @@ mod tests {
             .to_move(Black)
             .try_into()?;
 
-        board.unmake_move(&Move::from_square(E2, E1, Flag::PromoteTo(Queen)))?;
+        board.undo_last_move()?;
 
         assert!(board == expected_board);
 
@@ -1363,7 +2250,7 @@ mod tests {
             .piece(G8, King, Black)
             .piece(C1, Knight, White)
             .to_move(Black)
-            .make_move(Move::from_square(E2, C1, Flag::CaptureWithPromotion(Knight, Queen)))
+            .make_move(Move::from_square(E2, C1, Flag::CaptureWithPromotion(Queen)))
             .try_into()?;
 
         let expected_board: Board = BoardBuilder::default()
@@ -1374,7 +2261,7 @@ mod tests {
             .to_move(Black)
             .try_into()?;
 
-        board.unmake_move(&Move::from_square(E2, C1, Flag::CaptureWithPromotion(Knight, Queen)))?;
+        board.undo_last_move()?;
 
         assert!(board == expected_board);
 
@@ -1402,7 +2289,7 @@ mod tests {
             .make_move(Move::from_square(F8, C5, Flag::None))
             .try_into()?;
 
-        board.unmake_move(&Move::from_square(E1, G1, Flag::KingsideCastle))?;
+        board.undo_last_move()?;
 
         assert!(board == expected_board);
 
@@ -1432,7 +2319,7 @@ mod tests {
             .make_move(Move::from_square(E1, G1, Flag::KingsideCastle))
             .try_into()?;
 
-        board.unmake_move(&Move::from_square(E8, G8, Flag::KingsideCastle))?;
+        board.undo_last_move()?;
 
         assert!(board == expected_board);
 
@@ -1464,7 +2351,7 @@ mod tests {
             .make_move(Move::from_square(D8, D7, Flag::None))
             .try_into()?;
 
-        board.unmake_move(&Move::from_square(E1, C1, Flag::QueensideCastle))?;
+        board.undo_last_move()?;
 
         assert!(board == expected_board);
 
@@ -1498,10 +2385,107 @@ mod tests {
             .make_move(Move::from_square(E1, C1, Flag::QueensideCastle))
             .try_into()?;
 
-        board.unmake_move(&Move::from_square(E8, C8, Flag::QueensideCastle))?;
+        board.undo_last_move()?;
 
         assert!(board == expected_board);
 
         Ok(())
     }
+
+    #[test]
+    fn test_mirror() -> Result<()> {
+        for (fen, mirrored) in [
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b Kq a3 0 1",
+                "r3k2r/1ppbbppp/2n2q1P/pP2p3/3pn3/BN2PNP1/P1PPQPB1/R3K2R w Qk a6 0 1",
+            ),
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                "bq1bnrkr/npp1p1pp/p2p4/5p2/2P5/3PPN2/PP3PPP/BQNB1RKR b HFhf - 2 9",
+            ),
+        ] {
+            let board = BoardBuilder::try_from_fen(fen)?;
+            assert_eq!(board.mirror().to_fen(), mirrored);
+            assert_eq!(board.mirror(), BoardBuilder::try_from_fen(mirrored)?);
+            assert_eq!(board.mirror().mirror(), board);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_flip_vertical() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("r3k3/8/8/8/4Pp2/8/8/4K2R b Kq e3 0 1")?;
+
+        assert_eq!(board.flip_vertical().to_fen(), "4K2R/8/8/4Pp2/8/8/8/r3k3 b - - 0 1");
+        assert_eq!(
+            board.flip_vertical().flip_vertical().to_fen(),
+            "r3k3/8/8/8/4Pp2/8/8/4K2R b - - 0 1"
+        );
+        // Put on piece by piece, so the running sums are right
+        let flipped = board.flip_vertical();
+        for color in [White, Black] {
+            assert_eq!(flipped.position[color as usize], flipped.compute_position(color));
+            assert_eq!(flipped.material(color), board.material(color));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_material_and_piece_counts() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("4k3/pp6/8/8/8/8/PPP5/RN2K3 w - - 0 1")?;
+
+        assert_eq!(board.material(White), 3 * 100 + 500 + 300);
+        assert_eq!(board.material(Black), 2 * 100);
+        assert_eq!(board.piece_count(Pawn, White), 3);
+        assert_eq!(board.piece_count(Knight, White), 1);
+        assert_eq!(board.piece_count(Queen, Black), 0);
+        assert_eq!(board.piece_count(King, Black), 1);
+        assert_eq!(board.total_piece_count(), 9);
+        assert_eq!(board.king_square(Black), Some(E8.as_index()));
+        // A rook and a knight left
+        assert_eq!(board.phase(), 3);
+        assert_eq!(Board::starting_position().phase(), MAX_PHASE);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_material_and_position_is_kept_up_to_date() -> Result<()> {
+        // Captures, promotions with and without capture, en passant and castling for both sides,
+        // and king moves
+        let mut move_generator = MoveGenerator::new(BoardBuilder::try_from_fen(
+            "r3k2r/pPppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q2/1PPBBPpP/R3K2R b KQkq a3 0 1",
+        )?);
+        let original = move_generator.board.clone();
+
+        for mv in move_generator.generate_moves() {
+            move_generator.board.move_piece(&mv);
+            for reply in move_generator.generate_moves() {
+                move_generator.board.move_piece(&reply);
+                let board = &move_generator.board;
+                for color in [White, Black] {
+                    assert_eq!(board.position[color as usize], board.compute_position(color));
+                    let count = |piece| {
+                        (0..64)
+                            .filter(|&square| board.is_piece_at_square(square, piece, color))
+                            .count() as u32
+                    };
+                    for piece in [Pawn, Knight, Bishop, Rook, Queen, King] {
+                        assert_eq!(board.piece_count(piece, color), count(piece));
+                    }
+                    let king_square =
+                        (0..64).find(|&square| board.is_piece_at_square(square, King, color));
+                    assert_eq!(board.king_square(color), king_square);
+                }
+                move_generator.board.undo_last_move()?;
+            }
+            move_generator.board.undo_last_move()?;
+        }
+
+        assert!(move_generator.board == original);
+
+        Ok(())
+    }
 }
@@ -1,9 +1,54 @@
-use crate::board::{Board, BoardState};
-use crate::move_generation::Move;
+use std::fmt;
+
+use crate::board::{Board, BoardState, CastlingRookFiles};
+use crate::move_generation::{Move, MoveGenerator};
 use crate::piece::{Color, Piece};
 use crate::square::Square;
 use anyhow::{anyhow, bail, Result};
-use std::collections::HashSet;
+
+// Why a position can't come up in a game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoardError {
+    // A FEN needs all six fields, the clocks included
+    WrongNumberOfFenFields(usize),
+    // The piece placement of a FEN isn't 8 ranks of 8 squares
+    InvalidPiecePlacement,
+    MissingKing(Color),
+    TooManyKings(Color),
+    TooManyPawns(Color),
+    TooManyPieces(Color),
+    PawnOnBackRank(Square),
+    // The side that just moved left its king in check
+    OpponentInCheck,
+    // Not behind a pawn that could just have moved two squares
+    InvalidEnPassantSquare(Square),
+}
+
+impl fmt::Display for BoardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BoardError::WrongNumberOfFenFields(count) => {
+                write!(f, "FEN has {count} fields instead of 6")
+            }
+            BoardError::InvalidPiecePlacement => {
+                write!(f, "FEN piece placement must have 8 ranks of 8 squares")
+            }
+            BoardError::MissingKing(color) => write!(f, "{color:?} has no king"),
+            BoardError::TooManyKings(color) => write!(f, "{color:?} has more than one king"),
+            BoardError::TooManyPawns(color) => write!(f, "{color:?} has more than 8 pawns"),
+            BoardError::TooManyPieces(color) => write!(f, "{color:?} has more than 16 pieces"),
+            BoardError::PawnOnBackRank(square) => {
+                write!(f, "pawn on the first or last rank at {square}")
+            }
+            BoardError::OpponentInCheck => write!(f, "the side not to move is in check"),
+            BoardError::InvalidEnPassantSquare(square) => {
+                write!(f, "invalid en passant square {square}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BoardError {}
 
 pub struct BoardBuilder {
     board: Board,
@@ -74,7 +119,92 @@ impl BoardBuilder {
         self
     }
 
+    // Fails with a BoardError if the position can't come up in a game
     pub fn try_from_fen(fen: &str) -> Result<Board> {
+        let board = Self::try_from_fen_unchecked(fen)?;
+        Self::validate(&board)?;
+        Ok(board)
+    }
+
+    // Reads a diagram like the one Board's Display prints, or a simpler one with a rank per line
+    // and '.' for the empty squares, like "r . b q k b n r". Rank numbers, file letters and the
+    // "White to move." line are optional. Castling and en passant rights are left to the
+    // builder's methods.
+    pub fn try_from_ascii(diagram: &str) -> Result<Self> {
+        let mut builder = Self::new();
+        let mut ranks: Vec<Vec<char>> = Vec::new();
+        for line in diagram.lines().map(str::trim) {
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(color) = line.strip_suffix(" to move.") {
+                builder = builder.to_move(match color {
+                    "White" => Color::White,
+                    "Black" => Color::Black,
+                    _ => bail!("unknown side to move: {color}"),
+                });
+                continue;
+            }
+            let files: String = line.split_whitespace().collect();
+            if files.eq_ignore_ascii_case("abcdefgh") {
+                continue;
+            }
+
+            // Without the rank number in front
+            let line = match line.split_once(char::is_whitespace) {
+                Some((rank, squares))
+                    if rank.len() == 1 && rank.chars().all(|c| c.is_ascii_digit()) =>
+                {
+                    squares.trim()
+                }
+                _ => line,
+            };
+            let squares = match line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                // Display's "['r', ' ', 'b', ...]"
+                Some(squares) => squares
+                    .split(',')
+                    .map(|square| {
+                        let square = square.trim();
+                        match square.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+                            Some(symbol) if symbol.chars().count() == 1 => {
+                                Ok(symbol.chars().next().unwrap())
+                            }
+                            _ => Err(anyhow!("expected a quoted symbol, got {square}")),
+                        }
+                    })
+                    .collect::<Result<_>>()?,
+                None => line.chars().filter(|c| !c.is_whitespace()).collect(),
+            };
+            ranks.push(squares);
+        }
+
+        if ranks.len() != 8 {
+            bail!("expected 8 ranks, got {}", ranks.len());
+        }
+        for (row, squares) in ranks.into_iter().enumerate() {
+            let rank = 7 - row;
+            if squares.len() != 8 {
+                bail!("expected 8 squares on rank {}, got {}", rank + 1, squares.len());
+            }
+            for (file, symbol) in squares.into_iter().enumerate() {
+                if matches!(symbol, '.' | ' ' | '-' | '_') {
+                    continue;
+                }
+                let (piece, color) = Piece::from_symbol(symbol)
+                    .ok_or_else(|| anyhow!("invalid piece symbol {symbol} on rank {}", rank + 1))?;
+                builder = builder.piece(Square::from_index(rank * 8 + file), piece, color);
+            }
+        }
+
+        Ok(builder)
+    }
+
+    // Without checking that the position is possible, for deliberately odd ones like a board
+    // without kings
+    pub fn try_from_fen_unchecked(fen: &str) -> Result<Board> {
         // 0: board arrangement
         // 1: active color
         // 2: Castling availability
@@ -82,43 +212,42 @@ impl BoardBuilder {
         // 4: Halfmove clock
         // 5: Fullmove number
         let fen_string_fields: Vec<&str> = fen.split_whitespace().collect();
+        if fen_string_fields.len() != 6 {
+            bail!(BoardError::WrongNumberOfFenFields(fen_string_fields.len()));
+        }
 
         let mut squares: [Option<Piece>; 64] = [None; 64];
         let mut colors: [Option<Color>; 64] = [None; 64];
-        let mut file = 0;
-        let mut rank = 7;
-
-        for symbol in fen_string_fields[0].chars() {
-            match symbol {
-                '/' => {
-                    file = 0;
-                    rank -= 1;
-                }
-                '1'..='8' => file += symbol.to_digit(10).unwrap(),
-                piece_char => {
-                    let (piece, color) = match piece_char {
-                        'P' => (Piece::Pawn, Color::White),
-                        'p' => (Piece::Pawn, Color::Black),
-                        'N' => (Piece::Knight, Color::White),
-                        'n' => (Piece::Knight, Color::Black),
-                        'B' => (Piece::Bishop, Color::White),
-                        'b' => (Piece::Bishop, Color::Black),
-                        'R' => (Piece::Rook, Color::White),
-                        'r' => (Piece::Rook, Color::Black),
-                        'Q' => (Piece::Queen, Color::White),
-                        'q' => (Piece::Queen, Color::Black),
-                        'K' => (Piece::King, Color::White),
-                        'k' => (Piece::King, Color::Black),
-                        _ => bail!("invalid piece symbol in FEN"),
-                    };
-
-                    let index = rank * 8 + file as usize;
-                    squares[index] = Some(piece);
-                    colors[index] = Some(color);
-
-                    file += 1;
+        let ranks: Vec<&str> = fen_string_fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            bail!(BoardError::InvalidPiecePlacement);
+        }
+
+        // The ranks are listed from the 8th down to the 1st
+        for (rank, rank_symbols) in (0..8).rev().zip(ranks) {
+            let mut file = 0;
+            for symbol in rank_symbols.chars() {
+                match symbol {
+                    '1'..='8' => file += symbol.to_digit(10).unwrap() as usize,
+                    piece_char => {
+                        let Some((piece, color)) = Piece::from_symbol(piece_char) else {
+                            bail!("invalid piece symbol in FEN")
+                        };
+                        if file >= 8 {
+                            bail!(BoardError::InvalidPiecePlacement);
+                        }
+
+                        let index = rank * 8 + file;
+                        squares[index] = Some(piece);
+                        colors[index] = Some(color);
+
+                        file += 1;
+                    }
                 }
             }
+            if file != 8 {
+                bail!(BoardError::InvalidPiecePlacement);
+            }
         }
 
         let to_move = match fen_string_fields[1] {
@@ -129,13 +258,59 @@ impl BoardBuilder {
             }
         };
 
-        let valid_casting_right_chars: HashSet<char> =
-            ['K', 'Q', 'k', 'q', '-'].iter().cloned().collect();
-        let castling_rights: HashSet<char> = fen_string_fields[2].chars().collect();
-        if !castling_rights.is_subset(&valid_casting_right_chars) {
-            bail!(
-                "invalid castling rights in fen, must be a combination of 'K', 'Q', 'k', and 'q' or '-'",
-            );
+        // Besides the classic notation, castling rights can be given as the files of the
+        // castling rooks (Shredder-FEN), which is how Chess960 positions are written. With the
+        // classic notation, the outermost rook on that side of the king is the castling rook.
+        // Indexed by color, then kingside and queenside
+        let mut castling_priviledges = [[false; 2]; 2];
+        let mut castling_rook_files = [CastlingRookFiles::default(); 2];
+        let mut chess960 = false;
+        for symbol in fen_string_fields[2].chars().filter(|&symbol| symbol != '-') {
+            let color = if symbol.is_ascii_uppercase() {
+                Color::White
+            } else {
+                Color::Black
+            };
+            let back_rank_start = if color == Color::White { 0 } else { 56 };
+            let is_piece_on_file = |file: usize, piece: Piece| {
+                squares[back_rank_start + file] == Some(piece)
+                    && colors[back_rank_start + file] == Some(color)
+            };
+            let king_file = (0..8)
+                .find(|&file| is_piece_on_file(file, Piece::King))
+                .unwrap_or(4);
+            let rook_files = &mut castling_rook_files[color as usize];
+            let priviledges = &mut castling_priviledges[color as usize];
+
+            match symbol.to_ascii_lowercase() {
+                'k' => {
+                    priviledges[0] = true;
+                    rook_files.kingside = (king_file + 1..8)
+                        .rev()
+                        .find(|&file| is_piece_on_file(file, Piece::Rook))
+                        .unwrap_or(7);
+                }
+                'q' => {
+                    priviledges[1] = true;
+                    rook_files.queenside = (0..king_file)
+                        .find(|&file| is_piece_on_file(file, Piece::Rook))
+                        .unwrap_or(0);
+                }
+                file_symbol @ 'a'..='h' => {
+                    chess960 = true;
+                    let file = file_symbol as usize - 'a' as usize;
+                    if file > king_file {
+                        priviledges[0] = true;
+                        rook_files.kingside = file;
+                    } else {
+                        priviledges[1] = true;
+                        rook_files.queenside = file;
+                    }
+                }
+                _ => bail!(
+                    "invalid castling rights in fen, must be a combination of 'K', 'Q', 'k', and 'q', rook files or '-'",
+                ),
+            }
         }
 
         let half_move_clock: u32 = fen_string_fields[4]
@@ -146,22 +321,80 @@ impl BoardBuilder {
             .parse()
             .map_err(|_| anyhow!("failed to parse full move number from fen"))?;
 
-        Ok(Board {
-            squares,
-            colors,
+        let [white_castling_priviledges, black_castling_priviledges] = castling_priviledges;
+        let mut board = Board {
             to_move,
             full_move_number,
             board_state: BoardState {
                 captured_piece: None,
                 en_passant_square: Self::parse_en_passant_square(fen_string_fields[3])?,
-                white_kingside_castling_priviledge: castling_rights.contains(&'K'),
-                black_kingside_castling_priviledge: castling_rights.contains(&'k'),
-                white_queenside_castling_priviledge: castling_rights.contains(&'Q'),
-                black_queenside_castling_priviledge: castling_rights.contains(&'q'),
+                white_kingside_castling_priviledge: white_castling_priviledges[0],
+                black_kingside_castling_priviledge: black_castling_priviledges[0],
+                white_queenside_castling_priviledge: white_castling_priviledges[1],
+                black_queenside_castling_priviledge: black_castling_priviledges[1],
                 half_move_clock,
             },
             board_state_history: Vec::new(),
-        })
+            castling_rook_files,
+            chess960,
+            ..Board::default()
+        };
+        for square in 0..64 {
+            if let (Some(piece), Some(color)) = (squares[square], colors[square]) {
+                board.put_piece(square, piece, color);
+            }
+        }
+
+        Ok(board)
+    }
+
+    pub fn validate(board: &Board) -> Result<(), BoardError> {
+        for color in [Color::White, Color::Black] {
+            match board.piece_count(Piece::King, color) {
+                0 => return Err(BoardError::MissingKing(color)),
+                1 => {}
+                _ => return Err(BoardError::TooManyKings(color)),
+            }
+            if board.piece_count(Piece::Pawn, color) > 8 {
+                return Err(BoardError::TooManyPawns(color));
+            }
+            if (0..64)
+                .filter(|&square| board.colors[square] == Some(color))
+                .count()
+                > 16
+            {
+                return Err(BoardError::TooManyPieces(color));
+            }
+        }
+        if let Some(square) = (0..8)
+            .chain(56..64)
+            .find(|&square| board.squares[square] == Some(Piece::Pawn))
+        {
+            return Err(BoardError::PawnOnBackRank(Square::from_index(square)));
+        }
+        if MoveGenerator::new(board.clone()).is_in_check(board.to_move.opposite_color()) {
+            return Err(BoardError::OpponentInCheck);
+        }
+
+        if let Some(square) = board.board_state.en_passant_square {
+            let invalid = Err(BoardError::InvalidEnPassantSquare(Square::from_index(square)));
+            let moved = board.to_move.opposite_color();
+            // The pawn that moved two squares is in front of the en passant square, seen from
+            // its side, and the square it came from is behind
+            let (pawn_square, origin_square) = match (moved, square / 8) {
+                (Color::White, 2) => (square + 8, square - 8),
+                (Color::Black, 5) => (square - 8, square + 8),
+                _ => return invalid,
+            };
+            if !board.is_square_empty(square)
+                || !board.is_square_empty(origin_square)
+                || !board.is_piece_at_square(pawn_square, Piece::Pawn, moved)
+            {
+                return invalid;
+            }
+        }
+
+        Ok(())
     }
 
     fn parse_en_passant_square(en_passant_sqaure_field: &str) -> Result<Option<usize>> {
@@ -191,8 +424,11 @@ impl Default for BoardBuilder {
 
 #[cfg(test)]
 mod tests {
-    use crate::board_builder::BoardBuilder;
+    use crate::board::Board;
+    use crate::board_builder::{BoardBuilder, BoardError};
+    use crate::piece::Color;
     use crate::square::Square;
+    use anyhow::Result;
 
     #[test]
     fn test_from_fen_invalid_piece_position_char() {
@@ -201,6 +437,121 @@ mod tests {
         assert_eq!(board.err().unwrap().to_string(), "invalid piece symbol in FEN")
     }
 
+    #[test]
+    fn test_from_ascii() -> Result<()> {
+        let (board, _) = Board::starting_position().play_uci_moves(&["e2e4", "c7c5", "g1f3"])?;
+        let parsed: Board = BoardBuilder::try_from_ascii(&board.to_string())?.try_into()?;
+        assert_eq!(
+            parsed.to_fen(),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b - - 0 1"
+        );
+
+        let board: Board = BoardBuilder::try_from_ascii(
+            "
+            . . . . k . . r
+            . . . . . p p p
+            . . . . . . . .
+            . . . . . . . .
+            . . . . . . . .
+            . . . . . . . .
+            . . . . . P P P
+            . . . . . . K .
+            ",
+        )?
+        .to_move(Color::Black)
+        .can_kingside_castle(Color::Black, true)
+        .try_into()?;
+        assert_eq!(board.to_fen(), "4k2r/5ppp/8/8/8/8/5PPP/6K1 b k - 0 1");
+
+        assert!(BoardBuilder::try_from_ascii("8/8/8/8/8/8/8/8").is_err());
+        assert!(BoardBuilder::try_from_ascii(&"........\n".repeat(7)).is_err());
+        assert!(BoardBuilder::try_from_ascii(&"....x...\n".repeat(8)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_fen_impossible_positions() {
+        let error = |fen| {
+            *BoardBuilder::try_from_fen(fen)
+                .unwrap_err()
+                .downcast_ref::<BoardError>()
+                .unwrap()
+        };
+
+        assert_eq!(
+            error("8/8/8/8/8/8/8/4K3 w - - 0 1"),
+            BoardError::MissingKing(Color::Black)
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"),
+            BoardError::TooManyKings(Color::White)
+        );
+        assert_eq!(
+            error("4k3/pppppppp/p7/8/8/8/8/4K3 w - - 0 1"),
+            BoardError::TooManyPawns(Color::Black)
+        );
+        assert_eq!(
+            error("4k3/8/8/8/QQQQQQQQ/QQQQQQQQ/8/K7 w - - 0 1"),
+            BoardError::TooManyPieces(Color::White)
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/P3K3 w - - 0 1"),
+            BoardError::PawnOnBackRank(Square::A1)
+        );
+        assert_eq!(error("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"), BoardError::OpponentInCheck);
+        // No pawn that just moved two squares, and the wrong side to move
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 b - e3 0 1"),
+            BoardError::InvalidEnPassantSquare(Square::E3)
+        );
+        assert_eq!(
+            error("4k3/8/8/8/4P3/8/8/4K3 w - e3 0 1"),
+            BoardError::InvalidEnPassantSquare(Square::E3)
+        );
+        assert!(BoardBuilder::try_from_fen("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1").is_ok());
+
+        assert!(BoardBuilder::try_from_fen_unchecked("8/8/8/8/8/8/8/8 w - - 0 1").is_ok());
+    }
+
+    #[test]
+    fn test_from_fen_malformed() {
+        let error = |fen| {
+            *BoardBuilder::try_from_fen_unchecked(fen)
+                .unwrap_err()
+                .downcast_ref::<BoardError>()
+                .unwrap()
+        };
+
+        assert_eq!(error(""), BoardError::WrongNumberOfFenFields(0));
+        assert_eq!(error("4k3/8/8/8/8/8/8/4K3 w"), BoardError::WrongNumberOfFenFields(2));
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - -"),
+            BoardError::WrongNumberOfFenFields(4)
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/8/4K3 w - - 0 1"),
+            BoardError::InvalidPiecePlacement
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/4K3 w - - 0 1"),
+            BoardError::InvalidPiecePlacement
+        );
+        // Too many and too few squares on a rank
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K4 w - - 0 1"),
+            BoardError::InvalidPiecePlacement
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/88K w - - 0 1"),
+            BoardError::InvalidPiecePlacement
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/7/4K3 w - - 0 1"),
+            BoardError::InvalidPiecePlacement
+        );
+    }
+
     #[test]
     fn test_from_fen_invalid_to_move_color() {
         let board = BoardBuilder::try_from_fen("8/8/8/8/8/8/8/8 - - - 0 1");
@@ -237,7 +588,7 @@ mod tests {
 
         assert_eq!(
             board.err().unwrap().to_string(),
-            "invalid castling rights in fen, must be a combination of 'K', 'Q', 'k', and 'q' or '-'"
+            "invalid castling rights in fen, must be a combination of 'K', 'Q', 'k', and 'q', rook files or '-'"
         )
     }
 
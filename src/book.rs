@@ -0,0 +1,565 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{bail, Result};
+use tracing::{debug, warn};
+
+use crate::{
+    board::Board,
+    board_builder::BoardBuilder,
+    move_generation::{Flag, Move, MoveGenerator},
+    pgn::parse_games,
+    piece::{Color, Piece},
+    polyglot::polyglot_key,
+    search::SearchLimits,
+    strength::Random,
+};
+
+// Games are only followed this far into the opening by default
+pub const DEFAULT_BOOK_PLIES: u32 = 20;
+// The book is played from for at most this many plies of the game by default
+pub const DEFAULT_BOOK_DEPTH: u32 = 30;
+pub const MAX_BOOK_DEPTH: u32 = 200;
+// With no variety only the top move is played, with full variety every move can be
+pub const DEFAULT_BOOK_VARIETY: u32 = 50;
+pub const MAX_BOOK_VARIETY: u32 = 100;
+// Preferred moves, e.g. from the repertoire, are this many times as likely to be played
+const PREFERRED_MOVE_WEIGHT_FACTOR: u64 = 4;
+// Every entry is a big-endian 8 byte position key, 2 byte move, 2 byte weight and 4 byte learn
+// value, the same layout as a Polyglot book. Talia keeps the number of games the move was played
+// in as the learn value.
+const ENTRY_SIZE: usize = 16;
+
+// An opening book in the Polyglot file format: entries sorted by position key, with each move's
+// weight saying how often it should be played. Positions are keyed with Polyglot's own random
+// table, so books built by other tools can be played from and Talia's books work elsewhere.
+#[derive(Debug, Clone, Default)]
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BookEntry {
+    key: u64,
+    mv: u16,
+    weight: u16,
+    games: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookMove {
+    pub mv: Move,
+    // How well the move scored
+    pub weight: u16,
+    // How often the move was played
+    pub games: u32,
+}
+
+// What makes a book move more likely to be played, e.g. with the "Book Weighting" option
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BookWeighting {
+    #[default]
+    Score,
+    Frequency,
+}
+
+impl BookWeighting {
+    pub const ALL: [BookWeighting; 2] = [BookWeighting::Score, BookWeighting::Frequency];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BookWeighting::Score => "Score",
+            BookWeighting::Frequency => "Frequency",
+        }
+    }
+
+    pub fn try_from_name(name: &str) -> Result<Self> {
+        match Self::ALL
+            .into_iter()
+            .find(|weighting| weighting.name().eq_ignore_ascii_case(name))
+        {
+            Some(weighting) => Ok(weighting),
+            None => bail!("Unknown book weighting: {name}"),
+        }
+    }
+}
+
+// How the engine plays from its book
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookSettings {
+    pub weighting: BookWeighting,
+    // Moves are only played if they're at least (MAX_BOOK_VARIETY - variety)% as good as the
+    // top move, so that rare and bad lines aren't followed
+    pub variety: u32,
+    // Plies of the game after which the book isn't used anymore
+    pub max_plies: u32,
+}
+
+impl Default for BookSettings {
+    fn default() -> Self {
+        Self {
+            weighting: BookWeighting::default(),
+            variety: DEFAULT_BOOK_VARIETY,
+            max_plies: DEFAULT_BOOK_DEPTH,
+        }
+    }
+}
+
+impl Book {
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            bail!("Not an opening book: the size is not a multiple of {ENTRY_SIZE} bytes");
+        }
+
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| BookEntry {
+                key: u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+                mv: u16::from_be_bytes(entry[8..10].try_into().unwrap()),
+                weight: u16::from_be_bytes(entry[10..12].try_into().unwrap()),
+                games: u32::from_be_bytes(entry[12..16].try_into().unwrap()),
+            })
+            .collect();
+        // Lookups rely on the order, don't trust the file for it
+        entries.sort_by_key(|entry| (entry.key, u16::MAX - entry.weight, entry.mv));
+
+        Ok(Self { entries })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.entries.len() * ENTRY_SIZE);
+        for entry in &self.entries {
+            bytes.extend_from_slice(&entry.key.to_be_bytes());
+            bytes.extend_from_slice(&entry.mv.to_be_bytes());
+            bytes.extend_from_slice(&entry.weight.to_be_bytes());
+            bytes.extend_from_slice(&entry.games.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    // Number of moves in the book, over all positions
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // The book moves in the position, the best scoring first. Moves that aren't legal in the
+    // position (from a hash collision) are left out.
+    pub fn moves(&self, board: &Board) -> Vec<BookMove> {
+        let key = polyglot_key(board);
+        let first = self.entries.partition_point(|entry| entry.key < key);
+        let entries = self.entries[first..]
+            .iter()
+            .take_while(|entry| entry.key == key);
+
+        let legal_moves = MoveGenerator::new(board.without_history()).generate_moves();
+        entries
+            .filter_map(|entry| {
+                legal_moves
+                    .iter()
+                    .find(|mv| encode_move(board, mv) == entry.mv)
+                    .map(|mv| BookMove {
+                        mv: mv.clone(),
+                        weight: entry.weight,
+                        games: entry.games,
+                    })
+            })
+            .collect()
+    }
+
+    // A random book move among the ones the settings and limits allow, each as likely as its
+    // share of their total weight. None when the position is out of book or the game is past the
+    // book depth.
+    pub fn pick_move(
+        &self,
+        board: &Board,
+        settings: &BookSettings,
+        limits: &SearchLimits,
+        random: &mut Random,
+    ) -> Option<Move> {
+        let ply =
+            2 * board.full_move_number.saturating_sub(1) + u32::from(board.to_move == Color::Black);
+        if ply >= settings.max_plies {
+            return None;
+        }
+
+        let moves = self.moves(board);
+        // Other tools leave the learn value at zero rather than counting games, so in their books
+        // the weights are all there is
+        let by_frequency = settings.weighting == BookWeighting::Frequency
+            && moves.iter().any(|book_move| book_move.games > 0);
+        let weighted_moves: Vec<(Move, u64)> = moves
+            .into_iter()
+            .filter(|book_move| limits.allows_root_move(&book_move.mv))
+            .map(|book_move| {
+                let mut weight = if by_frequency {
+                    book_move.games as u64
+                } else {
+                    book_move.weight as u64
+                };
+                if limits.preferred_moves.contains(&book_move.mv) {
+                    weight *= PREFERRED_MOVE_WEIGHT_FACTOR;
+                }
+                (book_move.mv, weight)
+            })
+            .collect();
+
+        let best_weight = weighted_moves.iter().map(|(_, weight)| *weight).max()?;
+        let variety = settings.variety.min(MAX_BOOK_VARIETY) as u64;
+        let min_weight =
+            best_weight * (MAX_BOOK_VARIETY as u64 - variety) / MAX_BOOK_VARIETY as u64;
+        let candidates: Vec<(Move, u64)> = weighted_moves
+            .into_iter()
+            .filter(|(_, weight)| *weight > 0 && *weight >= min_weight)
+            .collect();
+        let total_weight: u64 = candidates.iter().map(|(_, weight)| weight).sum();
+        if total_weight == 0 {
+            return None;
+        }
+
+        let mut pick = random.next_u64() % total_weight;
+        for (mv, weight) in candidates {
+            if pick < weight {
+                return Some(mv);
+            }
+            pick -= weight;
+        }
+
+        None
+    }
+
+    // Builds a book out of the first max_plies plies of every game in a PGN database. A move is
+    // weighted by how well it scored: 2 points for every win and 1 for every draw of the side
+    // that played it, so moves that only ever lost are left out. Games without a result are
+    // skipped, and a game is only followed up to its first move that can't be read.
+    pub fn from_pgn(pgn: &str, max_plies: u32) -> Self {
+        // Points and number of games of every move
+        let mut stats: HashMap<(u64, u16), (u64, u32)> = HashMap::new();
+        let mut games = 0;
+
+        for game in parse_games(pgn) {
+            let game = match game {
+                Ok(game) => game,
+                Err(err) => {
+                    warn!(%err, "skipping game that can't be read");
+                    continue;
+                }
+            };
+            let Some(winner) = game.tag("Result").and_then(parse_result) else {
+                continue;
+            };
+            let board = match game.tag("FEN") {
+                Some(fen) => match BoardBuilder::try_from_fen(fen) {
+                    Ok(board) => board,
+                    Err(err) => {
+                        warn!(fen, %err, "skipping game with an invalid FEN");
+                        continue;
+                    }
+                },
+                None => Board::starting_position(),
+            };
+            games += 1;
+
+            let mut move_generator = MoveGenerator::new(board);
+            for san in game
+                .main_line
+                .moves
+                .iter()
+                .map(|mv| mv.san.as_str())
+                .take(max_plies as usize)
+            {
+                let mv = match Move::try_from_san(san, &mut move_generator) {
+                    Ok(mv) => mv,
+                    Err(err) => {
+                        warn!(san, %err, "stopping at a move that can't be read");
+                        break;
+                    }
+                };
+
+                let board = &move_generator.board;
+                let score = match winner {
+                    Some(color) if color == board.to_move => 2,
+                    Some(_) => 0,
+                    None => 1,
+                };
+                let (points, games) = stats
+                    .entry((polyglot_key(board), encode_move(board, &mv)))
+                    .or_default();
+                *points += score;
+                *games = games.saturating_add(1);
+                move_generator.board.move_piece(&mv);
+            }
+        }
+
+        // Weights only have 16 bits, so the moves of very popular positions are scaled down
+        // together to keep their proportions
+        let mut max_points: HashMap<u64, u64> = HashMap::new();
+        for (&(key, _), &(points, _)) in &stats {
+            let max = max_points.entry(key).or_default();
+            *max = (*max).max(points);
+        }
+        let mut entries: Vec<BookEntry> = stats
+            .into_iter()
+            .map(|((key, mv), (points, games))| {
+                let max = max_points[&key];
+                let weight = if max > u16::MAX as u64 {
+                    points * u16::MAX as u64 / max
+                } else {
+                    points
+                };
+                BookEntry {
+                    key,
+                    mv,
+                    weight: weight as u16,
+                    games,
+                }
+            })
+            .filter(|entry| entry.weight > 0)
+            .collect();
+        entries.sort_by_key(|entry| (entry.key, u16::MAX - entry.weight, entry.mv));
+
+        debug!(games, moves = entries.len(), "built opening book");
+        Self { entries }
+    }
+}
+
+// Polyglot's move encoding: target square in bits 0-5, starting square in bits 6-11 and the
+// promotion piece in bits 12-14. Castling is written as the king capturing its own rook.
+fn encode_move(board: &Board, mv: &Move) -> u16 {
+    let target_square = match mv.flag {
+        Flag::KingsideCastle | Flag::QueensideCastle => board.castling_rook_square(mv),
+        _ => mv.target_square,
+    };
+    let promotion = match mv.flag {
+        Flag::PromoteTo(piece) | Flag::CaptureWithPromotion(piece) => match piece {
+            Piece::Knight => 1,
+            Piece::Bishop => 2,
+            Piece::Rook => 3,
+            _ => 4,
+        },
+        _ => 0,
+    };
+
+    (promotion << 12 | mv.starting_square << 6 | target_square) as u16
+}
+
+fn parse_result(result: &str) -> Option<Option<Color>> {
+    match result {
+        "1-0" => Some(Some(Color::White)),
+        "0-1" => Some(Some(Color::Black)),
+        "1/2-1/2" => Some(None),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Board,
+        board_builder::BoardBuilder,
+        move_generation::{Flag, Move},
+        search::SearchLimits,
+        square::Square::*,
+        strength::Random,
+    };
+
+    use super::{encode_move, Book, BookSettings, BookWeighting};
+
+    const GAMES: &str = r#"[Event "Test"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 {main line} Nc6 (2... d6 3. d4) 3. Bb5 a6 1-0
+
+[Event "Test"]
+[Result "1/2-1/2"]
+
+1.e4 c5 2.Nf3 $1 d6 ; Sicilian
+3.d4 1/2-1/2
+
+[Event "Test"]
+[Result "0-1"]
+
+1. d4 d5 0-1
+
+[Event "Unfinished"]
+[Result "*"]
+
+1. c4 *
+"#;
+
+    #[test]
+    fn test_book_from_pgn() {
+        let book = Book::from_pgn(GAMES, 3);
+
+        // White scored 1.5 out of 2 with e4 and lost with d4, c4 was never finished
+        let board = Board::starting_position();
+        let moves = book.moves(&board);
+        assert_eq!(moves.len(), 1);
+        assert!(moves[0].mv == Move::from_square(E2, E4, Flag::PawnDoublePush));
+        assert_eq!(moves[0].weight, 3);
+        assert_eq!(moves[0].games, 2);
+
+        // Only the first 3 plies are in the book
+        let board = Board::from_start_and_moves(None, &["e2e4", "e7e5"]).unwrap();
+        let moves = book.moves(&board);
+        assert_eq!(moves.len(), 1);
+        assert!(moves[0].mv == Move::from_square(G1, F3, Flag::None));
+        let board = board.play_uci_moves(&["g1f3"]).unwrap().0;
+        assert!(book.moves(&board).is_empty());
+    }
+
+    #[test]
+    fn test_pick_move_follows_weights() {
+        let book = Book::from_pgn(GAMES, 20);
+        let mut random = Random::from_clock();
+
+        let settings = BookSettings::default();
+        let limits = SearchLimits::default();
+
+        let board = Board::starting_position();
+        for _ in 0..10 {
+            let mv = book
+                .pick_move(&board, &settings, &limits, &mut random)
+                .unwrap();
+            assert!(mv == Move::from_square(E2, E4, Flag::PawnDoublePush));
+        }
+
+        // Out of book
+        let board = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(book
+            .pick_move(&board, &settings, &limits, &mut random)
+            .is_none());
+    }
+
+    #[test]
+    fn test_book_variety_weighting_and_depth() {
+        // 1. d4 was played three times but only scored a draw, 1. e4 won its only game
+        let games = "[Result \"1-0\"]\n\n1. e4 1-0\n\n[Result \"1/2-1/2\"]\n\n1. d4 1/2-1/2\n\n\
+                     [Result \"0-1\"]\n\n1. d4 0-1\n\n[Result \"0-1\"]\n\n1. d4 0-1\n";
+        let book = Book::from_pgn(games, 20);
+        let board = Board::starting_position();
+        let e4 = Move::from_square(E2, E4, Flag::PawnDoublePush);
+        let d4 = Move::from_square(D2, D4, Flag::PawnDoublePush);
+        let limits = SearchLimits::default();
+        let mut random = Random::from_clock();
+
+        // Without variety, only the top move by score or by frequency is played
+        let mut settings = BookSettings {
+            variety: 0,
+            ..BookSettings::default()
+        };
+        assert!(
+            book.pick_move(&board, &settings, &limits, &mut random)
+                .unwrap()
+                == e4
+        );
+        settings.weighting = BookWeighting::Frequency;
+        assert!(
+            book.pick_move(&board, &settings, &limits, &mut random)
+                .unwrap()
+                == d4
+        );
+
+        // With full variety both get played
+        settings.variety = 100;
+        let mut picked_e4 = false;
+        let mut picked_d4 = false;
+        for _ in 0..100 {
+            let mv = book
+                .pick_move(&board, &settings, &limits, &mut random)
+                .unwrap();
+            picked_e4 |= mv == e4;
+            picked_d4 |= mv == d4;
+        }
+        assert!(picked_e4 && picked_d4);
+
+        // Preferred moves are played more often, moves the search may not play never are
+        settings.variety = 0;
+        settings.weighting = BookWeighting::Score;
+        let preferred_d4 = SearchLimits {
+            preferred_moves: vec![d4.clone()],
+            ..SearchLimits::default()
+        };
+        assert!(
+            book.pick_move(&board, &settings, &preferred_d4, &mut random)
+                .unwrap()
+                == d4
+        );
+        let excluded_e4 = SearchLimits {
+            excluded_moves: vec![e4.clone()],
+            ..SearchLimits::default()
+        };
+        assert!(
+            book.pick_move(&board, &settings, &excluded_e4, &mut random)
+                .unwrap()
+                == d4
+        );
+
+        // Past the book depth, the book is left alone
+        settings.max_plies = 0;
+        assert!(book
+            .pick_move(&board, &settings, &limits, &mut random)
+            .is_none());
+        assert_eq!(
+            BookWeighting::try_from_name("frequency").unwrap(),
+            BookWeighting::Frequency
+        );
+        assert!(BookWeighting::try_from_name("Popularity").is_err());
+    }
+
+    #[test]
+    fn test_book_round_trips_through_bytes() {
+        let book = Book::from_pgn(GAMES, 20);
+        let bytes = book.to_bytes();
+
+        assert_eq!(bytes.len(), book.len() * 16);
+        let loaded = Book::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.entries, book.entries);
+        assert!(Book::from_bytes(&bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn test_reads_polyglot_entries() {
+        // 1. e4 in the starting position as any Polyglot tool writes it: e2 is square 12 and e4
+        // square 28
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&0x463b96181691fc9c_u64.to_be_bytes());
+        bytes.extend_from_slice(&(12_u16 << 6 | 28).to_be_bytes());
+        bytes.extend_from_slice(&10_u16.to_be_bytes());
+        bytes.extend_from_slice(&0_u32.to_be_bytes());
+        let book = Book::from_bytes(&bytes).unwrap();
+
+        let moves = book.moves(&Board::starting_position());
+        assert_eq!(moves.len(), 1);
+        assert!(moves[0].mv == Move::from_square(E2, E4, Flag::PawnDoublePush));
+        assert_eq!(moves[0].weight, 10);
+
+        // Books built by Talia use the same keys
+        let book = Book::from_pgn(GAMES, 1);
+        assert!(book
+            .entries
+            .iter()
+            .all(|entry| entry.key == 0x463b96181691fc9c));
+    }
+
+    #[test]
+    fn test_castling_is_encoded_as_king_takes_rook() {
+        let board = Board::starting_position();
+        let castle = Move::from_square(E1, G1, Flag::KingsideCastle);
+
+        // e1 is square 4 and h1 square 7
+        assert_eq!(encode_move(&board, &castle), 4 << 6 | 7);
+    }
+}
@@ -1,132 +1,968 @@
-use std::fs::OpenOptions;
-use std::io::prelude::*;
+use std::io::{prelude::*, BufRead};
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex, MutexGuard,
+};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 use crate::{
     board::Board,
     board_builder::BoardBuilder,
-    move_generation::{Move, MoveGenerator},
-    search::find_best_move,
+    book::{Book, BookSettings, BookWeighting, MAX_BOOK_DEPTH, MAX_BOOK_VARIETY},
+    evaluate::DefaultEvaluator,
+    logging::{default_log_file, LogFile},
+    mate_solver::solve_mate,
+    move_generation::{Flag, Move, MoveGenerator},
+    nnue::Network,
+    repertoire::Repertoire,
+    score::Score,
+    search::{Search, SearchAlgorithm, SearchLimits, SearchProgress, SearchResult},
+    strength::{
+        root_noise_for_skill_level, Random, StrengthLimit, DEFAULT_ELO, MAX_ELO, MAX_SKILL_LEVEL,
+        MIN_ELO,
+    },
+    tablebase::{
+        TablebaseSettings, MAX_TABLEBASE_PIECES, MAX_TABLEBASE_TIMEOUT, MIN_TABLEBASE_PIECES,
+    },
+    transposition_table::{DEFAULT_HASH_SIZE_MB, MAX_HASH_SIZE_MB, MIN_HASH_SIZE_MB},
+    weights::{EvalWeights, Term, MAX_WEIGHT, MIN_WEIGHT},
 };
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
+use tracing::{error, info, info_span};
+
+// The protocol is chosen by the first command the GUI sends: 'xboard' for CECP, anything else
+// is treated as UCI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Protocol {
+    Uci,
+    Xboard,
+}
+
+// Everything that can follow 'go', which is where a searchmoves list ends
+const GO_KEYWORDS: [&str; 12] = [
+    "searchmoves",
+    "ponder",
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "depth",
+    "nodes",
+    "mate",
+    "movetime",
+    "infinite",
+];
+
+// How often an infinite search that already finished checks whether it was told to stop
+const INFINITE_SEARCH_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Where responses go. Shared with the search thread, which sends the best move itself.
+#[derive(Clone)]
+struct Output(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl Output {
+    fn respond(&self, data: &str) {
+        // A poisoned lock only means another thread panicked mid-write, the stream is still fine
+        let mut output = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        if let Err(err) = writeln!(output, "{data}").and_then(|_| output.flush()) {
+            error!(%err, response = data, "failed to write response");
+        }
+        info!(response = data);
+    }
+}
 
 pub struct Bot {
-    board: Board,
+    // Holds the current position, which is updated in place as the game goes on rather than
+    // building a new generator for every command
+    move_generator: MoveGenerator,
+    // The moves of the game so far, if it started from the starting position. UCI only.
+    game_moves: Option<Vec<Move>>,
+    output: Output,
+    log_file: LogFile,
+    protocol: Protocol,
+    // xboard only: in force mode the engine just follows the moves it's given without thinking
+    force_mode: bool,
+    // xboard only: set with 'sd'
+    max_depth: Option<u32>,
+    // xboard only: the engine's clock from 'time', the increment from 'level' and the fixed
+    // time per move from 'st'
+    clock: Option<Duration>,
+    increment: Option<Duration>,
+    move_time: Option<Duration>,
+    chess960: bool,
+    limit_strength: bool,
+    elo: u32,
+    skill_level: u32,
+    ponder: bool,
+    // The book is only played from while OwnBook is on
+    own_book: bool,
+    book: Option<Book>,
+    book_settings: BookSettings,
+    repertoire: Option<Repertoire>,
+    tablebase: TablebaseSettings,
+    search_algorithm: SearchAlgorithm,
+    // Of the hand-crafted evaluation, set one by one or from a weights file
+    weights: EvalWeights,
+    // Loaded with EvalFile, evaluates positions instead of the hand-crafted evaluation
+    network: Option<Arc<Network>>,
+    hash_size_mb: usize,
+    // Shared with the search thread, which holds the lock while it searches
+    searcher: Arc<Mutex<Box<dyn Search + Send>>>,
+    stop_search: Arc<AtomicBool>,
+    // The search runs on its own thread so that commands like stop can be handled meanwhile.
+    // The thread hands its best move back once it's done.
+    search_thread: Option<JoinHandle<Move>>,
 }
 
 impl Bot {
     pub fn new() -> Self {
+        Self::with_output(std::io::stdout())
+    }
+
+    pub fn with_output(output: impl Write + Send + 'static) -> Self {
         Self {
-            board: Board::starting_position(),
+            move_generator: MoveGenerator::default(),
+            game_moves: Some(Vec::new()),
+            output: Output(Arc::new(Mutex::new(Box::new(output)))),
+            log_file: LogFile::new(Some(default_log_file())),
+            protocol: Protocol::Uci,
+            force_mode: false,
+            max_depth: None,
+            clock: None,
+            increment: None,
+            move_time: None,
+            chess960: false,
+            limit_strength: false,
+            elo: DEFAULT_ELO,
+            skill_level: MAX_SKILL_LEVEL,
+            ponder: false,
+            own_book: false,
+            book: None,
+            book_settings: BookSettings::default(),
+            repertoire: None,
+            tablebase: TablebaseSettings::default(),
+            search_algorithm: SearchAlgorithm::default(),
+            weights: EvalWeights::default(),
+            network: None,
+            hash_size_mb: DEFAULT_HASH_SIZE_MB,
+            searcher: Arc::new(Mutex::new(SearchAlgorithm::default().create(
+                DEFAULT_HASH_SIZE_MB,
+                EvalWeights::default(),
+                None,
+            ))),
+            stop_search: Arc::new(AtomicBool::new(false)),
+            search_thread: None,
         }
     }
 
-    pub fn start_uci(&mut self) -> Result<()> {
-        loop {
-            let input = self.get_uci_move_input();
+    // Replaces the searcher, so nothing learnt by the previous one is kept
+    pub fn set_search_algorithm(&mut self, search_algorithm: SearchAlgorithm) {
+        self.stop_search();
+        self.search_algorithm = search_algorithm;
+        *self.searcher() = self.create_searcher();
+    }
+
+    // Replaces the searcher like set_search_algorithm. None goes back to the hand-crafted
+    // evaluation.
+    pub fn set_network(&mut self, network: Option<Network>) {
+        self.stop_search();
+        self.network = network.map(Arc::new);
+        *self.searcher() = self.create_searcher();
+    }
+
+    // Replaces the searcher like set_search_algorithm
+    pub fn set_weights(&mut self, weights: EvalWeights) {
+        self.stop_search();
+        self.weights = weights;
+        *self.searcher() = self.create_searcher();
+    }
+
+    fn create_searcher(&self) -> Box<dyn Search + Send> {
+        self.search_algorithm
+            .create(self.hash_size_mb, self.weights, self.network.clone())
+    }
+
+    // Only to be called while no search is running, otherwise it waits for the search to finish
+    fn searcher(&self) -> MutexGuard<'_, Box<dyn Search + Send>> {
+        // A search that panicked leaves the lock poisoned, the searcher is replaced afterwards
+        self.searcher.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    // None disables logging
+    pub fn set_log_file(&mut self, path: Option<PathBuf>) {
+        self.log_file.set(path);
+    }
+
+    // The handle stays in sync with the Log File option
+    pub fn log_file(&self) -> LogFile {
+        self.log_file.clone()
+    }
+
+    // Reads commands line by line until the input ends or the GUI quits
+    pub fn start(&mut self, input: impl BufRead) -> Result<()> {
+        for line in input.lines() {
+            let input = line?;
             let split_input: Vec<&str> = input.split_whitespace().collect();
             let commands = split_input.as_slice();
-            self.log(&input);
+            let _span = info_span!("command", command = input.as_str()).entered();
+            info!("received command");
+            if commands == ["quit"] {
+                break;
+            }
             if let Err(e) = self.process_commands(commands) {
-                self.log("Talia encountered a critical error");
-                self.log(&e.to_string());
+                error!(err = %e, "failed to process command");
             }
         }
-    }
 
-    fn get_uci_move_input(&self) -> String {
-        let mut input = String::new();
-        std::io::stdin()
-            .read_line(&mut input)
-            .expect("failed to read line");
+        self.quit();
+        Ok(())
+    }
 
-        input.trim().to_owned()
+    // Returning from the command loop instead of exiting the process lets embedders carry on,
+    // so the search thread must not outlive the session
+    fn quit(&mut self) {
+        self.stop_search();
+        info!("quit");
     }
 
     fn process_commands(&mut self, commands: &[&str]) -> Result<()> {
+        match (self.protocol, commands) {
+            (_, ["xboard"]) => {
+                self.protocol = Protocol::Xboard;
+                Ok(())
+            }
+            (Protocol::Uci, _) => self.process_uci_commands(commands),
+            (Protocol::Xboard, _) => self.process_xboard_commands(commands),
+        }
+    }
+
+    fn process_uci_commands(&mut self, commands: &[&str]) -> Result<()> {
         match commands {
-            ["uci"] => self.respond("uciok"),
-            ["isready"] => self.respond("readyok"),
+            ["uci"] => {
+                self.output.respond(&format!(
+                    "option name Hash type spin default {DEFAULT_HASH_SIZE_MB} min {MIN_HASH_SIZE_MB} max {MAX_HASH_SIZE_MB}"
+                ));
+                self.output.respond("option name Clear Hash type button");
+                let algorithms: Vec<String> = SearchAlgorithm::ALL
+                    .iter()
+                    .map(|algorithm| format!("var {}", algorithm.name()))
+                    .collect();
+                self.output.respond(&format!(
+                    "option name Search Algorithm type combo default {} {}",
+                    self.search_algorithm.name(),
+                    algorithms.join(" ")
+                ));
+                self.output
+                    .respond("option name Ponder type check default false");
+                self.output.respond(&format!(
+                    "option name Log File type string default {}",
+                    self.log_file
+                        .path()
+                        .map_or(String::from("<empty>"), |path| path.display().to_string())
+                ));
+                self.output
+                    .respond("option name EvalFile type string default <empty>");
+                self.output
+                    .respond("option name OwnBook type check default false");
+                self.output
+                    .respond("option name BookFile type string default <empty>");
+                let weightings: Vec<String> = BookWeighting::ALL
+                    .iter()
+                    .map(|weighting| format!("var {}", weighting.name()))
+                    .collect();
+                self.output.respond(&format!(
+                    "option name Book Weighting type combo default {} {}",
+                    self.book_settings.weighting.name(),
+                    weightings.join(" ")
+                ));
+                self.output.respond(&format!(
+                    "option name Book Variety type spin default {} min 0 max {MAX_BOOK_VARIETY}",
+                    self.book_settings.variety
+                ));
+                self.output.respond(&format!(
+                    "option name Book Depth type spin default {} min 0 max {MAX_BOOK_DEPTH}",
+                    self.book_settings.max_plies
+                ));
+                self.output
+                    .respond("option name Repertoire File type string default <empty>");
+                self.output.respond(&format!(
+                    "option name Online Tablebase type check default {}",
+                    self.tablebase.enabled
+                ));
+                self.output.respond(&format!(
+                    "option name Tablebase URL type string default {}",
+                    self.tablebase.url
+                ));
+                self.output.respond(&format!(
+                    "option name Tablebase Timeout type spin default {} min 0 max {}",
+                    self.tablebase.timeout.as_millis(),
+                    MAX_TABLEBASE_TIMEOUT.as_millis()
+                ));
+                self.output.respond(&format!(
+                    "option name Tablebase Pieces type spin default {} min {MIN_TABLEBASE_PIECES} max {MAX_TABLEBASE_PIECES}",
+                    self.tablebase.max_pieces
+                ));
+                self.output
+                    .respond("option name UCI_Chess960 type check default false");
+                self.output
+                    .respond("option name UCI_LimitStrength type check default false");
+                self.output.respond(&format!(
+                    "option name UCI_Elo type spin default {DEFAULT_ELO} min {MIN_ELO} max {MAX_ELO}"
+                ));
+                self.output.respond(&format!(
+                    "option name Skill Level type spin default {MAX_SKILL_LEVEL} min 0 max {MAX_SKILL_LEVEL}"
+                ));
+                self.output
+                    .respond("option name Weights File type string default <empty>");
+                for term in Term::ALL {
+                    self.output.respond(&format!(
+                        "option name {} type spin default {} min {MIN_WEIGHT} max {MAX_WEIGHT}",
+                        term.name(),
+                        self.weights[term]
+                    ));
+                }
+                self.output.respond("uciok");
+            }
+            ["isready"] => self.output.respond("readyok"),
+            ["setoption", ..] => self.handle_setoption_command(commands)?,
             ["position", ..] => self.handle_position_command(commands)?,
             ["go", ..] => self.handle_go_command(commands)?,
-            // TODO: Handle stop once clock is implemented in searcher
-            ["ucinewgame"] | ["stop"] => {}
-            ["quit"] => std::process::exit(0),
+            // Without time management there is nothing to adjust when the opponent plays the
+            // expected move, so just play the best move found while pondering
+            ["stop"] | ["ponderhit"] => self.stop_search(),
+            ["ucinewgame"] => self.start_new_game(),
+            // Not part of UCI, shows how the hand-crafted evaluation sees the current position
+            ["eval"] => {
+                let breakdown = DefaultEvaluator::with_weights(self.weights)
+                    .evaluate_detailed(&self.move_generator.board);
+                self.output.respond(&breakdown.to_string());
+            }
             _ => bail!("unrecognized UCI command"),
         }
         Ok(())
     }
 
+    fn process_xboard_commands(&mut self, commands: &[&str]) -> Result<()> {
+        // Every command but this one refers to the position after the engine's move, so the
+        // search has to finish first
+        if commands != ["?"] {
+            self.wait_for_search();
+        }
+
+        match commands {
+            ["protover", ..] => self.output.respond(
+                "feature myname=\"Talia\" usermove=1 setboard=1 ping=1 sigint=0 sigterm=0 colors=0 done=1",
+            ),
+            ["new"] => {
+                self.start_new_game();
+                self.force_mode = false;
+                self.max_depth = None;
+                self.clock = None;
+                self.increment = None;
+                self.move_time = None;
+            }
+            ["force"] | ["result", ..] => self.force_mode = true,
+            ["go"] => {
+                self.force_mode = false;
+                self.start_xboard_search();
+            }
+            ["usermove", mv] => {
+                match Move::try_from_uci(mv, &self.move_generator.board) {
+                    Ok(mv) => {
+                        self.move_generator.board.move_piece(&mv);
+                        if !self.force_mode {
+                            self.start_xboard_search();
+                        }
+                    }
+                    Err(_) => self.output.respond(&format!("Illegal move: {mv}")),
+                }
+            }
+            // The GUI shows the error to the user, and the position stays as it was
+            ["setboard", fen @ ..] => match BoardBuilder::try_from_fen(&fen.join(" ")) {
+                Ok(board) => self.set_board(board),
+                Err(err) => self
+                    .output
+                    .respond(&format!("tellusererror Illegal position: {err}")),
+            },
+            ["sd", depth] => {
+                self.max_depth = Some(depth.parse().map_err(|_| anyhow!("invalid depth"))?)
+            }
+            ["ping", number] => self.output.respond(&format!("pong {number}")),
+            ["?"] => self.stop_search(),
+            // Clocks are in centiseconds
+            ["time", centiseconds] => {
+                let centiseconds: u64 = centiseconds.parse().map_err(|_| anyhow!("invalid time"))?;
+                self.clock = Some(Duration::from_millis(centiseconds * 10));
+            }
+            // Format: 'level 40 5 0', only the increment in seconds matters as the clock is
+            // reported before every move
+            ["level", _, _, increment] => {
+                let increment: f64 = increment.parse().map_err(|_| anyhow!("invalid increment"))?;
+                self.increment = Some(Duration::from_secs_f64(increment.max(0.0)));
+            }
+            ["st", seconds] => {
+                let seconds: u64 = seconds.parse().map_err(|_| anyhow!("invalid time"))?;
+                self.move_time = Some(Duration::from_secs(seconds));
+            }
+            ["otim", _] => {}
+            ["accepted", ..] | ["rejected", ..] | ["random"] | ["post"] | ["nopost"] | ["hard"]
+            | ["easy"] | ["computer"] | ["name", ..] | ["rating", ..] => {}
+            _ => bail!("unrecognized xboard command"),
+        }
+        Ok(())
+    }
+
+    fn start_xboard_search(&mut self) {
+        // The GUI reports the result itself once the game is over
+        if !self.move_generator.has_legal_moves() {
+            return;
+        }
+
+        // The engine always plays the side to move
+        self.start_search(SearchLimits {
+            depth: self.max_depth,
+            movetime: self.move_time,
+            wtime: self.clock,
+            btime: self.clock,
+            winc: self.increment,
+            binc: self.increment,
+            ..SearchLimits::default()
+        });
+    }
+
     fn handle_position_command(&mut self, pos_command: &[&str]) -> Result<()> {
         // Format: 'position startpos moves e2e4 e7e5'
         // Or: 'position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 moves e2e4 e7e5'
         // Note: 'moves' section is optional
         // (Thanks Sebastian for figuring this out, so I don't have to read the specs <3)
-        match pos_command {
-            ["position", "startpos", "moves", moves @ ..] => {
-                self.board = Board::starting_position();
-                self.play_moves_on_board(moves);
+        let (board, moves) = match pos_command {
+            ["position", "startpos", "moves", moves @ ..] => (Board::starting_position(), moves),
+            ["position", "startpos"] => (Board::starting_position(), &[][..]),
+            ["position", "fen", fen_0, fen_1, fen_2, fen_3, fen_4, fen_5, "moves", moves @ ..] => {
+                let full_fen_string =
+                    format!("{} {} {} {} {} {}", fen_0, fen_1, fen_2, fen_3, fen_4, fen_5);
+
+                (BoardBuilder::try_from_fen(&full_fen_string)?, moves)
+            }
+            ["position", "fen", fen_0, fen_1, fen_2, fen_3, fen_4, fen_5] => {
+                let full_fen_string =
+                    format!("{} {} {} {} {} {}", fen_0, fen_1, fen_2, fen_3, fen_4, fen_5);
+
+                (BoardBuilder::try_from_fen(&full_fen_string)?, &[][..])
+            }
+            _ => bail!("position command is in an unknown format"),
+        };
+
+        // The position is only replaced once all of it turned out to be valid, so a bad
+        // command leaves the previous one intact
+        let mut board = board;
+        board.chess960 = self.chess960;
+        let (mut board, played_moves) = board.play_uci_moves(moves)?;
+        // The game's moves are never taken back, and would only make every copy of the board
+        // for a search bigger
+        board.clear_history();
+        self.move_generator.board = board;
+        // Openings are only known from the regular starting position
+        let from_starting_position = matches!(pos_command, ["position", "startpos", ..]);
+        self.game_moves = (from_starting_position && !self.chess960).then_some(played_moves);
+
+        Ok(())
+    }
+
+    // Moves are read and written in the notation of the current variant
+    fn set_board(&mut self, mut board: Board) {
+        board.chess960 = self.chess960;
+        self.move_generator.board = board;
+        self.game_moves = None;
+    }
+
+    // Nothing learned in the previous game should carry over to the next one
+    fn start_new_game(&mut self) {
+        self.stop_search();
+        self.searcher().clear();
+        self.set_board(Board::starting_position());
+        self.game_moves = Some(Vec::new());
+    }
+
+    fn handle_setoption_command(&mut self, setoption_command: &[&str]) -> Result<()> {
+        // Format: 'setoption name Hash value 32'
+        match setoption_command {
+            ["setoption", "name", "Hash", "value", size_mb] => {
+                let size_mb: usize = size_mb
+                    .parse()
+                    .map_err(|_| anyhow!("Hash value must be a number of megabytes"))?;
+                let size_mb = size_mb.clamp(MIN_HASH_SIZE_MB, MAX_HASH_SIZE_MB);
+                self.stop_search();
+                self.hash_size_mb = size_mb;
+                self.searcher().set_hash_size(size_mb);
 
                 Ok(())
             }
-            ["position", "startpos"] => {
-                self.board = Board::starting_position();
+            ["setoption", "name", "UCI_Chess960", "value", chess960] => {
+                self.chess960 = chess960
+                    .parse()
+                    .map_err(|_| anyhow!("UCI_Chess960 value must be true or false"))?;
+                self.move_generator.board.chess960 = self.chess960;
+
                 Ok(())
             }
-            ["position", "fen", fen_0, fen_1, fen_2, fen_3, fen_4, fen_5, "moves", moves @ ..] => {
-                let full_fen_string =
-                    format!("{} {} {} {} {} {}", fen_0, fen_1, fen_2, fen_3, fen_4, fen_5);
+            ["setoption", "name", "UCI_LimitStrength", "value", limit_strength] => {
+                self.limit_strength = limit_strength
+                    .parse()
+                    .map_err(|_| anyhow!("UCI_LimitStrength value must be true or false"))?;
 
-                self.board = BoardBuilder::try_from_fen(&full_fen_string)?;
-                self.play_moves_on_board(moves);
+                Ok(())
+            }
+            ["setoption", "name", "UCI_Elo", "value", elo] => {
+                let elo: u32 = elo
+                    .parse()
+                    .map_err(|_| anyhow!("UCI_Elo value must be a number"))?;
+                self.elo = elo.clamp(MIN_ELO, MAX_ELO);
 
                 Ok(())
             }
-            ["position", "fen", fen_0, fen_1, fen_2, fen_3, fen_4, fen_5] => {
-                let full_fen_string =
-                    format!("{} {} {} {} {} {}", fen_0, fen_1, fen_2, fen_3, fen_4, fen_5);
+            ["setoption", "name", "Skill", "Level", "value", skill_level] => {
+                let skill_level: u32 = skill_level
+                    .parse()
+                    .map_err(|_| anyhow!("Skill Level value must be a number"))?;
+                self.skill_level = skill_level.min(MAX_SKILL_LEVEL);
 
-                self.board = BoardBuilder::try_from_fen(&full_fen_string)?;
                 Ok(())
             }
-            _ => bail!("position command is in an unknown format"),
+            ["setoption", "name", "Ponder", "value", ponder] => {
+                self.ponder = ponder
+                    .parse()
+                    .map_err(|_| anyhow!("Ponder value must be true or false"))?;
+
+                Ok(())
+            }
+            // An empty path disables logging
+            ["setoption", "name", "Log", "File", "value", path @ ..] => {
+                let path = path.join(" ");
+                if path.is_empty() || path == "<empty>" {
+                    self.set_log_file(None);
+                } else {
+                    self.set_log_file(Some(PathBuf::from(path)));
+                }
+
+                Ok(())
+            }
+            ["setoption", "name", "OwnBook", "value", own_book] => {
+                self.own_book = own_book
+                    .parse()
+                    .map_err(|_| anyhow!("OwnBook value must be true or false"))?;
+
+                Ok(())
+            }
+            // An empty path unloads the book. The book is read right away, so a bad file is
+            // reported here rather than in the middle of a game.
+            ["setoption", "name", "BookFile", "value", path @ ..] => {
+                let path = path.join(" ");
+                if path.is_empty() || path == "<empty>" {
+                    self.book = None;
+                } else {
+                    let path = PathBuf::from(path);
+                    let book = Book::load(&path)
+                        .map_err(|err| anyhow!("failed to load {}: {err}", path.display()))?;
+                    info!(path = %path.display(), moves = book.len(), "loaded opening book");
+                    self.book = Some(book);
+                }
+
+                Ok(())
+            }
+            // An empty path goes back to the hand-crafted evaluation. Like the book, the network
+            // is read right away.
+            ["setoption", "name", "EvalFile", "value", path @ ..] => {
+                let path = path.join(" ");
+                if path.is_empty() || path == "<empty>" {
+                    self.set_network(None);
+                } else {
+                    let path = PathBuf::from(path);
+                    let network = Network::load(&path)
+                        .map_err(|err| anyhow!("failed to load {}: {err}", path.display()))?;
+                    info!(
+                        path = %path.display(),
+                        hidden_size = network.hidden_size(),
+                        "loaded network"
+                    );
+                    self.set_network(Some(network));
+                }
+
+                Ok(())
+            }
+            // An empty path drops the repertoire
+            ["setoption", "name", "Repertoire", "File", "value", path @ ..] => {
+                let path = path.join(" ");
+                if path.is_empty() || path == "<empty>" {
+                    self.repertoire = None;
+                } else {
+                    let repertoire = Repertoire::load(&PathBuf::from(&path))
+                        .map_err(|err| anyhow!("failed to load {path}: {err}"))?;
+                    self.repertoire = Some(repertoire);
+                }
+
+                Ok(())
+            }
+            ["setoption", "name", "Book", "Weighting", "value", name] => {
+                self.book_settings.weighting = BookWeighting::try_from_name(name)?;
+
+                Ok(())
+            }
+            ["setoption", "name", "Book", "Variety", "value", variety] => {
+                let variety: u32 = variety
+                    .parse()
+                    .map_err(|_| anyhow!("Book Variety value must be a number"))?;
+                self.book_settings.variety = variety.min(MAX_BOOK_VARIETY);
+
+                Ok(())
+            }
+            ["setoption", "name", "Book", "Depth", "value", max_plies] => {
+                let max_plies: u32 = max_plies
+                    .parse()
+                    .map_err(|_| anyhow!("Book Depth value must be a number of plies"))?;
+                self.book_settings.max_plies = max_plies.min(MAX_BOOK_DEPTH);
+
+                Ok(())
+            }
+            ["setoption", "name", "Online", "Tablebase", "value", enabled] => {
+                self.tablebase.enabled = enabled
+                    .parse()
+                    .map_err(|_| anyhow!("Online Tablebase value must be true or false"))?;
+
+                Ok(())
+            }
+            ["setoption", "name", "Tablebase", "URL", "value", url] => {
+                self.tablebase.url = url.to_string();
+
+                Ok(())
+            }
+            ["setoption", "name", "Tablebase", "Timeout", "value", timeout_ms] => {
+                let timeout_ms: u64 = timeout_ms
+                    .parse()
+                    .map_err(|_| anyhow!("Tablebase Timeout value must be a number of ms"))?;
+                self.tablebase.timeout =
+                    Duration::from_millis(timeout_ms).min(MAX_TABLEBASE_TIMEOUT);
+
+                Ok(())
+            }
+            ["setoption", "name", "Tablebase", "Pieces", "value", max_pieces] => {
+                let max_pieces: u32 = max_pieces
+                    .parse()
+                    .map_err(|_| anyhow!("Tablebase Pieces value must be a number"))?;
+                self.tablebase.max_pieces =
+                    max_pieces.clamp(MIN_TABLEBASE_PIECES, MAX_TABLEBASE_PIECES);
+
+                Ok(())
+            }
+            ["setoption", "name", "Clear", "Hash"] => {
+                self.stop_search();
+                self.searcher().clear();
+
+                Ok(())
+            }
+            ["setoption", "name", "Search", "Algorithm", "value", name] => {
+                self.set_search_algorithm(SearchAlgorithm::try_from_name(name)?);
+
+                Ok(())
+            }
+            // An empty path goes back to the built-in weights
+            ["setoption", "name", "Weights", "File", "value", path @ ..] => {
+                let path = path.join(" ");
+                let weights = if path.is_empty() || path == "<empty>" {
+                    EvalWeights::default()
+                } else {
+                    EvalWeights::load(&PathBuf::from(&path))
+                        .map_err(|err| anyhow!("failed to load {path}: {err}"))?
+                };
+                self.set_weights(weights);
+
+                Ok(())
+            }
+            ["setoption", "name", name, "value", weight] if Term::try_from_name(name).is_ok() => {
+                let term = Term::try_from_name(name)?;
+                let weight: i32 = weight
+                    .parse()
+                    .map_err(|_| anyhow!("{} value must be a number", term.name()))?;
+                let mut weights = self.weights;
+                weights[term] = weight.clamp(MIN_WEIGHT, MAX_WEIGHT);
+                self.set_weights(weights);
+
+                Ok(())
+            }
+            _ => bail!("setoption command is in an unknown format"),
         }
     }
 
-    fn handle_go_command(&mut self, _go_command: &[&str]) -> Result<()> {
-        // TODO: Handle time and increments
-        let depth = 6;
-        let mut move_generator = MoveGenerator::new(self.board.clone());
-        let mut moves = move_generator.generate_moves();
-        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, depth);
-        self.board.move_piece(&best_move);
+    fn handle_go_command(&mut self, go_command: &[&str]) -> Result<()> {
+        // The GUI waits for a bestmove even when the game is already over, and there is nothing
+        // to search
+        if !self.move_generator.has_legal_moves() {
+            self.stop_search();
+            self.output.respond("bestmove 0000");
+            return Ok(());
+        }
 
-        self.respond(&format!("bestmove {best_move}"));
+        let limits = self.parse_search_limits(go_command)?;
+        self.start_search(limits);
 
         Ok(())
     }
 
-    fn play_moves_on_board(&mut self, moves: &[&str]) {
-        for mv in moves {
-            // Need a move generator to check if the move is legal
-            let mut move_generator = MoveGenerator::new(self.board.clone());
-            let mv = Move::try_from_uci(mv, &mut move_generator).unwrap();
-            self.board.move_piece(&mv);
+    fn start_search(&mut self, mut limits: SearchLimits) {
+        let infinite = limits.infinite;
+        limits.max_root_noise = root_noise_for_skill_level(self.skill_level);
+        limits.tablebase = self.tablebase.clone();
+        let strength_limit = self.limit_strength.then(|| StrengthLimit::new(self.elo));
+        if let Some(strength_limit) = strength_limit {
+            strength_limit.apply(&mut limits);
+        }
+        // Both the book and the search follow the repertoire
+        if let (Some(repertoire), Some(game_moves), Protocol::Uci) =
+            (&self.repertoire, &self.game_moves, self.protocol)
+        {
+            repertoire.apply(game_moves, &self.move_generator.board, &mut limits);
+        }
+
+        self.stop_search();
+        self.stop_search.store(false, Ordering::Relaxed);
+
+        let book_move = self.book_move(&limits);
+        // The weakened engine picks among all the legal moves, not just the best one
+        let legal_moves = strength_limit.map(|_| self.move_generator.generate_moves());
+        let board = self.move_generator.board.without_history();
+        let protocol = self.protocol;
+        let ponder = self.ponder;
+        let output = self.output.clone();
+        let stop = Arc::clone(&self.stop_search);
+        let searcher = Arc::clone(&self.searcher);
+        self.search_thread = Some(thread::spawn(move || {
+            let mut searcher = searcher.lock().unwrap_or_else(|err| err.into_inner());
+            let mut report_progress = |progress: &SearchProgress| {
+                if protocol == Protocol::Uci {
+                    output.respond(&uci_info(&board, progress))
+                }
+            };
+            let result = match book_move {
+                Some(book_move) => {
+                    info!(
+                        book_move = board.move_to_uci(&book_move),
+                        san = board.move_to_san(&book_move),
+                        "playing from the book"
+                    );
+                    SearchResult {
+                        best_move: book_move.clone(),
+                        ponder_move: None,
+                        score: Score::default(),
+                        pv: vec![book_move],
+                        stats: Default::default(),
+                    }
+                }
+                None => {
+                    // Without a mate, the GUI still needs a move
+                    let mate = limits
+                        .mate
+                        .and_then(|moves| solve_mate(&board, moves, &stop));
+                    let search_result = match mate {
+                        Some(mate) => {
+                            report_progress(&SearchProgress::Iteration {
+                                depth: mate.stats.depth,
+                                score: mate.score,
+                                pv: mate.pv.clone(),
+                                nodes: mate.stats.nodes,
+                                time: mate.stats.time,
+                                hashfull: 0,
+                            });
+                            mate
+                        }
+                        None => searcher.search(&board, &limits, &stop, &mut report_progress),
+                    };
+                    match strength_limit.zip(legal_moves) {
+                        Some((strength_limit, legal_moves)) => strength_limit.choose_move(
+                            search_result,
+                            &legal_moves,
+                            &limits,
+                            &stop,
+                            |limits| searcher.search(&board, limits, &stop, &mut |_| {}),
+                        ),
+                        None => search_result,
+                    }
+                }
+            };
+            let best_move = result.best_move;
+
+            // An infinite search must not send its best move before being told to stop,
+            // even if it ran out of depth or found a forced mate
+            if infinite {
+                while !stop.load(Ordering::Relaxed) {
+                    thread::sleep(INFINITE_SEARCH_POLL_INTERVAL);
+                }
+            }
+            let best_move_uci = board.move_to_uci(&best_move);
+            info!(
+                best_move = best_move_uci,
+                san = board.move_to_san(&best_move),
+                "search finished"
+            );
+            let ponder_move = result.ponder_move.filter(|_| ponder);
+            match protocol {
+                Protocol::Uci => match ponder_move {
+                    Some(ponder_move) => {
+                        let mut board = board.without_history();
+                        board.move_piece(&best_move);
+                        let ponder_move_uci = board.move_to_uci(&ponder_move);
+                        output
+                            .respond(&format!("bestmove {best_move_uci} ponder {ponder_move_uci}"));
+                    }
+                    None => output.respond(&format!("bestmove {best_move_uci}")),
+                },
+                Protocol::Xboard => output.respond(&format!("move {best_move_uci}")),
+            }
+
+            best_move
+        }));
+    }
+
+    // Analysis, mate searches and restricted searches want the engine's own opinion, so the
+    // book is only used for regular moves in a game
+    fn book_move(&self, limits: &SearchLimits) -> Option<Move> {
+        if !self.own_book || limits.infinite || limits.mate.is_some() {
+            return None;
         }
+        self.book.as_ref()?.pick_move(
+            &self.move_generator.board,
+            &self.book_settings,
+            limits,
+            &mut Random::from_clock(),
+        )
     }
 
-    fn respond(&self, data: &str) {
-        println!("{data}");
-        self.log(data);
+    fn parse_search_limits(&self, go_command: &[&str]) -> Result<SearchLimits> {
+        // Format: 'go depth 8', 'go nodes 100000', 'go movetime 1000' or 'go infinite', limits
+        // can be combined
+        // 'go wtime 60000 btime 60000 winc 1000 binc 1000 movestogo 20' plays on the clock
+        // 'go searchmoves e2e4 d2d4' restricts the search to the listed moves
+        // 'go mate 3' looks for a mate in 3 first
+        let mut limits = SearchLimits::default();
+        let mut tokens = go_command.iter().skip(1).peekable();
+        while let Some(&token) = tokens.next() {
+            match token {
+                "searchmoves" => {
+                    // The move list goes on until the next keyword. The GUI still waits for a
+                    // bestmove if some of it isn't legal here, so those moves are left out, and
+                    // without any legal ones all moves are searched.
+                    while let Some(&mv) = tokens.next_if(|token| !GO_KEYWORDS.contains(token)) {
+                        match Move::try_from_uci(mv, &self.move_generator.board) {
+                            // Passing isn't something the search can be restricted to
+                            Ok(mv) if mv.flag != Flag::NullMove => limits.search_moves.push(mv),
+                            _ => self
+                                .output
+                                .respond(&format!("info string ignoring illegal searchmove {mv}")),
+                        }
+                    }
+                    if limits.search_moves.is_empty() {
+                        self.output
+                            .respond("info string no legal searchmoves, searching all moves");
+                    }
+                }
+                // A ponder search must not end before the GUI says stop or ponderhit
+                "infinite" | "ponder" => limits.infinite = true,
+                "depth" => {
+                    let depth = tokens.next().ok_or(anyhow!("missing value for depth"))?;
+                    limits.depth = Some(depth.parse().map_err(|_| anyhow!("invalid depth"))?);
+                }
+                "nodes" => {
+                    let nodes = tokens.next().ok_or(anyhow!("missing value for nodes"))?;
+                    limits.nodes = Some(nodes.parse().map_err(|_| anyhow!("invalid nodes"))?);
+                }
+                "mate" => {
+                    let moves = tokens.next().ok_or(anyhow!("missing value for mate"))?;
+                    limits.mate = Some(moves.parse().map_err(|_| anyhow!("invalid mate"))?);
+                }
+                "movestogo" => {
+                    let moves = tokens
+                        .next()
+                        .ok_or(anyhow!("missing value for movestogo"))?;
+                    limits.movestogo =
+                        Some(moves.parse().map_err(|_| anyhow!("invalid movestogo"))?);
+                }
+                "movetime" | "wtime" | "btime" | "winc" | "binc" => {
+                    let millis = tokens.next().ok_or(anyhow!("missing value for {token}"))?;
+                    // Clocks can go negative when the GUI adds lag to them
+                    let millis: i64 = millis.parse().map_err(|_| anyhow!("invalid {token}"))?;
+                    let time = Some(Duration::from_millis(millis.max(0) as u64));
+                    match token {
+                        "movetime" => limits.movetime = time,
+                        "wtime" => limits.wtime = time,
+                        "btime" => limits.btime = time,
+                        "winc" => limits.winc = time,
+                        _ => limits.binc = time,
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(limits)
     }
 
-    fn log(&self, data: &str) {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open("/tmp/talia.log")
-            .expect("Unable to open file");
+    // Stops the running search (if any) and waits for it to send its best move
+    fn stop_search(&mut self) {
+        self.stop_search.store(true, Ordering::Relaxed);
+        self.wait_for_search();
+    }
 
-        writeln!(file, "{data}").expect("Unable to write to log file");
+    // With xboard, the engine keeps track of the game itself, so its own move is played on the
+    // board. A UCI GUI always sends the full position instead.
+    fn wait_for_search(&mut self) {
+        if let Some(search_thread) = self.search_thread.take() {
+            match search_thread.join() {
+                Ok(best_move) => {
+                    if self.protocol == Protocol::Xboard {
+                        self.move_generator.board.move_piece(&best_move);
+                    }
+                }
+                Err(_) => {
+                    error!("search thread panicked, resetting the searcher");
+                    *self.searcher() = self.create_searcher();
+                }
+            }
+        }
+    }
+}
+
+// The moves are written in the position's notation, which depends on chess960
+fn uci_info(board: &Board, progress: &SearchProgress) -> String {
+    match progress {
+        SearchProgress::Iteration {
+            depth,
+            score,
+            pv,
+            nodes,
+            time,
+            hashfull,
+        } => {
+            let mut board = board.without_history();
+            let pv: Vec<String> = pv
+                .iter()
+                .map(|mv| {
+                    let uci = board.move_to_uci(mv);
+                    board.move_piece(mv);
+                    uci
+                })
+                .collect();
+
+            format!(
+                "info depth {depth} score {score} nodes {nodes} time {} hashfull {hashfull} pv {}",
+                time.as_millis(),
+                pv.join(" ")
+            )
+        }
+        SearchProgress::CurrentMove { mv, move_number } => {
+            format!("info currmove {} currmovenumber {move_number}", board.move_to_uci(mv))
+        }
     }
 }
 
@@ -136,23 +972,191 @@ impl Default for Bot {
     }
 }
 
+impl Drop for Bot {
+    fn drop(&mut self) {
+        self.stop_search();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         board::Board,
         board_builder::BoardBuilder,
-        bot::Bot,
+        book::Book,
+        bot::{Bot, Protocol},
         move_generation::{Flag, Move},
+        nnue::{Network, FEATURES},
+        piece::Color,
+        search::SearchAlgorithm,
         square::Square,
+        strength::{MAX_SKILL_LEVEL, MIN_ELO},
+        tablebase::{TablebaseSettings, MAX_TABLEBASE_PIECES},
+        weights::{EvalWeights, Term, MAX_WEIGHT},
+    };
+    use std::{
+        io::{Cursor, Write},
+        path::PathBuf,
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
     };
 
+    // Lets a test read what the bot wrote after handing it the writer
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_uci_session_over_streams() {
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        let input = Cursor::new("uci\nisready\nposition startpos moves e2e4\ngo depth 1\n");
+        bot.start(input).unwrap();
+
+        let lines = output.lines();
+        assert!(lines.iter().any(|line| line == "uciok"));
+        assert!(lines.iter().any(|line| line == "readyok"));
+        assert!(lines
+            .last()
+            .is_some_and(|line| line.starts_with("bestmove ")));
+    }
+
+    #[test]
+    fn test_info_lines_during_search() {
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        bot.process_commands(&["go", "depth", "2"]).unwrap();
+        bot.wait_for_search();
+
+        let lines = output.lines();
+        let info: Vec<&str> = lines
+            .iter()
+            .find(|line| line.starts_with("info depth 2 "))
+            .unwrap()
+            .split_whitespace()
+            .collect();
+        assert!(matches!(
+            info.as_slice(),
+            [
+                "info",
+                "depth",
+                "2",
+                "score",
+                "cp",
+                _,
+                "nodes",
+                _,
+                "time",
+                _,
+                "hashfull",
+                _,
+                "pv",
+                ..
+            ]
+        ));
+        assert!(info.len() > 13);
+    }
+
+    #[test]
+    fn test_bestmove_with_ponder_move() {
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        bot.process_commands(&["setoption", "name", "Ponder", "value", "true"])
+            .unwrap();
+        bot.process_commands(&["go", "depth", "3"]).unwrap();
+        bot.wait_for_search();
+
+        let lines = output.lines();
+        let bestmove: Vec<&str> = lines.last().unwrap().split_whitespace().collect();
+        assert!(matches!(bestmove.as_slice(), ["bestmove", _, "ponder", _]));
+    }
+
+    #[test]
+    fn test_go_without_legal_moves() {
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        // Checkmate, then stalemate
+        for fen in ["7k/6Q1/6K1/8/8/8/8/8", "7k/5Q2/6K1/8/8/8/8/8"] {
+            bot.process_commands(&["position", "fen", fen, "b", "-", "-", "0", "1"])
+                .unwrap();
+            bot.process_commands(&["go", "depth", "2"]).unwrap();
+            bot.wait_for_search();
+
+            assert_eq!(output.lines().last().unwrap(), "bestmove 0000");
+        }
+    }
+
+    #[test]
+    fn test_quit_ends_session() {
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        bot.start(Cursor::new("isready\nquit\nisready\n")).unwrap();
+
+        assert_eq!(output.lines(), vec!["readyok"]);
+    }
+
+    #[test]
+    fn test_log_file_option() {
+        let mut bot = Bot::with_output(SharedBuffer::default());
+        let log_file = bot.log_file();
+
+        bot.process_commands(&[
+            "setoption",
+            "name",
+            "Log",
+            "File",
+            "value",
+            "my",
+            "talia.log",
+        ])
+        .unwrap();
+        assert_eq!(log_file.path(), Some(PathBuf::from("my talia.log")));
+
+        bot.process_commands(&["setoption", "name", "Log", "File", "value"])
+            .unwrap();
+        assert_eq!(log_file.path(), None);
+    }
+
+    #[test]
+    fn test_quit_stops_and_joins_search() {
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        bot.start(Cursor::new("go infinite\nquit\n")).unwrap();
+
+        assert!(bot.search_thread.is_none());
+        assert!(output
+            .lines()
+            .last()
+            .is_some_and(|line| line.starts_with("bestmove ")));
+    }
+
     #[test]
     fn test_uci_command_position() {
         let mut bot = Bot::new();
         let command = ["position", "startpos"];
         bot.process_commands(&command).unwrap();
 
-        assert!(bot.board == Board::starting_position())
+        assert!(bot.move_generator.board == Board::starting_position())
     }
 
     #[test]
@@ -161,13 +1165,15 @@ mod tests {
         let command = ["position", "startpos", "moves", "e2e4", "e7e5"];
         bot.process_commands(&command).unwrap();
 
-        let expected_board: Board = BoardBuilder::from_starting_position()
+        let mut expected_board: Board = BoardBuilder::from_starting_position()
             .make_move(Move::from_square(Square::E2, Square::E4, Flag::PawnDoublePush))
             .make_move(Move::from_square(Square::E7, Square::E5, Flag::PawnDoublePush))
             .try_into()
             .unwrap();
+        // The moves of the game are never taken back
+        expected_board.clear_history();
 
-        assert!(bot.board == expected_board)
+        assert!(bot.move_generator.board == expected_board)
     }
 
     #[test]
@@ -185,7 +1191,10 @@ mod tests {
         ];
         bot.process_commands(&command).unwrap();
 
-        assert!(bot.board.to_fen() == "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+        assert!(
+            bot.move_generator.board.to_fen()
+                == "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        )
     }
 
     #[test]
@@ -205,11 +1214,645 @@ mod tests {
         ];
         bot.process_commands(&command).unwrap();
 
+        let mut expected_board: Board = BoardBuilder::from_starting_position()
+            .make_move(Move::from_square(Square::E2, Square::E4, Flag::PawnDoublePush))
+            .try_into()
+            .unwrap();
+        // The moves of the game are never taken back
+        expected_board.clear_history();
+
+        assert!(bot.move_generator.board == expected_board);
+    }
+
+    #[test]
+    fn test_uci_command_position_with_illegal_move_keeps_board() {
+        let mut bot = Bot::new();
+        bot.process_commands(&["position", "startpos", "moves", "e2e4"])
+            .unwrap();
+        let board = bot.move_generator.board.clone();
+
+        let result = bot.process_commands(&["position", "startpos", "moves", "d2d4", "d7d4"]);
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "rejected position at move d7d4: Not a legal move"
+        );
+        assert!(bot.move_generator.board == board);
+        assert!(bot
+            .process_commands(&["position", "startpos", "moves", "e2"])
+            .is_err());
+        assert!(bot.move_generator.board == board);
+    }
+
+    #[test]
+    fn test_uci_chess960_castling_notation() {
+        let mut bot = Bot::new();
+        let fen = [
+            "position",
+            "fen",
+            "r3k2r/8/8/8/8/8/8/R3K2R",
+            "w",
+            "KQkq",
+            "-",
+            "0",
+            "1",
+            "moves",
+        ];
+        bot.process_commands(&["setoption", "name", "UCI_Chess960", "value", "true"])
+            .unwrap();
+        bot.process_commands(&[fen.as_slice(), &["e1h1", "e8a8"]].concat())
+            .unwrap();
+
+        assert_eq!(bot.move_generator.board.to_fen(), "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2");
+    }
+
+    #[test]
+    fn test_parse_search_limits() {
+        let bot = Bot::new();
+        let limits = bot
+            .parse_search_limits(&["go", "depth", "4", "nodes", "5000"])
+            .unwrap();
+        assert_eq!(limits.depth, Some(4));
+        assert_eq!(limits.nodes, Some(5000));
+        assert!(!limits.infinite);
+
+        let limits = bot.parse_search_limits(&["go", "infinite"]).unwrap();
+        assert!(limits.infinite);
+
+        assert!(bot.parse_search_limits(&["go", "depth"]).is_err());
+        assert!(bot.parse_search_limits(&["go", "nodes", "many"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_search_limits_clock() {
+        let bot = Bot::new();
+        let limits = bot
+            .parse_search_limits(&[
+                "go",
+                "wtime",
+                "60000",
+                "btime",
+                "-20",
+                "winc",
+                "1000",
+                "binc",
+                "1000",
+                "movestogo",
+                "20",
+                "movetime",
+                "500",
+            ])
+            .unwrap();
+
+        assert_eq!(limits.wtime, Some(Duration::from_secs(60)));
+        // A clock that went negative because of lag counts as empty
+        assert_eq!(limits.btime, Some(Duration::ZERO));
+        assert_eq!(limits.winc, Some(Duration::from_secs(1)));
+        assert_eq!(limits.binc, Some(Duration::from_secs(1)));
+        assert_eq!(limits.movestogo, Some(20));
+        assert_eq!(limits.movetime, Some(Duration::from_millis(500)));
+
+        assert!(bot.parse_search_limits(&["go", "wtime"]).is_err());
+        assert!(bot
+            .parse_search_limits(&["go", "movetime", "soon"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_search_limits_searchmoves() {
+        let bot = Bot::new();
+        let limits = bot
+            .parse_search_limits(&["go", "searchmoves", "e2e4", "d2d4", "depth", "3"])
+            .unwrap();
+
+        assert_eq!(
+            limits.search_moves,
+            vec![
+                Move::from_square(Square::E2, Square::E4, Flag::PawnDoublePush),
+                Move::from_square(Square::D2, Square::D4, Flag::PawnDoublePush),
+            ]
+        );
+        assert_eq!(limits.depth, Some(3));
+
+        // Illegal moves are left out, and the rest of the list is still read
+        let limits = bot
+            .parse_search_limits(&["go", "searchmoves", "e2e4", "zz", "d2d4", "depth", "3"])
+            .unwrap();
+        assert_eq!(limits.search_moves.len(), 2);
+        assert_eq!(limits.depth, Some(3));
+    }
+
+    #[test]
+    fn test_go_with_illegal_searchmoves_still_plays() {
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        bot.process_commands(&["go", "searchmoves", "e2e5", "depth", "1"])
+            .unwrap();
+        bot.wait_for_search();
+
+        let lines = output.lines();
+        assert_eq!(lines[0], "info string ignoring illegal searchmove e2e5");
+        assert_eq!(lines[1], "info string no legal searchmoves, searching all moves");
+        assert!(lines.last().unwrap().starts_with("bestmove "));
+        assert_ne!(lines.last().unwrap(), "bestmove 0000");
+    }
+
+    #[test]
+    fn test_uci_command_ucinewgame_resets_state() {
+        let mut bot = Bot::new();
+        bot.process_commands(&["setoption", "name", "Hash", "value", "1"])
+            .unwrap();
+        bot.process_commands(&["position", "startpos", "moves", "e2e4"])
+            .unwrap();
+        bot.process_commands(&["go", "depth", "4"]).unwrap();
+        bot.wait_for_search();
+        assert!(bot.searcher().hashfull() > 0);
+
+        bot.process_commands(&["ucinewgame"]).unwrap();
+
+        assert_eq!(bot.searcher().hashfull(), 0);
+        assert!(bot.move_generator.board == Board::starting_position());
+    }
+
+    #[test]
+    fn test_uci_command_clear_hash() {
+        let mut bot = Bot::new();
+        bot.process_commands(&["setoption", "name", "Hash", "value", "1"])
+            .unwrap();
+        bot.process_commands(&["go", "depth", "4"]).unwrap();
+        bot.wait_for_search();
+        assert!(bot.searcher().hashfull() > 0);
+
+        bot.process_commands(&["setoption", "name", "Clear", "Hash"])
+            .unwrap();
+
+        assert_eq!(bot.searcher().hashfull(), 0);
+    }
+
+    #[test]
+    fn test_uci_command_search_algorithm() {
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        bot.process_commands(&["uci"]).unwrap();
+        assert!(output.lines().contains(&String::from(
+            "option name Search Algorithm type combo default AlphaBeta var AlphaBeta var MCTS"
+        )));
+
+        bot.process_commands(&[
+            "setoption",
+            "name",
+            "Search",
+            "Algorithm",
+            "value",
+            "alphabeta",
+        ])
+        .unwrap();
+        assert!(bot.search_algorithm == SearchAlgorithm::AlphaBeta);
+        assert!(bot
+            .process_commands(&[
+                "setoption",
+                "name",
+                "Search",
+                "Algorithm",
+                "value",
+                "Random"
+            ])
+            .is_err());
+    }
+
+    #[test]
+    fn test_uci_command_go_mate() {
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        bot.process_commands(&[
+            "position",
+            "fen",
+            "7k/8/8/8/8/8/R7/1R5K",
+            "w",
+            "-",
+            "-",
+            "0",
+            "1",
+        ])
+        .unwrap();
+        bot.process_commands(&["go", "mate", "2"]).unwrap();
+        bot.wait_for_search();
+
+        let lines = output.lines();
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("info depth 3 score mate 2 ")));
+        assert!(lines
+            .last()
+            .is_some_and(|line| line.starts_with("bestmove ")));
+    }
+
+    #[test]
+    fn test_eval_file() {
+        let network_file =
+            std::env::temp_dir().join(format!("talia-network-{}.nnue", std::process::id()));
+        // Scores every position as a draw
+        Network::new(1, vec![0; FEATURES], vec![0], vec![0; 2], 0)
+            .unwrap()
+            .save(&network_file)
+            .unwrap();
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        let network_file_option = network_file.display().to_string();
+        bot.process_commands(&[
+            "setoption",
+            "name",
+            "EvalFile",
+            "value",
+            &network_file_option,
+        ])
+        .unwrap();
+        std::fs::remove_file(&network_file).unwrap();
+
+        bot.process_commands(&["position", "startpos", "moves", "e2e4"])
+            .unwrap();
+        bot.process_commands(&["go", "depth", "1"]).unwrap();
+        bot.wait_for_search();
+        assert!(output.lines()[0].starts_with("info depth 1 score cp 0 "));
+
+        // A file that doesn't exist is an error, an empty path goes back to the hand-crafted
+        // evaluation
+        assert!(bot
+            .process_commands(&[
+                "setoption",
+                "name",
+                "EvalFile",
+                "value",
+                &network_file_option
+            ])
+            .is_err());
+        bot.process_commands(&["setoption", "name", "EvalFile", "value", "<empty>"])
+            .unwrap();
+        bot.process_commands(&["go", "depth", "1"]).unwrap();
+        bot.wait_for_search();
+        assert!(!output.lines()[2].starts_with("info depth 1 score cp 0 "));
+    }
+
+    #[test]
+    fn test_eval_command() {
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        bot.process_commands(&["position", "startpos", "moves", "e2e4"])
+            .unwrap();
+        bot.process_commands(&["eval"]).unwrap();
+
+        let lines = output.lines();
+        assert!(lines[0].starts_with("Term"));
+        assert!(lines.iter().any(|line| line.starts_with("Material")));
+        assert!(lines.last().unwrap().starts_with("Eval: "));
+    }
+
+    #[test]
+    fn test_weight_options() {
+        let mut bot = Bot::new();
+        bot.process_commands(&["setoption", "name", "knight_value", "value", "310"])
+            .unwrap();
+        bot.process_commands(&["setoption", "name", "Bishop_Pair_Bonus", "value", "99999"])
+            .unwrap();
+        assert_eq!(bot.weights[Term::KnightValue], 310);
+        assert_eq!(bot.weights[Term::BishopPair], MAX_WEIGHT);
+        assert!(bot
+            .process_commands(&["setoption", "name", "knight_value", "value", "lots"])
+            .is_err());
+
+        let weights_file =
+            std::env::temp_dir().join(format!("talia-weights-{}.toml", std::process::id()));
+        std::fs::write(&weights_file, "rook_value = 520\n").unwrap();
+        let weights_file_option = weights_file.display().to_string();
+        bot.process_commands(&[
+            "setoption",
+            "name",
+            "Weights",
+            "File",
+            "value",
+            &weights_file_option,
+        ])
+        .unwrap();
+        std::fs::remove_file(&weights_file).unwrap();
+        // The file replaces the weights set before
+        assert_eq!(bot.weights[Term::RookValue], 520);
+        assert_eq!(
+            bot.weights[Term::KnightValue],
+            EvalWeights::default()[Term::KnightValue]
+        );
+
+        bot.process_commands(&["setoption", "name", "Weights", "File", "value", "<empty>"])
+            .unwrap();
+        assert_eq!(bot.weights, EvalWeights::default());
+    }
+
+    #[test]
+    fn test_own_book() {
+        let book_file = std::env::temp_dir().join(format!("talia-book-{}.bin", std::process::id()));
+        Book::from_pgn("[Result \"1-0\"]\n\n1. e4 e5 1-0\n", 20)
+            .save(&book_file)
+            .unwrap();
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        let book_file_option = book_file.display().to_string();
+        bot.process_commands(&["setoption", "name", "BookFile", "value", &book_file_option])
+            .unwrap();
+        std::fs::remove_file(&book_file).unwrap();
+
+        // The book is ignored until OwnBook is turned on
+        bot.process_commands(&["go", "depth", "1"]).unwrap();
+        bot.wait_for_search();
+        assert!(output.lines()[0].starts_with("info depth 1 "));
+
+        bot.process_commands(&["setoption", "name", "OwnBook", "value", "true"])
+            .unwrap();
+        bot.process_commands(&["go", "depth", "1"]).unwrap();
+        bot.wait_for_search();
+        assert_eq!(output.lines().last().unwrap(), "bestmove e2e4");
+
+        // Out of book, the engine searches as usual
+        bot.process_commands(&["position", "startpos", "moves", "d2d4"])
+            .unwrap();
+        let searched_lines = output.lines().len();
+        bot.process_commands(&["go", "depth", "1"]).unwrap();
+        bot.wait_for_search();
+        assert!(output.lines()[searched_lines].starts_with("info depth 1 "));
+
+        // Past the book depth as well
+        bot.process_commands(&["position", "startpos"]).unwrap();
+        bot.process_commands(&["setoption", "name", "Book", "Depth", "value", "0"])
+            .unwrap();
+        let searched_lines = output.lines().len();
+        bot.process_commands(&["go", "depth", "1"]).unwrap();
+        bot.wait_for_search();
+        assert!(output.lines()[searched_lines].starts_with("info depth 1 "));
+
+        assert!(bot
+            .process_commands(&["setoption", "name", "BookFile", "value", "missing.bin"])
+            .is_err());
+        assert!(bot
+            .process_commands(&["setoption", "name", "Book", "Weighting", "value", "Elo"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_repertoire_file() {
+        let temp_file = |extension: &str| {
+            std::env::temp_dir()
+                .join(format!("talia-repertoire-{}.{extension}", std::process::id()))
+        };
+        let book_file = temp_file("bin");
+        Book::from_pgn(
+            "[Result \"1-0\"]\n\n1. e4 e5 1-0\n\n[Result \"1/2-1/2\"]\n\n1. d4 d5 1/2-1/2\n",
+            20,
+        )
+        .save(&book_file)
+        .unwrap();
+        let repertoire_file = temp_file("json");
+        std::fs::write(&repertoire_file, r#"{"ban": ["e2e4"]}"#).unwrap();
+
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        let book_file_option = book_file.display().to_string();
+        let repertoire_file_option = repertoire_file.display().to_string();
+        bot.process_commands(&["setoption", "name", "BookFile", "value", &book_file_option])
+            .unwrap();
+        bot.process_commands(&["setoption", "name", "OwnBook", "value", "true"])
+            .unwrap();
+        bot.process_commands(&[
+            "setoption",
+            "name",
+            "Repertoire",
+            "File",
+            "value",
+            &repertoire_file_option,
+        ])
+        .unwrap();
+        std::fs::remove_file(&book_file).unwrap();
+        std::fs::remove_file(&repertoire_file).unwrap();
+
+        for _ in 0..5 {
+            bot.process_commands(&["go", "depth", "1"]).unwrap();
+            bot.wait_for_search();
+            assert_eq!(output.lines().last().unwrap(), "bestmove d2d4");
+        }
+
+        // Positions set up from a FEN have no known opening
+        bot.process_commands(&[
+            "position",
+            "fen",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+            "w",
+            "KQkq",
+            "-",
+            "0",
+            "1",
+        ])
+        .unwrap();
+        assert!(bot.game_moves.is_none());
+        bot.process_commands(&["position", "startpos", "moves", "e2e4"])
+            .unwrap();
+        assert_eq!(bot.game_moves.as_ref().map(Vec::len), Some(1));
+
+        bot.process_commands(&[
+            "setoption",
+            "name",
+            "Repertoire",
+            "File",
+            "value",
+            "<empty>",
+        ])
+        .unwrap();
+        assert!(bot.repertoire.is_none());
+        assert!(bot
+            .process_commands(&[
+                "setoption",
+                "name",
+                "Repertoire",
+                "File",
+                "value",
+                "missing.json"
+            ])
+            .is_err());
+    }
+
+    #[test]
+    fn test_mcts_search_sends_bestmove() {
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        bot.process_commands(&["setoption", "name", "Search", "Algorithm", "value", "MCTS"])
+            .unwrap();
+        bot.process_commands(&["go", "nodes", "500"]).unwrap();
+        bot.wait_for_search();
+
+        assert!(bot.search_algorithm == SearchAlgorithm::Mcts);
+        assert!(output
+            .lines()
+            .last()
+            .is_some_and(|line| line.starts_with("bestmove ")));
+    }
+
+    #[test]
+    fn test_uci_command_limit_strength_options() {
+        let mut bot = Bot::new();
+        bot.process_commands(&["setoption", "name", "UCI_LimitStrength", "value", "true"])
+            .unwrap();
+        bot.process_commands(&["setoption", "name", "UCI_Elo", "value", "900"])
+            .unwrap();
+
+        assert!(bot.limit_strength);
+        assert_eq!(bot.elo, MIN_ELO);
+        assert!(bot
+            .process_commands(&["setoption", "name", "UCI_Elo", "value", "strong"])
+            .is_err());
+
+        bot.process_commands(&["go"]).unwrap();
+        bot.wait_for_search();
+    }
+
+    #[test]
+    fn test_tablebase_options() {
+        let mut bot = Bot::new();
+        bot.process_commands(&["setoption", "name", "Online", "Tablebase", "value", "false"])
+            .unwrap();
+        bot.process_commands(&[
+            "setoption",
+            "name",
+            "Tablebase",
+            "URL",
+            "value",
+            "http://localhost:9000/standard",
+        ])
+        .unwrap();
+        bot.process_commands(&["setoption", "name", "Tablebase", "Timeout", "value", "500"])
+            .unwrap();
+        bot.process_commands(&["setoption", "name", "Tablebase", "Pieces", "value", "9"])
+            .unwrap();
+
+        assert_eq!(
+            bot.tablebase,
+            TablebaseSettings {
+                enabled: false,
+                url: "http://localhost:9000/standard".to_string(),
+                timeout: Duration::from_millis(500),
+                max_pieces: MAX_TABLEBASE_PIECES,
+            }
+        );
+        assert!(bot
+            .process_commands(&["setoption", "name", "Tablebase", "Timeout", "value", "soon"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_uci_command_skill_level() {
+        let mut bot = Bot::new();
+        bot.process_commands(&["setoption", "name", "Skill", "Level", "value", "35"])
+            .unwrap();
+        assert_eq!(bot.skill_level, MAX_SKILL_LEVEL);
+
+        bot.process_commands(&["setoption", "name", "Skill", "Level", "value", "3"])
+            .unwrap();
+        assert_eq!(bot.skill_level, 3);
+    }
+
+    #[test]
+    fn test_xboard_is_selected_by_first_command() {
+        let mut bot = Bot::new();
+        bot.process_commands(&["xboard"]).unwrap();
+
+        assert!(bot.protocol == Protocol::Xboard);
+        assert!(bot.process_commands(&["isready"]).is_err());
+    }
+
+    #[test]
+    fn test_xboard_force_mode_only_follows_moves() {
+        let mut bot = Bot::new();
+        bot.process_commands(&["xboard"]).unwrap();
+        bot.process_commands(&["new"]).unwrap();
+        bot.process_commands(&["force"]).unwrap();
+        bot.process_commands(&["usermove", "e2e4"]).unwrap();
+        bot.process_commands(&["usermove", "e7e5"]).unwrap();
+
         let expected_board: Board = BoardBuilder::from_starting_position()
             .make_move(Move::from_square(Square::E2, Square::E4, Flag::PawnDoublePush))
+            .make_move(Move::from_square(Square::E7, Square::E5, Flag::PawnDoublePush))
             .try_into()
             .unwrap();
+        assert!(bot.search_thread.is_none());
+        assert!(bot.move_generator.board == expected_board);
+    }
+
+    #[test]
+    fn test_xboard_engine_replies_to_user_move() {
+        let mut bot = Bot::new();
+        bot.process_commands(&["xboard"]).unwrap();
+        bot.process_commands(&["new"]).unwrap();
+        bot.process_commands(&["sd", "2"]).unwrap();
+        bot.process_commands(&["usermove", "e2e4"]).unwrap();
+        bot.process_commands(&["ping", "1"]).unwrap();
+
+        // The engine's reply is on the board once the search is over
+        assert!(bot.move_generator.board.to_move == Color::White);
+        assert_eq!(bot.move_generator.board.full_move_number, 2);
+    }
+
+    #[test]
+    fn test_xboard_clock() {
+        let mut bot = Bot::new();
+        bot.process_commands(&["xboard"]).unwrap();
+        bot.process_commands(&["new"]).unwrap();
+        bot.process_commands(&["level", "40", "5", "2"]).unwrap();
+        bot.process_commands(&["time", "30000"]).unwrap();
+        bot.process_commands(&["st", "3"]).unwrap();
+
+        assert_eq!(bot.clock, Some(Duration::from_secs(300)));
+        assert_eq!(bot.increment, Some(Duration::from_secs(2)));
+        assert_eq!(bot.move_time, Some(Duration::from_secs(3)));
+
+        bot.process_commands(&["new"]).unwrap();
+        assert_eq!(bot.clock, None);
+    }
+
+    #[test]
+    fn test_xboard_setboard() {
+        let mut bot = Bot::new();
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        bot.process_commands(&["xboard"]).unwrap();
+        bot.process_commands(&["setboard", "4k3/8/8/8/8/8/4P3/4K3", "w", "-", "-", "0", "1"])
+            .unwrap();
+
+        assert_eq!(bot.move_generator.board.to_fen(), fen);
+    }
+
+    #[test]
+    fn test_xboard_setboard_rejects_malformed_fen() {
+        let output = SharedBuffer::default();
+        let mut bot = Bot::with_output(output.clone());
+        bot.process_commands(&["xboard"]).unwrap();
+        bot.process_commands(&["protover", "2"]).unwrap();
+        bot.process_commands(&["setboard", "4k3/8/8/8/8/8/8/4K3", "w"])
+            .unwrap();
+
+        assert_eq!(
+            output.lines().last().unwrap(),
+            "tellusererror Illegal position: FEN has 2 fields instead of 6"
+        );
+        assert!(bot.move_generator.board == Board::starting_position());
+    }
+
+    #[test]
+    fn test_uci_command_go_infinite_searches_until_stop() {
+        let mut bot = Bot::new();
+        bot.process_commands(&["go", "infinite"]).unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        assert!(bot
+            .search_thread
+            .as_ref()
+            .is_some_and(|search_thread| !search_thread.is_finished()));
+
+        bot.process_commands(&["stop"]).unwrap();
 
-        assert!(bot.board == expected_board);
+        assert!(bot.search_thread.is_none());
     }
 }
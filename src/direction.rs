@@ -0,0 +1,208 @@
+use crate::piece::Color;
+
+// The board in the middle of a 10x12 frame of off-board sentinels, two rows deep above and below
+// and a column on each side, which is as far as a knight can jump. Stepping from a square inside
+// the frame lands either on a square or on a sentinel, so leaving the board is a lookup rather
+// than working out whether the step wrapped around to the other side.
+const MAILBOX: [Option<usize>; 120] = {
+    let mut mailbox = [None; 120];
+    let mut square = 0;
+    while square < 64 {
+        mailbox[MAILBOX_INDEX[square]] = Some(square);
+        square += 1;
+    }
+    mailbox
+};
+
+// Where each square of the board sits in the frame
+const MAILBOX_INDEX: [usize; 64] = {
+    let mut mailbox_index = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        mailbox_index[square] = 21 + square / 8 * 10 + square % 8;
+        square += 1;
+    }
+    mailbox_index
+};
+
+// A step a piece can take across the board. The first eight are the rays sliding pieces move
+// along, in the order of the rows of the precomputed squares-to-edge table, the rest are the
+// knight's jumps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    North,
+    South,
+    West,
+    East,
+    NorthWest,
+    SouthEast,
+    NorthEast,
+    SouthWest,
+    NorthNorthEast,
+    EastNorthEast,
+    EastSouthEast,
+    SouthSouthEast,
+    SouthSouthWest,
+    WestSouthWest,
+    WestNorthWest,
+    NorthNorthWest,
+}
+
+impl Direction {
+    pub const ORTHOGONALS: [Direction; 4] = [
+        Direction::North,
+        Direction::South,
+        Direction::West,
+        Direction::East,
+    ];
+    pub const DIAGONALS: [Direction; 4] = [
+        Direction::NorthWest,
+        Direction::SouthEast,
+        Direction::NorthEast,
+        Direction::SouthWest,
+    ];
+    // Queens slide along all of these and kings step along them
+    pub const RAYS: [Direction; 8] = [
+        Direction::North,
+        Direction::South,
+        Direction::West,
+        Direction::East,
+        Direction::NorthWest,
+        Direction::SouthEast,
+        Direction::NorthEast,
+        Direction::SouthWest,
+    ];
+    pub const KNIGHT_JUMPS: [Direction; 8] = [
+        Direction::NorthNorthEast,
+        Direction::EastNorthEast,
+        Direction::EastSouthEast,
+        Direction::SouthSouthEast,
+        Direction::SouthSouthWest,
+        Direction::WestSouthWest,
+        Direction::WestNorthWest,
+        Direction::NorthNorthWest,
+    ];
+
+    // The way the pawns of a color push
+    pub fn forward(color: Color) -> Self {
+        match color {
+            Color::White => Direction::North,
+            Color::Black => Direction::South,
+        }
+    }
+
+    // The two ways the pawns of a color capture
+    pub fn pawn_captures(color: Color) -> [Direction; 2] {
+        match color {
+            Color::White => [Direction::NorthWest, Direction::NorthEast],
+            Color::Black => [Direction::SouthEast, Direction::SouthWest],
+        }
+    }
+
+    // How many files and ranks one step goes, towards the h-file and the eighth rank
+    pub fn file_and_rank_steps(self) -> (isize, isize) {
+        match self {
+            Direction::North => (0, 1),
+            Direction::South => (0, -1),
+            Direction::West => (-1, 0),
+            Direction::East => (1, 0),
+            Direction::NorthWest => (-1, 1),
+            Direction::SouthEast => (1, -1),
+            Direction::NorthEast => (1, 1),
+            Direction::SouthWest => (-1, -1),
+            Direction::NorthNorthEast => (1, 2),
+            Direction::EastNorthEast => (2, 1),
+            Direction::EastSouthEast => (2, -1),
+            Direction::SouthSouthEast => (1, -2),
+            Direction::SouthSouthWest => (-1, -2),
+            Direction::WestSouthWest => (-2, -1),
+            Direction::WestNorthWest => (-2, 1),
+            Direction::NorthNorthWest => (-1, 2),
+        }
+    }
+
+    // What one step adds to a square index, e.g. 8 for north
+    pub fn offset(self) -> isize {
+        let (file_step, rank_step) = self.file_and_rank_steps();
+        rank_step * 8 + file_step
+    }
+
+    // The square one step away, or None when the step would leave the board rather than wrap
+    // around to the other side of it
+    pub fn from(self, square: usize) -> Option<usize> {
+        let (file_step, rank_step) = self.file_and_rank_steps();
+        let framed = MAILBOX_INDEX[square] as isize + rank_step * 10 + file_step;
+        MAILBOX[framed as usize]
+    }
+
+    // Along a rank or a file, the way rooks move
+    pub fn is_orthogonal(self) -> bool {
+        Self::ORTHOGONALS.contains(&self)
+    }
+
+    // The way bishops move
+    pub fn is_diagonal(self) -> bool {
+        Self::DIAGONALS.contains(&self)
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+            Direction::East => Direction::West,
+            Direction::NorthWest => Direction::SouthEast,
+            Direction::SouthEast => Direction::NorthWest,
+            Direction::NorthEast => Direction::SouthWest,
+            Direction::SouthWest => Direction::NorthEast,
+            Direction::NorthNorthEast => Direction::SouthSouthWest,
+            Direction::EastNorthEast => Direction::WestSouthWest,
+            Direction::EastSouthEast => Direction::WestNorthWest,
+            Direction::SouthSouthEast => Direction::NorthNorthWest,
+            Direction::SouthSouthWest => Direction::NorthNorthEast,
+            Direction::WestSouthWest => Direction::EastNorthEast,
+            Direction::WestNorthWest => Direction::EastSouthEast,
+            Direction::NorthNorthWest => Direction::SouthSouthEast,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::square::{square_index, File, Rank, Square::*};
+
+    use super::Direction;
+
+    #[test]
+    fn test_direction_steps() {
+        assert_eq!(Direction::North.from(E4.as_index()), Some(E5.as_index()));
+        assert_eq!(Direction::SouthWest.from(E4.as_index()), Some(D3.as_index()));
+        assert_eq!(Direction::WestNorthWest.from(E4.as_index()), Some(C5.as_index()));
+        // No wrapping around from the h-file to the a-file or off the top of the board
+        assert_eq!(Direction::East.from(H4.as_index()), None);
+        assert_eq!(Direction::EastNorthEast.from(G1.as_index()), None);
+        assert_eq!(Direction::NorthNorthWest.from(B7.as_index()), None);
+
+        assert_eq!(Direction::NorthEast.offset(), 9);
+        assert_eq!(Direction::SouthSouthWest.offset(), -17);
+        assert!(Direction::RAYS.iter().all(|direction| {
+            direction.offset() == -direction.opposite().offset()
+                && direction.is_orthogonal() != direction.is_diagonal()
+        }));
+        assert!(!Direction::NorthNorthEast.is_orthogonal());
+    }
+
+    #[test]
+    fn test_direction_steps_stay_on_board() {
+        for square in 0..64 {
+            for direction in Direction::RAYS.into_iter().chain(Direction::KNIGHT_JUMPS) {
+                let (file_step, rank_step) = direction.file_and_rank_steps();
+                let expected = File::of(square)
+                    .offset(file_step)
+                    .zip(Rank::of(square).offset(rank_step))
+                    .map(|(file, rank)| square_index(file, rank));
+                assert_eq!(direction.from(square), expected);
+            }
+        }
+    }
+}
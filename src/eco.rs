@@ -0,0 +1,238 @@
+use crate::move_generation::Move;
+
+// An opening from the ECO (Encyclopaedia of Chess Openings) classification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opening {
+    pub eco: &'static str,
+    pub name: &'static str,
+}
+
+// The most common openings by ECO code, name and their moves in UCI notation from the starting
+// position. Not the whole encyclopaedia, lines that aren't in here are classified by the longest
+// known line they start with.
+const OPENINGS: &[(&str, &str, &str)] = &[
+    ("A00", "Polish Opening", "b2b4"),
+    ("A00", "Grob Opening", "g2g4"),
+    ("A00", "Van't Kruijs Opening", "e2e3"),
+    ("A01", "Nimzo-Larsen Attack", "b2b3"),
+    ("A02", "Bird Opening", "f2f4"),
+    ("A04", "Zukertort Opening", "g1f3"),
+    ("A06", "Zukertort Opening", "g1f3 d7d5"),
+    ("A09", "Réti Opening", "g1f3 d7d5 c2c4"),
+    ("A10", "English Opening", "c2c4"),
+    ("A13", "English Opening: Agincourt Defense", "c2c4 e7e6"),
+    ("A15", "English Opening: Anglo-Indian Defense", "c2c4 g8f6"),
+    ("A20", "English Opening: King's English Variation", "c2c4 e7e5"),
+    ("A30", "English Opening: Symmetrical Variation", "c2c4 c7c5"),
+    ("A40", "Queen's Pawn Game", "d2d4"),
+    ("A43", "Benoni Defense: Old Benoni", "d2d4 c7c5"),
+    ("A45", "Indian Defense", "d2d4 g8f6"),
+    ("A45", "Trompowsky Attack", "d2d4 g8f6 c1g5"),
+    ("A46", "Indian Defense: Knights Variation", "d2d4 g8f6 g1f3"),
+    ("A51", "Indian Defense: Budapest Defense", "d2d4 g8f6 c2c4 e7e5"),
+    ("A56", "Benoni Defense", "d2d4 g8f6 c2c4 c7c5"),
+    ("A57", "Benko Gambit", "d2d4 g8f6 c2c4 c7c5 d4d5 b7b5"),
+    ("A80", "Dutch Defense", "d2d4 f7f5"),
+    ("B00", "King's Pawn Game", "e2e4"),
+    ("B00", "Nimzowitsch Defense", "e2e4 b8c6"),
+    ("B00", "Owen Defense", "e2e4 b7b6"),
+    ("B01", "Scandinavian Defense", "e2e4 d7d5"),
+    ("B02", "Alekhine Defense", "e2e4 g8f6"),
+    ("B06", "Modern Defense", "e2e4 g7g6"),
+    ("B07", "Pirc Defense", "e2e4 d7d6"),
+    ("B10", "Caro-Kann Defense", "e2e4 c7c6"),
+    (
+        "B12",
+        "Caro-Kann Defense: Advance Variation",
+        "e2e4 c7c6 d2d4 d7d5 e4e5",
+    ),
+    (
+        "B13",
+        "Caro-Kann Defense: Exchange Variation",
+        "e2e4 c7c6 d2d4 d7d5 e4d5",
+    ),
+    ("B20", "Sicilian Defense", "e2e4 c7c5"),
+    ("B22", "Sicilian Defense: Alapin Variation", "e2e4 c7c5 c2c3"),
+    ("B23", "Sicilian Defense: Closed", "e2e4 c7c5 b1c3"),
+    ("B27", "Sicilian Defense", "e2e4 c7c5 g1f3"),
+    ("B30", "Sicilian Defense: Old Sicilian", "e2e4 c7c5 g1f3 b8c6"),
+    ("B40", "Sicilian Defense: French Variation", "e2e4 c7c5 g1f3 e7e6"),
+    ("B50", "Sicilian Defense: Modern Variations", "e2e4 c7c5 g1f3 d7d6"),
+    ("B54", "Sicilian Defense: Open", "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4"),
+    (
+        "B70",
+        "Sicilian Defense: Dragon Variation",
+        "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4 g8f6 b1c3 g7g6",
+    ),
+    (
+        "B90",
+        "Sicilian Defense: Najdorf Variation",
+        "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4 g8f6 b1c3 a7a6",
+    ),
+    ("C00", "French Defense", "e2e4 e7e6"),
+    (
+        "C01",
+        "French Defense: Exchange Variation",
+        "e2e4 e7e6 d2d4 d7d5 e4d5",
+    ),
+    ("C02", "French Defense: Advance Variation", "e2e4 e7e6 d2d4 d7d5 e4e5"),
+    (
+        "C03",
+        "French Defense: Tarrasch Variation",
+        "e2e4 e7e6 d2d4 d7d5 b1d2",
+    ),
+    ("C10", "French Defense: Paulsen Variation", "e2e4 e7e6 d2d4 d7d5 b1c3"),
+    (
+        "C11",
+        "French Defense: Classical Variation",
+        "e2e4 e7e6 d2d4 d7d5 b1c3 g8f6",
+    ),
+    (
+        "C15",
+        "French Defense: Winawer Variation",
+        "e2e4 e7e6 d2d4 d7d5 b1c3 f8b4",
+    ),
+    ("C20", "King's Pawn Game", "e2e4 e7e5"),
+    ("C21", "Center Game", "e2e4 e7e5 d2d4 e5d4"),
+    ("C23", "Bishop's Opening", "e2e4 e7e5 f1c4"),
+    ("C25", "Vienna Game", "e2e4 e7e5 b1c3"),
+    ("C30", "King's Gambit", "e2e4 e7e5 f2f4"),
+    ("C33", "King's Gambit Accepted", "e2e4 e7e5 f2f4 e5f4"),
+    ("C40", "King's Knight Opening", "e2e4 e7e5 g1f3"),
+    ("C41", "Philidor Defense", "e2e4 e7e5 g1f3 d7d6"),
+    ("C42", "Petrov's Defense", "e2e4 e7e5 g1f3 g8f6"),
+    (
+        "C44",
+        "King's Knight Opening: Normal Variation",
+        "e2e4 e7e5 g1f3 b8c6",
+    ),
+    ("C44", "Scotch Game", "e2e4 e7e5 g1f3 b8c6 d2d4"),
+    ("C45", "Scotch Game", "e2e4 e7e5 g1f3 b8c6 d2d4 e5d4 f3d4"),
+    ("C46", "Three Knights Opening", "e2e4 e7e5 g1f3 b8c6 b1c3"),
+    ("C47", "Four Knights Game", "e2e4 e7e5 g1f3 b8c6 b1c3 g8f6"),
+    ("C50", "Italian Game", "e2e4 e7e5 g1f3 b8c6 f1c4"),
+    ("C50", "Italian Game: Giuoco Piano", "e2e4 e7e5 g1f3 b8c6 f1c4 f8c5"),
+    (
+        "C51",
+        "Italian Game: Evans Gambit",
+        "e2e4 e7e5 g1f3 b8c6 f1c4 f8c5 b2b4",
+    ),
+    (
+        "C53",
+        "Italian Game: Classical Variation",
+        "e2e4 e7e5 g1f3 b8c6 f1c4 f8c5 c2c3",
+    ),
+    (
+        "C55",
+        "Italian Game: Two Knights Defense",
+        "e2e4 e7e5 g1f3 b8c6 f1c4 g8f6",
+    ),
+    (
+        "C57",
+        "Italian Game: Two Knights Defense, Knight Attack",
+        "e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 f3g5",
+    ),
+    ("C60", "Ruy Lopez", "e2e4 e7e5 g1f3 b8c6 f1b5"),
+    ("C65", "Ruy Lopez: Berlin Defense", "e2e4 e7e5 g1f3 b8c6 f1b5 g8f6"),
+    (
+        "C68",
+        "Ruy Lopez: Exchange Variation",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5c6",
+    ),
+    (
+        "C70",
+        "Ruy Lopez: Morphy Defense",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4",
+    ),
+    (
+        "C78",
+        "Ruy Lopez: Morphy Defense",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4 g8f6 e1g1",
+    ),
+    (
+        "C84",
+        "Ruy Lopez: Closed",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4 g8f6 e1g1 f8e7",
+    ),
+    ("D00", "Queen's Pawn Game", "d2d4 d7d5"),
+    ("D00", "Queen's Pawn Game: London System", "d2d4 d7d5 c1f4"),
+    ("D00", "Blackmar-Diemer Gambit", "d2d4 d7d5 e2e4"),
+    ("D02", "Queen's Pawn Game: Zukertort Variation", "d2d4 d7d5 g1f3"),
+    ("D06", "Queen's Gambit", "d2d4 d7d5 c2c4"),
+    (
+        "D07",
+        "Queen's Gambit Declined: Chigorin Defense",
+        "d2d4 d7d5 c2c4 b8c6",
+    ),
+    (
+        "D08",
+        "Queen's Gambit Declined: Albin Countergambit",
+        "d2d4 d7d5 c2c4 e7e5",
+    ),
+    ("D10", "Slav Defense", "d2d4 d7d5 c2c4 c7c6"),
+    ("D20", "Queen's Gambit Accepted", "d2d4 d7d5 c2c4 d5c4"),
+    ("D30", "Queen's Gambit Declined", "d2d4 d7d5 c2c4 e7e6"),
+    ("D35", "Queen's Gambit Declined", "d2d4 d7d5 c2c4 e7e6 b1c3 g8f6"),
+    ("D43", "Semi-Slav Defense", "d2d4 d7d5 c2c4 c7c6 g1f3 g8f6 b1c3 e7e6"),
+    ("D80", "Grünfeld Defense", "d2d4 g8f6 c2c4 g7g6 b1c3 d7d5"),
+    ("E00", "Indian Defense", "d2d4 g8f6 c2c4 e7e6"),
+    ("E00", "Catalan Opening", "d2d4 g8f6 c2c4 e7e6 g2g3"),
+    ("E10", "Indian Defense", "d2d4 g8f6 c2c4 e7e6 g1f3"),
+    ("E12", "Queen's Indian Defense", "d2d4 g8f6 c2c4 e7e6 g1f3 b7b6"),
+    ("E20", "Nimzo-Indian Defense", "d2d4 g8f6 c2c4 e7e6 b1c3 f8b4"),
+    ("E60", "King's Indian Defense", "d2d4 g8f6 c2c4 g7g6"),
+    ("E61", "King's Indian Defense", "d2d4 g8f6 c2c4 g7g6 b1c3 f8g7"),
+];
+
+// The opening of a game played from the starting position, by the longest known line the moves
+// start with. None if not even the first move is known.
+pub fn classify(moves: &[Move]) -> Option<Opening> {
+    let moves: Vec<String> = moves.iter().map(ToString::to_string).collect();
+
+    OPENINGS
+        .iter()
+        .filter_map(|&(eco, name, line)| {
+            let line: Vec<&str> = line.split(' ').collect();
+            let matches = line.len() <= moves.len()
+                && line.iter().zip(&moves).all(|(expected, mv)| expected == mv);
+            matches.then_some((line.len(), Opening { eco, name }))
+        })
+        .max_by_key(|(length, _)| *length)
+        .map(|(_, opening)| opening)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::Board, move_generation::Move};
+
+    use super::{classify, Opening, OPENINGS};
+
+    fn moves(uci: &str) -> Vec<Move> {
+        let moves: Vec<&str> = uci.split(' ').collect();
+        Board::starting_position().play_uci_moves(&moves).unwrap().1
+    }
+
+    #[test]
+    fn test_classify_by_longest_known_line() {
+        assert_eq!(
+            classify(&moves("e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4 g8f6 e1g1 f8e7 f1e1")),
+            Some(Opening {
+                eco: "C84",
+                name: "Ruy Lopez: Closed"
+            })
+        );
+        assert_eq!(classify(&moves("e2e4 c7c5 g1f3 h7h6")).unwrap().eco, "B27");
+        assert_eq!(classify(&moves("a2a3")), None);
+        assert_eq!(classify(&[]), None);
+    }
+
+    #[test]
+    fn test_every_line_is_legal_and_known_once() {
+        for (i, (eco, _, line)) in OPENINGS.iter().enumerate() {
+            assert!(OPENINGS[i + 1..].iter().all(|(_, _, other)| other != line));
+            assert!(eco.len() == 3 && ('A'..='E').contains(&eco.chars().next().unwrap()));
+            let moves: Vec<&str> = line.split(' ').collect();
+            assert!(Board::from_start_and_moves(None, &moves).is_ok());
+        }
+    }
+}
@@ -0,0 +1,403 @@
+use crate::{
+    board::Board,
+    piece::{Color, Piece},
+    square::{File, Rank, Square, SquareColor},
+};
+
+// Added to an ending that is a known win, so the engine happily trades down into it
+pub const KNOWN_WIN_BONUS: i32 = 1000;
+// Per square the losing king is away from the center, or from the corner it gets mated in
+const KING_TO_EDGE_WEIGHT: i32 = 20;
+// Per square of distance between the kings that the winning king has closed
+const KING_PROXIMITY_WEIGHT: i32 = 10;
+// Mop-up kicks in with at least this much more material, and this few pawns left on the board
+const MOP_UP_MIN_ADVANTAGE: i32 = 400;
+const MOP_UP_MAX_PAWNS: u32 = 2;
+// Weaker than the elementary endings above, to stay below the material the winning side has
+const MOP_UP_KING_TO_EDGE_WEIGHT: i32 = 10;
+const MOP_UP_KING_PROXIMITY_WEIGHT: i32 = 4;
+// The general evaluation is multiplied by a scale out of FULL_SCALE in endings that are hard or
+// impossible to win whatever the material says
+pub const FULL_SCALE: i32 = 64;
+const OPPOSITE_COLORED_BISHOPS_SCALE: i32 = 32;
+const ROOK_VERSUS_ROOK_AND_PAWN_SCALE: i32 = 32;
+const SMALL_ADVANTAGE_WITHOUT_PAWNS_SCALE: i32 = 8;
+
+// How many pieces of each kind a side has, kings left out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Material {
+    pawns: u32,
+    knights: u32,
+    bishops: u32,
+    rooks: u32,
+    queens: u32,
+}
+
+impl Material {
+    fn of(board: &Board, color: Color) -> Self {
+        Self {
+            pawns: board.piece_count(Piece::Pawn, color),
+            knights: board.piece_count(Piece::Knight, color),
+            bishops: board.piece_count(Piece::Bishop, color),
+            rooks: board.piece_count(Piece::Rook, color),
+            queens: board.piece_count(Piece::Queen, color),
+        }
+    }
+
+    fn only(pawns: u32, knights: u32, bishops: u32, rooks: u32, queens: u32) -> Self {
+        Self {
+            pawns,
+            knights,
+            bishops,
+            rooks,
+            queens,
+        }
+    }
+
+    fn is_bare_king(&self) -> bool {
+        *self == Material::default()
+    }
+
+    fn value(&self) -> i32 {
+        self.pawns as i32 * Piece::Pawn.piece_value() + self.piece_value()
+    }
+
+    fn piece_value(&self) -> i32 {
+        self.knights as i32 * Piece::Knight.piece_value()
+            + self.bishops as i32 * Piece::Bishop.piece_value()
+            + self.rooks as i32 * Piece::Rook.piece_value()
+            + self.queens as i32 * Piece::Queen.piece_value()
+    }
+}
+
+// Evaluation of endings the general evaluation gets wrong at low depth, picked by the material
+// on the board: mating a bare king with a queen, a rook, or bishop and knight, and the rook pawn
+// with the wrong bishop. From white's perspective, None for any other position.
+pub fn evaluate_endgame(board: &Board) -> Option<i32> {
+    for strong_side in [Color::White, Color::Black] {
+        let weak_side = strong_side.opposite_color();
+        if !Material::of(board, weak_side).is_bare_king() {
+            continue;
+        }
+
+        let material = Material::of(board, strong_side);
+        let eval = if material == Material::only(0, 0, 0, 0, 1) {
+            mate_with_major_piece(board, strong_side, Piece::Queen)
+        } else if material == Material::only(0, 0, 0, 1, 0) {
+            mate_with_major_piece(board, strong_side, Piece::Rook)
+        } else if material == Material::only(0, 1, 1, 0, 0) {
+            mate_with_bishop_and_knight(board, strong_side)
+        } else if material.pawns > 0
+            && material == Material::only(material.pawns, 0, 1, 0, 0)
+            && is_wrong_bishop_draw(board, strong_side)
+        {
+            0
+        } else {
+            return None;
+        };
+
+        return Some(match strong_side {
+            Color::White => eval,
+            Color::Black => -eval,
+        });
+    }
+
+    None
+}
+
+// Without it, a won ending with few pawns left gets shuffled around until the fifty-move rule
+// ends it: reward the winning side for driving the losing king to the edge and following it with
+// its own king, which is what mating needs. From white's perspective.
+pub fn mop_up(board: &Board) -> i32 {
+    let white = Material::of(board, Color::White);
+    let black = Material::of(board, Color::Black);
+    let advantage = white.value() - black.value();
+    if advantage.abs() < MOP_UP_MIN_ADVANTAGE || white.pawns + black.pawns > MOP_UP_MAX_PAWNS {
+        return 0;
+    }
+
+    let strong_side = if advantage > 0 {
+        Color::White
+    } else {
+        Color::Black
+    };
+    let strong_king = king_square(board, strong_side);
+    let weak_king = king_square(board, strong_side.opposite_color());
+    let eval = MOP_UP_KING_TO_EDGE_WEIGHT * weak_king.center_distance() as i32
+        + MOP_UP_KING_PROXIMITY_WEIGHT * king_proximity(strong_king, weak_king);
+
+    match strong_side {
+        Color::White => eval,
+        Color::Black => -eval,
+    }
+}
+
+// Neither side has enough left to ever mate: bare kings, a single minor piece, or only bishops
+// that all stand on the same square color
+pub fn is_insufficient_material(board: &Board) -> bool {
+    let white = Material::of(board, Color::White);
+    let black = Material::of(board, Color::Black);
+    let minor_pieces_only =
+        |material: &Material| material.pawns == 0 && material.rooks == 0 && material.queens == 0;
+    if !minor_pieces_only(&white) || !minor_pieces_only(&black) {
+        return false;
+    }
+    if white.knights + white.bishops + black.knights + black.bishops <= 1 {
+        return true;
+    }
+    if white.knights + black.knights > 0 {
+        return false;
+    }
+
+    let mut bishops = (0..64).filter(|&square| board.squares[square] == Some(Piece::Bishop));
+    let first = bishops.next().expect("there are at least two bishops");
+    bishops.all(|square| square_color(square) == square_color(first))
+}
+
+// How much of the general evaluation to keep, out of FULL_SCALE. Nothing when the side ahead can't
+// mate, little when it is up less than a rook without pawns, and half with opposite colored bishops
+// and nothing else, or rook and pawn against rook.
+pub fn drawishness_scale(board: &Board) -> i32 {
+    let white = Material::of(board, Color::White);
+    let black = Material::of(board, Color::Black);
+    let (strong, weak) = if white.value() >= black.value() {
+        (white, black)
+    } else {
+        (black, white)
+    };
+
+    if strong.pawns == 0 {
+        let advantage = strong.piece_value() - weak.piece_value();
+        // A single minor piece or two knights can't force mate
+        if strong.piece_value() <= Piece::Bishop.piece_value()
+            || strong == Material::only(0, 2, 0, 0, 0)
+        {
+            return 0;
+        }
+        if advantage <= Piece::Bishop.piece_value() {
+            return SMALL_ADVANTAGE_WITHOUT_PAWNS_SCALE;
+        }
+    }
+
+    if white == Material::only(white.pawns, 0, 1, 0, 0)
+        && black == Material::only(black.pawns, 0, 1, 0, 0)
+        && has_opposite_colored_bishops(board)
+    {
+        return OPPOSITE_COLORED_BISHOPS_SCALE;
+    }
+    if strong == Material::only(1, 0, 0, 1, 0) && weak == Material::only(0, 0, 0, 1, 0) {
+        return ROOK_VERSUS_ROOK_AND_PAWN_SCALE;
+    }
+
+    FULL_SCALE
+}
+
+fn has_opposite_colored_bishops(board: &Board) -> bool {
+    let bishop = |color| {
+        (0..64)
+            .find(|&square| board.is_piece_at_square(square, Piece::Bishop, color))
+            .expect("both sides have a bishop")
+    };
+
+    square_color(bishop(Color::White)) != square_color(bishop(Color::Black))
+}
+
+// Any edge will do: drive the king there and follow it with the own king
+fn mate_with_major_piece(board: &Board, strong_side: Color, piece: Piece) -> i32 {
+    let strong_king = king_square(board, strong_side);
+    let weak_king = king_square(board, strong_side.opposite_color());
+
+    KNOWN_WIN_BONUS
+        + piece.piece_value()
+        + KING_TO_EDGE_WEIGHT * weak_king.center_distance() as i32
+        + KING_PROXIMITY_WEIGHT * king_proximity(strong_king, weak_king)
+}
+
+// Mate can only be forced in a corner the bishop covers
+fn mate_with_bishop_and_knight(board: &Board, strong_side: Color) -> i32 {
+    let strong_king = king_square(board, strong_side);
+    let weak_king = king_square(board, strong_side.opposite_color());
+    let bishop = (0..64)
+        .find(|&square| board.is_piece_at_square(square, Piece::Bishop, strong_side))
+        .expect("the bishop is on the board");
+    // a1 and h8 are dark, h1 and a8 are light
+    let corners = match square_color(bishop) {
+        SquareColor::Dark => [Square::A1, Square::H8],
+        SquareColor::Light => [Square::H1, Square::A8],
+    };
+    let corner_distance = corners
+        .iter()
+        .map(|&corner| weak_king.manhattan_distance(corner))
+        .min()
+        .unwrap() as i32;
+
+    KNOWN_WIN_BONUS
+        + Piece::Bishop.piece_value()
+        + Piece::Knight.piece_value()
+        + KING_TO_EDGE_WEIGHT * (14 - corner_distance)
+        + KING_PROXIMITY_WEIGHT * king_proximity(strong_king, weak_king)
+}
+
+// Rook pawns can't be promoted against a king in the corner when the bishop doesn't cover the
+// promotion square
+fn is_wrong_bishop_draw(board: &Board, strong_side: Color) -> bool {
+    let pawn_files: Vec<File> = (0..64)
+        .filter(|&square| board.is_piece_at_square(square, Piece::Pawn, strong_side))
+        .map(File::of)
+        .collect();
+    let file = pawn_files[0];
+    if !(file == File::A || file == File::H) || pawn_files.iter().any(|&other| other != file) {
+        return false;
+    }
+
+    let promotion_square = Square::new(file, Rank::EIGHTH.relative_to(strong_side));
+    let bishop = (0..64)
+        .find(|&square| board.is_piece_at_square(square, Piece::Bishop, strong_side))
+        .expect("the bishop is on the board");
+    let weak_king = king_square(board, strong_side.opposite_color());
+
+    square_color(bishop) != promotion_square.color() && weak_king.distance(promotion_square) <= 1
+}
+
+pub fn king_square(board: &Board, color: Color) -> Square {
+    let square = board
+        .king_square(color)
+        .expect("both kings are on the board");
+    Square::from_index(square)
+}
+
+// King tropism: 13 with the kings side by side on a file or rank, down to 0 in opposite corners
+pub fn king_proximity(a: Square, b: Square) -> i32 {
+    14 - a.manhattan_distance(b) as i32
+}
+
+// Of a square index, as the board's arrays are indexed
+pub fn square_color(square: usize) -> SquareColor {
+    Square::from_index(square).color()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board_builder::BoardBuilder;
+
+    use super::{
+        drawishness_scale, evaluate_endgame, is_insufficient_material, mop_up, FULL_SCALE,
+        KNOWN_WIN_BONUS, OPPOSITE_COLORED_BISHOPS_SCALE, ROOK_VERSUS_ROOK_AND_PAWN_SCALE,
+        SMALL_ADVANTAGE_WITHOUT_PAWNS_SCALE,
+    };
+
+    fn eval(fen: &str) -> Option<i32> {
+        evaluate_endgame(&BoardBuilder::try_from_fen(fen).unwrap())
+    }
+
+    #[test]
+    fn test_mate_with_rook_or_queen_drives_king_to_edge() {
+        let centered = eval("8/8/8/3k4/8/8/8/R3K3 w - - 0 1").unwrap();
+        let cornered = eval("k7/8/1K6/8/8/8/8/1R6 w - - 0 1").unwrap();
+        assert!(centered > KNOWN_WIN_BONUS);
+        assert!(cornered > centered);
+
+        // The same for black, from white's perspective
+        assert_eq!(eval("r3k3/8/8/8/3K4/8/8/8 w - - 0 1").unwrap(), -centered);
+        assert!(eval("8/8/8/3k4/8/8/8/Q3K3 w - - 0 1").unwrap() > centered);
+    }
+
+    #[test]
+    fn test_bishop_and_knight_mate_in_the_bishops_corner() {
+        // Dark squared bishop, so a1 and h8 are the corners to mate in
+        let right_corner = eval("7k/8/5K2/8/8/8/8/2B1N3 w - - 0 1").unwrap();
+        let wrong_corner = eval("k7/8/2K5/8/8/8/8/2B1N3 w - - 0 1").unwrap();
+        assert!(right_corner > wrong_corner);
+        assert!(wrong_corner > KNOWN_WIN_BONUS);
+    }
+
+    #[test]
+    fn test_rook_pawn_with_wrong_bishop() {
+        // a8 is light, a dark squared bishop can't drive the king out
+        assert_eq!(eval("1k6/8/8/P7/8/8/8/2B1K3 w - - 0 1"), Some(0));
+        assert_eq!(eval("k7/8/8/P7/P7/8/8/2B1K3 w - - 0 1"), Some(0));
+        // The right bishop, the king too far away, or a pawn that isn't a rook pawn
+        assert_eq!(eval("1k6/8/8/P7/8/8/8/3BK3 w - - 0 1"), None);
+        assert_eq!(eval("8/8/8/P3k3/8/8/8/2B1K3 w - - 0 1"), None);
+        assert_eq!(eval("1k6/8/8/1P6/8/8/8/2B1K3 w - - 0 1"), None);
+    }
+
+    #[test]
+    fn test_other_material_is_left_to_the_general_evaluation() {
+        assert_eq!(eval("8/8/8/3k4/8/8/p7/R3K3 w - - 0 1"), None);
+        assert_eq!(eval("8/8/8/3k4/8/8/8/RR2K3 w - - 0 1"), None);
+        assert_eq!(eval("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), None);
+    }
+
+    #[test]
+    fn test_mop_up() {
+        let mop_up = |fen| mop_up(&BoardBuilder::try_from_fen(fen).unwrap());
+
+        let centered = mop_up("8/8/8/3k4/8/8/6P1/R1Q1K3 w - - 0 1");
+        let cornered = mop_up("k7/2K5/8/8/8/8/6P1/1RQ5 w - - 0 1");
+        assert!(centered > 0);
+        assert!(cornered > centered);
+        assert!(mop_up("k7/2K5/8/8/8/8/6p1/r2q4 w - - 0 1") < 0);
+
+        // Not with balanced material, or with too many pawns left
+        assert_eq!(mop_up("k7/2K5/8/8/8/8/6n1/1R6 w - - 0 1"), 0);
+        assert_eq!(mop_up("k7/2K5/8/8/8/8/PPP5/R2Q4 w - - 0 1"), 0);
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        let insufficient =
+            |fen| is_insufficient_material(&BoardBuilder::try_from_fen(fen).unwrap());
+
+        assert!(insufficient("4k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(insufficient("4k1n1/8/8/8/8/8/8/4K3 w - - 0 1"));
+        // Bishops all on dark squares
+        assert!(insufficient("1b2k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/8/B1B1K3 w - - 0 1"));
+
+        assert!(!insufficient("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1"));
+        assert!(!insufficient("2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(!insufficient("4k1n1/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/P7/4K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"));
+    }
+
+    #[test]
+    fn test_drawishness_scale() {
+        let scale = |fen| drawishness_scale(&BoardBuilder::try_from_fen(fen).unwrap());
+
+        assert_eq!(
+            scale("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            FULL_SCALE
+        );
+        // Not enough to mate with
+        assert_eq!(scale("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1"), 0);
+        assert_eq!(scale("4k3/8/8/8/8/8/8/1N2K1N1 w - - 0 1"), 0);
+        assert_eq!(scale("4k3/8/8/8/8/8/8/1n2K1n1 w - - 0 1"), 0);
+        // Rook against minor piece, and a minor piece up against a rook
+        assert_eq!(
+            scale("4k3/8/8/8/8/8/8/R3K1n1 w - - 0 1"),
+            SMALL_ADVANTAGE_WITHOUT_PAWNS_SCALE
+        );
+        assert_eq!(
+            scale("4k3/8/8/8/8/8/8/R2BK1r1 w - - 0 1"),
+            SMALL_ADVANTAGE_WITHOUT_PAWNS_SCALE
+        );
+        // A rook up is enough, and so are pawns
+        assert_eq!(scale("4k3/8/8/8/8/8/8/R2NK1n1 w - - 0 1"), FULL_SCALE);
+        assert_eq!(scale("4k3/8/8/8/8/8/P7/2B1K1n1 w - - 0 1"), FULL_SCALE);
+        // c1 is dark and c8 light
+        assert_eq!(
+            scale("2b1k3/5p2/8/8/8/8/PP6/2B1K3 w - - 0 1"),
+            OPPOSITE_COLORED_BISHOPS_SCALE
+        );
+        assert_eq!(scale("1b2k3/5p2/8/8/8/8/PP6/2B1K3 w - - 0 1"), FULL_SCALE);
+        assert_eq!(scale("2b1k3/5p2/8/8/8/8/PP6/2B1KN2 w - - 0 1"), FULL_SCALE);
+        assert_eq!(
+            scale("4k3/8/8/8/8/8/r4P2/4K2R w - - 0 1"),
+            ROOK_VERSUS_ROOK_AND_PAWN_SCALE
+        );
+        assert_eq!(scale("4k3/8/8/8/8/8/r3PP2/4K2R w - - 0 1"), FULL_SCALE);
+    }
+}
@@ -0,0 +1,343 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    board::Board,
+    board_builder::BoardBuilder,
+    evaluate::Evaluator,
+    move_generation::{Move, MoveGenerator},
+    search::{AlphaBeta, Search, SearchLimits, SearchProgress, SearchResult},
+};
+
+// Talia for other Rust programs: the same search the UCI front end runs, without any protocol
+// parsing or threads of its own. go blocks until the search is done; to end it early, call stop
+// on a StopHandle from another thread. Positions are searched with S, see with_search.
+pub struct Engine<S = AlphaBeta> {
+    board: Board,
+    chess960: bool,
+    searcher: S,
+    stop: Arc<AtomicBool>,
+}
+
+// Lets another thread end a running search, which then returns the best move found so far
+#[derive(Debug, Clone)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::with_search(AlphaBeta::new())
+    }
+}
+
+impl<E: Evaluator> Engine<AlphaBeta<E>> {
+    // The default search, with a custom evaluation
+    pub fn with_evaluator(evaluator: E) -> Self {
+        Self::with_search(AlphaBeta::with_evaluator(evaluator))
+    }
+}
+
+impl<S: Search> Engine<S> {
+    pub fn with_search(searcher: S) -> Self {
+        Self {
+            board: Board::starting_position(),
+            chess960: false,
+            searcher,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn searcher(&self) -> &S {
+        &self.searcher
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    // Moves are given in UCI notation. Without a FEN, the moves are played from the starting
+    // position. The position is left untouched if any of it is invalid.
+    pub fn set_position(&mut self, fen: Option<&str>, moves: &[&str]) -> Result<()> {
+        let mut board = match fen {
+            Some(fen) => BoardBuilder::try_from_fen(fen)?,
+            None => Board::starting_position(),
+        };
+        board.chess960 = self.chess960;
+        self.board = board.play_uci_moves(moves)?.0;
+        self.board.clear_history();
+
+        Ok(())
+    }
+
+    // Castling moves are read and written as the king capturing its own rook
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+        self.board.chess960 = chess960;
+    }
+
+    pub fn set_hash_size(&mut self, size_mb: usize) {
+        self.searcher.set_hash_size(size_mb);
+    }
+
+    pub fn new_game(&mut self) {
+        self.searcher.clear();
+        self.board = Board::starting_position();
+        self.board.chess960 = self.chess960;
+    }
+
+    pub fn go(&mut self, limits: &SearchLimits) -> Result<SearchResult> {
+        self.go_with_progress(limits, |_| {})
+    }
+
+    // Like go, but hands every progress update to on_progress while searching. To consume them
+    // elsewhere, send them over a channel from the callback.
+    pub fn go_with_progress(
+        &mut self,
+        limits: &SearchLimits,
+        mut on_progress: impl FnMut(&SearchProgress),
+    ) -> Result<SearchResult> {
+        let mut move_generator = MoveGenerator::new(self.board.without_history());
+        if !move_generator.has_legal_moves() {
+            bail!("no legal moves in this position");
+        }
+        // The limits can rule out every move, e.g. searchmoves that aren't legal here
+        if !move_generator
+            .iter_moves()
+            .any(|mv| limits.allows_root_move(&mv))
+        {
+            bail!("the search limits exclude every legal move");
+        }
+
+        self.stop.store(false, Ordering::Relaxed);
+        Ok(self
+            .searcher
+            .search(&self.board, limits, &self.stop, &mut on_progress))
+    }
+
+    // Searches for at most the given time. Stopping early through a StopHandle is fine too: the
+    // move is always the best one of the last completed iteration.
+    pub fn best_move_within(&mut self, time: Duration) -> Result<Move> {
+        let limits = SearchLimits {
+            movetime: Some(time),
+            ..SearchLimits::default()
+        };
+
+        Ok(self.go(&limits)?.best_move)
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle(Arc::clone(&self.stop))
+    }
+
+    pub fn stop(&self) {
+        self.stop_handle().stop();
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        sync::mpsc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        board::Board,
+        evaluate::Evaluator,
+        move_generation::{Flag, Move, MoveGenerator},
+        score::Score,
+        search::{SearchLimits, SearchProgress},
+        square::Square,
+    };
+    use anyhow::Result;
+
+    use super::Engine;
+
+    #[test]
+    fn test_set_position_with_moves() -> Result<()> {
+        let mut engine = Engine::new();
+        engine.set_position(None, &["e2e4", "e7e5"])?;
+
+        assert_eq!(
+            engine.board().to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_position_keeps_previous_one() -> Result<()> {
+        let mut engine = Engine::new();
+        engine.set_position(None, &["e2e4"])?;
+        let board = engine.board().clone();
+
+        assert!(engine.set_position(None, &["e2e4", "e2e4"]).is_err());
+        assert!(engine.set_position(Some("not a fen"), &[]).is_err());
+        assert!(engine
+            .set_position(Some("4k3/8/8/8/8/8/8/4K3 w"), &[])
+            .is_err());
+        assert!(engine
+            .set_position(Some("4k3/8/8/8/8/8/8/8/4K3 w - - 0 1"), &[])
+            .is_err());
+        assert!(*engine.board() == board);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_go_finds_mate_in_one() -> Result<()> {
+        let mut engine = Engine::new();
+        engine.set_position(Some("1r4k1/5ppp/8/8/8/8/5PPP/7K b - - 0 1"), &[])?;
+
+        let result = engine.go(&SearchLimits::depth(2))?;
+
+        assert!(result.best_move == Move::from_square(Square::B8, Square::B1, Flag::None));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_go_reports_every_iteration() -> Result<()> {
+        let mut engine = Engine::new();
+        let (sender, receiver) = mpsc::channel();
+
+        let result = engine.go_with_progress(&SearchLimits::depth(3), |progress| {
+            sender.send(progress.clone()).unwrap();
+        })?;
+
+        let depths: Vec<u32> = receiver
+            .try_iter()
+            .filter_map(|progress| match progress {
+                SearchProgress::Iteration { depth, pv, .. } => {
+                    assert!(!pv.is_empty());
+                    Some(depth)
+                }
+                SearchProgress::CurrentMove { .. } => None,
+            })
+            .collect();
+        assert_eq!(depths, vec![1, 2, 3]);
+        assert_eq!(result.stats.depth, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_evaluator() -> Result<()> {
+        struct CountingEvaluator(Cell<u64>);
+
+        impl Evaluator for CountingEvaluator {
+            fn evaluate(&self, _board: &Board) -> Score {
+                self.0.set(self.0.get() + 1);
+                Score::Cp(0)
+            }
+        }
+
+        let mut engine = Engine::with_evaluator(CountingEvaluator(Cell::new(0)));
+        engine.set_position(Some("1r4k1/5ppp/8/8/8/8/5PPP/7K b - - 0 1"), &[])?;
+        let result = engine.go(&SearchLimits::depth(2))?;
+
+        assert!(engine.searcher().evaluator().0.get() > 0);
+        // Mates are found by the search, whatever the evaluation
+        assert!(result.best_move == Move::from_square(Square::B8, Square::B1, Flag::None));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_go_without_legal_moves_fails() -> Result<()> {
+        let mut engine = Engine::new();
+        engine.set_position(Some("6k1/5ppp/8/8/8/8/5PPP/1r5K w - - 0 1"), &[])?;
+
+        assert!(engine.go(&SearchLimits::depth(2)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_go_with_only_illegal_search_moves_fails() -> Result<()> {
+        let mut engine = Engine::new();
+        let limits = SearchLimits {
+            depth: Some(2),
+            search_moves: vec![Move::from_square(Square::E2, Square::E5, Flag::None)],
+            ..SearchLimits::default()
+        };
+
+        assert!(engine.go(&limits).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_go_with_every_move_excluded_fails() -> Result<()> {
+        let mut engine = Engine::new();
+        engine.set_position(Some("7k/8/8/8/8/8/6PP/7K w - - 0 1"), &[])?;
+        let limits = SearchLimits {
+            depth: Some(2),
+            excluded_moves: MoveGenerator::new(engine.board().clone())
+                .generate_moves()
+                .to_vec(),
+            ..SearchLimits::default()
+        };
+
+        assert!(engine.go(&limits).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_best_move_within() -> Result<()> {
+        let mut engine = Engine::new();
+        engine.set_position(Some("1r4k1/5ppp/8/8/8/8/5PPP/7K b - - 0 1"), &[])?;
+
+        let start_time = Instant::now();
+        let best_move = engine.best_move_within(Duration::from_millis(100))?;
+
+        assert!(start_time.elapsed() < Duration::from_secs(1));
+        assert!(best_move == Move::from_square(Square::B8, Square::B1, Flag::None));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stop_from_another_thread() -> Result<()> {
+        let mut engine = Engine::new();
+        let stop_handle = engine.stop_handle();
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            stop_handle.stop();
+        });
+
+        let limits = SearchLimits {
+            infinite: true,
+            ..SearchLimits::default()
+        };
+        let result = engine.go(&limits)?;
+        stopper.join().unwrap();
+
+        let mut move_generator = MoveGenerator::new(Board::starting_position());
+        assert!(move_generator.generate_moves().contains(&result.best_move));
+
+        Ok(())
+    }
+}
@@ -1,38 +1,452 @@
-use crate::{move_generation::MoveGenerator, piece::Color};
+use std::fmt;
 
-pub fn evaluate(move_generator: &MoveGenerator) -> i32 {
-    let mut eval = 0;
-    let board = &move_generator.board;
+use anyhow::{bail, Result};
 
-    for square in 0..64 {
-        if let Some(piece) = board.squares[square] {
-            if board.colors[square].unwrap() == Color::White {
-                eval += piece.piece_value() + piece.position_value(square, Color::White)
+use crate::{
+    board::Board,
+    endgame::{
+        drawishness_scale, evaluate_endgame, is_insufficient_material, mop_up, square_color,
+        FULL_SCALE,
+    },
+    pawn_structure::{pawn_structure, pawns_of_side},
+    piece::{Color, Piece},
+    score::Score,
+    square::{File, Rank, SquareColor},
+    threats::{threats, threats_against},
+    weights::{EvalWeights, Features, Term},
+};
+
+// For keeping bishops on both square colors, which together cover what a single one can't
+pub const BISHOP_PAIR_BONUS: i32 = 30;
+// Rooks belong on files without pawns, or at least without their own, and on the seventh rank
+// where the enemy pawns start out. Connected rooks protect each other.
+pub const ROOK_OPEN_FILE_BONUS: i32 = 20;
+pub const ROOK_SEMI_OPEN_FILE_BONUS: i32 = 10;
+pub const ROOK_ON_SEVENTH_RANK_BONUS: i32 = 20;
+pub const CONNECTED_ROOKS_BONUS: i32 = 15;
+
+// Static evaluation used at the leaves of the search. Implement this to try out a different
+// evaluation without touching the search itself.
+pub trait Evaluator {
+    // From the side to move's perspective
+    fn evaluate(&self, board: &Board) -> Score;
+
+    // Called on the board a search starts from, e.g. to set up state that is updated as moves
+    // are made on it
+    fn prepare(&self, _board: &mut Board) {}
+}
+
+// Material, piece square tables, pawn structure, the bishop pair, rook placement and threats,
+// with dedicated evaluations for some well known endings, a push towards mate in other won
+// endings and a pull towards zero in drawish ones. How much the terms are worth is up to the
+// weights, e.g. from a tuned weights file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultEvaluator {
+    weights: EvalWeights,
+}
+
+impl DefaultEvaluator {
+    pub fn with_weights(weights: EvalWeights) -> Self {
+        Self { weights }
+    }
+
+    pub fn weights(&self) -> &EvalWeights {
+        &self.weights
+    }
+
+    // The evaluation split into its parts, each side's share from its own perspective. Adds up
+    // to the same as evaluate, for white.
+    pub fn evaluate_detailed(&self, board: &Board) -> EvalBreakdown {
+        let mop_up = mop_up(board);
+        let both_sides = |part: &dyn Fn(Color) -> i32| [part(Color::White), part(Color::Black)];
+        let terms = vec![
+            (
+                "Material",
+                both_sides(&|color| material(board, color).evaluate(&self.weights)),
+            ),
+            ("Piece square tables", both_sides(&|color| board.position(color))),
+            (
+                "Pawn structure",
+                both_sides(&|color| pawns_of_side(board, color).evaluate(&self.weights)),
+            ),
+            (
+                "Bishop pair",
+                both_sides(&|color| {
+                    if has_bishop_pair(board, color) {
+                        self.weights[Term::BishopPair]
+                    } else {
+                        0
+                    }
+                }),
+            ),
+            (
+                "Rook placement",
+                both_sides(&|color| rook_placement(board, color).evaluate(&self.weights)),
+            ),
+            (
+                "Threats",
+                both_sides(&|color| threats_against(board, color).evaluate(&self.weights)),
+            ),
+            ("Mop up", [mop_up.max(0), (-mop_up).max(0)]),
+        ];
+        let scale = drawishness_scale(board);
+        let (eval, decided_by) = if is_insufficient_material(board) {
+            (0, Some("insufficient material"))
+        } else if let Some(eval) = evaluate_endgame(board) {
+            (eval, Some("known ending"))
+        } else {
+            let sum: i32 = terms.iter().map(|(_, [white, black])| white - black).sum();
+            (sum * scale / FULL_SCALE, None)
+        };
+
+        EvalBreakdown {
+            terms,
+            scale,
+            decided_by,
+            eval,
+        }
+    }
+}
+
+// What evaluate_detailed found. There are no separate king safety or mobility terms, the piece
+// square tables stand in for both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalBreakdown {
+    // Name, then white's and black's share
+    pub terms: Vec<(&'static str, [i32; 2])>,
+    // Out of FULL_SCALE, how much of the sum is kept in a drawish ending
+    pub scale: i32,
+    // Set when a rule, not the terms, gives the evaluation
+    pub decided_by: Option<&'static str>,
+    // From white's perspective
+    pub eval: i32,
+}
+
+impl fmt::Display for EvalBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<20} {:>7} {:>7} {:>7}", "Term", "White", "Black", "Total")?;
+        for (name, [white, black]) in &self.terms {
+            writeln!(f, "{name:<20} {white:>7} {black:>7} {:>7}", white - black)?;
+        }
+        writeln!(f, "Scale: {}/{FULL_SCALE}", self.scale)?;
+        match self.decided_by {
+            Some(rule) => write!(f, "Eval: {} ({rule}, white's perspective)", self.eval),
+            None => write!(f, "Eval: {} (white's perspective)", self.eval),
+        }
+    }
+}
+
+impl Evaluator for DefaultEvaluator {
+    fn evaluate(&self, board: &Board) -> Score {
+        // A dead draw, whatever the piece square tables think of where the pieces stand
+        if is_insufficient_material(board) {
+            return Score::Cp(0);
+        }
+
+        let eval = evaluate_endgame(board).unwrap_or_else(|| {
+            let eval = fixed_terms(board) + features(board).evaluate(&self.weights);
+            eval * drawishness_scale(board) / FULL_SCALE
+        });
+
+        if board.to_move == Color::White {
+            Score::Cp(eval)
+        } else {
+            Score::Cp(-eval)
+        }
+    }
+}
+
+// For catching terms that treat the colors differently, like piece square tables that drifted
+// apart: the mirror image of a position, with the colors swapped and the board flipped top to
+// bottom, has to evaluate the same for the side to move
+pub fn check_symmetry(evaluator: &impl Evaluator, board: &Board) -> Result<()> {
+    let mirrored = board.mirror();
+    let eval = evaluator.evaluate(board);
+    let mirrored_eval = evaluator.evaluate(&mirrored);
+    if eval != mirrored_eval {
+        bail!(
+            "{} evaluates to {eval:?}, its mirror image {} to {mirrored_eval:?}",
+            board.to_fen(),
+            mirrored.to_fen()
+        );
+    }
+
+    Ok(())
+}
+
+// How often each of the weighted terms applies. From white's perspective.
+pub fn features(board: &Board) -> Features {
+    material(board, Color::White) - material(board, Color::Black)
+        + pawn_structure(board)
+        + bishop_pair(board)
+        + rook_placement(board, Color::White)
+        - rook_placement(board, Color::Black)
+        + threats(board)
+}
+
+// The piece square tables and the push towards mate, which don't depend on the weights. From
+// white's perspective.
+pub fn fixed_terms(board: &Board) -> i32 {
+    board.position(Color::White) - board.position(Color::Black) + mop_up(board)
+}
+
+fn material(board: &Board, color: Color) -> Features {
+    let mut features = Features::default();
+    for piece in [
+        Piece::Pawn,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+    ] {
+        features[Term::piece_value(piece).unwrap()] = board.piece_count(piece, color) as i32;
+    }
+
+    features
+}
+
+// From white's perspective
+fn bishop_pair(board: &Board) -> Features {
+    let mut features = Features::default();
+    features[Term::BishopPair] = match (
+        has_bishop_pair(board, Color::White),
+        has_bishop_pair(board, Color::Black),
+    ) {
+        (true, false) => 1,
+        (false, true) => -1,
+        _ => 0,
+    };
+
+    features
+}
+
+fn has_bishop_pair(board: &Board, color: Color) -> bool {
+    let bishops: Vec<usize> = (0..64)
+        .filter(|&square| board.is_piece_at_square(square, Piece::Bishop, color))
+        .collect();
+
+    bishops
+        .iter()
+        .any(|&square| square_color(square) == SquareColor::Dark)
+        && bishops
+            .iter()
+            .any(|&square| square_color(square) == SquareColor::Light)
+}
+
+fn rook_placement(board: &Board, color: Color) -> Features {
+    let rooks: Vec<usize> = (0..64)
+        .filter(|&square| board.is_piece_at_square(square, Piece::Rook, color))
+        .collect();
+    let has_pawn_on_file = |file: File, color: Color| {
+        (file.index()..64)
+            .step_by(8)
+            .any(|square| board.is_piece_at_square(square, Piece::Pawn, color))
+    };
+    let mut features = Features::default();
+    for &rook in &rooks {
+        let file = File::of(rook);
+        if !has_pawn_on_file(file, color) {
+            if has_pawn_on_file(file, color.opposite_color()) {
+                features[Term::RookSemiOpenFile] += 1;
             } else {
-                eval -= piece.piece_value() + piece.position_value(square, Color::Black)
+                features[Term::RookOpenFile] += 1;
             }
         }
+        if Rank::of(rook).relative_to(color) == Rank::SEVENTH {
+            features[Term::RookOnSeventhRank] += 1;
+        }
     }
 
-    if move_generator.board.to_move == Color::White {
-        eval
-    } else {
-        -eval
+    if let [first, second] = rooks[..] {
+        if are_connected(board, first, second) {
+            features[Term::ConnectedRooks] += 1;
+        }
     }
+
+    features
+}
+
+// On the same rank or file with nothing in between. first is the lower square.
+fn are_connected(board: &Board, first: usize, second: usize) -> bool {
+    let step = if Rank::of(first) == Rank::of(second) {
+        1
+    } else if File::of(first) == File::of(second) {
+        8
+    } else {
+        return false;
+    };
+
+    (first + step..second)
+        .step_by(step)
+        .all(|square| board.is_square_empty(square))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{board::Board, move_generation::MoveGenerator};
+    use crate::{
+        board::Board,
+        board_builder::BoardBuilder,
+        move_generation::MoveGenerator,
+        piece::Color,
+        score::Score,
+        weights::{EvalWeights, Features, Term},
+    };
 
-    use super::evaluate;
+    use super::{
+        bishop_pair, check_symmetry, rook_placement, DefaultEvaluator, Evaluator,
+        BISHOP_PAIR_BONUS, CONNECTED_ROOKS_BONUS, ROOK_ON_SEVENTH_RANK_BONUS, ROOK_OPEN_FILE_BONUS,
+        ROOK_SEMI_OPEN_FILE_BONUS,
+    };
 
     #[test]
     fn test_starting_position_eval() {
         let board = Board::starting_position();
-        let move_generator = MoveGenerator::new(board);
 
-        let eval = evaluate(&move_generator);
-        assert!(eval == 0);
+        let eval = DefaultEvaluator::default().evaluate(&board);
+        assert!(eval == Score::Cp(0));
+    }
+
+    #[test]
+    fn test_king_centralizes_in_the_end_game() {
+        let eval =
+            |fen| DefaultEvaluator::default().evaluate(&BoardBuilder::try_from_fen(fen).unwrap());
+
+        // With only pawns left the king belongs in the center, with all pieces on behind its pawns
+        assert!(
+            eval("4k3/ppp5/8/8/3K4/8/PPP5/8 w - - 0 1")
+                > eval("4k3/ppp5/8/8/8/8/PPP5/6K1 w - - 0 1")
+        );
+        assert!(
+            eval("rnbqkbnr/pppppppp/8/8/3K4/8/PPPPPPPP/RNBQ1BNR w kq - 0 1")
+                < eval("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+        );
+    }
+
+    #[test]
+    fn test_insufficient_material_eval() {
+        let eval =
+            |fen| DefaultEvaluator::default().evaluate(&BoardBuilder::try_from_fen(fen).unwrap());
+
+        assert_eq!(eval("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1"), Score::Cp(0));
+        assert_eq!(eval("8/8/8/8/2n5/8/8/K6k b - - 0 1"), Score::Cp(0));
+    }
+
+    #[test]
+    fn test_eval_with_weights() {
+        // Up a knight
+        let board = BoardBuilder::try_from_fen("4k3/p7/8/8/8/8/P7/1N2K3 w - - 0 1").unwrap();
+        let mut weights = EvalWeights::default();
+        let Score::Cp(default_eval) = DefaultEvaluator::default().evaluate(&board) else {
+            panic!("not a mate")
+        };
+
+        weights[Term::KnightValue] += 50;
+        assert_eq!(
+            DefaultEvaluator::with_weights(weights).evaluate(&board),
+            Score::Cp(default_eval + 50)
+        );
+    }
+
+    #[test]
+    fn test_evaluate_detailed_adds_up() {
+        let evaluator = DefaultEvaluator::default();
+        for fen in [
+            "r3k2r/pPppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q2/1PPBBPpP/R3K2R b KQkq a3 0 1",
+            "4k3/pp6/8/8/8/8/PPP5/1N2K3 w - - 0 1",
+            // Opposite colored bishops, scaled down
+            "4kb2/p4p2/8/8/8/8/P4PP1/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        ] {
+            let board = BoardBuilder::try_from_fen(fen).unwrap();
+            let breakdown = evaluator.evaluate_detailed(&board);
+            let eval = match board.to_move {
+                Color::White => breakdown.eval,
+                Color::Black => -breakdown.eval,
+            };
+            assert_eq!(Score::Cp(eval), evaluator.evaluate(&board), "{fen}");
+        }
+
+        let board = Board::starting_position();
+        let breakdown = evaluator.evaluate_detailed(&board);
+        assert!(breakdown
+            .terms
+            .iter()
+            .all(|(_, [white, black])| white == black));
+        assert_eq!(breakdown.terms[0].1[0], board.material(Color::White));
+    }
+
+    #[test]
+    fn test_evaluation_is_symmetric() {
+        let evaluator = DefaultEvaluator::default();
+        let corpus = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "4kb2/p4p2/8/8/8/8/P4PP1/2B1K3 w - - 0 1",
+            "8/8/8/4k3/8/8/8/R3K3 w - - 0 1",
+            "8/5k2/8/8/8/2BN4/8/4K3 b - - 0 1",
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+            "4k3/1r6/8/8/8/8/4P3/4KR2 w - - 0 1",
+        ];
+
+        // The positions themselves and everything a move away from them
+        for fen in corpus {
+            let mut move_generator = MoveGenerator::new(BoardBuilder::try_from_fen(fen).unwrap());
+            check_symmetry(&evaluator, &move_generator.board).unwrap();
+            for mv in move_generator.generate_moves() {
+                move_generator.board.move_piece(&mv);
+                check_symmetry(&evaluator, &move_generator.board).unwrap();
+                move_generator.board.undo_last_move().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_bishop_pair() {
+        let bishop_pair = |fen| {
+            bishop_pair(&BoardBuilder::try_from_fen(fen).unwrap()).evaluate(&EvalWeights::default())
+        };
+
+        assert_eq!(bishop_pair("2b1kb2/8/8/8/8/8/8/2B1KB2 w - - 0 1"), 0);
+        assert_eq!(bishop_pair("2b1kn2/8/8/8/8/8/8/2B1KB2 w - - 0 1"), BISHOP_PAIR_BONUS);
+        assert_eq!(bishop_pair("2b1kb2/8/8/8/8/8/8/2B1K3 b - - 0 1"), -BISHOP_PAIR_BONUS);
+        assert_eq!(bishop_pair("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1"), BISHOP_PAIR_BONUS);
+        // Two bishops on the same color don't make a pair
+        assert_eq!(bishop_pair("4k3/8/8/8/8/8/8/B1B1K3 w - - 0 1"), 0);
+    }
+
+    #[test]
+    fn test_rook_placement() {
+        let rooks = |fen, color| {
+            rook_placement(&BoardBuilder::try_from_fen(fen).unwrap(), color)
+                .evaluate(&EvalWeights::default())
+        };
+
+        assert_eq!(
+            rook_placement(&Board::starting_position(), Color::White),
+            Features::default()
+        );
+        // d1 on an open file, e1 on one with only a black pawn, and connected
+        assert_eq!(
+            rooks("4k3/4p3/8/8/8/8/PPP2PPP/3RR1K1 w - - 0 1", Color::White),
+            ROOK_OPEN_FILE_BONUS + ROOK_SEMI_OPEN_FILE_BONUS + CONNECTED_ROOKS_BONUS
+        );
+        // A knight in between keeps them apart
+        assert_eq!(
+            rooks("4k3/4p3/8/8/8/8/PPP2PPP/3RNRK1 w - - 0 1", Color::White),
+            ROOK_OPEN_FILE_BONUS
+        );
+        // On the seventh rank, seen from black's side the second
+        assert_eq!(
+            rooks("4k3/8/8/8/8/8/rP6/4K3 w - - 0 1", Color::Black),
+            ROOK_ON_SEVENTH_RANK_BONUS + ROOK_OPEN_FILE_BONUS
+        );
+        assert_eq!(
+            rooks("r3k3/8/8/8/8/8/8/r3K3 w - - 0 1", Color::Black),
+            2 * ROOK_OPEN_FILE_BONUS + CONNECTED_ROOKS_BONUS
+        );
     }
 }
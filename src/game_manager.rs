@@ -1,12 +1,16 @@
 use anyhow::Result;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::AtomicBool;
 
 use crate::{
-    board::Board,
+    board::{Board, DisplayFormat},
     board_builder::BoardBuilder,
+    eco::{self, Opening},
     move_generation::{Move, MoveGenerator},
-    piece::Color,
-    search::{find_best_move, COUNTER},
+    pgn::{Line, PgnGame, PgnMove},
+    piece::{Color, Piece},
+    score::Score,
+    search::{AlphaBeta, Search, SearchLimits, SearchResult},
+    square::Square,
 };
 
 enum GameState {
@@ -15,10 +19,26 @@ enum GameState {
     Stalemate,
 }
 
+// A move of the game, with the notations GUIs and move lists show it in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayedMove {
+    pub mv: Move,
+    pub uci: String,
+    pub san: String,
+    // The position after the move
+    pub fen: String,
+}
+
 pub struct Game {
     player_color: Option<Color>,
+    // The position the game started from and the moves played since, for the PGN
+    starting_board: Board,
+    history: Vec<PlayedMove>,
     board: Board,
     engine_search_depth: u32,
+    searcher: Box<dyn Search>,
+    // The board is always shown from the player's side
+    display_format: DisplayFormat,
 }
 
 impl Game {
@@ -31,11 +51,143 @@ impl Game {
 
         Ok(Self {
             player_color,
+            starting_board: board.clone(),
+            history: Vec::new(),
             board,
             engine_search_depth,
+            searcher: Box::new(AlphaBeta::new()),
+            display_format: DisplayFormat {
+                unicode: true,
+                colored: true,
+                highlight_last_move: true,
+                flipped: false,
+            },
         })
     }
 
+    pub fn set_display_format(&mut self, display_format: DisplayFormat) {
+        self.display_format = display_format;
+    }
+
+    fn display_board(&self) -> String {
+        self.board.display_with(DisplayFormat {
+            flipped: self.player_color == Some(Color::Black),
+            ..self.display_format
+        })
+    }
+
+    // Names the pieces giving check like in SAN, e.g. "Double check from Nf6 and Bb5"
+    fn check_message(&self) -> Option<String> {
+        let checkers: Vec<String> = MoveGenerator::new(self.board.without_history())
+            .checkers()
+            .into_iter()
+            .map(|square| {
+                let piece = self.board.squares[square].expect("checkers are pieces");
+                let letter = match piece {
+                    Piece::Pawn => String::new(),
+                    _ => piece.to_symbol(Color::White).to_string(),
+                };
+                format!("{letter}{}", Square::from_index(square))
+            })
+            .collect();
+
+        match checkers.as_slice() {
+            [] => None,
+            [checker] => Some(format!("Check from {checker}")),
+            checkers => Some(format!("Double check from {}", checkers.join(" and "))),
+        }
+    }
+
+    pub fn set_searcher(&mut self, searcher: Box<dyn Search>) {
+        self.searcher = searcher;
+    }
+
+    // Openings are only known for games from the starting position
+    pub fn opening(&self) -> Option<Opening> {
+        if !self.started_from_starting_position() {
+            return None;
+        }
+        let moves: Vec<Move> = self
+            .history
+            .iter()
+            .map(|played| played.mv.clone())
+            .collect();
+        eco::classify(&moves)
+    }
+
+    // Every move played so far, oldest first
+    pub fn history(&self) -> &[PlayedMove] {
+        &self.history
+    }
+
+    // Takes back the last move, if there is one
+    pub fn take_back(&mut self) -> Option<PlayedMove> {
+        let played = self.history.pop()?;
+        self.board.undo_last_move().unwrap();
+
+        Some(played)
+    }
+
+    // The game so far in PGN, with the given result: "1-0", "0-1", "1/2-1/2" or "*"
+    pub fn to_pgn(&self, result: &str) -> String {
+        let player = |color| match self.player_color {
+            Some(player_color) if player_color == color => "Player",
+            _ => "Talia",
+        };
+        let mut tags = vec![
+            ("Event", String::from("Talia CLI game")),
+            ("Site", String::from("?")),
+            ("Date", String::from("????.??.??")),
+            ("Round", String::from("?")),
+            ("White", player(Color::White).to_string()),
+            ("Black", player(Color::Black).to_string()),
+            ("Result", result.to_string()),
+        ];
+        if let Some(opening) = self.opening() {
+            tags.push(("ECO", opening.eco.to_string()));
+            tags.push(("Opening", opening.name.to_string()));
+        }
+        if !self.started_from_starting_position() {
+            tags.push(("SetUp", String::from("1")));
+            tags.push(("FEN", self.starting_board.to_fen()));
+        }
+
+        let main_line = Line {
+            moves: self
+                .history
+                .iter()
+                .map(|played| PgnMove::new(&played.san))
+                .collect(),
+            ..Line::default()
+        };
+        let tags = tags
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+
+        PgnGame::new(tags, main_line, result).to_string()
+    }
+
+    fn started_from_starting_position(&self) -> bool {
+        self.starting_board.to_fen() == Board::starting_position().to_fen()
+    }
+
+    // Announces the opening whenever the game gets to a new one
+    fn play_move(&mut self, mv: Move) {
+        let opening = self.opening();
+        let san = mv.to_san(&mut MoveGenerator::new(self.board.without_history()));
+        self.board.move_piece(&mv);
+        self.history.push(PlayedMove {
+            uci: mv.to_string(),
+            san,
+            fen: self.board.to_fen(),
+            mv,
+        });
+        if let Some(new_opening) = self.opening().filter(|new| Some(*new) != opening) {
+            println!("Opening: {} {}", new_opening.eco, new_opening.name);
+        }
+    }
+
     // A pretty scrappy function to play via the CLI while UCI protocol is underway
     pub fn start_game(&mut self) -> Result<()> {
         loop {
@@ -44,10 +196,16 @@ impl Game {
                 GameState::Active => {}
                 GameState::Checkmate => {
                     println!("Checkmate!");
+                    let result = match self.board.to_move {
+                        Color::White => "0-1",
+                        Color::Black => "1-0",
+                    };
+                    println!("{}", self.to_pgn(result));
                     return Ok(());
                 }
                 GameState::Stalemate => {
                     println!("Stalemate!");
+                    println!("{}", self.to_pgn("1/2-1/2"));
                     return Ok(());
                 }
             }
@@ -56,17 +214,24 @@ impl Game {
                 .player_color
                 .is_some_and(|color| color == self.board.to_move)
             {
-                println!("{}", self.board);
+                println!("{}", self.display_board());
+                if let Some(message) = self.check_message() {
+                    println!("{message}");
+                }
                 let input = self.get_uci_move_input();
-                match Move::try_from_uci(&input, &mut move_generator) {
-                    Ok(mv) => self.board.move_piece(&mv),
+                if input == "undo" {
+                    self.take_back_player_move();
+                    continue;
+                }
+                match Move::try_from_uci(&input, &self.board) {
+                    Ok(mv) => self.play_move(mv),
                     Err(error) => println!("{}", error),
                 }
             } else {
                 // Talia plays
                 // Only print the board while Talia is thinking if there is no human player
                 if self.player_color.is_none() {
-                    println!("{}", self.board);
+                    println!("{}", self.display_board());
                 }
 
                 match self.check_game_state(&mut move_generator) {
@@ -82,19 +247,22 @@ impl Game {
                 }
 
                 println!("Talia is thinking ...");
-                let start_time = std::time::Instant::now();
-                let (best_move, mut best_eval) = find_best_move(
-                    &mut move_generator.generate_moves(),
-                    &mut move_generator,
-                    self.engine_search_depth,
+                let SearchResult {
+                    best_move,
+                    score: mut best_eval,
+                    stats,
+                    ..
+                } = self.searcher.search(
+                    &self.board,
+                    &SearchLimits::depth(self.engine_search_depth),
+                    &AtomicBool::new(false),
+                    &mut |_| {},
                 );
-                let end_time = std::time::Instant::now();
-                let elapsed_time = end_time.duration_since(start_time).as_millis();
                 println!(
                     "Talia thought for {} milliseconds and evaluted {} positions at depth {}",
-                    elapsed_time,
-                    COUNTER.load(Ordering::Relaxed),
-                    self.engine_search_depth
+                    stats.time.as_millis(),
+                    stats.nodes,
+                    stats.depth
                 );
 
                 println!("Best move: {:?}", best_move);
@@ -102,14 +270,26 @@ impl Game {
                 // Display the eval without perspective.
                 // Positive eval: white has advantage, negative eval: black has advantage
                 if move_generator.board.to_move == Color::Black {
-                    best_eval *= -1
+                    best_eval = -best_eval
+                }
+                self.play_move(best_move);
+                match best_eval {
+                    Score::Mate(moves) => println!("Eval: mate in {moves}"),
+                    Score::Cp(cp) => println!("Eval: {cp}"),
                 }
-                self.board.move_piece(&best_move);
-                println!("Eval: {best_eval}")
             }
         }
     }
 
+    // Takes back Talia's reply too, so it's the player's turn again
+    fn take_back_player_move(&mut self) {
+        if self.take_back().is_none() {
+            println!("No moves to take back");
+            return;
+        }
+        while self.player_color != Some(self.board.to_move) && self.take_back().is_some() {}
+    }
+
     pub fn start_uci(&mut self) -> Result<()> {
         loop {
             let input = self.get_uci_move_input();
@@ -125,16 +305,12 @@ impl Game {
     }
 
     fn check_game_state(&self, move_generator: &mut MoveGenerator) -> GameState {
-        let moves = move_generator.generate_moves();
-        match moves.is_empty() {
-            true => {
-                if move_generator.is_in_check(move_generator.board.to_move) {
-                    GameState::Checkmate
-                } else {
-                    GameState::Stalemate
-                }
-            }
-            false => GameState::Active,
+        if move_generator.has_legal_moves() {
+            GameState::Active
+        } else if move_generator.is_in_check(move_generator.board.to_move) {
+            GameState::Checkmate
+        } else {
+            GameState::Stalemate
         }
     }
 
@@ -147,3 +323,105 @@ impl Game {
         input.trim().to_owned()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{move_generation::Move, piece::Color};
+    use anyhow::Result;
+
+    use super::Game;
+
+    const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn play(game: &mut Game, moves: &[&str]) -> Result<()> {
+        for uci in moves {
+            let mv = Move::try_from_uci(uci, &game.board)?;
+            game.play_move(mv);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pgn_with_opening() -> Result<()> {
+        let mut game = Game::try_from_fen(STARTING_FEN, Some(Color::Black), 1)?;
+        play(&mut game, &["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"])?;
+
+        let pgn = game.to_pgn("*");
+
+        assert!(pgn.contains("[White \"Talia\"]\n[Black \"Player\"]\n"));
+        assert!(pgn.contains("[ECO \"C60\"]\n[Opening \"Ruy Lopez\"]\n"));
+        assert!(pgn.ends_with("\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 *\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_and_take_back() -> Result<()> {
+        let mut game = Game::try_from_fen(STARTING_FEN, Some(Color::White), 1)?;
+        play(&mut game, &["e2e4", "d7d5", "e4d5", "d8d5"])?;
+
+        let history = game.history();
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[2].uci, "e4d5");
+        assert_eq!(history[2].san, "exd5");
+        assert_eq!(
+            history[2].fen,
+            "rnbqkbnr/ppp1pppp/8/3P4/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2"
+        );
+        let fen_after_d5 = history[1].fen.clone();
+
+        // Talia's reply goes too
+        game.take_back_player_move();
+        assert_eq!(game.history().len(), 2);
+        assert_eq!(game.board.to_fen(), fen_after_d5);
+        assert!(game.to_pgn("*").ends_with("\n\n1. e4 d5 *\n"));
+
+        assert_eq!(game.take_back().unwrap().san, "d5");
+        assert_eq!(game.take_back().unwrap().san, "e4");
+        assert_eq!(game.take_back(), None);
+        assert_eq!(game.board.to_fen(), STARTING_FEN);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_board_from_the_players_side() -> Result<()> {
+        let mut game = Game::try_from_fen(STARTING_FEN, Some(Color::Black), 1)?;
+        assert!(game.display_board().starts_with("1 "));
+
+        game.player_color = Some(Color::White);
+        assert!(game.display_board().starts_with("8 "));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_message() -> Result<()> {
+        let mut game = Game::try_from_fen(STARTING_FEN, Some(Color::Black), 1)?;
+        assert_eq!(game.check_message(), None);
+
+        play(&mut game, &["e2e4", "f7f6", "d1h5"])?;
+        assert_eq!(game.check_message().as_deref(), Some("Check from Qh5"));
+
+        let game = Game::try_from_fen("4k3/8/5N2/1B6/8/8/8/4K3 b - - 0 1", None, 1)?;
+        assert_eq!(game.check_message().as_deref(), Some("Double check from Nf6 and Bb5"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pgn_from_fen() -> Result<()> {
+        let fen = "6k1/5ppp/8/8/8/8/5PPP/R5K1 b - - 0 30";
+        let mut game = Game::try_from_fen(fen, None, 1)?;
+        play(&mut game, &["h7h6", "a1a8"])?;
+
+        let pgn = game.to_pgn("1-0");
+
+        assert_eq!(game.opening(), None);
+        assert!(!pgn.contains("[ECO"));
+        assert!(pgn.contains(&format!("[SetUp \"1\"]\n[FEN \"{fen}\"]\n")));
+        assert!(pgn.ends_with("\n\n30... h6 31. Ra8+ 1-0\n"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+pub mod annotate;
+pub mod board;
+pub mod board_builder;
+pub mod book;
+pub mod bot;
+pub mod direction;
+pub mod eco;
+pub mod endgame;
+pub mod engine;
+pub mod evaluate;
+pub mod game_manager;
+pub mod logging;
+pub mod mate_solver;
+pub mod mcts;
+pub mod move_generation;
+pub mod move_list;
+pub mod move_ordering;
+pub mod nnue;
+pub mod pawn_structure;
+pub mod pgn;
+pub mod piece;
+pub mod piece_square_table;
+pub mod polyglot;
+pub mod render;
+pub mod repertoire;
+pub mod score;
+pub mod search;
+pub mod square;
+pub mod strength;
+pub mod tablebase;
+pub mod threats;
+pub mod transposition_table;
+pub mod tuner;
+pub mod weights;
+pub mod zobrist;
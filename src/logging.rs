@@ -0,0 +1,130 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use tracing::Subscriber;
+use tracing_subscriber::{fmt::MakeWriter, EnvFilter};
+
+// Filter directives in the usual RUST_LOG syntax, e.g. TALIA_LOG=talia::search=debug
+const LOG_FILTER_ENV_VAR: &str = "TALIA_LOG";
+const DEFAULT_LOG_FILTER: &str = "info";
+
+pub fn default_log_file() -> PathBuf {
+    std::env::temp_dir().join("talia.log")
+}
+
+// Handle to the file logs are appended to, which can be changed or disabled (None) while the
+// engine runs. Logging is best effort: a file that can't be written to is silently skipped,
+// it must never take the engine down.
+#[derive(Debug, Clone, Default)]
+pub struct LogFile(Arc<RwLock<Option<PathBuf>>>);
+
+impl LogFile {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self(Arc::new(RwLock::new(path)))
+    }
+
+    pub fn set(&self, path: Option<PathBuf>) {
+        *self.0.write().unwrap_or_else(|err| err.into_inner()) = path;
+    }
+
+    pub fn path(&self) -> Option<PathBuf> {
+        self.0.read().unwrap_or_else(|err| err.into_inner()).clone()
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = Box<dyn Write + 'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        let file = self
+            .path()
+            .and_then(|path| OpenOptions::new().create(true).append(true).open(path).ok());
+
+        match file {
+            Some(file) => Box::new(file),
+            None => Box::new(io::sink()),
+        }
+    }
+}
+
+fn subscriber(log_file: LogFile, json: bool) -> Box<dyn Subscriber + Send + Sync> {
+    let filter = EnvFilter::try_from_env(LOG_FILTER_ENV_VAR)
+        .unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(log_file)
+        .with_ansi(false);
+
+    if json {
+        Box::new(builder.json().finish())
+    } else {
+        Box::new(builder.finish())
+    }
+}
+
+// Installs the global subscriber. JSON output is meant for digging through logs with tools,
+// e.g. to find out where the time went in a game lost on time.
+pub fn init(log_file: LogFile, json: bool) {
+    // Only fails if a subscriber is already installed, which is fine to keep
+    let _ = tracing::subscriber::set_global_default(subscriber(log_file, json));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{subscriber, LogFile};
+    use std::path::PathBuf;
+
+    fn temp_log_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("talia_{name}_{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_logs_are_written_to_log_file() {
+        let path = temp_log_file("plain");
+        let log_file = LogFile::new(Some(path.clone()));
+
+        tracing::subscriber::with_default(subscriber(log_file.clone(), false), || {
+            tracing::info!(command = "isready", "received command");
+            log_file.set(None);
+            tracing::info!("not logged");
+        });
+
+        let logs = std::fs::read_to_string(&path).unwrap();
+        assert!(logs.contains("received command command=\"isready\""));
+        assert!(!logs.contains("not logged"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_json_logs() {
+        let path = temp_log_file("json");
+        let log_file = LogFile::new(Some(path.clone()));
+
+        tracing::subscriber::with_default(subscriber(log_file, true), || {
+            tracing::info!(nodes = 42, "search finished");
+        });
+
+        let logs = std::fs::read_to_string(&path).unwrap();
+        let line: serde_json::Value = serde_json::from_str(logs.trim()).unwrap();
+        assert_eq!(line["fields"]["message"], "search finished");
+        assert_eq!(line["fields"]["nodes"], 42);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unwritable_log_file_is_ignored() {
+        let log_file = LogFile::new(Some(std::env::temp_dir().join("missing_directory/talia.log")));
+
+        tracing::subscriber::with_default(subscriber(log_file, false), || {
+            tracing::info!("nowhere to go");
+        });
+    }
+}
@@ -1,40 +1,382 @@
-use anyhow::Result;
-use clap::Parser;
-
-pub mod board;
-pub mod board_builder;
-pub mod bot;
-pub mod evaluate;
-pub mod game_manager;
-pub mod move_generation;
-pub mod piece;
-pub mod piece_square_table;
-pub mod search;
-pub mod square;
-use crate::bot::Bot;
-use crate::game_manager::Game;
-use crate::piece::Color;
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+use talia::annotate::{
+    analyze, annotate, annotate_blunders, find_blunders, DEFAULT_ANNOTATION_DEPTH,
+    DEFAULT_BLUNDER_THRESHOLD,
+};
+use talia::board::DisplayFormat;
+use talia::board_builder::BoardBuilder;
+use talia::book::{Book, DEFAULT_BOOK_PLIES};
+use talia::bot::Bot;
+use talia::evaluate::DefaultEvaluator;
+use talia::game_manager::Game;
+use talia::logging::{self, default_log_file, LogFile};
+use talia::mate_solver::solve_mate;
+use talia::pgn::parse_games;
+use talia::piece::Color;
+use talia::piece_square_table::{use_piece_square_tables, PieceSquareTables};
+use talia::render::{render_svg, PieceSet, RenderOptions};
+use talia::search::{SearchAlgorithm, SearchLimits};
+use talia::transposition_table::DEFAULT_HASH_SIZE_MB;
+use talia::tuner::{self, DEFAULT_LEARNING_RATE, DEFAULT_TUNING_ITERATIONS};
+use talia::weights::EvalWeights;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 struct Args {
     #[arg(long, default_value_t = false)]
     cli: bool,
+    // Where to log the engine's communication with the GUI, defaults to talia.log in the temp
+    // directory
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+    #[arg(long, default_value_t = false)]
+    no_log: bool,
+    // Write logs as JSON lines instead of plain text
+    #[arg(long, default_value_t = false)]
+    log_json: bool,
+    // Same as the "Search Algorithm" UCI option
+    #[arg(long, default_value = "AlphaBeta")]
+    search_algorithm: String,
+    // Show the board of the CLI game with letters, without Unicode or colors
+    #[arg(long, default_value_t = false)]
+    plain_board: bool,
+    // File with piece square tables to use instead of the built-in ones
+    #[arg(long)]
+    piece_square_tables: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    // Look for a forced mate in the given position, e.g. to check a puzzle or study
+    Solve {
+        fen: String,
+        // Longest mate to look for, in moves
+        #[arg(long, default_value_t = 3)]
+        moves: u32,
+    },
+    // Show what each part of the evaluation adds up to in the given position
+    Eval {
+        fen: String,
+        #[arg(long)]
+        weights: Option<PathBuf>,
+    },
+    // Build an opening book out of the games in a PGN file
+    MakeBook {
+        pgn: PathBuf,
+        #[arg(long)]
+        output: PathBuf,
+        // How many plies of every game go into the book
+        #[arg(long, default_value_t = DEFAULT_BOOK_PLIES)]
+        max_plies: u32,
+    },
+    // Search every position of the games in a PGN file and write them back with the evaluations
+    // and the engine's best lines
+    Annotate {
+        pgn: PathBuf,
+        #[arg(long)]
+        output: PathBuf,
+        #[arg(long, default_value_t = DEFAULT_ANNOTATION_DEPTH)]
+        depth: u32,
+    },
+    // List the moves of the games in a PGN file that lost at least the threshold, in centipawns,
+    // against the engine's best move, with the lines that refute them
+    BlunderCheck {
+        pgn: PathBuf,
+        #[arg(long, default_value_t = DEFAULT_BLUNDER_THRESHOLD)]
+        threshold: i32,
+        #[arg(long, default_value_t = DEFAULT_ANNOTATION_DEPTH)]
+        depth: u32,
+        // Also write the games with the blunders marked
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    // Draw the position as an SVG image
+    Img {
+        fen: String,
+        output: PathBuf,
+        // unicode or letters
+        #[arg(long, default_value = "unicode")]
+        piece_set: String,
+        // In pixels
+        #[arg(long, default_value_t = RenderOptions::default().square_size)]
+        square_size: u32,
+        #[arg(long, default_value_t = RenderOptions::default().light_square_color)]
+        light: String,
+        #[arg(long, default_value_t = RenderOptions::default().dark_square_color)]
+        dark: String,
+        // With black at the bottom
+        #[arg(long, default_value_t = false)]
+        flipped: bool,
+        #[arg(long, default_value_t = false)]
+        no_coordinates: bool,
+    },
+    // Tune the evaluation weights on positions labeled with their game's result, one FEN and
+    // result per line, and write them to a weights file
+    Tune {
+        positions: PathBuf,
+        #[arg(long)]
+        output: PathBuf,
+        // Weights file to start from, the built-in weights otherwise
+        #[arg(long)]
+        weights: Option<PathBuf>,
+        #[arg(long, default_value_t = DEFAULT_TUNING_ITERATIONS)]
+        iterations: u32,
+        #[arg(long, default_value_t = DEFAULT_LEARNING_RATE)]
+        learning_rate: f64,
+    },
 }
 
 fn main() -> Result<()> {
     println!("Talia Chess Engine: v1.1.1");
     let args = Args::parse();
+    let log_file = if args.no_log {
+        None
+    } else {
+        Some(args.log_file.unwrap_or_else(default_log_file))
+    };
+    let search_algorithm = SearchAlgorithm::try_from_name(&args.search_algorithm)?;
+    if let Some(path) = &args.piece_square_tables {
+        use_piece_square_tables(PieceSquareTables::load(path)?)?;
+    }
+
+    match args.command {
+        Some(Command::Solve { fen, moves }) => return solve(&fen, moves),
+        Some(Command::Eval { fen, weights }) => return eval(&fen, weights.as_deref()),
+        Some(Command::MakeBook {
+            pgn,
+            output,
+            max_plies,
+        }) => return make_book(&pgn, &output, max_plies),
+        Some(Command::Annotate { pgn, output, depth }) => {
+            return annotate_games(&pgn, &output, depth, search_algorithm)
+        }
+        Some(Command::BlunderCheck {
+            pgn,
+            threshold,
+            depth,
+            output,
+        }) => return blunder_check(&pgn, threshold, depth, output.as_deref(), search_algorithm),
+        Some(Command::Tune {
+            positions,
+            output,
+            weights,
+            iterations,
+            learning_rate,
+        }) => return tune(&positions, &output, weights.as_deref(), iterations, learning_rate),
+        Some(Command::Img {
+            fen,
+            output,
+            piece_set,
+            square_size,
+            light,
+            dark,
+            flipped,
+            no_coordinates,
+        }) => {
+            let options = RenderOptions {
+                square_size,
+                light_square_color: light,
+                dark_square_color: dark,
+                piece_set: PieceSet::try_from_name(&piece_set)?,
+                coordinates: !no_coordinates,
+                flipped,
+            };
+            return img(&fen, &output, &options);
+        }
+        None => {}
+    }
 
     if args.cli {
         let search_depth = 6;
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        logging::init(LogFile::new(log_file), args.log_json);
         let mut game = Game::try_from_fen(fen, Some(Color::White), search_depth)?;
+        if args.plain_board {
+            game.set_display_format(DisplayFormat::default());
+        }
+        game.set_searcher(search_algorithm.create(
+            DEFAULT_HASH_SIZE_MB,
+            EvalWeights::default(),
+            None,
+        ));
         game.start_game()?;
     } else {
         let mut bot = Bot::new();
-        bot.start_uci()?;
+        bot.set_log_file(log_file);
+        bot.set_search_algorithm(search_algorithm);
+        logging::init(bot.log_file(), args.log_json);
+        bot.start(std::io::stdin().lock())?;
     }
 
     Ok(())
 }
+
+fn solve(fen: &str, moves: u32) -> Result<()> {
+    let board = BoardBuilder::try_from_fen(fen)?;
+    match solve_mate(&board, moves, &AtomicBool::new(false)) {
+        Some(result) => {
+            let pv: Vec<String> = result.pv.iter().map(ToString::to_string).collect();
+            println!(
+                "Mate in {}: {} ({} nodes in {} ms)",
+                result.pv.len().div_ceil(2),
+                pv.join(" "),
+                result.stats.nodes,
+                result.stats.time.as_millis()
+            );
+        }
+        None => println!("No mate in {moves} found"),
+    }
+
+    Ok(())
+}
+
+fn img(fen: &str, output: &Path, options: &RenderOptions) -> Result<()> {
+    let board = BoardBuilder::try_from_fen(fen)?;
+    std::fs::write(output, render_svg(&board, options))?;
+    println!("Wrote {}", output.display());
+
+    Ok(())
+}
+
+fn eval(fen: &str, weights: Option<&Path>) -> Result<()> {
+    let board = BoardBuilder::try_from_fen(fen)?;
+    let weights = match weights {
+        Some(path) => EvalWeights::load(path)?,
+        None => EvalWeights::default(),
+    };
+    println!(
+        "{}",
+        DefaultEvaluator::with_weights(weights).evaluate_detailed(&board)
+    );
+
+    Ok(())
+}
+
+fn make_book(pgn: &Path, output: &Path, max_plies: u32) -> Result<()> {
+    // PGN files in the wild aren't always UTF-8
+    let pgn = String::from_utf8_lossy(&std::fs::read(pgn)?).into_owned();
+    let book = Book::from_pgn(&pgn, max_plies);
+    book.save(output)?;
+    println!("Wrote {} book moves to {}", book.len(), output.display());
+
+    Ok(())
+}
+
+fn annotate_games(
+    pgn: &Path,
+    output: &Path,
+    depth: u32,
+    search_algorithm: SearchAlgorithm,
+) -> Result<()> {
+    let pgn = String::from_utf8_lossy(&std::fs::read(pgn)?).into_owned();
+    let games = parse_games(&pgn);
+    let mut searcher = search_algorithm.create(DEFAULT_HASH_SIZE_MB, EvalWeights::default(), None);
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..SearchLimits::default()
+    };
+
+    let mut annotated = Vec::new();
+    for (number, game) in games.iter().enumerate() {
+        let number = number + 1;
+        match game
+            .as_ref()
+            .map_err(|err| anyhow!("{err}"))
+            .and_then(|game| annotate(game, searcher.as_mut(), &limits))
+        {
+            Ok(game) => {
+                println!("Annotated game {number} of {}", games.len());
+                annotated.push(game.to_string());
+            }
+            Err(err) => println!("Skipping game {number}: {err}"),
+        }
+    }
+    std::fs::write(output, annotated.join("\n"))?;
+    println!("Wrote {} annotated games to {}", annotated.len(), output.display());
+
+    Ok(())
+}
+
+fn blunder_check(
+    pgn: &Path,
+    threshold: i32,
+    depth: u32,
+    output: Option<&Path>,
+    search_algorithm: SearchAlgorithm,
+) -> Result<()> {
+    let pgn = String::from_utf8_lossy(&std::fs::read(pgn)?).into_owned();
+    let games = parse_games(&pgn);
+    let mut searcher = search_algorithm.create(DEFAULT_HASH_SIZE_MB, EvalWeights::default(), None);
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..SearchLimits::default()
+    };
+
+    let mut annotated = Vec::new();
+    for (number, game) in games.iter().enumerate() {
+        let number = number + 1;
+        let (game, positions) =
+            match game
+                .as_ref()
+                .map_err(|err| anyhow!("{err}"))
+                .and_then(|game| {
+                    analyze(game, searcher.as_mut(), &limits).map(|positions| (game, positions))
+                }) {
+                Ok(analyzed) => analyzed,
+                Err(err) => {
+                    println!("Skipping game {number}: {err}");
+                    continue;
+                }
+            };
+        let blunders = find_blunders(&positions, threshold);
+        println!(
+            "Game {number} ({} - {}), blunders: {}",
+            game.tag("White").unwrap_or("?"),
+            game.tag("Black").unwrap_or("?"),
+            blunders.len()
+        );
+        for blunder in &blunders {
+            println!("  {blunder}");
+        }
+        annotated.push(annotate_blunders(game, &blunders).to_string());
+    }
+    if let Some(output) = output {
+        std::fs::write(output, annotated.join("\n"))?;
+        println!("Wrote {} games to {}", annotated.len(), output.display());
+    }
+
+    Ok(())
+}
+
+fn tune(
+    positions: &Path,
+    output: &Path,
+    weights: Option<&Path>,
+    iterations: u32,
+    learning_rate: f64,
+) -> Result<()> {
+    let initial = match weights {
+        Some(path) => EvalWeights::load(path)?,
+        None => EvalWeights::default(),
+    };
+    let positions = tuner::load_positions(BufReader::new(File::open(positions)?))?;
+    println!("Tuning on {} positions", positions.len());
+
+    let tuned = tuner::tune(&positions, &initial, iterations, learning_rate, |iteration, error| {
+        if iteration % 10 == 0 || iteration == iterations {
+            println!("Iteration {iteration}: error {error:.6}");
+        }
+    });
+    tuned.save(output)?;
+    println!("Wrote the tuned weights to {}", output.display());
+
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use tracing::{debug, info_span};
+
+use crate::{
+    board::Board,
+    move_generation::{Move, MoveGenerator},
+    score::Score,
+    search::{SearchResult, SearchStats},
+};
+
+// Proves whether the side to move can force mate within the given number of moves, for puzzles
+// and studies. Much faster than the general search: it only has to find one forcing line, tries
+// checks first and only looks at checks for the mating move itself. Returns the shortest mate,
+// or None if there is none within max_moves or the solver was stopped.
+pub fn solve_mate(board: &Board, max_moves: u32, stop: &AtomicBool) -> Option<SearchResult> {
+    let _span = info_span!("mate_solver", max_moves).entered();
+    let start_time = Instant::now();
+    let mut solver = MateSolver {
+        move_generator: MoveGenerator::new(board.without_history()),
+        stop,
+        nodes: 0,
+    };
+
+    for moves in 1..=max_moves {
+        if let Some(pv) = solver.attack(moves) {
+            debug!(moves, nodes = solver.nodes, "mate found");
+            return Some(SearchResult {
+                best_move: pv[0].clone(),
+                ponder_move: pv.get(1).cloned(),
+                score: Score::Mate(moves.min(i8::MAX as u32) as i8),
+                pv,
+                stats: SearchStats {
+                    depth: 2 * moves - 1,
+                    nodes: solver.nodes,
+                    time: start_time.elapsed(),
+                },
+            });
+        }
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+    }
+
+    debug!(nodes = solver.nodes, "no mate found");
+    None
+}
+
+struct MateSolver<'a> {
+    move_generator: MoveGenerator,
+    stop: &'a AtomicBool,
+    nodes: u64,
+}
+
+impl MateSolver<'_> {
+    // A move of the side to move that mates within moves_left moves, followed by the rest of
+    // the line
+    fn attack(&mut self, moves_left: u32) -> Option<Vec<Move>> {
+        if self.stop.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.nodes += 1;
+
+        let mut moves: Vec<(Move, bool)> = self
+            .move_generator
+            .generate_moves()
+            .into_iter()
+            .map(|mv| {
+                let check = self.gives_check(&mv);
+                (mv, check)
+            })
+            // Only a check can be mate
+            .filter(|(_, check)| moves_left > 1 || *check)
+            .collect();
+        // Checks first, then captures, as those are the most forcing
+        moves.sort_by_key(|(mv, check)| (!check, !mv.is_capture()));
+
+        for (mv, _) in moves {
+            let previous_state = self.move_generator.board.make_move(&mv);
+            let line = self.defend(moves_left);
+            self.move_generator
+                .board
+                .unmake_move(&mv, previous_state)
+                .unwrap();
+
+            if let Some(mut line) = line {
+                line.insert(0, mv);
+                return Some(line);
+            }
+        }
+
+        None
+    }
+
+    // A line in which the side to move gets mated within moves_left moves of the opponent
+    // (including the one that was just played), whatever it does
+    fn defend(&mut self, moves_left: u32) -> Option<Vec<Move>> {
+        self.nodes += 1;
+        let replies = self.move_generator.generate_moves();
+        if replies.is_empty() {
+            let to_move = self.move_generator.board.to_move;
+            return self.move_generator.is_in_check(to_move).then(Vec::new);
+        }
+        if moves_left == 1 {
+            return None;
+        }
+
+        let mut line = None;
+        for reply in replies {
+            let previous_state = self.move_generator.board.make_move(&reply);
+            let mate = self.attack(moves_left - 1);
+            self.move_generator
+                .board
+                .unmake_move(&reply, previous_state)
+                .unwrap();
+
+            match mate {
+                // This reply escapes, so the attacking move doesn't work
+                None => return None,
+                Some(mate) if line.is_none() => line = Some([vec![reply], mate].concat()),
+                Some(_) => {}
+            }
+        }
+
+        line
+    }
+
+    fn gives_check(&mut self, mv: &Move) -> bool {
+        let previous_state = self.move_generator.board.make_move(mv);
+        let to_move = self.move_generator.board.to_move;
+        let check = self.move_generator.is_in_check(to_move);
+        self.move_generator
+            .board
+            .unmake_move(mv, previous_state)
+            .unwrap();
+
+        check
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use crate::{
+        board::Board,
+        board_builder::BoardBuilder,
+        move_generation::{Flag, Move, MoveGenerator},
+        score::Score,
+        square::Square,
+    };
+    use anyhow::Result;
+
+    use super::solve_mate;
+
+    #[test]
+    fn test_solve_mate_in_one() -> Result<()> {
+        let board: Board = BoardBuilder::try_from_fen("1r4k1/5ppp/8/8/8/8/5PPP/7K b - - 0 1")?;
+
+        let result = solve_mate(&board, 3, &AtomicBool::new(false)).unwrap();
+
+        assert!(result.best_move == Move::from_square(Square::B8, Square::B1, Flag::None));
+        assert_eq!(result.score, Score::Mate(1));
+        assert_eq!(result.pv.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_solve_mate_in_two() -> Result<()> {
+        // The rooks can't mate right away, one has to cut off the king first
+        let board: Board = BoardBuilder::try_from_fen("7k/8/8/8/8/8/R7/1R5K w - - 0 1")?;
+        let stop = AtomicBool::new(false);
+
+        assert!(solve_mate(&board, 1, &stop).is_none());
+        let result = solve_mate(&board, 2, &stop).unwrap();
+        assert_eq!(result.score, Score::Mate(2));
+        assert_eq!(result.stats.depth, 3);
+
+        // The line ends in checkmate
+        let mut move_generator = MoveGenerator::new(board);
+        for mv in &result.pv {
+            assert!(move_generator.generate_moves().contains(mv));
+            move_generator.board.move_piece(mv);
+        }
+        assert_eq!(result.pv.len(), 3);
+        assert!(move_generator.generate_moves().is_empty());
+        assert!(move_generator.is_in_check(move_generator.board.to_move));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stopped_solver_gives_up() -> Result<()> {
+        let board: Board = BoardBuilder::try_from_fen("7k/8/8/8/8/8/R7/1R5K w - - 0 1")?;
+
+        assert!(solve_mate(&board, 2, &AtomicBool::new(true)).is_none());
+
+        Ok(())
+    }
+}
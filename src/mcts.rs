@@ -0,0 +1,375 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info_span};
+
+use crate::{
+    board::Board,
+    evaluate::{DefaultEvaluator, Evaluator},
+    move_generation::{Move, MoveGenerator},
+    score::Score,
+    search::{Search, SearchLimits, SearchProgress, SearchResult, SearchStats},
+};
+
+// Playouts of a search without a node or time limit. The depth limit means nothing to MCTS.
+const DEFAULT_PLAYOUTS: u64 = 5_000;
+// How much the search favours rarely visited moves over the ones that look best so far
+const EXPLORATION: f64 = 1.5;
+// Progress is reported every this many playouts
+const REPORT_INTERVAL: u64 = 1_000;
+// Centipawn scale of the conversion between scores and win probabilities
+const CP_PER_WIN_PROBABILITY_UNIT: f64 = 400.0;
+
+// Monte Carlo tree search with PUCT selection. Instead of random rollouts, every new leaf is
+// valued with the static evaluation, so it plays much weaker than alpha-beta but is cheap and
+// a different kind of opponent. The tree is rebuilt for every search.
+pub struct Mcts<E = DefaultEvaluator> {
+    evaluator: E,
+}
+
+// Values are win probabilities in [0, 1] from the perspective of the side that played the move
+// leading to the node
+struct Node {
+    mv: Option<Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    expanded: bool,
+    // Set once the position is known to be checkmate or stalemate
+    terminal_value: Option<f64>,
+    prior: f64,
+    visits: u64,
+    value_sum: f64,
+}
+
+impl Node {
+    fn new(mv: Option<Move>, parent: Option<usize>, prior: f64) -> Self {
+        Self {
+            mv,
+            parent,
+            children: Vec::new(),
+            expanded: false,
+            terminal_value: None,
+            prior,
+            visits: 0,
+            value_sum: 0.0,
+        }
+    }
+
+    // Unvisited moves are assumed to be even
+    fn mean_value(&self) -> f64 {
+        if self.visits == 0 {
+            0.5
+        } else {
+            self.value_sum / self.visits as f64
+        }
+    }
+}
+
+impl Mcts {
+    pub fn new() -> Self {
+        Self::with_evaluator(DefaultEvaluator::default())
+    }
+}
+
+impl<E: Evaluator> Mcts<E> {
+    pub fn with_evaluator(evaluator: E) -> Self {
+        Self { evaluator }
+    }
+
+    // Walks down the tree along the most promising moves, expands the leaf it ends up in and
+    // passes its value back up. Returns how deep the leaf was.
+    fn playout(&self, tree: &mut Vec<Node>, move_generator: &mut MoveGenerator) -> u32 {
+        let mut node = 0;
+        let mut path = Vec::new();
+        while tree[node].expanded && !tree[node].children.is_empty() {
+            node = select_child(tree, node);
+            let mv = tree[node].mv.clone().unwrap();
+            let previous_state = move_generator.board.make_move(&mv);
+            path.push((mv, previous_state));
+        }
+
+        // From the perspective of the side to move in the leaf
+        let value = match tree[node].terminal_value {
+            Some(value) => value,
+            None => self.expand(tree, node, move_generator),
+        };
+
+        for (mv, previous_state) in path.iter().rev() {
+            move_generator
+                .board
+                .unmake_move(mv, previous_state.clone())
+                .unwrap();
+        }
+
+        // The value flips at every level, the side that moved into the leaf gets its opposite
+        let mut value = 1.0 - value;
+        let mut current = Some(node);
+        while let Some(index) = current {
+            tree[index].visits += 1;
+            tree[index].value_sum += value;
+            value = 1.0 - value;
+            current = tree[index].parent;
+        }
+
+        path.len() as u32
+    }
+
+    // Adds the children of the node and returns its value for the side to move
+    fn expand(&self, tree: &mut Vec<Node>, node: usize, move_generator: &mut MoveGenerator) -> f64 {
+        tree[node].expanded = true;
+        let moves = move_generator.generate_moves();
+        if moves.is_empty() {
+            let value = if move_generator.is_in_check(move_generator.board.to_move) {
+                0.0
+            } else {
+                0.5
+            };
+            tree[node].terminal_value = Some(value);
+            return value;
+        }
+
+        let prior = 1.0 / moves.len() as f64;
+        for mv in moves {
+            let child = tree.len();
+            tree[node].children.push(child);
+            tree.push(Node::new(Some(mv), Some(node), prior));
+        }
+
+        win_probability(self.evaluator.evaluate(&move_generator.board))
+    }
+}
+
+impl Default for Mcts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: Evaluator> Search for Mcts<E> {
+    fn search(
+        &mut self,
+        board: &Board,
+        limits: &SearchLimits,
+        stop: &AtomicBool,
+        on_progress: &mut dyn FnMut(&SearchProgress),
+    ) -> SearchResult {
+        let start_time = Instant::now();
+        let time_budget = limits.time_budget(board.to_move);
+        let max_playouts = limits
+            .nodes
+            .unwrap_or(if limits.infinite || time_budget.is_some() {
+                u64::MAX
+            } else {
+                DEFAULT_PLAYOUTS
+            });
+        let _span = info_span!("mcts", max_playouts).entered();
+
+        let mut move_generator = MoveGenerator::new(board.without_history());
+        self.evaluator.prepare(&mut move_generator.board);
+        let mut tree = vec![Node::new(None, None, 1.0)];
+        self.expand(&mut tree, 0, &mut move_generator);
+        // Moves the search may not play are never looked at
+        let root_children = std::mem::take(&mut tree[0].children);
+        tree[0].children = root_children
+            .into_iter()
+            .filter(|&child| limits.allows_root_move(tree[child].mv.as_ref().unwrap()))
+            .collect();
+        assert!(!tree[0].children.is_empty(), "the position must have a legal move");
+
+        let mut playouts = 0;
+        let mut max_depth = 0;
+        while playouts < max_playouts
+            && !stop.load(Ordering::Relaxed)
+            && time_budget.is_none_or(|budget| start_time.elapsed() < budget)
+        {
+            max_depth = max_depth.max(self.playout(&mut tree, &mut move_generator));
+            playouts += 1;
+
+            if playouts % REPORT_INTERVAL == 0 {
+                let result = result(&tree, max_depth, playouts, start_time.elapsed());
+                on_progress(&SearchProgress::Iteration {
+                    depth: result.stats.depth,
+                    score: result.score,
+                    pv: result.pv,
+                    nodes: playouts,
+                    time: result.stats.time,
+                    hashfull: 0,
+                });
+            }
+        }
+
+        debug!(playouts, max_depth, "mcts finished");
+        result(&tree, max_depth, playouts, start_time.elapsed())
+    }
+
+    // Nothing is kept between searches
+    fn clear(&mut self) {}
+
+    fn set_hash_size(&mut self, _size_mb: usize) {}
+
+    fn hashfull(&self) -> u32 {
+        0
+    }
+}
+
+fn select_child(tree: &[Node], node: usize) -> usize {
+    let parent_visits = (tree[node].visits as f64).sqrt();
+    let puct = |child: usize| {
+        let child = &tree[child];
+        child.mean_value() + EXPLORATION * child.prior * parent_visits / (1 + child.visits) as f64
+    };
+
+    *tree[node]
+        .children
+        .iter()
+        .max_by(|&&a, &&b| puct(a).total_cmp(&puct(b)))
+        .unwrap()
+}
+
+// The most visited move is the one the search trusts the most
+fn most_visited_child(tree: &[Node], node: usize) -> Option<usize> {
+    tree[node]
+        .children
+        .iter()
+        .copied()
+        .max_by_key(|&child| tree[child].visits)
+}
+
+fn result(tree: &[Node], max_depth: u32, playouts: u64, time: Duration) -> SearchResult {
+    let mut pv = Vec::new();
+    let mut node = 0;
+    while let Some(child) = most_visited_child(tree, node) {
+        if tree[child].visits == 0 {
+            break;
+        }
+        pv.push(tree[child].mv.clone().unwrap());
+        node = child;
+    }
+
+    let best_child = most_visited_child(tree, 0).unwrap();
+    let best = &tree[best_child];
+    // A move into checkmate is the only mate MCTS knows for sure
+    let score = if best.terminal_value == Some(0.0) {
+        Score::Mate(1)
+    } else {
+        score_from_win_probability(best.mean_value())
+    };
+
+    SearchResult {
+        best_move: best.mv.clone().unwrap(),
+        ponder_move: pv.get(1).cloned(),
+        score,
+        pv,
+        stats: SearchStats {
+            depth: max_depth,
+            nodes: playouts,
+            time,
+        },
+    }
+}
+
+fn win_probability(score: Score) -> f64 {
+    match score {
+        Score::Cp(cp) => 1.0 / (1.0 + 10f64.powf(-cp as f64 / CP_PER_WIN_PROBABILITY_UNIT)),
+        Score::Mate(moves) if moves > 0 => 1.0,
+        Score::Mate(_) => 0.0,
+    }
+}
+
+fn score_from_win_probability(probability: f64) -> Score {
+    let probability = probability.clamp(0.001, 0.999);
+    let cp = -CP_PER_WIN_PROBABILITY_UNIT * (1.0 / probability - 1.0).log10();
+    Score::Cp(cp.round() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use crate::{
+        board::Board,
+        board_builder::BoardBuilder,
+        move_generation::{Flag, Move, MoveGenerator},
+        score::Score,
+        search::{Search, SearchLimits, SearchProgress, SearchResult},
+        square::Square,
+    };
+    use anyhow::Result;
+
+    use super::{score_from_win_probability, win_probability, Mcts};
+
+    fn search(board: &Board, limits: &SearchLimits) -> SearchResult {
+        Mcts::new().search(board, limits, &AtomicBool::new(false), &mut |_| {})
+    }
+
+    #[test]
+    fn test_win_probability_round_trip() {
+        assert_eq!(win_probability(Score::Cp(0)), 0.5);
+        assert!(win_probability(Score::Cp(300)) > 0.8);
+        assert_eq!(
+            score_from_win_probability(win_probability(Score::Cp(150))),
+            Score::Cp(150)
+        );
+        assert_eq!(score_from_win_probability(0.5), Score::Cp(0));
+    }
+
+    #[test]
+    fn test_mcts_finds_mate_in_one() -> Result<()> {
+        let board: Board = BoardBuilder::try_from_fen("1r4k1/5ppp/8/8/8/8/5PPP/7K b - - 0 1")?;
+        let limits = SearchLimits {
+            nodes: Some(2_000),
+            ..SearchLimits::default()
+        };
+
+        let result = search(&board, &limits);
+
+        assert!(result.best_move == Move::from_square(Square::B8, Square::B1, Flag::None));
+        assert_eq!(result.score, Score::Mate(1));
+        assert_eq!(result.stats.nodes, 2_000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mcts_takes_hanging_queen() -> Result<()> {
+        let board: Board = BoardBuilder::try_from_fen("4k3/pppp4/8/6q1/8/5N2/PPP5/4K3 w - - 0 1")?;
+        let limits = SearchLimits {
+            nodes: Some(2_000),
+            ..SearchLimits::default()
+        };
+
+        let result = search(&board, &limits);
+
+        assert!(result.best_move == Move::from_square(Square::F3, Square::G5, Flag::Capture));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mcts_respects_search_moves_and_reports_progress() {
+        let board = Board::starting_position();
+        let allowed = Move::from_square(Square::A2, Square::A3, Flag::None);
+        let limits = SearchLimits {
+            nodes: Some(2_000),
+            search_moves: vec![allowed.clone()],
+            ..SearchLimits::default()
+        };
+
+        let mut iterations = 0;
+        let result =
+            Mcts::new().search(&board, &limits, &AtomicBool::new(false), &mut |progress| {
+                if let SearchProgress::Iteration { pv, .. } = progress {
+                    assert!(!pv.is_empty());
+                    iterations += 1;
+                }
+            });
+
+        assert!(result.best_move == allowed);
+        assert_eq!(iterations, 2);
+        let mut move_generator = MoveGenerator::new(board);
+        move_generator.board.move_piece(&allowed);
+        assert!(move_generator
+            .generate_moves()
+            .contains(result.ponder_move.as_ref().unwrap()));
+    }
+}
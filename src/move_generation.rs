@@ -1,11 +1,15 @@
 use anyhow::{anyhow, bail, Result};
 use core::fmt;
+use std::sync::OnceLock;
 
 use crate::board::Board;
+use crate::direction::Direction;
+use crate::move_list::{self, MoveList};
 use crate::piece::{Color, Piece};
-use crate::square::Square;
+use crate::square::{square_index, File, Rank, Square};
 
 #[derive(Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Move {
     pub starting_square: usize,
     pub target_square: usize,
@@ -13,7 +17,7 @@ pub struct Move {
 }
 
 impl Move {
-    pub fn new(start: usize, target: usize, flag: Flag) -> Self {
+    pub const fn new(start: usize, target: usize, flag: Flag) -> Self {
         Self {
             starting_square: start,
             target_square: target,
@@ -29,25 +33,228 @@ impl Move {
         }
     }
 
-    pub fn try_from_uci(
-        algebraic_notation: &str,
-        move_generator: &mut MoveGenerator,
-    ) -> Result<Self> {
-        let promotion_piece = match algebraic_notation.chars().nth(4) {
-            Some('q') => Some(Piece::Queen),
-            Some('r') => Some(Piece::Rook),
-            Some('n') => Some(Piece::Knight),
-            Some('b') => Some(Piece::Bishop),
+    // Passes the turn to the other side
+    pub const fn null() -> Self {
+        Self::new(0, 0, Flag::NullMove)
+    }
+
+    pub fn is_capture(&self) -> bool {
+        matches!(
+            self.flag,
+            Flag::Capture | Flag::CaptureWithPromotion(_) | Flag::EnPassantCapture
+        )
+    }
+
+    // Only the moves of the piece on the starting square are generated to check that the move is
+    // legal. "0000" is a null move, which GUIs send e.g. to analyze the threats in a position.
+    pub fn try_from_uci(algebraic_notation: &str, board: &Board) -> Result<Self> {
+        if algebraic_notation == "0000" {
+            if MoveGenerator::new(board.without_history()).is_in_check(board.to_move) {
+                bail!("Can't pass the turn while in check");
+            }
+            return Ok(Self::null());
+        }
+        // Slicing below needs the squares to be exactly two ASCII characters each
+        if !(4..=5).contains(&algebraic_notation.len()) || !algebraic_notation.is_ascii() {
+            bail!("Invalid UCI move: {algebraic_notation}");
+        }
+
+        let promotion_piece = match algebraic_notation.chars().nth(4).map(Piece::from_symbol) {
             None => None,
-            _ => bail!("Not a known promotion piece of q, r, n or b"),
+            // Always lowercase in UCI
+            Some(Some((piece, Color::Black))) if !matches!(piece, Piece::Pawn | Piece::King) => {
+                Some(piece)
+            }
+            Some(_) => bail!("Not a known promotion piece of q, r, n or b"),
         };
 
         let starting_square =
             Square::from_algebraic_notation(&algebraic_notation[0..2])?.as_index();
         let target_square = Square::from_algebraic_notation(&algebraic_notation[2..4])?.as_index();
 
+        let moves =
+            MoveGenerator::new(board.without_history()).generate_moves_from(starting_square);
+
+        // In Chess960 mode castling is written as the king capturing its own rook. The king's
+        // destination square alone would be ambiguous with regular king moves.
+        if board.chess960 {
+            let is_castling =
+                |mv: &Move| matches!(mv.flag, Flag::KingsideCastle | Flag::QueensideCastle);
+            if let Some(castling_move) = moves.iter().find(|mv| {
+                is_castling(mv)
+                    && mv.starting_square == starting_square
+                    && board.castling_rook_square(mv) == target_square
+            }) {
+                return Ok(castling_move.clone());
+            }
+
+            let moves = moves.into_iter().filter(|mv| !is_castling(mv)).collect();
+            return Self::find_move(moves, starting_square, target_square, promotion_piece);
+        }
+
+        Self::find_move(moves, starting_square, target_square, promotion_piece)
+    }
+
+    // Standard algebraic notation as found in PGN files, e.g. Nf3, exd5, O-O or e8=Q+
+    pub fn try_from_san(san: &str, move_generator: &mut MoveGenerator) -> Result<Self> {
+        let invalid = || anyhow!("Invalid SAN move: {san}");
+        let notation = san.trim_end_matches(['+', '#', '!', '?']);
         let moves = move_generator.generate_moves();
 
+        let castling_flag = match notation {
+            "O-O" | "0-0" => Some(Flag::KingsideCastle),
+            "O-O-O" | "0-0-0" => Some(Flag::QueensideCastle),
+            _ => None,
+        };
+        if let Some(flag) = castling_flag {
+            return moves
+                .into_iter()
+                .find(|mv| mv.flag == flag)
+                .ok_or(anyhow!("Not a legal move"));
+        }
+
+        let (notation, promotion_piece) = match notation.split_once('=') {
+            Some((notation, piece)) => {
+                (notation, Some(Self::san_piece(piece).ok_or_else(invalid)?))
+            }
+            None => (notation, None),
+        };
+        if notation.len() < 2 || !notation.is_ascii() {
+            return Err(invalid());
+        }
+
+        let (piece, notation) = match Self::san_piece(&notation[..1]) {
+            Some(piece) => (piece, &notation[1..]),
+            None => (Piece::Pawn, notation),
+        };
+        if notation.len() < 2 {
+            return Err(invalid());
+        }
+        let (disambiguation, target) = notation.split_at(notation.len() - 2);
+        let target_square = Square::from_algebraic_notation(target)?.as_index();
+        // Whatever is left tells apart pieces that can reach the same square, e.g. the d in Nbd7
+        let disambiguation = disambiguation.trim_end_matches('x');
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in disambiguation.chars() {
+            match c {
+                'a'..='h' => from_file = File::new(c as usize - 'a' as usize),
+                '1'..='8' => from_rank = Rank::new(c as usize - '1' as usize),
+                _ => return Err(invalid()),
+            }
+        }
+
+        let board = &move_generator.board;
+        let mut candidates = moves.into_iter().filter(|mv| {
+            let mv_promotion = match mv.flag {
+                Flag::PromoteTo(piece) | Flag::CaptureWithPromotion(piece) => Some(piece),
+                _ => None,
+            };
+            board.squares[mv.starting_square] == Some(piece)
+                && mv.target_square == target_square
+                && !matches!(mv.flag, Flag::KingsideCastle | Flag::QueensideCastle)
+                && mv_promotion == promotion_piece
+                && from_file.is_none_or(|file| File::of(mv.starting_square) == file)
+                && from_rank.is_none_or(|rank| Rank::of(mv.starting_square) == rank)
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(mv), None) => Ok(mv),
+            (Some(_), Some(_)) => bail!("Ambiguous SAN move: {san}"),
+            (None, _) => bail!("Not a legal move"),
+        }
+    }
+
+    // The move in standard algebraic notation, in the position of the move generator
+    pub fn to_san(&self, move_generator: &mut MoveGenerator) -> String {
+        if self.flag == Flag::NullMove {
+            return String::from("--");
+        }
+        let mut san = match self.flag {
+            Flag::KingsideCastle => String::from("O-O"),
+            Flag::QueensideCastle => String::from("O-O-O"),
+            _ => {
+                let piece = move_generator.board.squares[self.starting_square].unwrap();
+                let is_capture = self.is_capture();
+                let starting_square = Square::from_index(self.starting_square).to_string();
+                let target_square = Square::from_index(self.target_square).to_string();
+
+                let mut san = String::new();
+                if piece == Piece::Pawn {
+                    if is_capture {
+                        san.push_str(&starting_square[..1]);
+                    }
+                } else {
+                    san.push(piece.to_symbol(Color::White));
+                    // Other pieces of the same kind that can go to the same square
+                    let moves = move_generator.generate_moves();
+                    let board = &move_generator.board;
+                    let rivals: Vec<usize> = moves
+                        .into_iter()
+                        .filter(|mv| {
+                            mv.target_square == self.target_square
+                                && mv.starting_square != self.starting_square
+                                && board.squares[mv.starting_square] == Some(piece)
+                        })
+                        .map(|mv| mv.starting_square)
+                        .collect();
+                    let file = File::of(self.starting_square);
+                    let rank = Rank::of(self.starting_square);
+                    if rivals.iter().all(|&rival| File::of(rival) != file) {
+                        if !rivals.is_empty() {
+                            san.push_str(&starting_square[..1]);
+                        }
+                    } else if rivals.iter().all(|&rival| Rank::of(rival) != rank) {
+                        san.push_str(&starting_square[1..]);
+                    } else {
+                        san.push_str(&starting_square);
+                    }
+                }
+                if is_capture {
+                    san.push('x');
+                }
+                san.push_str(&target_square);
+                if let Flag::PromoteTo(piece) | Flag::CaptureWithPromotion(piece) = self.flag {
+                    san.push('=');
+                    san.push(piece.to_symbol(Color::White));
+                }
+
+                san
+            }
+        };
+
+        let previous_state = move_generator.board.make_move(self);
+        let to_move = move_generator.board.to_move;
+        if move_generator.is_in_check(to_move) {
+            if !move_generator.has_legal_moves() {
+                san.push('#');
+            } else {
+                san.push('+');
+            }
+        }
+        move_generator
+            .board
+            .unmake_move(self, previous_state)
+            .unwrap();
+
+        san
+    }
+
+    // Pieces are always uppercase in SAN, and pawns have no letter
+    fn san_piece(symbol: &str) -> Option<Piece> {
+        let mut chars = symbol.chars();
+        match (chars.next().and_then(Piece::from_symbol), chars.next()) {
+            (Some((piece, Color::White)), None) if piece != Piece::Pawn => Some(piece),
+            _ => None,
+        }
+    }
+
+    fn find_move(
+        moves: MoveList,
+        starting_square: usize,
+        target_square: usize,
+        promotion_piece: Option<Piece>,
+    ) -> Result<Self> {
         match promotion_piece {
             None => moves
                 .into_iter()
@@ -62,9 +269,7 @@ impl Move {
                         && mv.target_square == target_square
                         && match mv.flag {
                             Flag::PromoteTo(piece) if piece == promotion_piece => true,
-                            Flag::CaptureWithPromotion(_, piece) if piece == promotion_piece => {
-                                true
-                            }
+                            Flag::CaptureWithPromotion(piece) if piece == promotion_piece => true,
                             _ => false,
                         }
                 })
@@ -90,15 +295,17 @@ impl fmt::Debug for Move {
 
 impl fmt::Display for Move {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.flag == Flag::NullMove {
+            return write!(f, "0000");
+        }
         let mut output = format!(
-            "{:?}{:?}",
+            "{}{}",
             Square::from_index(self.starting_square),
             Square::from_index(self.target_square),
-        )
-        .to_lowercase();
+        );
 
         match self.flag {
-            Flag::PromoteTo(piece) | Flag::CaptureWithPromotion(_, piece) => {
+            Flag::PromoteTo(piece) | Flag::CaptureWithPromotion(piece) => {
                 // Color::Black to get lowercase
                 output.push_str(piece.to_symbol(Color::Black).to_string().as_str())
             }
@@ -110,6 +317,7 @@ impl fmt::Display for Move {
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Flag {
     None,
     KingsideCastle,
@@ -117,14 +325,160 @@ pub enum Flag {
     PawnDoublePush,
     EnPassantCapture,
     PromoteTo(Piece),
-    Capture(Piece),
-    // captured piece, promotion piece
-    CaptureWithPromotion(Piece, Piece),
+    // What was captured is kept in the board's state, so it can be put back
+    Capture,
+    // promotion piece
+    CaptureWithPromotion(Piece),
+    NullMove,
+}
+
+// The squares along every ray from every square, and the squares between and through every pair
+// of squares that share a rank, file or diagonal. Built on first use.
+struct RayTables {
+    rays: Vec<[Vec<usize>; 8]>,
+    between: Vec<Vec<usize>>,
+    lines: Vec<Vec<usize>>,
+}
+
+static RAY_TABLES: OnceLock<RayTables> = OnceLock::new();
+
+impl RayTables {
+    fn get() -> &'static Self {
+        RAY_TABLES.get_or_init(Self::new)
+    }
+
+    fn new() -> Self {
+        let rays: Vec<[Vec<usize>; 8]> = (0..64)
+            .map(|square| {
+                Direction::RAYS.map(|direction| {
+                    std::iter::successors(direction.from(square), |&next| direction.from(next))
+                        .collect()
+                })
+            })
+            .collect();
+
+        let mut between = vec![Vec::new(); 64 * 64];
+        let mut lines = vec![Vec::new(); 64 * 64];
+        for a in 0..64 {
+            for b in 0..64 {
+                let Some(direction) = direction_between(a, b) else {
+                    continue;
+                };
+                let ray = &rays[a][direction as usize];
+                between[a * 64 + b] = ray
+                    .iter()
+                    .copied()
+                    .take_while(|&square| square != b)
+                    .collect();
+
+                let mut line: Vec<usize> = rays[a][direction.opposite() as usize].clone();
+                line.push(a);
+                line.extend(ray);
+                line.sort_unstable();
+                lines[a * 64 + b] = line;
+            }
+        }
+
+        Self {
+            rays,
+            between,
+            lines,
+        }
+    }
+}
+
+// The ray a sliding piece would move along to get from one square to the other, if any
+pub fn direction_between(from: usize, to: usize) -> Option<Direction> {
+    let file_difference = File::of(to).index() as isize - File::of(from).index() as isize;
+    let rank_difference = Rank::of(to).index() as isize - Rank::of(from).index() as isize;
+    if from == to
+        || (file_difference != 0
+            && rank_difference != 0
+            && file_difference.abs() != rank_difference.abs())
+    {
+        return None;
+    }
+
+    let steps = (file_difference.signum(), rank_difference.signum());
+    Direction::RAYS
+        .into_iter()
+        .find(|direction| direction.file_and_rank_steps() == steps)
+}
+
+// The squares from the given one to the edge of the board, nearest first. Knights don't slide, so
+// there are none along their jumps.
+pub fn ray(square: usize, direction: Direction) -> &'static [usize] {
+    RayTables::get()
+        .rays
+        .get(square)
+        .and_then(|rays| rays.get(direction as usize))
+        .map_or(&[], Vec::as_slice)
+}
+
+// The squares strictly between two squares on a shared rank, file or diagonal, nearest to `from`
+// first. Empty when the squares are neighbours or not in line, e.g. a knight's jump apart.
+pub fn between(from: usize, to: usize) -> &'static [usize] {
+    &RayTables::get().between[from * 64 + to]
+}
+
+// The whole rank, file or diagonal through two squares, from edge to edge in square order. Empty
+// when the squares are not in line.
+pub fn line(a: usize, b: usize) -> &'static [usize] {
+    &RayTables::get().lines[a * 64 + b]
+}
+
+// A piece that can't leave the line between its king and an enemy rook, bishop or queen without
+// exposing the king
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pin {
+    pub pinned: usize,
+    pub pinner: usize,
+    // The squares between the king and the pinner and the pinner's own, the only ones the pinned
+    // piece can still move to
+    pub ray: Vec<usize>,
+}
+
+// The legal moves, generated a square at a time as they are asked for. Made by
+// MoveGenerator::iter_moves.
+pub struct MoveIter<'a> {
+    move_generator: &'a mut MoveGenerator,
+    checkers: Vec<usize>,
+    pins: Vec<Pin>,
+    next_square: usize,
+    moves_from_square: move_list::IntoIter,
+}
+
+impl Iterator for MoveIter<'_> {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        loop {
+            if let Some(mv) = self.moves_from_square.next() {
+                return Some(mv);
+            }
+            let square = self.next_square;
+            if square == 64 {
+                return None;
+            }
+            self.next_square += 1;
+
+            let board = &self.move_generator.board;
+            if board.colors[square] != Some(board.to_move) {
+                continue;
+            }
+            let mut moves = MoveList::new();
+            self.move_generator
+                .generate_pseudo_legal_moves_from(&mut moves, square);
+            self.move_generator
+                .filter_legal_moves(&mut moves, &self.checkers, &self.pins);
+            self.moves_from_square = moves.into_iter();
+        }
+    }
 }
 
 pub struct MoveGenerator {
+    // For every square, how far each of the eight rays goes before reaching the edge
     num_squares_to_edge: [[usize; 8]; 64],
-    direction_offsets: [isize; 8],
     pub board: Board,
 }
 
@@ -137,89 +491,181 @@ impl Default for MoveGenerator {
 impl MoveGenerator {
     pub fn new(board: Board) -> Self {
         Self {
-            direction_offsets: [8, -8, -1, 1, 7, -7, 9, -9],
             num_squares_to_edge: Self::precompute_move_data(),
             board,
         }
     }
 
-    pub fn generate_moves(&mut self) -> Vec<Move> {
-        let mut legal_moves = Vec::new();
-        let pseudo_legal_moves = self.generate_pseudo_legal_moves();
-        let to_move = self.board.to_move;
+    pub fn generate_moves(&mut self) -> MoveList {
+        let mut moves = MoveList::new();
+        self.generate_moves_into(&mut moves);
+        moves
+    }
 
-        for mv in pseudo_legal_moves {
-            // If castling path is not clear, can't castle
-            if (mv.flag == Flag::KingsideCastle || mv.flag == Flag::QueensideCastle)
-                && !self.is_castling_path_clear(&mv)
-            {
-                continue;
-            }
+    // Replaces whatever the list held with the legal moves, so that a caller generating moves
+    // over and over, like the search at each ply, can keep reusing the same list
+    pub fn generate_moves_into(&mut self, moves: &mut MoveList) {
+        moves.clear();
+        self.generate_pseudo_legal_moves(moves);
+        self.legal_moves(moves);
+    }
+
+    // The same moves as generate_moves in the same order, for callers that may not need all of
+    // them, like checking whether there is any legal move at all
+    pub fn iter_moves(&mut self) -> MoveIter<'_> {
+        let checkers = self.checkers();
+        let pins = self.pinned_pieces(self.board.to_move);
+        MoveIter {
+            move_generator: self,
+            checkers,
+            pins,
+            next_square: 0,
+            moves_from_square: MoveList::new().into_iter(),
+        }
+    }
 
-            self.board.move_piece(&mv);
+    pub fn has_legal_moves(&mut self) -> bool {
+        self.iter_moves().next().is_some()
+    }
 
-            let in_check_after_move = self.is_in_check(to_move);
+    // Only the legal captures, en passant and capturing promotions included, e.g. for the
+    // quiescence search. Quiet moves are left out before their legality is checked.
+    pub fn generate_capture_moves(&mut self) -> MoveList {
+        let mut moves = MoveList::new();
+        self.generate_capture_moves_into(&mut moves);
+        moves
+    }
 
-            self.board.unmake_move(&mv).unwrap();
+    pub fn generate_capture_moves_into(&mut self, moves: &mut MoveList) {
+        moves.clear();
+        self.generate_pseudo_legal_moves(moves);
+        moves.retain(Move::is_capture);
+        self.legal_moves(moves);
+    }
 
-            if !in_check_after_move {
-                legal_moves.push(mv);
-            }
+    // The legal moves of the piece on the square, none if it isn't the side to move's
+    pub fn generate_moves_from(&mut self, square: usize) -> MoveList {
+        let mut moves = MoveList::new();
+        if self.board.colors[square] == Some(self.board.to_move) {
+            self.generate_pseudo_legal_moves_from(&mut moves, square);
+        }
+        self.legal_moves(&mut moves);
+        moves
+    }
+
+    // Whether the move is one of the legal moves, without generating all of them. Meant for moves
+    // from elsewhere, like the transposition table or user input, that may not fit the position.
+    pub fn is_legal(&mut self, mv: &Move) -> bool {
+        if self.board.colors[mv.starting_square] != Some(self.board.to_move) {
+            return false;
+        }
+        let mut pseudo_legal_moves = MoveList::new();
+        self.generate_pseudo_legal_moves_from(&mut pseudo_legal_moves, mv.starting_square);
+        if !pseudo_legal_moves.contains(mv) {
+            return false;
         }
 
-        legal_moves
+        let checkers = self.checkers();
+        let pins = self.pinned_pieces(self.board.to_move);
+        self.is_pseudo_legal_move_legal(mv, &checkers, &pins)
     }
 
-    fn generate_pseudo_legal_moves(&mut self) -> Vec<Move> {
-        let mut moves: Vec<Move> = Vec::new();
+    // Drops the pseudo legal moves that would leave the king in check
+    fn legal_moves(&mut self, moves: &mut MoveList) {
+        let checkers = self.checkers();
+        let pins = self.pinned_pieces(self.board.to_move);
+        self.filter_legal_moves(moves, &checkers, &pins);
+    }
 
+    // The checkers and pins are the side to move's, passed in so that they can be worked out once
+    // for several batches of moves
+    fn filter_legal_moves(&mut self, moves: &mut MoveList, checkers: &[usize], pins: &[Pin]) {
+        moves.retain(|mv| self.is_pseudo_legal_move_legal(mv, checkers, pins));
+    }
+
+    fn is_pseudo_legal_move_legal(&mut self, mv: &Move, checkers: &[usize], pins: &[Pin]) -> bool {
+        let to_move = self.board.to_move;
+        let is_castling = mv.flag == Flag::KingsideCastle || mv.flag == Flag::QueensideCastle;
+        // If castling path is not clear, can't castle
+        if is_castling && !self.is_castling_path_clear(mv) {
+            return false;
+        }
+
+        let is_king_move = self.board.squares[mv.starting_square] == Some(Piece::King);
+        // Neither blocking nor capturing can stop two checkers at once
+        if checkers.len() > 1 && !is_king_move {
+            return false;
+        }
+
+        // The king can't step back along the ray of a slider checking it, so it doesn't block
+        // the attacks on where it goes
+        if is_king_move && !is_castling {
+            return !self.is_square_attacked_ignoring(
+                mv.target_square,
+                to_move.opposite_color(),
+                Some(mv.starting_square),
+            );
+        }
+
+        // Out of check, only king moves, moves of pinned pieces and en passant captures, which
+        // take two pieces off the rank at once, can leave the king attacked
+        let can_expose_king = !checkers.is_empty()
+            || mv.flag == Flag::EnPassantCapture
+            || is_king_move
+            || pins.iter().any(|pin| pin.pinned == mv.starting_square);
+        if !can_expose_king {
+            return true;
+        }
+
+        let previous_state = self.board.make_move(mv);
+
+        let in_check_after_move = self.is_in_check(to_move);
+
+        self.board.unmake_move(mv, previous_state).unwrap();
+
+        !in_check_after_move
+    }
+
+    fn generate_pseudo_legal_moves(&mut self, moves: &mut MoveList) {
         for square in 0..64 {
-            let piece = self.board.squares[square];
-            let color = self.board.colors[square];
-            match color {
-                None => continue,
-                Some(color) if color != self.board.to_move => continue,
-                _ => (),
+            if self.board.colors[square] == Some(self.board.to_move) {
+                self.generate_pseudo_legal_moves_from(moves, square);
             }
+        }
+    }
 
-            let piece = piece.expect("Piece should not be None if color exists");
-            match piece {
-                Piece::Queen | Piece::Rook | Piece::Bishop => {
-                    self.generate_sliding_moves(&mut moves, square)
-                }
-                Piece::Knight => self.generate_knight_moves(&mut moves, square),
-                Piece::Pawn => self.generate_pawn_moves(&mut moves, square),
-                Piece::King => self.generate_king_moves(&mut moves, square),
+    fn generate_pseudo_legal_moves_from(&mut self, moves: &mut MoveList, square: usize) {
+        let piece = self.board.squares[square].expect("Piece should not be None if color exists");
+        match piece {
+            Piece::Queen | Piece::Rook | Piece::Bishop => {
+                self.generate_sliding_moves(moves, square)
             }
+            Piece::Knight => self.generate_knight_moves(moves, square),
+            Piece::Pawn => self.generate_pawn_moves(moves, square),
+            Piece::King => self.generate_king_moves(moves, square),
         }
-
-        moves
     }
 
-    fn generate_sliding_moves(&mut self, moves: &mut Vec<Move>, start_square: usize) {
+    fn generate_sliding_moves(&mut self, moves: &mut MoveList, start_square: usize) {
         let piece = self.board.squares[start_square]
             .expect("should not be generating sliding moves from an empty square");
 
-        let start_direction_index = if piece == Piece::Bishop { 4 } else { 0 };
-        let end_direction_index = if piece == Piece::Rook { 4 } else { 8 };
+        let directions: &[Direction] = match piece {
+            Piece::Bishop => &Direction::DIAGONALS,
+            Piece::Rook => &Direction::ORTHOGONALS,
+            _ => &Direction::RAYS,
+        };
 
-        for direction_index in start_direction_index..end_direction_index {
-            for n in 0..self.num_squares_to_edge[start_square][direction_index] {
-                let target_square = start_square as isize
-                    + self.direction_offsets[direction_index] * (n as isize + 1);
+        for &direction in directions {
+            for n in 0..self.num_squares_to_edge[start_square][direction as usize] {
+                let target_square = start_square as isize + direction.offset() * (n as isize + 1);
                 let target_square = target_square as usize;
                 let color_on_target_square = self.board.colors[target_square];
 
                 match color_on_target_square {
                     Some(color) => {
                         if color != self.board.to_move {
-                            let captured_piece = self.board.squares[target_square]
-                                .expect("piece should not be None if color exists");
-                            moves.push(Move::new(
-                                start_square,
-                                target_square,
-                                Flag::Capture(captured_piece),
-                            ));
+                            moves.push(Move::new(start_square, target_square, Flag::Capture));
                         }
                         // Blocked by friendly piece, cannot go on further.
                         break;
@@ -233,70 +679,42 @@ impl MoveGenerator {
         }
     }
 
-    fn generate_knight_moves(&mut self, moves: &mut Vec<Move>, start_square: usize) {
-        let knight_move_offsets = [-17, -15, -10, -6, 6, 10, 15, 17];
-
-        for offset in knight_move_offsets {
-            let target_square = {
-                let tmp = start_square as isize + offset;
-                if !(0..64).contains(&tmp) {
-                    continue;
-                }
-                tmp as usize
-            };
-
-            if Self::is_pacman_move(start_square, target_square) {
+    fn generate_knight_moves(&mut self, moves: &mut MoveList, start_square: usize) {
+        for direction in Direction::KNIGHT_JUMPS {
+            let Some(target_square) = direction.from(start_square) else {
                 continue;
-            }
+            };
 
             match self.board.colors[target_square] {
                 None => moves.push(Move::new(start_square, target_square, Flag::None)),
                 Some(color) if color != self.board.to_move => {
-                    let captured_piece = self.board.squares[target_square]
-                        .expect("piece should not be None if color exists");
-                    moves.push(Move::new(
-                        start_square,
-                        target_square,
-                        Flag::Capture(captured_piece),
-                    ))
+                    moves.push(Move::new(start_square, target_square, Flag::Capture))
                 }
                 _ => continue,
             }
         }
     }
 
-    fn generate_pawn_moves(&mut self, moves: &mut Vec<Move>, start_square: usize) {
-        let pawn_move_offsets = match self.board.to_move {
-            Color::White => [8, 16, 7, 9],
-            Color::Black => [-8, -16, -7, -9],
-        };
+    fn generate_pawn_moves(&mut self, moves: &mut MoveList, start_square: usize) {
+        let forward = Direction::forward(self.board.to_move).offset();
 
-        let target_one_up_index = start_square as isize + pawn_move_offsets[0];
-        let target_one_up_rank = target_one_up_index / 8;
-        let can_move_up_one_rank = self.board.squares[target_one_up_index as usize].is_none();
+        let target_one_up_index = (start_square as isize + forward) as usize;
+        let can_move_up_one_rank = self.board.squares[target_one_up_index].is_none();
+        let is_promotion_rank = |square| matches!(Rank::of(square), Rank::FIRST | Rank::EIGHTH);
 
         if can_move_up_one_rank {
-            let target_one_up_index = target_one_up_index as usize;
-            let is_promotion_move = target_one_up_rank == 0 || target_one_up_rank == 7;
+            let is_promotion_move = is_promotion_rank(target_one_up_index);
             if !is_promotion_move {
                 moves.push(Move::new(start_square, target_one_up_index, Flag::None));
             } else {
-                self.add_promotion_moves(moves, start_square, target_one_up_index, None);
+                self.add_promotion_moves(moves, start_square, target_one_up_index, false);
             }
         }
 
-        for capture_offset in &pawn_move_offsets[2..] {
-            let target_square = {
-                let tmp = start_square as isize + capture_offset;
-                if !(0..64).contains(&tmp) {
-                    continue;
-                }
-                tmp as usize
-            };
-
-            if Self::is_pacman_move(start_square, target_square) {
+        for direction in Direction::pawn_captures(self.board.to_move) {
+            let Some(target_square) = direction.from(start_square) else {
                 continue;
-            }
+            };
 
             let is_occupied_by_opponent_piece =
                 self.board.colors[target_square].is_some_and(|color| color != self.board.to_move);
@@ -307,22 +725,12 @@ impl MoveGenerator {
                 .is_some_and(|index| index == target_square);
 
             if is_occupied_by_opponent_piece || can_capture_en_passant {
-                let target_rank = target_square / 8;
-                let is_promotion_move = target_rank == 0 || target_rank == 7;
-
-                if is_promotion_move {
-                    let captured_piece = self.board.squares[target_square];
-                    self.add_promotion_moves(moves, start_square, target_square, captured_piece);
+                if is_promotion_rank(target_square) {
+                    self.add_promotion_moves(moves, start_square, target_square, true);
                 } else if can_capture_en_passant {
                     moves.push(Move::new(start_square, target_square, Flag::EnPassantCapture));
                 } else {
-                    let captured_piece = self.board.squares[target_square]
-                        .expect("piece should not be None if color exists");
-                    moves.push(Move::new(
-                        start_square,
-                        target_square,
-                        Flag::Capture(captured_piece),
-                    ));
+                    moves.push(Move::new(start_square, target_square, Flag::Capture));
                 }
             }
         }
@@ -333,14 +741,12 @@ impl MoveGenerator {
         }
 
         // If pawn already moved, it cannot move up by two
-        let starting_rank = start_square / 8;
-        let has_moved = (starting_rank != 1 && self.board.to_move == Color::White)
-            || (starting_rank != 6 && self.board.to_move == Color::Black);
+        let has_moved = Rank::of(start_square).relative_to(self.board.to_move) != Rank::SECOND;
         if has_moved {
             return;
         }
 
-        let target_two_up_index = start_square as isize + pawn_move_offsets[1];
+        let target_two_up_index = start_square as isize + forward * 2;
         if self.board.squares[target_two_up_index as usize].is_none() {
             moves.push(Move::new(
                 start_square,
@@ -350,104 +756,94 @@ impl MoveGenerator {
         }
     }
 
-    fn generate_king_moves(&mut self, moves: &mut Vec<Move>, start_square: usize) {
-        for offset in self.direction_offsets {
-            let target_square = {
-                let tmp = start_square as isize + offset;
-                if !(0..64).contains(&tmp) {
-                    continue;
-                }
-                tmp as usize
-            };
-
-            if Self::is_pacman_move(start_square, target_square) {
+    fn generate_king_moves(&mut self, moves: &mut MoveList, start_square: usize) {
+        for direction in Direction::RAYS {
+            let Some(target_square) = direction.from(start_square) else {
                 continue;
-            }
+            };
 
             if self.board.colors[target_square].is_none() {
                 moves.push(Move::new(start_square, target_square, Flag::None));
             } else if self.board.colors[target_square]
                 .is_some_and(|color| color != self.board.colors[start_square].unwrap())
             {
-                let captured_piece = self.board.squares[target_square]
-                    .expect("piece should not be None if color exists");
-                moves.push(Move::new(start_square, target_square, Flag::Capture(captured_piece)));
+                moves.push(Move::new(start_square, target_square, Flag::Capture));
             }
         }
 
-        // TODO: Refactor this
-        match self.board.to_move {
-            Color::White => {
-                let kingside_castling_path_clear = self.board.squares[Square::F1.as_index()]
-                    .is_none()
-                    && self.board.squares[Square::G1.as_index()].is_none();
-                if self.board.board_state.white_kingside_castling_priviledge
-                    && kingside_castling_path_clear
-                {
-                    moves.push(Move::new(
-                        start_square,
-                        Square::G1.as_index(),
-                        Flag::KingsideCastle,
-                    ));
-                }
-
-                let queenside_castling_path_clear = self.board.squares[Square::D1.as_index()]
-                    .is_none()
-                    && self.board.squares[Square::C1.as_index()].is_none()
-                    && self.board.squares[Square::B1.as_index()].is_none();
-                if self.board.board_state.white_queenside_castling_priviledge
-                    && queenside_castling_path_clear
-                {
-                    moves.push(Move::new(
-                        start_square,
-                        Square::C1.as_index(),
-                        Flag::QueensideCastle,
-                    ))
-                }
+        let castling_priviledges = match self.board.to_move {
+            Color::White => [
+                (
+                    Flag::KingsideCastle,
+                    self.board.board_state.white_kingside_castling_priviledge,
+                ),
+                (
+                    Flag::QueensideCastle,
+                    self.board.board_state.white_queenside_castling_priviledge,
+                ),
+            ],
+            Color::Black => [
+                (
+                    Flag::KingsideCastle,
+                    self.board.board_state.black_kingside_castling_priviledge,
+                ),
+                (
+                    Flag::QueensideCastle,
+                    self.board.board_state.black_queenside_castling_priviledge,
+                ),
+            ],
+        };
+        for (flag, can_castle) in castling_priviledges {
+            if !can_castle {
+                continue;
             }
-            Color::Black => {
-                let kingside_castling_path_clear = self.board.squares[Square::F8.as_index()]
-                    .is_none()
-                    && self.board.squares[Square::G8.as_index()].is_none();
-                if self.board.board_state.black_kingside_castling_priviledge
-                    && kingside_castling_path_clear
-                {
-                    moves.push(Move::new(
-                        start_square,
-                        Square::G8.as_index(),
-                        Flag::KingsideCastle,
-                    ));
-                }
 
-                let queenside_castling_path_clear = self.board.squares[Square::D8.as_index()]
-                    .is_none()
-                    && self.board.squares[Square::C8.as_index()].is_none()
-                    && self.board.squares[Square::B8.as_index()].is_none();
-                if self.board.board_state.black_queenside_castling_priviledge
-                    && queenside_castling_path_clear
-                {
-                    moves.push(Move::new(
-                        start_square,
-                        Square::C8.as_index(),
-                        Flag::QueensideCastle,
-                    ))
-                }
+            let castling_move = Move::new(start_square, start_square, flag.clone());
+            if let Some(king_target_square) = self.castling_king_target(&castling_move) {
+                moves.push(Move::new(start_square, king_target_square, flag));
             }
         }
     }
 
-    fn precompute_move_data() -> [[usize; 8]; 64] {
-        let mut num_squares_to_edge = [[0; 8]; 64];
-        for file in 0..8 {
-            for rank in 0..8 {
-                let num_north = 7 - rank;
-                let num_south = rank;
-                let num_east = 7 - file;
-                let num_west = file;
+    // In Chess960 the king and rook can start anywhere on the back rank, so every square
+    // between the furthest squares either of them touches has to be empty, except for the
+    // castling king and rook themselves. Returns where the king ends up if castling is possible.
+    fn castling_king_target(&self, castling_move: &Move) -> Option<usize> {
+        let king_square = castling_move.starting_square;
+        let rook_square = self.board.castling_rook_square(castling_move);
+        let is_own_rook = self.board.squares[rook_square] == Some(Piece::Rook)
+            && self.board.colors[rook_square] == Some(self.board.to_move);
+        if !is_own_rook {
+            return None;
+        }
 
-                let square_index = rank * 8 + file;
+        let (king_target_square, rook_target_square) =
+            Board::castling_target_squares(castling_move);
+        let squares = [
+            king_square,
+            rook_square,
+            king_target_square,
+            rook_target_square,
+        ];
+        let lowest = *squares.iter().min().unwrap();
+        let highest = *squares.iter().max().unwrap();
+        let path_is_empty = (lowest..=highest).all(|square| {
+            square == king_square || square == rook_square || self.board.squares[square].is_none()
+        });
 
-                num_squares_to_edge[square_index] = [
+        path_is_empty.then_some(king_target_square)
+    }
+
+    fn precompute_move_data() -> [[usize; 8]; 64] {
+        let mut num_squares_to_edge = [[0; 8]; 64];
+        for file in File::all() {
+            for rank in Rank::all() {
+                let num_north = rank.distance(Rank::EIGHTH);
+                let num_south = rank.distance(Rank::FIRST);
+                let num_east = file.distance(File::H);
+                let num_west = file.distance(File::A);
+
+                num_squares_to_edge[square_index(file, rank)] = [
                     num_north,
                     num_south,
                     num_west,
@@ -465,197 +861,178 @@ impl MoveGenerator {
 
     fn add_promotion_moves(
         &mut self,
-        moves: &mut Vec<Move>,
+        moves: &mut MoveList,
         start: usize,
         target: usize,
-        captured_piece: Option<Piece>,
+        is_capture: bool,
     ) {
-        match captured_piece {
-            None => {
-                moves.push(Move::new(start, target, Flag::PromoteTo(Piece::Queen)));
-                moves.push(Move::new(start, target, Flag::PromoteTo(Piece::Rook)));
-                moves.push(Move::new(start, target, Flag::PromoteTo(Piece::Bishop)));
-                moves.push(Move::new(start, target, Flag::PromoteTo(Piece::Knight)));
-            }
-            Some(piece) => {
-                moves.push(Move::new(
-                    start,
-                    target,
-                    Flag::CaptureWithPromotion(piece, Piece::Queen),
-                ));
-                moves.push(Move::new(
-                    start,
-                    target,
-                    Flag::CaptureWithPromotion(piece, Piece::Rook),
-                ));
-                moves.push(Move::new(
-                    start,
-                    target,
-                    Flag::CaptureWithPromotion(piece, Piece::Bishop),
-                ));
-                moves.push(Move::new(
-                    start,
-                    target,
-                    Flag::CaptureWithPromotion(piece, Piece::Knight),
-                ));
-            }
+        for piece in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight] {
+            let flag = if is_capture {
+                Flag::CaptureWithPromotion(piece)
+            } else {
+                Flag::PromoteTo(piece)
+            };
+            moves.push(Move::new(start, target, flag));
         }
     }
 
-    pub fn is_pacman_move(start: usize, target: usize) -> bool {
-        let starting_rank = start as isize / 8;
-        let starting_file = start as isize % 8;
-        let target_rank = target as isize / 8;
-        let target_file = target as isize % 8;
+    pub fn is_in_check(&mut self, color_to_check: Color) -> bool {
+        let king_square = self.king_square(color_to_check);
 
-        // Prevents pieces from teleporting from one side to another Pacman-style
-        // Two ranks or columns is the most a non-sliding piece can legally move
-        (target_rank - starting_rank).abs() > 2 || (target_file - starting_file).abs() > 2
+        self.is_square_attacked(king_square, color_to_check.opposite_color())
     }
 
-    pub fn is_in_check(&mut self, color_to_check: Color) -> bool {
-        let king_square = (0..64)
-            .find(|&square| {
-                self.board.colors[square].is_some()
-                    && self.board.squares[square].is_some()
-                    && self.board.colors[square].unwrap() == color_to_check
-                    && self.board.squares[square].unwrap() == Piece::King
-            })
-            .expect("could not find the king");
-
-        let to_move = color_to_check.opposite_color();
-        if self.is_checked_by_knight(king_square, to_move)
-            || self.is_checked_by_pawn(king_square, to_move)
-            || self.is_checked_by_sliding_piece(king_square, to_move)
-            || self.is_checked_by_enemy_king(king_square)
-        {
-            return true;
-        }
-        false
+    // The squares of the pieces giving check to the side to move, two of them in a double check
+    pub fn checkers(&self) -> Vec<usize> {
+        let to_move = self.board.to_move;
+        self.attackers_to(self.king_square(to_move), to_move.opposite_color())
     }
 
-    fn is_checked_by_enemy_king(&self, king_square: usize) -> bool {
-        for offset in self.direction_offsets {
-            let potential_enemy_king_square = {
-                let tmp = king_square as isize + offset;
-                if !(0..64).contains(&tmp) {
-                    continue;
-                }
-                tmp as usize
-            };
+    fn king_square(&self, color: Color) -> usize {
+        self.board
+            .king_square(color)
+            .expect("could not find the king")
+    }
 
-            if Self::is_pacman_move(king_square, potential_enemy_king_square) {
-                continue;
-            }
+    // The pieces of the given color that are pinned to their king, e.g. to explain why a move
+    // is illegal. Pieces pinned to the queen or another piece are free to move.
+    pub fn pinned_pieces(&self, color: Color) -> Vec<Pin> {
+        let king_square = self.king_square(color);
+        let mut pins = Vec::new();
 
-            if let Some(Piece::King) = self.board.squares[potential_enemy_king_square] {
-                return true;
+        for direction in Direction::RAYS {
+            let mut pieces = ray(king_square, direction)
+                .iter()
+                .copied()
+                .filter(|&square| self.board.squares[square].is_some());
+            let (Some(pinned), Some(pinner)) = (pieces.next(), pieces.next()) else {
+                continue;
+            };
+            let slider = if direction.is_orthogonal() {
+                Piece::Rook
+            } else {
+                Piece::Bishop
+            };
+            let is_pinner = self.board.colors[pinner] == Some(color.opposite_color())
+                && matches!(self.board.squares[pinner], Some(piece) if piece == slider || piece == Piece::Queen);
+            if self.board.colors[pinned] == Some(color) && is_pinner {
+                let mut ray = between(king_square, pinner).to_vec();
+                ray.push(pinner);
+                pins.push(Pin {
+                    pinned,
+                    pinner,
+                    ray,
+                });
             }
         }
 
-        false
+        pins
     }
 
-    fn is_checked_by_sliding_piece(&self, king_square: usize, to_move: Color) -> bool {
-        let color_to_check = to_move.opposite_color();
+    // The squares of the pieces of the given color that attack the square, whatever is on it. A
+    // piece defending one of its own counts as attacking it, and pinned pieces count too.
+    pub fn attackers_to(&self, square: usize, by_color: Color) -> Vec<usize> {
+        self.attackers_to_ignoring(square, by_color, None)
+    }
 
-        for direction_index in 0..8 {
-            for n in 0..self.num_squares_to_edge[king_square][direction_index] {
-                let direction_offset = self.direction_offsets[direction_index];
-                let potential_sliding_piece_square = (king_square as isize
-                    + self.direction_offsets[direction_index] * (n as isize + 1))
-                    as usize;
+    // The squares the side not to move attacks, where the side to move's king can't go
+    pub fn calculate_opponent_attack_map(&self) -> [bool; 64] {
+        self.attack_map(self.board.to_move.opposite_color(), false)
+    }
 
-                if self.board.colors[potential_sliding_piece_square].is_none() {
-                    continue;
-                }
-                // If blocked by friendly piece, just stop looking in this irection
-                if self.board.colors[potential_sliding_piece_square] == Some(color_to_check) {
-                    break;
-                }
-                if self.board.colors[potential_sliding_piece_square] == Some(to_move) {
-                    match self.board.squares[potential_sliding_piece_square].unwrap() {
-                        Piece::King | Piece::Pawn | Piece::Knight => break,
-                        Piece::Rook => {
-                            if direction_offset == 8
-                                || direction_offset == -8
-                                || direction_offset == 1
-                                || direction_offset == -1
-                            {
-                                return true;
-                            } else {
-                                break;
-                            }
-                        }
-                        Piece::Bishop => {
-                            if direction_offset == 7
-                                || direction_offset == -7
-                                || direction_offset == 9
-                                || direction_offset == -9
-                            {
-                                return true;
-                            } else {
-                                break;
-                            }
-                        }
-                        Piece::Queen => {
-                            return true;
-                        }
-                    }
-                }
-            }
+    // Every square the given color attacks. When seeing through the king, the other side's king
+    // doesn't block sliders, so the squares behind it on a checking ray count as attacked, as the
+    // king can't escape there.
+    pub fn attack_map(&self, by_color: Color, see_through_king: bool) -> [bool; 64] {
+        let ignored = see_through_king.then(|| self.king_square(by_color.opposite_color()));
+        let mut attack_map = [false; 64];
+        for (square, attacked) in attack_map.iter_mut().enumerate() {
+            *attacked = self.is_square_attacked_ignoring(square, by_color, ignored);
         }
 
-        false
+        attack_map
     }
 
-    fn is_checked_by_pawn(&self, king_square: usize, to_move: Color) -> bool {
-        let king_checking_pawn_squares = match to_move.opposite_color() {
-            Color::White => [7, 9],
-            Color::Black => [-7, -9],
+    // Whether any piece of the given color attacks the square, stopping at the first attacker
+    // found rather than collecting all of them
+    pub fn is_square_attacked(&self, square: usize, by_color: Color) -> bool {
+        self.is_square_attacked_ignoring(square, by_color, None)
+    }
+
+    fn is_square_attacked_ignoring(
+        &self,
+        square: usize,
+        by_color: Color,
+        ignored: Option<usize>,
+    ) -> bool {
+        self.find_attackers(square, by_color, ignored, |_| true)
+    }
+
+    // Like attackers_to with the ignored square taken to be empty
+    fn attackers_to_ignoring(
+        &self,
+        square: usize,
+        by_color: Color,
+        ignored: Option<usize>,
+    ) -> Vec<usize> {
+        let mut attackers = Vec::new();
+        self.find_attackers(square, by_color, ignored, |attacker_square| {
+            attackers.push(attacker_square);
+            false
+        });
+        attackers
+    }
+
+    // Hands every attacker of the square to found until it returns true, and returns whether it
+    // did. The ignored square, if any, doesn't block sliders.
+    fn find_attackers(
+        &self,
+        square: usize,
+        by_color: Color,
+        ignored: Option<usize>,
+        mut found: impl FnMut(usize) -> bool,
+    ) -> bool {
+        let is_attacker = |attacker_square: usize, piece: Piece| {
+            self.board.squares[attacker_square] == Some(piece)
+                && self.board.colors[attacker_square] == Some(by_color)
         };
 
-        for capture_offset in &king_checking_pawn_squares {
-            let potential_pawn_square = {
-                let tmp = king_square as isize + capture_offset;
-                if !(0..64).contains(&tmp) {
-                    continue;
+        // Pawns attack the square from where a pawn of the other color on it would capture
+        for direction in Direction::pawn_captures(by_color.opposite_color()) {
+            if let Some(pawn_square) = direction.from(square) {
+                if is_attacker(pawn_square, Piece::Pawn) && found(pawn_square) {
+                    return true;
                 }
-                tmp as usize
-            };
-
-            if Self::is_pacman_move(potential_pawn_square, king_square) {
-                continue;
             }
-
-            if self.board.squares[potential_pawn_square] == Some(Piece::Pawn)
-                && self.board.colors[potential_pawn_square] == Some(to_move)
-            {
-                return true;
+        }
+        for direction in Direction::KNIGHT_JUMPS {
+            if let Some(knight_square) = direction.from(square) {
+                if is_attacker(knight_square, Piece::Knight) && found(knight_square) {
+                    return true;
+                }
             }
         }
-
-        false
-    }
-
-    fn is_checked_by_knight(&self, king_square: usize, to_move: Color) -> bool {
-        let knight_move_offsets = [-17, -15, -10, -6, 6, 10, 15, 17];
-
-        for offset in knight_move_offsets {
-            let knight_square = {
-                let tmp = king_square as isize + offset;
-                if !(0..64).contains(&tmp) {
-                    continue;
+        for direction in Direction::RAYS {
+            if let Some(king_square) = direction.from(square) {
+                if is_attacker(king_square, Piece::King) && found(king_square) {
+                    return true;
                 }
-                tmp as usize
-            };
-
-            if Self::is_pacman_move(knight_square, king_square) {
-                continue;
             }
 
-            if self.board.squares[knight_square] == Some(Piece::Knight)
-                && self.board.colors[knight_square] == Some(to_move)
+            // Only the first piece along the ray can be a sliding attacker
+            let Some(&first_piece_square) = ray(square, direction).iter().find(|&&ray_square| {
+                self.board.squares[ray_square].is_some() && Some(ray_square) != ignored
+            }) else {
+                continue;
+            };
+            let slider = if direction.is_orthogonal() {
+                Piece::Rook
+            } else {
+                Piece::Bishop
+            };
+            if (is_attacker(first_piece_square, slider)
+                || is_attacker(first_piece_square, Piece::Queen))
+                && found(first_piece_square)
             {
                 return true;
             }
@@ -664,113 +1041,28 @@ impl MoveGenerator {
         false
     }
 
-    fn calculate_opponent_attack_map(&mut self) -> [bool; 64] {
-        let mut attack_map = [false; 64];
-        let mut moves = Vec::new();
-        let original_to_move = self.board.to_move;
-        self.board.to_move = self.board.to_move.opposite_color();
-
-        for square in 0..64 {
-            if !self.board.colors[square].is_some_and(|color| color == self.board.to_move) {
-                continue;
-            }
-
-            match self.board.squares[square].unwrap() {
-                Piece::Pawn => {
-                    let pawn_move_offsets = match self.board.to_move {
-                        Color::White => [8, 16, 7, 9],
-                        Color::Black => [-8, -16, -7, -9],
-                    };
-
-                    for capture_offset in &pawn_move_offsets[2..] {
-                        let target_square = {
-                            let tmp = square as isize + capture_offset;
-                            if !(0..64).contains(&tmp) {
-                                continue;
-                            }
-                            tmp as usize
-                        };
-
-                        if Self::is_pacman_move(square, target_square) {
-                            continue;
-                        }
-
-                        attack_map[target_square] = true;
-                    }
-                }
-                Piece::Queen | Piece::Bishop | Piece::Rook => {
-                    self.generate_sliding_moves(&mut moves, square);
-                }
-                Piece::Knight => {
-                    self.generate_knight_moves(&mut moves, square);
-                }
-                Piece::King => {
-                    self.generate_king_moves(&mut moves, square);
-                }
-            }
-        }
-
-        for mv in moves {
-            attack_map[mv.target_square] = true;
-        }
-
-        self.board.to_move = original_to_move;
-        attack_map
-    }
-
-    fn is_castling_path_clear(&mut self, mv: &Move) -> bool {
-        // TODO: Fix this outright war crime
-        if mv.flag == Flag::KingsideCastle {
-            let attacked_map = self.calculate_opponent_attack_map();
-
-            if self.board.to_move == Color::White {
-                if attacked_map[Square::E1.as_index()]
-                    || attacked_map[Square::F1.as_index()]
-                    || attacked_map[Square::G1.as_index()]
-                {
-                    return false;
-                }
-            } else if attacked_map[Square::E8.as_index()]
-                || attacked_map[Square::F8.as_index()]
-                || attacked_map[Square::G8.as_index()]
-            {
-                return false;
-            }
-        } else if mv.flag == Flag::QueensideCastle {
-            let attacked_squares = self.calculate_opponent_attack_map();
-
-            if self.board.to_move == Color::White {
-                if attacked_squares[Square::E1.as_index()]
-                    || attacked_squares[Square::D1.as_index()]
-                    || attacked_squares[Square::C1.as_index()]
-                {
-                    return false;
-                }
-            } else if attacked_squares[Square::E8.as_index()]
-                || attacked_squares[Square::D8.as_index()]
-                || attacked_squares[Square::C8.as_index()]
-            {
-                return false;
-            }
-        }
+    // The king may not castle out of, through, or into check
+    fn is_castling_path_clear(&self, mv: &Move) -> bool {
+        let opponent = self.board.to_move.opposite_color();
+        let lowest = mv.starting_square.min(mv.target_square);
+        let highest = mv.starting_square.max(mv.target_square);
 
-        true
+        (lowest..=highest).all(|square| !self.is_square_attacked(square, opponent))
     }
 
     #[allow(unused)]
     fn can_kingside_castle(&self) -> bool {
-        match self.board.to_move {
-            Color::White => {
-                self.board.board_state.white_kingside_castling_priviledge
-                    && self.board.squares[Square::F1.as_index()].is_none()
-                    && self.board.squares[Square::G1.as_index()].is_none()
-            }
-            Color::Black => {
-                self.board.board_state.black_kingside_castling_priviledge
-                    && self.board.squares[Square::F8.as_index()].is_none()
-                    && self.board.squares[Square::G8.as_index()].is_none()
-            }
-        }
+        let can_castle = match self.board.to_move {
+            Color::White => self.board.board_state.white_kingside_castling_priviledge,
+            Color::Black => self.board.board_state.black_kingside_castling_priviledge,
+        };
+        let king_square = self.board.king_square(self.board.to_move);
+
+        can_castle
+            && king_square.is_some_and(|king_square| {
+                let castling_move = Move::new(king_square, king_square, Flag::KingsideCastle);
+                self.castling_king_target(&castling_move).is_some()
+            })
     }
 
     #[cfg(test)]
@@ -787,11 +1079,11 @@ impl MoveGenerator {
         }
 
         for mv in moves.iter() {
-            self.board.move_piece(mv);
+            let previous_state = self.board.make_move(mv);
             if !self.is_in_check(self.board.to_move.opposite_color()) {
                 num += self.perft_test(depth - 1);
             }
-            self.board.unmake_move(mv).unwrap();
+            self.board.unmake_move(mv, previous_state).unwrap();
         }
 
         num
@@ -802,7 +1094,11 @@ impl MoveGenerator {
 mod tests {
     use crate::board::Board;
     use crate::board_builder::BoardBuilder;
-    use crate::move_generation::{Flag, Move, MoveGenerator};
+    use crate::direction::Direction;
+    use crate::move_generation::{
+        between, direction_between, line, ray, Flag, Move, MoveGenerator, Pin,
+    };
+    use crate::move_list::MoveList;
     use crate::piece::{
         Color::*,
         Piece::{self, *},
@@ -811,6 +1107,7 @@ mod tests {
     use anyhow::Result;
 
     #[test]
+    #[allow(clippy::cmp_owned)]
     fn test_move_uci_output() -> Result<()> {
         let mv = Move::from_square(Square::E4, Square::E5, Flag::None);
         let uci_output = format!("{mv}");
@@ -822,6 +1119,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::cmp_owned)]
     fn test_move_uci_output_with_promotion() -> Result<()> {
         let mv = Move::from_square(Square::E7, Square::E8, Flag::PromoteTo(Piece::Queen));
         let uci_output = format!("{mv}");
@@ -833,12 +1131,10 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::cmp_owned)]
     fn test_move_uci_output_with_capture_with_promotion() -> Result<()> {
-        let mv = Move::from_square(
-            Square::E7,
-            Square::F8,
-            Flag::CaptureWithPromotion(Piece::Knight, Piece::Queen),
-        );
+        let mv =
+            Move::from_square(Square::E7, Square::F8, Flag::CaptureWithPromotion(Piece::Queen));
         let uci_output = format!("{mv}");
 
         dbg!(&uci_output);
@@ -847,6 +1143,280 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_move_from_malformed_uci() {
+        let board = Board::starting_position();
+
+        for uci in ["", "e2", "e2e", "e2e4qq", "é2e4"] {
+            assert!(Move::try_from_uci(uci, &board).is_err());
+        }
+    }
+
+    #[test]
+    fn test_move_from_uci_and_san_promotion_pieces() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1")?;
+
+        let mv = Move::try_from_uci("b7b8n", &board)?;
+        assert_eq!(mv.flag, Flag::PromoteTo(Piece::Knight));
+        for uci in ["b7b8N", "b7b8k", "b7b8p", "b7b8x"] {
+            assert!(Move::try_from_uci(uci, &board).is_err());
+        }
+
+        let mut move_generator = MoveGenerator::new(board);
+        let mv = Move::try_from_san("b8=R+", &mut move_generator)?;
+        assert_eq!(mv.flag, Flag::PromoteTo(Piece::Rook));
+        for san in ["b8=r", "b8=P", "b8=QQ"] {
+            assert!(Move::try_from_san(san, &mut move_generator).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_null_move() -> Result<()> {
+        let mut board = BoardBuilder::try_from_fen(
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2",
+        )?;
+        let original = board.clone();
+        let null_move = Move::try_from_uci("0000", &board)?;
+        assert!(null_move == Move::null());
+        assert_eq!(null_move.to_string(), "0000");
+        assert_eq!(null_move.to_san(&mut MoveGenerator::new(board.clone())), "--");
+
+        board.move_piece(&null_move);
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 1 2"
+        );
+        board.undo_last_move()?;
+        assert!(board == original);
+
+        // Passing while in check would leave the king to be captured
+        let board = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/4K2r w - - 0 1")?;
+        assert!(Move::try_from_uci("0000", &board).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_iter_moves() -> Result<()> {
+        let board = BoardBuilder::try_from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1",
+        )?;
+        let mut move_generator = MoveGenerator::new(board);
+
+        let moves: MoveList = move_generator.iter_moves().collect();
+        assert_eq!(moves, move_generator.generate_moves());
+        assert!(move_generator.has_legal_moves());
+
+        // Stalemate
+        let board = BoardBuilder::try_from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1")?;
+        assert!(!MoveGenerator::new(board).has_legal_moves());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_moves_into() -> Result<()> {
+        let mut moves = MoveList::new();
+        let mut move_generator = MoveGenerator::default();
+        move_generator.generate_moves_into(&mut moves);
+        assert_eq!(moves.len(), 20);
+
+        // Whatever was in the list before is replaced
+        let board = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1")?;
+        let mut move_generator = MoveGenerator::new(board);
+        move_generator.generate_moves_into(&mut moves);
+        assert_eq!(moves, move_generator.generate_moves());
+        move_generator.generate_capture_moves_into(&mut moves);
+        assert!(moves.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_capture_moves() -> Result<()> {
+        // Kiwipete, with plenty of captures, and the en passant capture on a3
+        let board = BoardBuilder::try_from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1",
+        )?;
+        let mut move_generator = MoveGenerator::new(board);
+
+        let mut captures = move_generator.generate_capture_moves();
+        let mut expected: MoveList = move_generator
+            .generate_moves()
+            .into_iter()
+            .filter(Move::is_capture)
+            .collect();
+        let key = |mv: &Move| (mv.starting_square, mv.target_square);
+        captures.sort_by_key(key);
+        expected.sort_by_key(key);
+
+        assert_eq!(captures, expected);
+        assert!(captures.iter().any(|mv| mv.flag == Flag::EnPassantCapture));
+        assert!(MoveGenerator::default().generate_capture_moves().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_moves_from() -> Result<()> {
+        // The knight on d2 is pinned by the bishop on b4
+        let board = BoardBuilder::try_from_fen("4k3/8/8/8/1b6/8/3N4/4K1N1 w - - 0 1")?;
+        let targets = |square: Square| -> Vec<usize> {
+            let mut targets: Vec<usize> = board
+                .moves_from(square)
+                .iter()
+                .map(|mv| mv.target_square)
+                .collect();
+            targets.sort_unstable();
+            targets
+        };
+
+        assert_eq!(targets(G1), vec![E2.as_index(), F3.as_index(), H3.as_index()]);
+        assert!(targets(D2).is_empty());
+        // Black's bishop and an empty square
+        assert!(targets(B4).is_empty());
+        assert!(targets(A1).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_legal() -> Result<()> {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            // The d-pawn is pinned, and castling queenside would go through check
+            "r3k2r/8/8/8/1b6/8/3P4/R3K2R w KQkq - 0 1",
+            // Taking en passant would expose the king
+            "8/8/8/K2pP2r/8/8/8/7k w - d6 0 1",
+        ] {
+            let mut move_generator = MoveGenerator::new(BoardBuilder::try_from_fen(fen)?);
+            let legal_moves = move_generator.generate_moves();
+            let mut pseudo_legal_moves = MoveList::new();
+            move_generator.generate_pseudo_legal_moves(&mut pseudo_legal_moves);
+            for mv in pseudo_legal_moves {
+                assert_eq!(move_generator.is_legal(&mv), legal_moves.contains(&mv));
+            }
+        }
+
+        let board = Board::starting_position();
+        assert!(board.is_legal(&Move::from_square(E2, E4, Flag::PawnDoublePush)));
+        assert!(!board.is_legal(&Move::from_square(E2, E4, Flag::None)));
+        assert!(!board.is_legal(&Move::from_square(E7, E5, Flag::PawnDoublePush)));
+        assert!(!board.is_legal(&Move::from_square(E3, E4, Flag::None)));
+        assert!(!board.is_legal(&Move::null()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rays_and_lines() {
+        let squares = |squares: &[Square]| -> Vec<usize> {
+            squares.iter().map(|square| square.as_index()).collect()
+        };
+
+        assert_eq!(ray(E4.as_index(), Direction::NorthEast), squares(&[F5, G6, H7]));
+        assert!(ray(H4.as_index(), Direction::East).is_empty());
+        assert!(ray(E4.as_index(), Direction::NorthNorthEast).is_empty());
+
+        assert_eq!(between(A1.as_index(), D4.as_index()), squares(&[B2, C3]));
+        assert_eq!(between(E8.as_index(), E5.as_index()), squares(&[E7, E6]));
+        assert!(between(E4.as_index(), E5.as_index()).is_empty());
+        assert!(between(B1.as_index(), C3.as_index()).is_empty());
+
+        assert_eq!(
+            line(C3.as_index(), E5.as_index()),
+            squares(&[A1, B2, C3, D4, E5, F6, G7, H8])
+        );
+        assert_eq!(line(D1.as_index(), D1.as_index()), Vec::<usize>::new());
+        assert!(line(B1.as_index(), C3.as_index()).is_empty());
+        assert_eq!(
+            direction_between(H1.as_index(), A8.as_index()),
+            Some(Direction::NorthWest)
+        );
+        assert_eq!(direction_between(A1.as_index(), B3.as_index()), None);
+    }
+
+    #[test]
+    fn test_move_from_san() -> Result<()> {
+        // Both knights can reach d2, and the e-pawn can promote by pushing or capturing
+        let board = BoardBuilder::try_from_fen("k2q4/4P3/8/8/8/5N2/8/1N2K2R w K - 0 1")?;
+        let mut move_generator = MoveGenerator::new(board);
+
+        assert!(
+            Move::try_from_san("Nbd2", &mut move_generator)?
+                == Move::from_square(B1, D2, Flag::None)
+        );
+        assert!(
+            Move::try_from_san("exd8=Q+", &mut move_generator)?
+                == Move::from_square(E7, D8, Flag::CaptureWithPromotion(Queen))
+        );
+        assert!(
+            Move::try_from_san("e8=N", &mut move_generator)?
+                == Move::from_square(E7, E8, Flag::PromoteTo(Knight))
+        );
+        assert!(
+            Move::try_from_san("O-O", &mut move_generator)?
+                == Move::from_square(E1, G1, Flag::KingsideCastle)
+        );
+        assert!(Move::try_from_san("Nd2", &mut move_generator).is_err());
+        assert!(Move::try_from_san("O-O-O", &mut move_generator).is_err());
+        assert!(Move::try_from_san("Qd4", &mut move_generator).is_err());
+        assert!(Move::try_from_san("x", &mut move_generator).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_to_san() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("k2q4/4P3/8/8/8/5N2/8/1N2K2R w K - 0 1")?;
+        let mut move_generator = MoveGenerator::new(board);
+
+        for san in ["Nbd2", "Nfd2", "exd8=Q+", "e8=N", "O-O", "Rh7", "Ke2"] {
+            let mv = Move::try_from_san(san, &mut move_generator)?;
+            assert_eq!(mv.to_san(&mut move_generator), san);
+        }
+
+        // Rooks on the same file are told apart by their rank
+        let board = BoardBuilder::try_from_fen("7k/R5pp/8/8/8/8/R7/6K1 w - - 0 1")?;
+        let mut move_generator = MoveGenerator::new(board);
+        let mv = Move::from_square(A2, A5, Flag::None);
+        assert_eq!(mv.to_san(&mut move_generator), "R2a5");
+        let mv = Move::from_square(A7, A8, Flag::None);
+        assert_eq!(mv.to_san(&mut move_generator), "Ra8#");
+
+        // Neither the file nor the rank is enough for the queen on h4
+        let board = BoardBuilder::try_from_fen("2k5/8/8/8/4Q2Q/8/8/K6Q w - - 0 1")?;
+        let mut move_generator = MoveGenerator::new(board.clone());
+        let mv = Move::from_square(H4, E1, Flag::None);
+        assert_eq!(mv.to_san(&mut move_generator), "Qh4e1");
+        assert_eq!(board.move_to_san(&mv), "Qh4e1");
+
+        let board = BoardBuilder::try_from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1")?;
+        let mv = Move::from_square(E5, D6, Flag::EnPassantCapture);
+        assert_eq!(board.move_to_san(&mv), "exd6");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_from_uci_chess960_castling() -> Result<()> {
+        let mut board = BoardBuilder::try_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1")?;
+        board.chess960 = true;
+
+        let kingside_castle = Move::try_from_uci("e1h1", &board)?;
+        let queenside_castle = Move::try_from_uci("e1a1", &board)?;
+
+        assert!(kingside_castle == Move::from_square(E1, G1, Flag::KingsideCastle));
+        assert!(queenside_castle == Move::from_square(E1, C1, Flag::QueensideCastle));
+        assert!(Move::try_from_uci("e1g1", &board).is_err());
+        assert_eq!(board.move_to_uci(&kingside_castle), "e1h1");
+        assert_eq!(board.move_to_uci(&queenside_castle), "e1a1");
+
+        Ok(())
+    }
+
     #[test]
     fn test_move_generation_depth_1() -> Result<()> {
         let board = BoardBuilder::from_starting_position().try_into()?;
@@ -1091,6 +1661,84 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_move_generation_chess960_position_1_perft_depth_1() -> Result<()> {
+        let board = BoardBuilder::try_from_fen(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        )?;
+        let mut move_generator = MoveGenerator::new(board);
+        let number_of_positions = move_generator.perft_test(1);
+
+        assert_eq!(number_of_positions, 21);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_generation_chess960_position_1_perft_depth_2() -> Result<()> {
+        let board = BoardBuilder::try_from_fen(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        )?;
+        let mut move_generator = MoveGenerator::new(board);
+        let number_of_positions = move_generator.perft_test(2);
+
+        assert_eq!(number_of_positions, 528);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_generation_chess960_position_1_perft_depth_3() -> Result<()> {
+        let board = BoardBuilder::try_from_fen(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        )?;
+        let mut move_generator = MoveGenerator::new(board);
+        let number_of_positions = move_generator.perft_test(3);
+
+        assert_eq!(number_of_positions, 12189);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_generation_chess960_position_2_perft_depth_1() -> Result<()> {
+        let board = BoardBuilder::try_from_fen(
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+        )?;
+        let mut move_generator = MoveGenerator::new(board);
+        let number_of_positions = move_generator.perft_test(1);
+
+        assert_eq!(number_of_positions, 21);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_generation_chess960_position_2_perft_depth_2() -> Result<()> {
+        let board = BoardBuilder::try_from_fen(
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+        )?;
+        let mut move_generator = MoveGenerator::new(board);
+        let number_of_positions = move_generator.perft_test(2);
+
+        assert_eq!(number_of_positions, 807);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_generation_chess960_position_2_perft_depth_3() -> Result<()> {
+        let board = BoardBuilder::try_from_fen(
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+        )?;
+        let mut move_generator = MoveGenerator::new(board);
+        let number_of_positions = move_generator.perft_test(3);
+
+        assert_eq!(number_of_positions, 18002);
+
+        Ok(())
+    }
+
     #[test]
     fn test_num_squares_to_edge() {
         let move_generator = MoveGenerator::default();
@@ -1131,7 +1779,7 @@ mod tests {
     #[test]
     fn test_generate_sliding_moves_empty_white() {
         let mut move_generator = MoveGenerator::default();
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_sliding_moves(&mut moves, A1.as_index());
         move_generator.generate_sliding_moves(&mut moves, C1.as_index());
         move_generator.generate_sliding_moves(&mut moves, D1.as_index());
@@ -1147,7 +1795,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_sliding_moves(&mut moves, A8.as_index());
         move_generator.generate_sliding_moves(&mut moves, C8.as_index());
@@ -1166,7 +1814,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_sliding_moves(&mut moves, A1.as_index());
         move_generator.generate_sliding_moves(&mut moves, C1.as_index());
@@ -1196,7 +1844,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_sliding_moves(&mut moves, A8.as_index());
         move_generator.generate_sliding_moves(&mut moves, C8.as_index());
@@ -1228,7 +1876,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_sliding_moves(&mut moves, A1.as_index());
         move_generator.generate_sliding_moves(&mut moves, C1.as_index());
@@ -1252,13 +1900,13 @@ mod tests {
     fn test_generate_sliding_moves_from_corner() -> Result<()> {
         let board = BoardBuilder::try_from_fen("Qr5k/r7/2N5/8/8/8/8/6K1 w - - 0 1")?;
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_sliding_moves(&mut moves, A8.as_index());
 
         assert_eq!(moves.len(), 3);
-        assert!(moves.contains(&Move::from_square(A8, A7, Flag::Capture(Rook))));
-        assert!(moves.contains(&Move::from_square(A8, B8, Flag::Capture(Rook))));
+        assert!(moves.contains(&Move::from_square(A8, A7, Flag::Capture)));
+        assert!(moves.contains(&Move::from_square(A8, B8, Flag::Capture)));
         assert!(moves.contains(&Move::from_square(A8, B7, Flag::None)));
 
         Ok(())
@@ -1267,7 +1915,7 @@ mod tests {
     #[test]
     fn test_generate_knight_moves_starting_position() {
         let mut move_generator = MoveGenerator::default();
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_knight_moves(&mut moves, B1.as_index());
         move_generator.generate_knight_moves(&mut moves, G1.as_index());
 
@@ -1290,7 +1938,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_knight_moves(&mut moves, H1.as_index());
 
         assert_eq!(moves.len(), 2);
@@ -1311,7 +1959,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_knight_moves(&mut moves, G2.as_index());
 
         assert_eq!(moves.len(), 4);
@@ -1327,14 +1975,14 @@ mod tests {
     fn test_generate_knight_moves_with_pieces_on_target_square() -> Result<()> {
         let board = BoardBuilder::try_from_fen("k7/3R1n2/2n3R1/4N3/2R3n1/3n1R2/8/KR6 w - - 0 1")?;
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_knight_moves(&mut moves, E5.as_index());
         assert_eq!(moves.len(), 4);
-        assert!(moves.contains(&Move::from_square(E5, C6, Flag::Capture(Knight))));
-        assert!(moves.contains(&Move::from_square(E5, D3, Flag::Capture(Knight))));
-        assert!(moves.contains(&Move::from_square(E5, G4, Flag::Capture(Knight))));
-        assert!(moves.contains(&Move::from_square(E5, F7, Flag::Capture(Knight))));
+        assert!(moves.contains(&Move::from_square(E5, C6, Flag::Capture)));
+        assert!(moves.contains(&Move::from_square(E5, D3, Flag::Capture)));
+        assert!(moves.contains(&Move::from_square(E5, G4, Flag::Capture)));
+        assert!(moves.contains(&Move::from_square(E5, F7, Flag::Capture)));
 
         Ok(())
     }
@@ -1342,7 +1990,7 @@ mod tests {
     #[test]
     fn test_generate_pawn_moves_from_starting_position_white() {
         let mut move_generator = MoveGenerator::default();
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         for square in 0..64 {
             if move_generator
@@ -1379,7 +2027,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         for square in 0..64 {
             if move_generator
@@ -1425,7 +2073,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, F4.as_index());
         move_generator.generate_pawn_moves(&mut moves, C4.as_index());
@@ -1450,7 +2098,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, F5.as_index());
         move_generator.generate_pawn_moves(&mut moves, C5.as_index());
@@ -1470,7 +2118,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_pawn_moves(&mut moves, E2.as_index());
 
         assert_eq!(moves.len(), 1);
@@ -1490,7 +2138,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, E7.as_index());
 
@@ -1512,12 +2160,12 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_pawn_moves(&mut moves, E4.as_index());
 
         assert_eq!(moves.len(), 2);
-        assert!(moves.contains(&Move::from_square(E4, D5, Flag::Capture(Pawn))));
-        assert!(moves.contains(&Move::from_square(E4, F5, Flag::Capture(Pawn))));
+        assert!(moves.contains(&Move::from_square(E4, D5, Flag::Capture)));
+        assert!(moves.contains(&Move::from_square(E4, F5, Flag::Capture)));
 
         Ok(())
     }
@@ -1535,13 +2183,13 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, E5.as_index());
 
         assert_eq!(moves.len(), 2);
-        assert!(moves.contains(&Move::from_square(E5, F4, Flag::Capture(Pawn))));
-        assert!(moves.contains(&Move::from_square(E5, D4, Flag::Capture(Pawn))));
+        assert!(moves.contains(&Move::from_square(E5, F4, Flag::Capture)));
+        assert!(moves.contains(&Move::from_square(E5, D4, Flag::Capture)));
 
         Ok(())
     }
@@ -1561,12 +2209,12 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, H4.as_index());
 
         assert_eq!(moves.len(), 2);
-        assert!(moves.contains(&Move::from_square(H4, G5, Flag::Capture(Pawn))));
+        assert!(moves.contains(&Move::from_square(H4, G5, Flag::Capture)));
         assert!(moves.contains(&Move::from_square(H4, H5, Flag::None)));
 
         Ok(())
@@ -1585,12 +2233,12 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, A5.as_index());
 
         assert_eq!(moves.len(), 2);
-        assert!(moves.contains(&Move::from_square(A5, B4, Flag::Capture(Pawn))));
+        assert!(moves.contains(&Move::from_square(A5, B4, Flag::Capture)));
         assert!(moves.contains(&Move::from_square(A5, A4, Flag::None)));
 
         Ok(())
@@ -1610,7 +2258,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, A3.as_index());
 
@@ -1631,7 +2279,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, H5.as_index());
 
@@ -1649,7 +2297,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, E4.as_index());
 
@@ -1668,7 +2316,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, E5.as_index());
 
@@ -1687,7 +2335,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, H7.as_index());
 
@@ -1710,7 +2358,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, A2.as_index());
 
@@ -1732,7 +2380,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, E7.as_index());
 
@@ -1755,7 +2403,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, E2.as_index());
 
@@ -1779,31 +2427,15 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, E7.as_index());
 
         assert_eq!(moves.len(), 4);
-        assert!(moves.contains(&Move::from_square(
-            E7,
-            D8,
-            Flag::CaptureWithPromotion(Queen, Queen)
-        )));
-        assert!(moves.contains(&Move::from_square(
-            E7,
-            D8,
-            Flag::CaptureWithPromotion(Queen, Rook)
-        )));
-        assert!(moves.contains(&Move::from_square(
-            E7,
-            D8,
-            Flag::CaptureWithPromotion(Queen, Bishop)
-        )));
-        assert!(moves.contains(&Move::from_square(
-            E7,
-            D8,
-            Flag::CaptureWithPromotion(Queen, Knight)
-        )));
+        assert!(moves.contains(&Move::from_square(E7, D8, Flag::CaptureWithPromotion(Queen))));
+        assert!(moves.contains(&Move::from_square(E7, D8, Flag::CaptureWithPromotion(Rook))));
+        assert!(moves.contains(&Move::from_square(E7, D8, Flag::CaptureWithPromotion(Bishop))));
+        assert!(moves.contains(&Move::from_square(E7, D8, Flag::CaptureWithPromotion(Knight))));
 
         Ok(())
     }
@@ -1820,30 +2452,14 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_pawn_moves(&mut moves, E2.as_index());
 
         assert_eq!(moves.len(), 4);
-        assert!(moves.contains(&Move::from_square(
-            E2,
-            D1,
-            Flag::CaptureWithPromotion(Queen, Queen)
-        )));
-        assert!(moves.contains(&Move::from_square(
-            E2,
-            D1,
-            Flag::CaptureWithPromotion(Queen, Rook)
-        )));
-        assert!(moves.contains(&Move::from_square(
-            E2,
-            D1,
-            Flag::CaptureWithPromotion(Queen, Bishop)
-        )));
-        assert!(moves.contains(&Move::from_square(
-            E2,
-            D1,
-            Flag::CaptureWithPromotion(Queen, Knight)
-        )));
+        assert!(moves.contains(&Move::from_square(E2, D1, Flag::CaptureWithPromotion(Queen))));
+        assert!(moves.contains(&Move::from_square(E2, D1, Flag::CaptureWithPromotion(Rook))));
+        assert!(moves.contains(&Move::from_square(E2, D1, Flag::CaptureWithPromotion(Bishop))));
+        assert!(moves.contains(&Move::from_square(E2, D1, Flag::CaptureWithPromotion(Knight))));
 
         Ok(())
     }
@@ -1858,14 +2474,14 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, E5.as_index());
 
         assert!(moves.len() == 3);
         assert!(moves.contains(&Move::from_square(E5, E6, Flag::None)));
         assert!(moves.contains(&Move::from_square(E5, D6, Flag::EnPassantCapture)));
-        assert!(moves.contains(&Move::from_square(E5, F6, Flag::Capture(Knight))));
+        assert!(moves.contains(&Move::from_square(E5, F6, Flag::Capture)));
 
         Ok(())
     }
@@ -1880,7 +2496,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, E5.as_index());
 
@@ -1902,14 +2518,14 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
 
         move_generator.generate_pawn_moves(&mut moves, E4.as_index());
 
         assert!(moves.len() == 3);
         assert!(moves.contains(&Move::from_square(E4, E3, Flag::None)));
         assert!(moves.contains(&Move::from_square(E4, D3, Flag::EnPassantCapture)));
-        assert!(moves.contains(&Move::from_square(E4, F3, Flag::Capture(Knight))));
+        assert!(moves.contains(&Move::from_square(E4, F3, Flag::Capture)));
 
         Ok(())
     }
@@ -1925,7 +2541,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_pawn_moves(&mut moves, E4.as_index());
 
         assert!(moves.len() == 2);
@@ -1945,7 +2561,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_pawn_moves(&mut moves, A5.as_index());
 
         assert!(moves.len() == 2);
@@ -1965,7 +2581,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_pawn_moves(&mut moves, H5.as_index());
 
         assert!(moves.len() == 2);
@@ -1986,7 +2602,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_pawn_moves(&mut moves, A4.as_index());
 
         assert!(moves.len() == 2);
@@ -2007,7 +2623,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_pawn_moves(&mut moves, H4.as_index());
 
         assert!(moves.len() == 2);
@@ -2027,7 +2643,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_king_moves(&mut moves, E4.as_index());
 
         assert!(moves.len() == 8);
@@ -2055,7 +2671,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_king_moves(&mut moves, E4.as_index());
 
         assert!(moves.len() == 6);
@@ -2081,12 +2697,12 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_king_moves(&mut moves, E4.as_index());
 
         assert!(moves.len() == 8);
-        assert!(moves.contains(&Move::from_square(E4, E5, Flag::Capture(Knight))));
-        assert!(moves.contains(&Move::from_square(E4, F3, Flag::Capture(Knight))));
+        assert!(moves.contains(&Move::from_square(E4, E5, Flag::Capture)));
+        assert!(moves.contains(&Move::from_square(E4, F3, Flag::Capture)));
         assert!(moves.contains(&Move::from_square(E4, F4, Flag::None)));
         assert!(moves.contains(&Move::from_square(E4, D4, Flag::None)));
         assert!(moves.contains(&Move::from_square(E4, E3, Flag::None)));
@@ -2107,7 +2723,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_king_moves(&mut moves, E4.as_index());
 
         assert!(moves.len() == 8);
@@ -2135,7 +2751,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_king_moves(&mut moves, E4.as_index());
 
         assert!(moves.len() == 6);
@@ -2161,12 +2777,12 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_king_moves(&mut moves, E4.as_index());
 
         assert!(moves.len() == 8);
-        assert!(moves.contains(&Move::from_square(E4, E5, Flag::Capture(Knight))));
-        assert!(moves.contains(&Move::from_square(E4, F3, Flag::Capture(Knight))));
+        assert!(moves.contains(&Move::from_square(E4, E5, Flag::Capture)));
+        assert!(moves.contains(&Move::from_square(E4, F3, Flag::Capture)));
         assert!(moves.contains(&Move::from_square(E4, F4, Flag::None)));
         assert!(moves.contains(&Move::from_square(E4, D4, Flag::None)));
         assert!(moves.contains(&Move::from_square(E4, E3, Flag::None)));
@@ -2187,7 +2803,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_king_moves(&mut moves, H1.as_index());
 
         assert!(moves.len() == 3);
@@ -2208,7 +2824,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_king_moves(&mut moves, A1.as_index());
 
         assert!(moves.len() == 3);
@@ -2230,7 +2846,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_king_moves(&mut moves, H8.as_index());
 
         assert!(moves.len() == 3);
@@ -2252,7 +2868,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_king_moves(&mut moves, A8.as_index());
 
         assert!(moves.len() == 3);
@@ -2369,7 +2985,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_king_moves(&mut moves, E1.as_index());
 
         assert!(moves.len() == 3);
@@ -2394,7 +3010,7 @@ mod tests {
             .try_into()?;
 
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_king_moves(&mut moves, E1.as_index());
 
         assert!(moves.len() == 2);
@@ -2418,7 +3034,7 @@ mod tests {
 
         dbg!(&board);
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_king_moves(&mut moves, E8.as_index());
 
         assert!(moves.len() == 3);
@@ -2445,7 +3061,7 @@ mod tests {
 
         dbg!(&board);
         let mut move_generator = MoveGenerator::new(board);
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         move_generator.generate_king_moves(&mut moves, E8.as_index());
 
         assert!(moves.len() == 2);
@@ -2484,7 +3100,7 @@ mod tests {
     #[test]
     fn test_calculate_opponent_attack_squares_from_white() -> Result<()> {
         let board = BoardBuilder::from_starting_position().try_into()?;
-        let mut move_generator = MoveGenerator::new(board);
+        let move_generator = MoveGenerator::new(board);
         let attacked_squares = move_generator.calculate_opponent_attack_map();
 
         let squares_attacked = attacked_squares
@@ -2492,7 +3108,8 @@ mod tests {
             .filter(|&&attacked| attacked)
             .count();
 
-        assert!(squares_attacked == 8);
+        // Black's own pieces on the seventh and eighth ranks are defended, so they count too
+        assert!(squares_attacked == 22);
         Ok(())
     }
 
@@ -2502,7 +3119,7 @@ mod tests {
             .make_move(Move::from_square(E2, E4, Flag::PawnDoublePush))
             .try_into()?;
 
-        let mut move_generator = MoveGenerator::new(board);
+        let move_generator = MoveGenerator::new(board);
         let attacked_squares = move_generator.calculate_opponent_attack_map();
 
         let squares_attacked = attacked_squares
@@ -2510,7 +3127,112 @@ mod tests {
             .filter(|&&attacked| attacked)
             .count();
 
-        assert!(squares_attacked == 16);
+        assert!(squares_attacked == 29);
+        Ok(())
+    }
+
+    #[test]
+    fn test_attackers_to() -> Result<()> {
+        let board = BoardBuilder::try_from_fen(
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+        )?;
+        let move_generator = MoveGenerator::new(board);
+        let attackers = |square: Square, color| {
+            let mut attackers = move_generator.attackers_to(square.as_index(), color);
+            attackers.sort_unstable();
+            attackers
+        };
+
+        // The queen and the bishop both aim at f7, which only the king defends
+        assert_eq!(attackers(F7, White), vec![C4.as_index(), H5.as_index()]);
+        assert_eq!(attackers(F7, Black), vec![E8.as_index()]);
+        assert_eq!(attackers(E5, White), vec![H5.as_index()]);
+        assert_eq!(attackers(D4, Black), vec![E5.as_index(), C6.as_index()]);
+        assert!(attackers(A4, White).is_empty());
+
+        for square in 0..64 {
+            for color in [White, Black] {
+                assert_eq!(
+                    move_generator.is_square_attacked(square, color),
+                    !move_generator.attackers_to(square, color).is_empty()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkers() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("4k3/8/r4N2/1B6/8/8/8/4K3 b - - 0 1")?;
+        let mut move_generator = MoveGenerator::new(board);
+        let mut checkers = move_generator.checkers();
+        checkers.sort_unstable();
+
+        assert_eq!(checkers, vec![B5.as_index(), F6.as_index()]);
+        // The rook could take the knight or block the bishop, but only the king can get out of a
+        // double check
+        let moves = move_generator.generate_moves();
+        assert!(!moves.is_empty());
+        assert!(moves.iter().all(|mv| mv.starting_square == E8.as_index()));
+        assert!(MoveGenerator::default().checkers().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pinned_pieces() -> Result<()> {
+        // The knight is pinned by the bishop on a5 and the rook by the queen. The pawn on f2 isn't,
+        // since the knight on g3 stands between it and the bishop on h4.
+        let board = BoardBuilder::try_from_fen("4q2k/8/8/b7/7b/6n1/3NRP2/4K3 w - - 0 1")?;
+        let move_generator = MoveGenerator::new(board);
+        let mut pins = move_generator.pinned_pieces(White);
+        pins.sort_by_key(|pin| pin.pinned);
+
+        assert_eq!(
+            pins,
+            vec![
+                Pin {
+                    pinned: D2.as_index(),
+                    pinner: A5.as_index(),
+                    ray: vec![D2.as_index(), C3.as_index(), B4.as_index(), A5.as_index()],
+                },
+                Pin {
+                    pinned: E2.as_index(),
+                    pinner: E8.as_index(),
+                    ray: vec![
+                        E2.as_index(),
+                        E3.as_index(),
+                        E4.as_index(),
+                        E5.as_index(),
+                        E6.as_index(),
+                        E7.as_index(),
+                        E8.as_index()
+                    ],
+                },
+            ]
+        );
+        assert!(move_generator.pinned_pieces(Black).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_attack_map() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/r2K4 w - - 0 1")?;
+        let move_generator = MoveGenerator::new(board);
+
+        let attack_map = move_generator.attack_map(Black, false);
+        assert!(attack_map[C1.as_index()] && attack_map[D1.as_index()]);
+        assert!(!attack_map[E1.as_index()]);
+        assert!(attack_map[D8.as_index()] && attack_map[D7.as_index()]);
+        assert_eq!(attack_map.iter().filter(|&&attacked| attacked).count(), 3 + 7 + 5);
+
+        // The king would still be in check on e1
+        let attack_map = move_generator.attack_map(Black, true);
+        assert!(attack_map[E1.as_index()] && attack_map[H1.as_index()]);
+        assert!(!move_generator.attack_map(White, false)[E8.as_index()]);
+
         Ok(())
     }
 
@@ -2525,7 +3247,7 @@ mod tests {
             .make_move(Move::from_square(G8, C5, Flag::None))
             .try_into()?;
 
-        let mut move_generator = MoveGenerator::new(board);
+        let move_generator = MoveGenerator::new(board);
         assert!(move_generator.is_castling_path_clear(&Move::from_square(
             E1,
             G1,
@@ -2547,7 +3269,7 @@ mod tests {
             .make_move(Move::from_square(H2, H3, Flag::None))
             .try_into()?;
 
-        let mut move_generator = MoveGenerator::new(board);
+        let move_generator = MoveGenerator::new(board);
         assert!(move_generator.is_castling_path_clear(&Move::from_square(
             E8,
             G8,
@@ -2568,7 +3290,7 @@ mod tests {
             .make_move(Move::from_square(H5, G3, Flag::None))
             .try_into()?;
 
-        let mut move_generator = MoveGenerator::new(board);
+        let move_generator = MoveGenerator::new(board);
 
         assert!(!move_generator.is_castling_path_clear(&Move::from_square(
             E1,
@@ -2591,7 +3313,7 @@ mod tests {
             .make_move(Move::from_square(A2, A3, Flag::None))
             .try_into()?;
 
-        let mut move_generator = MoveGenerator::new(board);
+        let move_generator = MoveGenerator::new(board);
 
         assert!(!move_generator.is_castling_path_clear(&Move::from_square(
             E8,
@@ -2615,7 +3337,7 @@ mod tests {
             .make_move(Move::from_square(B4, C2, Flag::None))
             .try_into()?;
 
-        let mut move_generator = MoveGenerator::new(board);
+        let move_generator = MoveGenerator::new(board);
 
         assert!(!move_generator.is_castling_path_clear(&Move::from_square(
             E1,
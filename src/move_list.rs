@@ -0,0 +1,175 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use crate::move_generation::Move;
+
+// No position has more legal moves than this, the record is 218
+pub const MAX_MOVES: usize = 256;
+
+// A list of moves that lives on the stack, so generating moves at every node of a search or perft
+// doesn't allocate. Derefs to a slice for everything but adding moves.
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        Self {
+            moves: [const { Move::null() }; MAX_MOVES],
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, mv: Move) {
+        assert!(self.len < MAX_MOVES, "more than {MAX_MOVES} moves");
+        self.moves[self.len] = mv;
+        self.len += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    // Keeps the moves the predicate holds for, in the same order
+    pub fn retain(&mut self, mut predicate: impl FnMut(&Move) -> bool) {
+        let mut kept = 0;
+        for index in 0..self.len {
+            if predicate(&self.moves[index]) {
+                self.moves.swap(kept, index);
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [Move];
+
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+}
+
+impl DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut [Move] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl fmt::Debug for MoveList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl PartialEq for MoveList {
+    fn eq(&self, other: &Self) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl Eq for MoveList {}
+
+impl FromIterator<Move> for MoveList {
+    fn from_iter<I: IntoIterator<Item = Move>>(iter: I) -> Self {
+        let mut moves = Self::new();
+        for mv in iter {
+            moves.push(mv);
+        }
+        moves
+    }
+}
+
+impl Extend<Move> for MoveList {
+    fn extend<I: IntoIterator<Item = Move>>(&mut self, iter: I) {
+        for mv in iter {
+            self.push(mv);
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter {
+            list: self,
+            next: 0,
+        }
+    }
+}
+
+// Hands out the moves of a list by value
+pub struct IntoIter {
+    list: MoveList,
+    next: usize,
+}
+
+impl Iterator for IntoIter {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        if self.next == self.list.len {
+            return None;
+        }
+        let mv = std::mem::replace(&mut self.list.moves[self.next], Move::null());
+        self.next += 1;
+        Some(mv)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.list.len - self.next;
+        (left, Some(left))
+    }
+}
+
+impl ExactSizeIterator for IntoIter {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        move_generation::{Flag, Move},
+        square::Square::*,
+    };
+
+    use super::MoveList;
+
+    #[test]
+    fn test_move_list() {
+        let e4 = Move::from_square(E2, E4, Flag::PawnDoublePush);
+        let nf3 = Move::from_square(G1, F3, Flag::None);
+        let d4 = Move::from_square(D2, D4, Flag::PawnDoublePush);
+        let mut moves: MoveList = [e4.clone(), nf3.clone(), d4.clone()].into_iter().collect();
+
+        assert_eq!(moves.len(), 3);
+        assert_eq!(moves[1], nf3);
+        assert!(moves.contains(&d4));
+
+        moves.retain(|mv| mv.flag == Flag::PawnDoublePush);
+        assert_eq!(moves.to_vec(), vec![e4.clone(), d4.clone()]);
+
+        moves.push(nf3.clone());
+        assert_eq!(moves.into_iter().collect::<Vec<_>>(), vec![e4, d4, nf3]);
+    }
+}
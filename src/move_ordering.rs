@@ -0,0 +1,87 @@
+use crate::{
+    board::Board,
+    move_generation::{Flag, Move},
+};
+
+// Decides in which order the search tries moves. The sooner the best move is searched, the more
+// of the tree alpha-beta can cut off. One orderer is used for a whole search, so it can keep
+// state, such as which quiet moves caused cutoffs at a given ply.
+pub trait MoveOrderer {
+    // Called before every search, to forget what was learnt in the previous one
+    fn new_search(&mut self) {}
+
+    // Most promising moves first. ply is the distance from the root of the search.
+    fn order_moves(&mut self, board: &Board, moves: &mut [Move], ply: i32);
+
+    // The move, played in the given position, was too good for the opponent to allow
+    fn record_cutoff(&mut self, _board: &Board, _mv: &Move, _depth: u32, _ply: i32) {}
+}
+
+// Captures of valuable pieces by cheap ones first, then moves that improve the piece's square
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultMoveOrderer;
+
+impl MoveOrderer for DefaultMoveOrderer {
+    fn order_moves(&mut self, board: &Board, moves: &mut [Move], _ply: i32) {
+        moves.sort_unstable_by_key(|mv| guess_move_score(board, mv));
+    }
+}
+
+fn guess_move_score(board: &Board, mv: &Move) -> i32 {
+    let mut score_guess: i32 = 0;
+
+    let starting_piece = board.squares[mv.starting_square].unwrap();
+    let piece_color = board.colors[mv.starting_square].unwrap();
+    let capture_piece_multiplier = 10;
+
+    match mv.flag {
+        Flag::PromoteTo(piece) => score_guess += piece.piece_value(),
+        Flag::Capture => {
+            score_guess += capture_piece_multiplier * captured_piece_value(board, mv)
+                - starting_piece.piece_value()
+        }
+        Flag::CaptureWithPromotion(promotion_piece) => {
+            score_guess += promotion_piece.piece_value()
+                + capture_piece_multiplier * captured_piece_value(board, mv)
+                - starting_piece.piece_value()
+        }
+        _ => (),
+    }
+
+    let position_eval_diff = starting_piece.position_value(mv.target_square, piece_color)
+        - starting_piece.position_value(mv.starting_square, piece_color);
+    score_guess += position_eval_diff.blend(board.phase());
+
+    // Negate score so that the moves with the highest score will be first
+    -score_guess
+}
+
+fn captured_piece_value(board: &Board, mv: &Move) -> i32 {
+    board.squares[mv.target_square].map_or(0, |piece| piece.piece_value())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Board,
+        board_builder::BoardBuilder,
+        move_generation::{Flag, Move, MoveGenerator},
+        square::Square,
+    };
+    use anyhow::Result;
+
+    use super::{DefaultMoveOrderer, MoveOrderer};
+
+    #[test]
+    fn test_captures_of_valuable_pieces_come_first() -> Result<()> {
+        let board: Board = BoardBuilder::try_from_fen("4k3/8/8/6q1/8/1p3N2/P7/4K3 w - - 0 1")?;
+        let mut moves = MoveGenerator::new(board.without_history()).generate_moves();
+
+        DefaultMoveOrderer.order_moves(&board, &mut moves, 0);
+
+        assert!(moves[0] == Move::from_square(Square::F3, Square::G5, Flag::Capture));
+        assert!(moves[1] == Move::from_square(Square::A2, Square::B3, Flag::Capture));
+
+        Ok(())
+    }
+}
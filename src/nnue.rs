@@ -0,0 +1,386 @@
+use std::{fs, path::Path, sync::Arc};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    board::Board,
+    evaluate::Evaluator,
+    piece::{Color, Piece},
+    score::Score,
+};
+
+// Network files start with these bytes, followed by the hidden layer size and the weights, all
+// little-endian
+const MAGIC: &[u8; 4] = b"TNNU";
+const HEADER_SIZE: usize = 8;
+// HalfKP: one input for every combination of the perspective's king square and another piece on
+// a square. The five non-king pieces of the perspective's own side come first, then the enemy's.
+pub const FEATURES: usize = 64 * 10 * 64;
+// The hidden layer is clipped to [0, ACTIVATION_MAX], and the output is scaled from
+// ACTIVATION_MAX * OUTPUT_QUANTIZATION units to centipawns by OUTPUT_SCALE
+const ACTIVATION_MAX: i32 = 255;
+const OUTPUT_QUANTIZATION: i32 = 64;
+const OUTPUT_SCALE: i32 = 400;
+const MAX_HIDDEN_SIZE: usize = 4096;
+
+// A network with a HalfKP feature transformer feeding a hidden layer per perspective, and a
+// single output neuron reading both hidden layers, the side to move's first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Network {
+    hidden_size: usize,
+    // FEATURES rows of hidden_size weights
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    // The side to move's hidden layer, then the other side's
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl Network {
+    pub fn new(
+        hidden_size: usize,
+        feature_weights: Vec<i16>,
+        feature_biases: Vec<i16>,
+        output_weights: Vec<i16>,
+        output_bias: i32,
+    ) -> Result<Self> {
+        if hidden_size == 0 || hidden_size > MAX_HIDDEN_SIZE {
+            bail!("The hidden layer size must be between 1 and {MAX_HIDDEN_SIZE}");
+        }
+        if feature_weights.len() != FEATURES * hidden_size
+            || feature_biases.len() != hidden_size
+            || output_weights.len() != 2 * hidden_size
+        {
+            bail!("The number of weights doesn't match the hidden layer size");
+        }
+
+        Ok(Self {
+            hidden_size,
+            feature_weights,
+            feature_biases,
+            output_weights,
+            output_bias,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+            bail!("Not a Talia network file");
+        }
+        let hidden_size = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        if hidden_size == 0 || hidden_size > MAX_HIDDEN_SIZE {
+            bail!("The hidden layer size must be between 1 and {MAX_HIDDEN_SIZE}");
+        }
+        let weight_count = (FEATURES + 1 + 2) * hidden_size;
+        if bytes.len() != HEADER_SIZE + 2 * weight_count + 4 {
+            bail!("The network file has the wrong size for a hidden layer of {hidden_size}");
+        }
+
+        let mut weights = bytes[HEADER_SIZE..HEADER_SIZE + 2 * weight_count]
+            .chunks_exact(2)
+            .map(|weight| i16::from_le_bytes(weight.try_into().unwrap()));
+        let mut take = |count| weights.by_ref().take(count).collect::<Vec<i16>>();
+        let feature_weights = take(FEATURES * hidden_size);
+        let feature_biases = take(hidden_size);
+        let output_weights = take(2 * hidden_size);
+        let output_bias = i32::from_le_bytes(bytes[bytes.len() - 4..].try_into().unwrap());
+
+        Self::new(
+            hidden_size,
+            feature_weights,
+            feature_biases,
+            output_weights,
+            output_bias,
+        )
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&(self.hidden_size as u32).to_le_bytes());
+        for weight in self
+            .feature_weights
+            .iter()
+            .chain(&self.feature_biases)
+            .chain(&self.output_weights)
+        {
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.output_bias.to_le_bytes());
+
+        bytes
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden_size
+    }
+
+    fn feature_row(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden_size..(feature + 1) * self.hidden_size]
+    }
+
+    // From the side to move's perspective
+    fn output(&self, us: &[i32], them: &[i32]) -> i32 {
+        let sum: i64 = us
+            .iter()
+            .chain(them)
+            .zip(&self.output_weights)
+            .map(|(&value, &weight)| value.clamp(0, ACTIVATION_MAX) as i64 * weight as i64)
+            .sum();
+
+        ((sum + self.output_bias as i64) * OUTPUT_SCALE as i64
+            / (ACTIVATION_MAX * OUTPUT_QUANTIZATION) as i64) as i32
+    }
+}
+
+// The input of a piece on a square, seen by the side whose king is on the given square. Black
+// sees the board flipped, so both sides share the same weights.
+fn feature(
+    perspective: Color,
+    king_square: usize,
+    square: usize,
+    piece: Piece,
+    color: Color,
+) -> usize {
+    let orient = |square: usize| match perspective {
+        Color::White => square,
+        Color::Black => square ^ 56,
+    };
+    let piece_index = piece as usize + if color == perspective { 0 } else { 5 };
+
+    (orient(king_square) * 10 + piece_index) * 64 + orient(square)
+}
+
+// The hidden layers of both perspectives for a position, kept on the board and updated as
+// pieces are put on and taken off it. A king move changes every input of its side, so that
+// side's layer is recomputed instead.
+#[derive(Debug, Clone)]
+pub(crate) struct Accumulator {
+    network: Arc<Network>,
+    // Indexed by the perspective's color
+    values: [Vec<i32>; 2],
+    king_squares: [usize; 2],
+    // A side's layer is out of date while its king is off the board in the middle of a move
+    valid: [bool; 2],
+}
+
+impl PartialEq for Accumulator {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.network, &other.network)
+            && self.values == other.values
+            && self.king_squares == other.king_squares
+            && self.valid == other.valid
+    }
+}
+
+impl Eq for Accumulator {}
+
+impl Accumulator {
+    pub(crate) fn new(network: Arc<Network>, board: &Board) -> Self {
+        let mut accumulator = Self {
+            values: [Vec::new(), Vec::new()],
+            king_squares: [0; 2],
+            valid: [false; 2],
+            network,
+        };
+        for color in [Color::White, Color::Black] {
+            if let Some(king_square) = board.king_square(color) {
+                accumulator.king_squares[color as usize] = king_square;
+                accumulator.refresh(board, color);
+            }
+        }
+
+        accumulator
+    }
+
+    fn refresh(&mut self, board: &Board, perspective: Color) {
+        let king_square = self.king_squares[perspective as usize];
+        let mut values: Vec<i32> = self
+            .network
+            .feature_biases
+            .iter()
+            .map(|&bias| bias as i32)
+            .collect();
+        for square in 0..64 {
+            match (board.squares[square], board.colors[square]) {
+                (Some(piece), Some(color)) if piece != Piece::King => {
+                    let row = self.network.feature_row(feature(
+                        perspective,
+                        king_square,
+                        square,
+                        piece,
+                        color,
+                    ));
+                    for (value, &weight) in values.iter_mut().zip(row) {
+                        *value += weight as i32;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.values[perspective as usize] = values;
+        self.valid[perspective as usize] = true;
+    }
+
+    // Called after the piece was put on the board
+    pub(crate) fn put_piece(&mut self, board: &Board, square: usize, piece: Piece, color: Color) {
+        if piece == Piece::King {
+            self.king_squares[color as usize] = square;
+            self.refresh(board, color);
+        } else {
+            self.update(square, piece, color, 1);
+        }
+    }
+
+    pub(crate) fn remove_piece(&mut self, square: usize, piece: Piece, color: Color) {
+        // Unmaking a move puts the king back before taking it off its target square
+        if piece == Piece::King {
+            if self.king_squares[color as usize] == square {
+                self.valid[color as usize] = false;
+            }
+        } else {
+            self.update(square, piece, color, -1);
+        }
+    }
+
+    fn update(&mut self, square: usize, piece: Piece, color: Color, sign: i32) {
+        for perspective in [Color::White, Color::Black] {
+            if !self.valid[perspective as usize] {
+                continue;
+            }
+            let king_square = self.king_squares[perspective as usize];
+            let row =
+                self.network
+                    .feature_row(feature(perspective, king_square, square, piece, color));
+            for (value, &weight) in self.values[perspective as usize].iter_mut().zip(row) {
+                *value += sign * weight as i32;
+            }
+        }
+    }
+
+    fn evaluate(&self, to_move: Color) -> i32 {
+        self.network.output(
+            &self.values[to_move as usize],
+            &self.values[to_move.opposite_color() as usize],
+        )
+    }
+}
+
+// Evaluates with a neural network instead of the hand-crafted terms, e.g. with the EvalFile
+// option. The hidden layers are updated incrementally on the board the search starts from.
+#[derive(Debug, Clone)]
+pub struct NnueEvaluator {
+    network: Arc<Network>,
+}
+
+impl NnueEvaluator {
+    pub fn new(network: Arc<Network>) -> Self {
+        Self { network }
+    }
+}
+
+impl Evaluator for NnueEvaluator {
+    fn prepare(&self, board: &mut Board) {
+        board.nnue = Some(Box::new(Accumulator::new(Arc::clone(&self.network), board)));
+    }
+
+    fn evaluate(&self, board: &Board) -> Score {
+        let eval = match &board.nnue {
+            Some(accumulator)
+                if Arc::ptr_eq(&accumulator.network, &self.network)
+                    && accumulator.valid == [true; 2] =>
+            {
+                accumulator.evaluate(board.to_move)
+            }
+            // Boards the search didn't prepare are evaluated from scratch
+            _ => Accumulator::new(Arc::clone(&self.network), board).evaluate(board.to_move),
+        };
+
+        Score::Cp(eval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{board_builder::BoardBuilder, evaluate::Evaluator, move_generation::MoveGenerator};
+
+    use super::{Accumulator, Network, NnueEvaluator, FEATURES};
+
+    const HIDDEN_SIZE: usize = 4;
+
+    // Small weights that still tell the pieces and squares apart
+    fn test_network() -> Network {
+        let weight = |i: usize, modulus: usize| (i * 7919 % modulus) as i16 - (modulus / 2) as i16;
+        Network::new(
+            HIDDEN_SIZE,
+            (0..FEATURES * HIDDEN_SIZE).map(|i| weight(i, 61)).collect(),
+            (0..HIDDEN_SIZE).map(|i| weight(i, 31)).collect(),
+            (0..2 * HIDDEN_SIZE).map(|i| weight(i, 41)).collect(),
+            17,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_network_file_round_trip() {
+        let network = test_network();
+        let bytes = network.to_bytes();
+
+        assert_eq!(Network::from_bytes(&bytes).unwrap(), network);
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 2]).is_err());
+        assert!(Network::from_bytes(b"NNUE").is_err());
+        assert!(Network::new(HIDDEN_SIZE, Vec::new(), Vec::new(), Vec::new(), 0).is_err());
+    }
+
+    #[test]
+    fn test_incremental_updates_match_a_full_refresh() {
+        let network = Arc::new(test_network());
+        let evaluator = NnueEvaluator::new(Arc::clone(&network));
+        // Captures, promotions, en passant and castling for both sides
+        let mut move_generator = MoveGenerator::new(
+            BoardBuilder::try_from_fen(
+                "r3k2r/pPppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q2/1PPBBPpP/R3K2R b KQkq a3 0 1",
+            )
+            .unwrap(),
+        );
+        evaluator.prepare(&mut move_generator.board);
+        let original = move_generator.board.clone();
+
+        for mv in move_generator.generate_moves() {
+            move_generator.board.move_piece(&mv);
+            for reply in move_generator.generate_moves() {
+                move_generator.board.move_piece(&reply);
+                let board = &move_generator.board;
+                let refreshed = Accumulator::new(Arc::clone(&network), board);
+                assert!(**board.nnue.as_ref().unwrap() == refreshed);
+                move_generator.board.undo_last_move().unwrap();
+            }
+            move_generator.board.undo_last_move().unwrap();
+        }
+
+        assert!(move_generator.board == original);
+    }
+
+    #[test]
+    fn test_evaluation_is_the_same_for_both_colors() {
+        let evaluator = NnueEvaluator::new(Arc::new(test_network()));
+        let eval = |fen| evaluator.evaluate(&BoardBuilder::try_from_fen(fen).unwrap());
+
+        // The same position with the colors swapped and the board flipped
+        assert_eq!(
+            eval("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3"),
+            eval("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3")
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use std::cell::RefCell;
+
+use crate::{
+    board::Board,
+    piece::{Color, Piece},
+    square::{File, Rank},
+    weights::{Features, Term},
+};
+
+pub const DOUBLED_PAWN_PENALTY: i32 = 15;
+pub const ISOLATED_PAWN_PENALTY: i32 = 15;
+pub const BACKWARD_PAWN_PENALTY: i32 = 10;
+// By rank, counted from the pawn's own side. The further a passed pawn got, the harder it is to
+// stop.
+pub const PASSED_PAWN_BONUS: [i32; 8] = [0, 5, 10, 20, 35, 60, 100, 0];
+// About 100 bytes each, so one and a half megabytes
+const PAWN_HASH_TABLE_ENTRIES: usize = 16 * 1024;
+
+thread_local! {
+    // The same pawn structures come up over and over in a search, and the evaluator is shared
+    // read-only, so every search thread keeps its own table
+    static PAWN_HASH_TABLE: RefCell<PawnHashTable> =
+        RefCell::new(PawnHashTable::new(PAWN_HASH_TABLE_ENTRIES));
+}
+
+// Pawn structure terms by the hash of the pawns alone. The counts are kept rather than the
+// evaluation, so evaluators with different weights can share the table.
+pub struct PawnHashTable {
+    entries: Vec<Option<(u64, Features)>>,
+}
+
+impl PawnHashTable {
+    pub fn new(num_entries: usize) -> Self {
+        Self {
+            entries: vec![None; num_entries.max(1)],
+        }
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Features> {
+        self.entries[self.index(key)]
+            .filter(|(entry_key, _)| *entry_key == key)
+            .map(|(_, features)| features)
+    }
+
+    pub fn store(&mut self, key: u64, features: Features) {
+        let index = self.index(key);
+        self.entries[index] = Some((key, features));
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+}
+
+// Doubled, isolated, backward and passed pawns, which the piece square tables can't see. From
+// white's perspective.
+pub fn pawn_structure(board: &Board) -> Features {
+    let key = board.pawn_key();
+    PAWN_HASH_TABLE.with_borrow_mut(|table| {
+        table.probe(key).unwrap_or_else(|| {
+            let features = pawns_of_both_sides(board);
+            table.store(key, features);
+            features
+        })
+    })
+}
+
+fn pawns_of_both_sides(board: &Board) -> Features {
+    pawns_of_side(board, Color::White) - pawns_of_side(board, Color::Black)
+}
+
+// Penalties count negatively, so the side's pawn structure is worth its features times the weights
+pub fn pawns_of_side(board: &Board, color: Color) -> Features {
+    let own_pawns = pawns(board, color);
+    let enemy_pawns = pawns(board, color.opposite_color());
+    let mut pawns_on_file = [0; 8];
+    for &square in &own_pawns {
+        pawns_on_file[File::of(square).index()] += 1;
+    }
+
+    let mut features = Features::default();
+    for count in pawns_on_file {
+        if count > 1 {
+            features[Term::DoubledPawn] -= count - 1;
+        }
+    }
+
+    for &square in &own_pawns {
+        let file = File::of(square);
+        let rank = relative_rank(square, color);
+        let neighbours: Vec<usize> = own_pawns
+            .iter()
+            .copied()
+            .filter(|&other| File::of(other).distance(file) == 1)
+            .collect();
+
+        let is_passed = enemy_pawns.iter().all(|&enemy| {
+            File::of(enemy).distance(file) > 1 || relative_rank(enemy, color) <= rank
+        });
+        // Its neighbours have all gone ahead, and it can't advance without being taken
+        let is_backward = !neighbours.is_empty()
+            && neighbours
+                .iter()
+                .all(|&neighbour| relative_rank(neighbour, color) > rank)
+            && enemy_pawns.iter().any(|&enemy| {
+                File::of(enemy).distance(file) == 1
+                    && Some(relative_rank(enemy, color)) == rank.offset(2)
+            });
+
+        if is_passed {
+            features[Term::passed_pawn(rank.index())] += 1;
+        }
+        if neighbours.is_empty() {
+            features[Term::IsolatedPawn] -= 1;
+        } else if is_backward && !is_passed {
+            features[Term::BackwardPawn] -= 1;
+        }
+    }
+
+    features
+}
+
+fn pawns(board: &Board, color: Color) -> Vec<usize> {
+    (0..64)
+        .filter(|&square| board.is_piece_at_square(square, Piece::Pawn, color))
+        .collect()
+}
+
+// FIRST for the side's back rank, EIGHTH for the rank it promotes on
+fn relative_rank(square: usize, color: Color) -> Rank {
+    Rank::of(square).relative_to(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Board,
+        board_builder::BoardBuilder,
+        weights::{EvalWeights, Features, Term},
+    };
+
+    use super::{
+        pawn_structure, pawns_of_both_sides, PawnHashTable, BACKWARD_PAWN_PENALTY,
+        DOUBLED_PAWN_PENALTY, ISOLATED_PAWN_PENALTY, PASSED_PAWN_BONUS,
+    };
+
+    fn eval(fen: &str) -> i32 {
+        pawn_structure(&BoardBuilder::try_from_fen(fen).unwrap()).evaluate(&EvalWeights::default())
+    }
+
+    #[test]
+    fn test_symmetrical_structures_are_even() {
+        assert_eq!(pawn_structure(&Board::starting_position()), Features::default());
+        assert_eq!(eval("4k3/pp3ppp/8/8/8/8/PP3PPP/4K3 w - - 0 1"), 0);
+    }
+
+    #[test]
+    fn test_doubled_and_isolated_pawns() {
+        // The c-pawns are doubled and isolated, the black pawns are healthy
+        assert_eq!(
+            eval("4k3/1pp5/8/8/8/2P5/2P5/4K3 w - - 0 1"),
+            -DOUBLED_PAWN_PENALTY - 2 * ISOLATED_PAWN_PENALTY
+        );
+    }
+
+    #[test]
+    fn test_passed_pawns_by_rank() {
+        let far_advanced = eval("4k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(far_advanced, PASSED_PAWN_BONUS[6] - ISOLATED_PAWN_PENALTY);
+        assert!(far_advanced > eval("4k3/8/8/8/P7/8/8/4K3 w - - 0 1"));
+        // A black pawn in front on the neighbouring file stops it from being passed
+        assert_eq!(eval("4k3/1p6/8/8/P7/8/8/4K3 w - - 0 1"), 0);
+        assert_eq!(
+            eval("4k3/8/8/8/8/p7/8/4K3 w - - 0 1"),
+            -PASSED_PAWN_BONUS[5] + ISOLATED_PAWN_PENALTY
+        );
+    }
+
+    #[test]
+    fn test_pawn_hash_table() {
+        let features = |doubled_pawns| {
+            let mut features = Features::default();
+            features[Term::DoubledPawn] = doubled_pawns;
+            features
+        };
+        let mut table = PawnHashTable::new(16);
+        table.store(17, features(2));
+        assert_eq!(table.probe(17), Some(features(2)));
+        // Same slot, different pawns
+        assert_eq!(table.probe(1), None);
+        table.store(1, features(-1));
+        assert_eq!(table.probe(1), Some(features(-1)));
+        assert_eq!(table.probe(17), None);
+    }
+
+    #[test]
+    fn test_cached_evaluation_matches() {
+        let board =
+            BoardBuilder::try_from_fen("4k3/pp3p2/2p3p1/7p/3P4/2P2P2/PP5P/4K3 w - - 0 1").unwrap();
+        let features = pawns_of_both_sides(&board);
+
+        assert_eq!(pawn_structure(&board), features);
+        assert_eq!(pawn_structure(&board), features);
+    }
+
+    #[test]
+    fn test_backward_pawn() {
+        // d3 is left behind by e4 and can't go to d4, which c5 covers. e4 is passed and c5
+        // isolated.
+        assert_eq!(
+            eval("4k3/8/8/2p5/4P3/3P4/8/4K3 w - - 0 1"),
+            -BACKWARD_PAWN_PENALTY + PASSED_PAWN_BONUS[3] + ISOLATED_PAWN_PENALTY
+        );
+    }
+}
@@ -0,0 +1,478 @@
+use std::fmt;
+
+use anyhow::{bail, Result};
+
+// The move suffixes and the numeric annotation glyphs they stand for. Longer ones come first so
+// "!!" isn't read as "!".
+const SUFFIX_NAGS: [(&str, u8); 6] = [
+    ("!!", 3),
+    ("??", 4),
+    ("!?", 5),
+    ("?!", 6),
+    ("!", 1),
+    ("?", 2),
+];
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+// Export format lines are kept under this length
+const MAX_LINE_LENGTH: usize = 79;
+
+// A game with everything PGN can say about it, so it can be read and written back without losing
+// its annotations. Moves are kept as SAN and aren't checked against the position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnGame {
+    // In the order they were given
+    pub tags: Vec<(String, String)>,
+    pub main_line: Line,
+    // "1-0", "0-1", "1/2-1/2" or "*"
+    pub result: String,
+}
+
+// A sequence of moves, the main line of a game or a variation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Line {
+    // Before the first move
+    pub comments: Vec<String>,
+    pub moves: Vec<PgnMove>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PgnMove {
+    pub san: String,
+    // Numeric annotation glyphs, with suffixes like "!?" turned into theirs
+    pub nags: Vec<u8>,
+    // After the move
+    pub comments: Vec<String>,
+    // Lines that could have been played instead of this move
+    pub variations: Vec<Line>,
+}
+
+impl PgnMove {
+    pub fn new(san: &str) -> Self {
+        Self {
+            san: san.to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+impl Line {
+    // Unwrapped, numbered from the given move on, e.g. "3... Qe7 4. Nc3"
+    pub fn to_movetext(&self, number: u32, white_to_move: bool) -> String {
+        let mut tokens = Vec::new();
+        line_tokens(self, number, white_to_move, &mut tokens);
+        tokens.join(" ")
+    }
+}
+
+impl PgnGame {
+    pub fn new(tags: Vec<(String, String)>, main_line: Line, result: &str) -> Self {
+        Self {
+            tags,
+            main_line,
+            result: result.to_string(),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // The move number and side to move the game starts with, from the FEN tag if it has one
+    fn first_move(&self) -> (u32, bool) {
+        let fields: Vec<&str> = self
+            .tag("FEN")
+            .map(|fen| fen.split_whitespace().collect())
+            .unwrap_or_default();
+        let white_to_move = fields.get(1) != Some(&"b");
+        let number = fields
+            .get(5)
+            .and_then(|number| number.parse().ok())
+            .unwrap_or(1);
+
+        (number, white_to_move)
+    }
+}
+
+// Splits a PGN database into games. A game that can't be read doesn't stop the ones after it.
+pub fn parse_games(pgn: &str) -> Vec<Result<PgnGame>> {
+    let mut games = Vec::new();
+    let mut tags = Vec::new();
+    let mut movetext = String::new();
+
+    let mut finish_game = |tags: &mut Vec<(String, String)>, movetext: &mut String| {
+        if !movetext.trim().is_empty() {
+            games.push(parse_movetext(movetext).map(|(main_line, result)| {
+                let result = result
+                    .or_else(|| {
+                        tags.iter()
+                            .find(|(name, _)| name == "Result")
+                            .map(|(_, value)| value.clone())
+                    })
+                    .unwrap_or_else(|| String::from("*"));
+                PgnGame {
+                    tags: tags.clone(),
+                    main_line,
+                    result,
+                }
+            }));
+        }
+        tags.clear();
+        movetext.clear();
+    };
+
+    for line in pgn.lines() {
+        let trimmed = line.trim();
+        if let Some(tag) = trimmed
+            .strip_prefix('[')
+            .and_then(|tag| tag.strip_suffix(']'))
+        {
+            // A tag after moves starts the next game
+            if !movetext.trim().is_empty() {
+                finish_game(&mut tags, &mut movetext);
+            }
+            if let Some((name, value)) = tag.split_once(' ') {
+                tags.push((name.to_string(), unescape(value.trim())));
+            }
+        } else if !line.starts_with('%') {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+    finish_game(&mut tags, &mut movetext);
+
+    games
+}
+
+fn unescape(value: &str) -> String {
+    let value = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value);
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Move(String, Option<u8>),
+    Nag(u8),
+    Comment(String),
+    StartVariation,
+    EndVariation,
+    Result(String),
+}
+
+fn tokenize(movetext: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = movetext.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '{' => {
+                chars.next();
+                let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                tokens.push(Token::Comment(normalize_whitespace(&comment)));
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                tokens.push(Token::Comment(normalize_whitespace(&comment[1..])));
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::StartVariation);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::EndVariation);
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '{' | '}' | ';' | '(' | ')') {
+                        break;
+                    }
+                    // A NAG glued to the move before it
+                    if c == '$' && !word.is_empty() {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.extend(word_token(&word)?);
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn word_token(word: &str) -> Result<Option<Token>> {
+    if let Some(nag) = word.strip_prefix('$') {
+        return match nag.parse() {
+            Ok(nag) => Ok(Some(Token::Nag(nag))),
+            Err(_) => bail!("Invalid NAG: {word}"),
+        };
+    }
+    if RESULTS.contains(&word) {
+        return Ok(Some(Token::Result(word.to_string())));
+    }
+
+    // Move numbers, also when glued to the move like in "1.e4" or "3...a6"
+    let san = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if san.is_empty() {
+        return Ok(None);
+    }
+    // Unless the digits are the move, like castling written with zeros
+    let san = if san.len() < word.len() && !word[..word.len() - san.len()].ends_with('.') {
+        word
+    } else {
+        san
+    };
+
+    for (suffix, nag) in SUFFIX_NAGS {
+        if let Some(san) = san.strip_suffix(suffix) {
+            return Ok(Some(Token::Move(san.to_string(), Some(nag))));
+        }
+    }
+
+    Ok(Some(Token::Move(san.to_string(), None)))
+}
+
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// The main line with its variations, and the result the movetext ends with if it has one
+fn parse_movetext(movetext: &str) -> Result<(Line, Option<String>)> {
+    let mut tokens = tokenize(movetext)?.into_iter();
+    let mut result = None;
+    let main_line = parse_line(&mut tokens, &mut result, false)?;
+
+    Ok((main_line, result))
+}
+
+fn parse_line(
+    tokens: &mut impl Iterator<Item = Token>,
+    result: &mut Option<String>,
+    is_variation: bool,
+) -> Result<Line> {
+    let mut line = Line::default();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Move(san, nag) => line.moves.push(PgnMove {
+                san,
+                nags: nag.into_iter().collect(),
+                ..PgnMove::default()
+            }),
+            Token::Nag(nag) => match line.moves.last_mut() {
+                Some(mv) => mv.nags.push(nag),
+                None => bail!("${nag} comes before any move"),
+            },
+            Token::Comment(comment) => match line.moves.last_mut() {
+                Some(mv) => mv.comments.push(comment),
+                None => line.comments.push(comment),
+            },
+            Token::StartVariation => {
+                let variation = parse_line(tokens, result, true)?;
+                match line.moves.last_mut() {
+                    Some(mv) => mv.variations.push(variation),
+                    None => bail!("A variation comes before any move"),
+                }
+            }
+            Token::EndVariation if is_variation => return Ok(line),
+            Token::EndVariation => bail!("A variation is closed that was never opened"),
+            Token::Result(_) if is_variation => bail!("The game ends inside a variation"),
+            // Anything after the result belongs to no game
+            Token::Result(game_result) => {
+                *result = Some(game_result);
+                break;
+            }
+        }
+    }
+    if is_variation {
+        bail!("A variation is never closed");
+    }
+
+    Ok(line)
+}
+
+// Export format: the tags, a blank line, then the movetext wrapped to lines under 80 characters
+impl fmt::Display for PgnGame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{name} \"{value}\"]")?;
+        }
+        writeln!(f)?;
+
+        let mut tokens = Vec::new();
+        let (number, white_to_move) = self.first_move();
+        line_tokens(&self.main_line, number, white_to_move, &mut tokens);
+        tokens.push(self.result.clone());
+
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length > 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+                writeln!(f)?;
+                line_length = 0;
+            } else if line_length > 0 {
+                write!(f, " ")?;
+                line_length += 1;
+            }
+            line_length += token.len();
+            write!(f, "{token}")?;
+        }
+        writeln!(f)
+    }
+}
+
+fn line_tokens(line: &Line, mut number: u32, mut white_to_move: bool, tokens: &mut Vec<String>) {
+    comment_tokens(&line.comments, tokens);
+    // Black's moves only get a number at the start of a line or after an interruption
+    let mut needs_number = true;
+    for mv in &line.moves {
+        if white_to_move {
+            tokens.push(format!("{number}."));
+        } else if needs_number {
+            tokens.push(format!("{number}..."));
+        }
+        tokens.push(mv.san.clone());
+        tokens.extend(mv.nags.iter().map(|nag| format!("${nag}")));
+        comment_tokens(&mv.comments, tokens);
+        for variation in &mv.variations {
+            let mut variation_tokens = Vec::new();
+            line_tokens(variation, number, white_to_move, &mut variation_tokens);
+            match variation_tokens.as_mut_slice() {
+                [] => tokens.push(String::from("()")),
+                [first, .., last] => {
+                    first.insert(0, '(');
+                    last.push(')');
+                    tokens.extend(variation_tokens);
+                }
+                [only] => {
+                    tokens.push(format!("({only})"));
+                }
+            }
+        }
+        needs_number = !mv.comments.is_empty() || !mv.variations.is_empty();
+
+        if !white_to_move {
+            number += 1;
+        }
+        white_to_move = !white_to_move;
+    }
+}
+
+// Split into words so long comments can be wrapped
+fn comment_tokens(comments: &[String], tokens: &mut Vec<String>) {
+    for comment in comments {
+        let mut words: Vec<String> = comment.split_whitespace().map(String::from).collect();
+        match words.as_mut_slice() {
+            [] => tokens.push(String::from("{}")),
+            [first, .., last] => {
+                first.insert(0, '{');
+                last.push('}');
+                tokens.extend(words);
+            }
+            [only] => tokens.push(format!("{{{only}}}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_games, Line, PgnGame, PgnMove};
+
+    #[test]
+    fn test_parse_movetext() {
+        let game = parse_games(
+            "1. e4 {best by test} e5 (1... c5 2. Nf3) 2. Nf3 $1 Nc6?! ; developing\n3...a6 1-0",
+        )
+        .remove(0)
+        .unwrap();
+        let main_line: Vec<&str> = game
+            .main_line
+            .moves
+            .iter()
+            .map(|mv| mv.san.as_str())
+            .collect();
+
+        assert_eq!(main_line, ["e4", "e5", "Nf3", "Nc6", "a6"]);
+        assert_eq!(game.result, "1-0");
+        let moves = &game.main_line.moves;
+        assert_eq!(moves[0].comments, ["best by test"]);
+        assert_eq!(moves[1].variations[0].moves[1], PgnMove::new("Nf3"));
+        assert_eq!(moves[2].nags, [1]);
+        assert_eq!(moves[3].nags, [6]);
+        assert_eq!(moves[3].comments, ["developing"]);
+    }
+
+    #[test]
+    fn test_pgn_round_trip() {
+        let pgn = r#"[Event "Annotated \"game\""]
+[Result "1-0"]
+
+{A short game} 1. e4 e5 2. Nf3 (2. f4 {the King's Gambit} 2... exf4 (2... d5)
+3. Nf3 $1) 2... Nc6 $10 3. Bb5 {The Ruy Lopez, one of the oldest openings there
+is, and still one of the most popular ones at every level} 3... a6 1-0
+"#;
+        let game = parse_games(pgn).remove(0).unwrap();
+
+        assert_eq!(game.tag("Event"), Some("Annotated \"game\""));
+        assert_eq!(game.main_line.comments, ["A short game"]);
+        let kings_gambit = &game.main_line.moves[2].variations[0];
+        assert_eq!(kings_gambit.moves[0].comments, ["the King's Gambit"]);
+        assert_eq!(kings_gambit.moves[1].variations[0].moves[0].san, "d5");
+        assert_eq!(kings_gambit.moves[2].nags, [1]);
+        assert_eq!(game.to_string(), pgn);
+        assert_eq!(parse_games(&game.to_string()).remove(0).unwrap(), game);
+    }
+
+    #[test]
+    fn test_move_numbers_from_fen() {
+        let line = Line {
+            comments: Vec::new(),
+            moves: vec![PgnMove::new("h6"), PgnMove::new("Ra8+")],
+        };
+        let game = PgnGame::new(
+            vec![(
+                String::from("FEN"),
+                String::from("6k1/5ppp/8/8/8/8/5PPP/R5K1 b - - 0 30"),
+            )],
+            line,
+            "1-0",
+        );
+
+        assert!(game.to_string().ends_with("\n\n30... h6 31. Ra8+ 1-0\n"));
+    }
+
+    #[test]
+    fn test_invalid_movetext() {
+        for movetext in [
+            "1. e4 (1. d4",
+            "1. e4 e5)",
+            "(1. d4) 1. e4",
+            "$1 e4",
+            "1. e4 (e5 1-0)",
+        ] {
+            assert!(parse_games(movetext).remove(0).is_err(), "{movetext}");
+        }
+        // One broken game doesn't stop the next
+        let games = parse_games("[Event \"A\"]\n\n1. e4 )\n\n[Event \"B\"]\n\n1. d4 *\n");
+        assert!(games[0].is_err());
+        assert_eq!(games[1].as_ref().unwrap().tag("Event"), Some("B"));
+    }
+}
@@ -3,6 +3,7 @@ use core::fmt;
 use crate::piece_square_table::*;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Piece {
     Pawn,
     Knight,
@@ -39,6 +40,44 @@ impl Piece {
         }
     }
 
+    // The piece and its color for a FEN letter, like 'n' for a black knight
+    pub fn from_symbol(symbol: char) -> Option<(Piece, Color)> {
+        let piece = match symbol.to_ascii_lowercase() {
+            'p' => Piece::Pawn,
+            'n' => Piece::Knight,
+            'b' => Piece::Bishop,
+            'r' => Piece::Rook,
+            'q' => Piece::Queen,
+            'k' => Piece::King,
+            _ => return None,
+        };
+        let color = if symbol.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+
+        Some((piece, color))
+    }
+
+    // White's are the outlined symbols, black's the filled ones
+    pub fn to_unicode_symbol(&self, color: Color) -> char {
+        match (self, color) {
+            (Self::Pawn, Color::White) => '♙',
+            (Self::Pawn, Color::Black) => '♟',
+            (Self::Knight, Color::White) => '♘',
+            (Self::Knight, Color::Black) => '♞',
+            (Self::Bishop, Color::White) => '♗',
+            (Self::Bishop, Color::Black) => '♝',
+            (Self::Rook, Color::White) => '♖',
+            (Self::Rook, Color::Black) => '♜',
+            (Self::Queen, Color::White) => '♕',
+            (Self::Queen, Color::Black) => '♛',
+            (Self::King, Color::White) => '♔',
+            (Self::King, Color::Black) => '♚',
+        }
+    }
+
     pub fn is_sliding_piece(&self) -> bool {
         matches!(self, Piece::Queen | Piece::Rook | Piece::Bishop)
     }
@@ -47,7 +86,7 @@ impl Piece {
         PIECE_VALUE_TABLE[*self as usize]
     }
 
-    pub fn position_value(&self, square: usize, color: Color) -> i32 {
+    pub fn position_value(&self, square: usize, color: Color) -> Tapered {
         let index = match color {
             Color::White => {
                 let rank = square / 8;
@@ -59,11 +98,12 @@ impl Piece {
             Color::Black => square,
         };
 
-        PIECE_SQUARE_TABLES[*self as usize][index]
+        piece_square_tables().value(*self, index)
     }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Color {
     White,
     Black,
@@ -1,3 +1,15 @@
+use std::{
+    fmt, fs,
+    iter::Sum,
+    ops::{Add, AddAssign, Sub, SubAssign},
+    path::Path,
+    sync::OnceLock,
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::piece::Piece;
+
 // Source: https://www.chessprogramming.org/Simplified_Evaluation_Function
 
 #[rustfmt::skip]
@@ -72,7 +84,22 @@ pub const KING_MIDDLE_GAME_SQUARE_TABLE: [i32; 64] = [
      20, 30, 10,  0,  0, 10, 30, 20
 ];
 
-pub const PIECE_SQUARE_TABLES: [[i32; 64]; 6] = [
+// Once the queens and most pieces are gone the king is safe in the center and needed there
+#[rustfmt::skip]
+pub const KING_END_GAME_SQUARE_TABLE: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50
+];
+
+// Indexed by piece. These are the built-in tables, read from black's side: white's squares are
+// flipped.
+pub const MIDDLE_GAME_SQUARE_TABLES: [[i32; 64]; 6] = [
     PAWN_SQUARE_TABLE,
     KNIGHT_SQUARE_TABLE,
     BISHOP_SQUARE_TABLE,
@@ -80,3 +107,234 @@ pub const PIECE_SQUARE_TABLES: [[i32; 64]; 6] = [
     QUEEN_SQUARE_TABLE,
     KING_MIDDLE_GAME_SQUARE_TABLE,
 ];
+// Only the king changes its mind about where it belongs
+pub const END_GAME_SQUARE_TABLES: [[i32; 64]; 6] = [
+    PAWN_SQUARE_TABLE,
+    KNIGHT_SQUARE_TABLE,
+    BISHOP_SQUARE_TABLE,
+    ROOK_SQUARE_TABLE,
+    QUEEN_SQUARE_TABLE,
+    KING_END_GAME_SQUARE_TABLE,
+];
+
+// How much each piece counts towards the game phase, which goes from MAX_PHASE with all pieces
+// on the board down to 0 with only kings and pawns left
+pub const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+pub const MAX_PHASE: i32 = 24;
+
+// A middle game and an end game value, blended by how far the game has gone
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tapered {
+    pub middle_game: i32,
+    pub end_game: i32,
+}
+
+impl Tapered {
+    pub fn new(middle_game: i32, end_game: i32) -> Self {
+        Self {
+            middle_game,
+            end_game,
+        }
+    }
+
+    pub fn blend(self, phase: i32) -> i32 {
+        let phase = phase.clamp(0, MAX_PHASE);
+        (self.middle_game * phase + self.end_game * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Tapered {
+    type Output = Tapered;
+
+    fn add(self, other: Tapered) -> Tapered {
+        Tapered::new(self.middle_game + other.middle_game, self.end_game + other.end_game)
+    }
+}
+
+impl Sub for Tapered {
+    type Output = Tapered;
+
+    fn sub(self, other: Tapered) -> Tapered {
+        Tapered::new(self.middle_game - other.middle_game, self.end_game - other.end_game)
+    }
+}
+
+impl AddAssign for Tapered {
+    fn add_assign(&mut self, other: Tapered) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Tapered {
+    fn sub_assign(&mut self, other: Tapered) {
+        *self = *self - other;
+    }
+}
+
+impl Sum for Tapered {
+    fn sum<I: Iterator<Item = Tapered>>(iter: I) -> Tapered {
+        iter.fold(Tapered::default(), Add::add)
+    }
+}
+
+const PIECE_NAMES: [&str; 6] = ["pawn", "knight", "bishop", "rook", "queen", "king"];
+
+// The tables used by the evaluation and move ordering, the built-in ones unless others were set
+// with use_piece_square_tables
+static PIECE_SQUARE_TABLES: OnceLock<PieceSquareTables> = OnceLock::new();
+
+pub fn piece_square_tables() -> &'static PieceSquareTables {
+    PIECE_SQUARE_TABLES.get_or_init(PieceSquareTables::default)
+}
+
+// Only works before the tables are first used, e.g. at startup, since boards keep running sums
+// of the values
+pub fn use_piece_square_tables(tables: PieceSquareTables) -> Result<()> {
+    PIECE_SQUARE_TABLES
+        .set(tables)
+        .map_err(|_| anyhow!("The piece square tables are already in use"))
+}
+
+// A middle game and an end game table for every piece, e.g. loaded from a file to try out other
+// values without recompiling
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PieceSquareTables {
+    middle_game: [[i32; 64]; 6],
+    end_game: [[i32; 64]; 6],
+}
+
+impl Default for PieceSquareTables {
+    fn default() -> Self {
+        Self {
+            middle_game: MIDDLE_GAME_SQUARE_TABLES,
+            end_game: END_GAME_SQUARE_TABLES,
+        }
+    }
+}
+
+// One "name = [...]" array per table, which is also valid TOML. The values are laid out like the
+// board seen from white's side, the eighth rank first.
+impl fmt::Display for PieceSquareTables {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (phase, tables) in [
+            ("middle_game", &self.middle_game),
+            ("end_game", &self.end_game),
+        ] {
+            for (name, table) in PIECE_NAMES.iter().zip(tables) {
+                writeln!(f, "{name}_{phase} = [")?;
+                for rank in table.chunks(8) {
+                    let values: Vec<String> =
+                        rank.iter().map(|value| format!("{value:>4},")).collect();
+                    writeln!(f, "   {}", values.join(""))?;
+                }
+                writeln!(f, "]")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PieceSquareTables {
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    // Tables that aren't mentioned keep their built-in values. '#' starts a comment.
+    pub fn parse(text: &str) -> Result<Self> {
+        let text: Vec<&str> = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap())
+            .collect();
+        let mut tables = Self::default();
+        let mut rest = text.join("\n");
+        while !rest.trim().is_empty() {
+            let (name, after) = rest
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expected 'name = [...]'"))?;
+            let name = name.trim();
+            let (values, after) = after
+                .trim_start()
+                .strip_prefix('[')
+                .and_then(|after| after.split_once(']'))
+                .ok_or_else(|| anyhow!("{name} must be an array"))?;
+            let values: Vec<i32> = values
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|value| !value.is_empty())
+                .map(|value| {
+                    value
+                        .parse()
+                        .map_err(|_| anyhow!("{name}: {value} is not a number"))
+                })
+                .collect::<Result<_>>()?;
+            if values.len() != 64 {
+                bail!("{name} needs 64 values, not {}", values.len());
+            }
+            *tables.table_mut(name)? = values.try_into().unwrap();
+            rest = after.to_string();
+        }
+
+        Ok(tables)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    // index is into the table as laid out, the eighth rank first
+    pub fn value(&self, piece: Piece, index: usize) -> Tapered {
+        Tapered::new(
+            self.middle_game[piece as usize][index],
+            self.end_game[piece as usize][index],
+        )
+    }
+
+    fn table_mut(&mut self, name: &str) -> Result<&mut [i32; 64]> {
+        let piece = PIECE_NAMES
+            .iter()
+            .position(|piece| name.starts_with(&format!("{piece}_")));
+        match (piece, piece.map(|piece| &name[PIECE_NAMES[piece].len() + 1..])) {
+            (Some(piece), Some("middle_game")) => Ok(&mut self.middle_game[piece]),
+            (Some(piece), Some("end_game")) => Ok(&mut self.end_game[piece]),
+            _ => bail!("Unknown piece square table: {name}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::piece::Piece;
+
+    use super::{PieceSquareTables, Tapered, KING_END_GAME_SQUARE_TABLE, MAX_PHASE};
+
+    #[test]
+    fn test_blend() {
+        let value = Tapered::new(-30, 30);
+
+        assert_eq!(value.blend(MAX_PHASE), -30);
+        assert_eq!(value.blend(0), 30);
+        assert_eq!(value.blend(MAX_PHASE / 2), 0);
+        assert_eq!(value.blend(MAX_PHASE + 8), -30);
+    }
+
+    #[test]
+    fn test_piece_square_tables_file() {
+        let tables = PieceSquareTables::default();
+        assert_eq!(PieceSquareTables::parse(&tables.to_string()).unwrap(), tables);
+
+        let knight_on_a8 =
+            "# Knights love corners\nknight_middle_game = [\n    50, 0, 0, 0, 0, 0, 0, 0,\n"
+                .to_string()
+                + &"    0, 0, 0, 0, 0, 0, 0, 0,\n".repeat(7)
+                + "]";
+        let tables = PieceSquareTables::parse(&knight_on_a8).unwrap();
+        assert_eq!(tables.value(Piece::Knight, 0).middle_game, 50);
+        assert_eq!(tables.value(Piece::Knight, 0).end_game, -50);
+        assert_eq!(tables.value(Piece::King, 0).end_game, KING_END_GAME_SQUARE_TABLE[0]);
+
+        assert!(PieceSquareTables::parse("knight_middle_game = [1, 2, 3]").is_err());
+        assert!(PieceSquareTables::parse("knight_middle_game = 1").is_err());
+        assert!(PieceSquareTables::parse(&knight_on_a8.replace("knight", "unicorn")).is_err());
+        assert!(PieceSquareTables::parse(&knight_on_a8.replace("50", "lots")).is_err());
+    }
+}
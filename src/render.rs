@@ -0,0 +1,207 @@
+use std::fmt::Write;
+
+use anyhow::{bail, Result};
+
+use crate::{
+    board::Board,
+    piece::{Color, Piece},
+    square::{Square, SquareColor},
+};
+
+// How the pieces are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PieceSet {
+    // The chess symbols of Unicode, like ♞, which need a font that has them
+    Unicode,
+    // The FEN letters in a disc of the piece's color, which any font can show
+    Letters,
+}
+
+impl PieceSet {
+    pub fn try_from_name(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "unicode" => Ok(PieceSet::Unicode),
+            "letters" => Ok(PieceSet::Letters),
+            _ => bail!("Unknown piece set: {name}, must be unicode or letters"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    // In pixels
+    pub square_size: u32,
+    // Any SVG color, like "#f0d9b5" or "white"
+    pub light_square_color: String,
+    pub dark_square_color: String,
+    pub piece_set: PieceSet,
+    // File letters and rank numbers in the squares along the edges
+    pub coordinates: bool,
+    // With black at the bottom
+    pub flipped: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            square_size: 60,
+            light_square_color: String::from("#f0d9b5"),
+            dark_square_color: String::from("#b58863"),
+            piece_set: PieceSet::Unicode,
+            coordinates: true,
+            flipped: false,
+        }
+    }
+}
+
+// An SVG image of the board, e.g. to share a position or put it in documentation
+pub fn render_svg(board: &Board, options: &RenderOptions) -> String {
+    let size = options.square_size;
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{0}" viewBox="0 0 {0} {0}">"#,
+        size * 8
+    )
+    .unwrap();
+
+    for row in 0..8 {
+        for column in 0..8 {
+            let (rank, file) = if options.flipped {
+                (row, 7 - column)
+            } else {
+                (7 - row, column)
+            };
+            let square = rank * 8 + file;
+            let (x, y) = (column as u32 * size, row as u32 * size);
+            let is_light = Square::from_index(square).color() == SquareColor::Light;
+            let (color, other_color) = if is_light {
+                (&options.light_square_color, &options.dark_square_color)
+            } else {
+                (&options.dark_square_color, &options.light_square_color)
+            };
+            writeln!(
+                svg,
+                r#"<rect x="{x}" y="{y}" width="{size}" height="{size}" fill="{color}"/>"#
+            )
+            .unwrap();
+
+            if options.coordinates {
+                let font_size = size / 5;
+                // The files along the bottom, the ranks along the left
+                if row == 7 {
+                    writeln!(
+                        svg,
+                        r#"<text x="{}" y="{}" font-size="{font_size}" font-family="sans-serif" text-anchor="end" fill="{other_color}">{}</text>"#,
+                        x + size - size / 20,
+                        y + size - size / 20,
+                        (b'a' + file as u8) as char
+                    )
+                    .unwrap();
+                }
+                if column == 0 {
+                    writeln!(
+                        svg,
+                        r#"<text x="{}" y="{}" font-size="{font_size}" font-family="sans-serif" fill="{other_color}">{}</text>"#,
+                        x + size / 20,
+                        y + font_size,
+                        rank + 1
+                    )
+                    .unwrap();
+                }
+            }
+
+            if let (Some(piece), Some(piece_color)) = (board.squares[square], board.colors[square])
+            {
+                render_piece(&mut svg, piece, piece_color, x, y, options);
+            }
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn render_piece(
+    svg: &mut String,
+    piece: Piece,
+    color: Color,
+    x: u32,
+    y: u32,
+    options: &RenderOptions,
+) {
+    let size = options.square_size;
+    let (center_x, center_y) = (x + size / 2, y + size / 2);
+    let (fill, outline) = match color {
+        Color::White => ("#ffffff", "#000000"),
+        Color::Black => ("#000000", "#ffffff"),
+    };
+    match options.piece_set {
+        PieceSet::Unicode => {
+            // The filled symbols for both sides, so the color is the fill's
+            let symbol = piece.to_unicode_symbol(Color::Black);
+            writeln!(
+                svg,
+                r#"<text x="{center_x}" y="{center_y}" font-size="{}" text-anchor="middle" dominant-baseline="central" fill="{fill}" stroke="{outline}" stroke-width="{}">{symbol}</text>"#,
+                size * 4 / 5,
+                (size / 40).max(1)
+            )
+            .unwrap();
+        }
+        PieceSet::Letters => {
+            writeln!(
+                svg,
+                r#"<circle cx="{center_x}" cy="{center_y}" r="{}" fill="{fill}" stroke="{outline}" stroke-width="{}"/>"#,
+                size * 2 / 5,
+                (size / 30).max(1)
+            )
+            .unwrap();
+            writeln!(
+                svg,
+                r#"<text x="{center_x}" y="{center_y}" font-size="{}" font-family="sans-serif" font-weight="bold" text-anchor="middle" dominant-baseline="central" fill="{outline}">{}</text>"#,
+                size / 2,
+                piece.to_symbol(Color::White)
+            )
+            .unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::Board;
+
+    use super::{render_svg, PieceSet, RenderOptions};
+
+    #[test]
+    fn test_render_svg() {
+        let svg = render_svg(&Board::starting_position(), &RenderOptions::default());
+
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="480""#));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect ").count(), 64);
+        assert_eq!(svg.matches('♟').count(), 16);
+        // a8 in the top left corner is light, with the black rook on it
+        assert!(svg.contains(
+            "<rect x=\"0\" y=\"0\" width=\"60\" height=\"60\" fill=\"#f0d9b5\"/>\n<text x=\"3\" y=\"12\" font-size=\"12\" font-family=\"sans-serif\" fill=\"#b58863\">8</text>\n<text x=\"30\" y=\"30\" font-size=\"48\" text-anchor=\"middle\" dominant-baseline=\"central\" fill=\"#000000\" stroke=\"#ffffff\" stroke-width=\"1\">♜</text>"
+        ));
+    }
+
+    #[test]
+    fn test_render_svg_flipped_with_letters() {
+        let options = RenderOptions {
+            piece_set: PieceSet::Letters,
+            coordinates: false,
+            flipped: true,
+            ..RenderOptions::default()
+        };
+        let svg = render_svg(&Board::starting_position(), &options);
+
+        assert_eq!(svg.matches("<circle ").count(), 32);
+        assert!(!svg.contains("font-size=\"12\""));
+        // h1 in the top left corner, with the white rook on it
+        assert!(svg.contains("<rect x=\"0\" y=\"0\" width=\"60\" height=\"60\" fill=\"#f0d9b5\"/>\n<circle cx=\"30\" cy=\"30\" r=\"24\" fill=\"#ffffff\""));
+        assert_eq!(PieceSet::try_from_name("Letters").unwrap(), PieceSet::Letters);
+        assert!(PieceSet::try_from_name("merida").is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+
+use crate::{
+    board::Board,
+    eco,
+    move_generation::{Move, MoveGenerator},
+    search::SearchLimits,
+};
+
+// Openings the engine should steer towards or stay away from, read from a JSON file such as
+// {"boost": ["e2e4", "C6"], "ban": ["B20", "d2d4 d7d5 c2c4 d5c4"]}. An entry is either an ECO
+// code, or the start of one, or a line of UCI moves from the starting position. Only the
+// engine's own moves are affected: the move that completes a line, or that enters an opening.
+#[derive(Debug, Clone, Default)]
+pub struct Repertoire {
+    boost: Vec<Entry>,
+    ban: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preference {
+    Boost,
+    Ban,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Entry {
+    Eco(String),
+    Line(Vec<Move>),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RepertoireFile {
+    #[serde(default)]
+    boost: Vec<String>,
+    #[serde(default)]
+    ban: Vec<String>,
+}
+
+impl Repertoire {
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let file: RepertoireFile =
+            serde_json::from_str(json).map_err(|err| anyhow!("invalid repertoire: {err}"))?;
+        let parse = |entries: Vec<String>| -> Result<Vec<Entry>> {
+            entries.iter().map(|entry| parse_entry(entry)).collect()
+        };
+
+        Ok(Self {
+            boost: parse(file.boost)?,
+            ban: parse(file.ban)?,
+        })
+    }
+
+    // How playing mv after the given moves from the starting position fits the repertoire.
+    // A ban wins over a boost.
+    pub fn preference(&self, game_moves: &[Move], mv: &Move) -> Option<Preference> {
+        let opening_before = eco::classify(game_moves);
+        let moves_after = [game_moves, std::slice::from_ref(mv)].concat();
+        let opening_after = eco::classify(&moves_after);
+
+        let matches = |entry: &Entry| match entry {
+            Entry::Line(line) => *line == moves_after,
+            Entry::Eco(code) => {
+                let in_opening = |opening: Option<eco::Opening>| {
+                    opening.is_some_and(|opening| opening.eco.starts_with(code.as_str()))
+                };
+                in_opening(opening_after) && !in_opening(opening_before)
+            }
+        };
+
+        if self.ban.iter().any(matches) {
+            Some(Preference::Ban)
+        } else if self.boost.iter().any(matches) {
+            Some(Preference::Boost)
+        } else {
+            None
+        }
+    }
+
+    // Keeps the search away from banned moves, unless there's nothing else to play, and marks
+    // boosted ones as preferred
+    pub fn apply(&self, game_moves: &[Move], board: &Board, limits: &mut SearchLimits) {
+        let moves: Vec<Move> = MoveGenerator::new(board.without_history())
+            .generate_moves()
+            .into_iter()
+            .filter(|mv| limits.allows_root_move(mv))
+            .collect();
+
+        let mut banned = Vec::new();
+        for mv in moves.iter() {
+            match self.preference(game_moves, mv) {
+                Some(Preference::Ban) => banned.push(mv.clone()),
+                Some(Preference::Boost) => limits.preferred_moves.push(mv.clone()),
+                None => {}
+            }
+        }
+        if banned.len() < moves.len() {
+            limits.excluded_moves.extend(banned);
+        }
+    }
+}
+
+fn parse_entry(entry: &str) -> Result<Entry> {
+    let is_eco = entry.len() <= 3
+        && entry.starts_with(['A', 'B', 'C', 'D', 'E'])
+        && entry[1..].chars().all(|c| c.is_ascii_digit());
+    if is_eco {
+        return Ok(Entry::Eco(entry.to_string()));
+    }
+
+    let mut board = Board::starting_position();
+    let mut line = Vec::new();
+    for uci in entry.split_whitespace() {
+        let mv = Move::try_from_uci(uci, &board)
+            .map_err(|err| anyhow!("invalid repertoire line \"{entry}\" at {uci}: {err}"))?;
+        board.move_piece(&mv);
+        line.push(mv);
+    }
+    if line.is_empty() {
+        bail!("empty repertoire entry");
+    }
+
+    Ok(Entry::Line(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Board,
+        move_generation::{Flag, Move},
+        search::SearchLimits,
+        square::Square::*,
+    };
+    use anyhow::Result;
+
+    use super::{Preference, Repertoire};
+
+    fn play(uci_moves: &[&str]) -> (Vec<Move>, Board) {
+        let (board, moves) = Board::starting_position()
+            .play_uci_moves(uci_moves)
+            .unwrap();
+
+        (moves, board)
+    }
+
+    #[test]
+    fn test_preference_by_line_and_eco() -> Result<()> {
+        let repertoire = Repertoire::from_json(r#"{"boost": ["e2e4", "C6"], "ban": ["B20"]}"#)?;
+        let e4 = Move::from_square(E2, E4, Flag::PawnDoublePush);
+
+        assert_eq!(repertoire.preference(&[], &e4), Some(Preference::Boost));
+        assert_eq!(
+            repertoire.preference(&[], &Move::from_square(D2, D4, Flag::PawnDoublePush)),
+            None
+        );
+
+        // 1. e4 c5 is the Sicilian
+        let (moves, _) = play(&["e2e4"]);
+        let c5 = Move::from_square(C7, C5, Flag::PawnDoublePush);
+        assert_eq!(repertoire.preference(&moves, &c5), Some(Preference::Ban));
+
+        // 3. Bb5 enters the Ruy Lopez, C60, but staying in it isn't boosted any further
+        let (moves, _) = play(&["e2e4", "e7e5", "g1f3", "b8c6"]);
+        let bb5 = Move::from_square(F1, B5, Flag::None);
+        assert_eq!(repertoire.preference(&moves, &bb5), Some(Preference::Boost));
+        let (moves, _) = play(&["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6"]);
+        let ba4 = Move::from_square(B5, A4, Flag::None);
+        assert_eq!(repertoire.preference(&moves, &ba4), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_to_search_limits() -> Result<()> {
+        let repertoire = Repertoire::from_json(r#"{"boost": ["e2e4 e7e5"], "ban": ["C"]}"#)?;
+        let (moves, board) = play(&["e2e4"]);
+        let mut limits = SearchLimits::default();
+
+        repertoire.apply(&moves, &board, &mut limits);
+
+        // The ban on all C openings wins over the boost, and keeps black from 1... e6 too
+        assert_eq!(limits.excluded_moves.len(), 2);
+        assert!(limits.preferred_moves.is_empty());
+        assert!(limits
+            .excluded_moves
+            .contains(&Move::from_square(E7, E6, Flag::None)));
+
+        // With only banned moves left, the search may still play them
+        let mut limits = SearchLimits {
+            search_moves: vec![Move::from_square(E7, E5, Flag::PawnDoublePush)],
+            ..SearchLimits::default()
+        };
+        repertoire.apply(&moves, &board, &mut limits);
+        assert!(limits.excluded_moves.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_repertoires() {
+        assert!(Repertoire::from_json(r#""e2e4""#).is_err());
+        assert!(Repertoire::from_json(r#"{"bost": ["e2e4"]}"#).is_err());
+        assert!(Repertoire::from_json(r#"{"ban": ["e2e5"]}"#).is_err());
+        assert!(Repertoire::from_json(r#"{"ban": [""]}"#).is_err());
+        assert!(Repertoire::from_json(r#"{"ban": ["e2e4"]}"#).is_ok());
+    }
+}
@@ -0,0 +1,115 @@
+use std::{cmp::Ordering, fmt, ops::Neg};
+
+use crate::search::{mate_in_moves, MATE_SCORE};
+
+// A score from the side to move's perspective. Mate(n) counts moves until mate: positive if the
+// side to move mates, negative if it gets mated. Any mate for the side to move is better than
+// every centipawn score, and the sooner the better.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Score {
+    Cp(i32),
+    Mate(i8),
+}
+
+impl Score {
+    // The search works on plain integers, with mates encoded as scores close to MATE_SCORE
+    pub fn from_search(score: i32) -> Self {
+        match mate_in_moves(score) {
+            Some(moves) => Score::Mate(moves.clamp(i8::MIN.into(), i8::MAX.into()) as i8),
+            None => Score::Cp(score),
+        }
+    }
+
+    pub fn to_search(self) -> i32 {
+        match self {
+            Score::Cp(cp) => cp,
+            Score::Mate(moves) if moves > 0 => MATE_SCORE - (2 * moves as i32 - 1),
+            Score::Mate(moves) => -(MATE_SCORE - 2 * (moves as i32).abs()),
+        }
+    }
+
+    pub fn is_mate(self) -> bool {
+        matches!(self, Score::Mate(_))
+    }
+}
+
+impl Default for Score {
+    fn default() -> Self {
+        Score::Cp(0)
+    }
+}
+
+// Flips the perspective to the other side
+impl Neg for Score {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        match self {
+            Score::Cp(cp) => Score::Cp(-cp),
+            Score::Mate(moves) => Score::Mate(moves.saturating_neg()),
+        }
+    }
+}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.to_search().cmp(&other.to_search())
+    }
+}
+
+// Formatted like the UCI `info score` field, e.g. `cp 35` or `mate -3`
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Score::Cp(cp) => write!(f, "cp {cp}"),
+            Score::Mate(moves) => write!(f, "mate {moves}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::search::MATE_SCORE;
+
+    use super::Score;
+
+    #[test]
+    fn test_from_search_score() {
+        // Mated in 2 moves: the side to move gets mated on the 4th ply
+        assert_eq!(Score::from_search(-(MATE_SCORE - 4)), Score::Mate(-2));
+        // Mating in 3 moves: the side to move delivers mate on the 5th ply
+        assert_eq!(Score::from_search(MATE_SCORE - 5), Score::Mate(3));
+        assert_eq!(Score::from_search(-120), Score::Cp(-120));
+
+        assert_eq!(Score::Mate(3).to_search(), MATE_SCORE - 5);
+        assert_eq!(Score::Mate(-2).to_search(), -(MATE_SCORE - 4));
+    }
+
+    #[test]
+    fn test_score_ordering() {
+        assert!(Score::Mate(1) > Score::Mate(3));
+        assert!(Score::Mate(3) > Score::Cp(900));
+        assert!(Score::Cp(900) > Score::Cp(-900));
+        assert!(Score::Cp(-900) > Score::Mate(-3));
+        assert!(Score::Mate(-3) > Score::Mate(-1));
+    }
+
+    #[test]
+    fn test_negation_flips_perspective() {
+        assert_eq!(-Score::Cp(35), Score::Cp(-35));
+        assert_eq!(-Score::Mate(2), Score::Mate(-2));
+        assert_eq!(-Score::Mate(i8::MIN), Score::Mate(i8::MAX));
+    }
+
+    #[test]
+    fn test_display_in_uci_format() {
+        assert_eq!(Score::Mate(-2).to_string(), "mate -2");
+        assert_eq!(Score::Cp(35).to_string(), "cp 35");
+    }
+}
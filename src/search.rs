@@ -1,141 +1,356 @@
 use anyhow::{bail, Result};
-use reqwest::{self, blocking::Client};
-use serde::Deserialize;
-use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, Receiver},
+    Arc,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info_span, warn};
 
 use crate::{
-    evaluate::evaluate,
-    move_generation::{Flag, Move, MoveGenerator},
+    board::Board,
+    endgame::is_insufficient_material,
+    evaluate::{DefaultEvaluator, Evaluator},
+    mcts::Mcts,
+    move_generation::{Move, MoveGenerator},
+    move_list::MoveList,
+    move_ordering::{DefaultMoveOrderer, MoveOrderer},
+    nnue::{Network, NnueEvaluator},
+    piece::Color,
+    score::Score,
+    strength::Random,
+    tablebase::{query_tablebase, TablebaseSettings},
+    transposition_table::{score_from_tt, score_to_tt, Bound, Entry, TranspositionTable},
+    weights::EvalWeights,
 };
 
 const INF: i32 = i32::MAX;
-pub static COUNTER: AtomicI32 = AtomicI32::new(0);
-
-#[allow(unused)]
-#[derive(Debug, Deserialize)]
-pub struct TablebaseResponse {
-    pub dtz: Option<i32>,
-    pub precise_dtz: Option<i32>,
-    pub dtm: Option<i32>,
-    pub checkmate: bool,
-    pub stalemate: bool,
-    pub insufficient_material: bool,
-    pub category: Category,
-    pub moves: Vec<TablebaseMove>,
+// Mate scores are offset by the ply at which the mate happens, so that faster mates score higher
+pub const MATE_SCORE: i32 = 1_000_000;
+pub(crate) const MAX_MATE_PLY: i32 = 1_000;
+// Deep enough to never be reached in practice, shallow enough to not overflow the stack when
+// searching "infinitely" in positions with very few moves
+pub const MAX_SEARCH_DEPTH: u32 = 64;
+// Only report the root move currently being searched once the search has been running for a
+// while, otherwise short searches would flood the GUI with info lines
+const CURRMOVE_REPORT_DELAY: Duration = Duration::from_secs(1);
+
+// Depth searched by a plain "go" without any other limit
+pub const DEFAULT_SEARCH_DEPTH: u32 = 6;
+// Enough to pick a preferred move over an equally good one, not enough to drop a pawn for it
+pub const PREFERRED_MOVE_BONUS: i32 = 30;
+// Without movestogo, assume the clock has to last this many more moves
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// Kept in reserve for the time it takes the GUI to receive the move
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+
+// Conditions under which the search stops on its own. Without a depth limit, the search goes as
+// deep as the other limits allow.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    // Time left on the clocks, and the increments after each move
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    // Moves left until the next time control
+    pub movestogo: Option<u32>,
+    // Look for a mate in this many moves with the mate solver before searching normally
+    pub mate: Option<u32>,
+    pub infinite: bool,
+    // If not empty, only these moves are considered at the root
+    pub search_moves: Vec<Move>,
+    // These moves are never considered at the root
+    pub excluded_moves: Vec<Move>,
+    // These root moves get PREFERRED_MOVE_BONUS on top of their score, e.g. from the repertoire
+    pub preferred_moves: Vec<Move>,
+    // Root move scores are randomly shifted by up to this many centipawns to weaken the play
+    pub max_root_noise: i32,
+    // Whether and where to look up positions with few pieces left before searching
+    pub tablebase: TablebaseSettings,
 }
 
-#[allow(unused)]
-#[derive(Debug, Deserialize)]
-pub struct TablebaseMove {
-    uci: String,
-    san: String,
-    dtz: Option<i32>,
-    precise_dtz: Option<i32>,
-    dtm: Option<i32>,
-    zeroing: bool,
-    checkmate: bool,
-    stalemate: bool,
-    insufficient_material: bool,
-    category: Category,
+impl SearchLimits {
+    pub fn depth(depth: u32) -> Self {
+        Self {
+            depth: Some(depth),
+            ..Self::default()
+        }
+    }
+
+    fn max_depth(&self) -> u32 {
+        let has_time_limit =
+            self.movetime.is_some() || self.wtime.is_some() || self.btime.is_some();
+        let unlimited = self.infinite || self.nodes.is_some() || has_time_limit;
+        let default_depth = if unlimited {
+            MAX_SEARCH_DEPTH
+        } else {
+            DEFAULT_SEARCH_DEPTH
+        };
+
+        self.depth.unwrap_or(default_depth).min(MAX_SEARCH_DEPTH)
+    }
+
+    // How long the search for a move of the given side may take, if it's limited by time at all.
+    // From the clock, an equal share of the time left for the remaining moves is used.
+    pub fn time_budget(&self, to_move: Color) -> Option<Duration> {
+        if self.infinite {
+            return None;
+        }
+
+        let (time_left, increment) = match to_move {
+            Color::White => (self.wtime, self.winc),
+            Color::Black => (self.btime, self.binc),
+        };
+        let clock_budget = time_left.map(|time_left| {
+            let moves_to_go = self.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+            let budget = time_left / moves_to_go + increment.unwrap_or_default() / 2;
+            budget.min(time_left.saturating_sub(MOVE_OVERHEAD))
+        });
+
+        match (self.movetime, clock_budget) {
+            (Some(movetime), Some(clock_budget)) => Some(movetime.min(clock_budget)),
+            (movetime, clock_budget) => movetime.or(clock_budget),
+        }
+    }
+
+    pub fn allows_root_move(&self, mv: &Move) -> bool {
+        (self.search_moves.is_empty() || self.search_moves.contains(mv))
+            && !self.excluded_moves.contains(mv)
+    }
 }
 
-#[derive(Debug, Deserialize, Eq, PartialEq)]
-pub enum Category {
-    #[serde(rename = "win")]
-    Win,
-    #[serde(rename = "loss")]
-    Loss,
-    #[serde(rename = "draw")]
-    Draw,
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    // Depth of the last fully searched iteration
+    pub depth: u32,
+    pub nodes: u64,
+    pub time: Duration,
 }
 
-impl TablebaseResponse {
-    fn get_best_move(&self) -> &TablebaseMove {
-        let mut best_move = &self.moves[0];
-
-        for mv in &self.moves {
-            match best_move.category {
-                // The category is from the opponent's perspective. So a loss is good
-                Category::Win => {
-                    if mv.category == Category::Draw || mv.category == Category::Loss {
-                        best_move = mv
-                    }
-                }
-                Category::Loss => {
-                    if mv.category == Category::Loss && mv.dtm > best_move.dtm {
-                        best_move = mv
-                    }
-                }
-                Category::Draw => {
-                    if mv.category == Category::Loss {
-                        best_move = mv
-                    }
-                }
-            }
-        }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub best_move: Move,
+    // The expected reply to the best move, which is what the engine ponders on
+    pub ponder_move: Option<Move>,
+    pub score: Score,
+    // Principal variation, starting with the best move
+    pub pv: Vec<Move>,
+    pub stats: SearchStats,
+}
+
+// Progress of a running search, e.g. for the UCI info lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchProgress {
+    // Sent after every completed iteration
+    Iteration {
+        depth: u32,
+        score: Score,
+        pv: Vec<Move>,
+        nodes: u64,
+        time: Duration,
+        hashfull: u32,
+    },
+    // The root move about to be searched, only sent once the search has run for a while
+    CurrentMove {
+        mv: Move,
+        move_number: usize,
+    },
+}
 
-        best_move
+// State shared by every node of a single search
+struct SearchContext<'a, E, O> {
+    start_time: Instant,
+    deadline: Option<Instant>,
+    transposition_table: &'a mut TranspositionTable,
+    evaluator: &'a E,
+    move_orderer: &'a mut O,
+    stop: &'a AtomicBool,
+    limits: &'a SearchLimits,
+    nodes: u64,
+    // One list of moves per ply, reused by every node at that ply
+    move_lists: Vec<MoveList>,
+}
+
+impl<E, O> SearchContext<'_, E, O> {
+    fn should_stop(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+            || self.limits.nodes.is_some_and(|max| self.nodes >= max)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    fn result(
+        &self,
+        move_generator: &mut MoveGenerator,
+        best_move: &Move,
+        score: i32,
+        depth: u32,
+    ) -> SearchResult {
+        let pv = principal_variation(move_generator, self.transposition_table, best_move, depth);
+
+        SearchResult {
+            best_move: best_move.clone(),
+            ponder_move: pv.get(1).cloned(),
+            score: Score::from_search(score),
+            pv,
+            stats: SearchStats {
+                depth,
+                nodes: self.nodes,
+                time: self.start_time.elapsed(),
+            },
+        }
     }
 }
 
-pub fn search(move_generator: &mut MoveGenerator, depth: u32, mut alpha: i32, beta: i32) -> i32 {
+// Once the search should stop it unwinds as fast as possible, returning meaningless scores that
+// the caller must discard
+fn search(
+    move_generator: &mut MoveGenerator,
+    context: &mut SearchContext<impl Evaluator, impl MoveOrderer>,
+    depth: u32,
+    ply: i32,
+    mut alpha: i32,
+    beta: i32,
+) -> i32 {
+    if context.should_stop() {
+        return 0;
+    }
+
     if depth == 0 {
-        COUNTER.fetch_add(1, Ordering::Relaxed);
-        return search_all_captures(move_generator, alpha, beta);
+        return search_all_captures(move_generator, context, ply, alpha, beta);
+    }
+
+    context.nodes += 1;
+    let key = move_generator.board.zobrist_key();
+    let mut tt_move = None;
+    if let Some(entry) = context.transposition_table.probe(key) {
+        if entry.depth >= depth {
+            let score = score_from_tt(entry.score, ply);
+            match entry.bound {
+                Bound::Exact => return score.clamp(alpha, beta),
+                Bound::Lower if score >= beta => return beta,
+                Bound::Upper if score <= alpha => return alpha,
+                _ => {}
+            }
+        }
+        tt_move = entry.best_move.clone();
     }
 
-    let mut moves = move_generator.generate_moves();
+    let moves = move_list_at(&mut context.move_lists, ply);
+    move_generator.generate_moves_into(moves);
     if moves.is_empty() {
         if move_generator.is_in_check(move_generator.board.to_move) {
-            // Prefer getting mated later rather than sooner; high depth
-            // remaining is worse than low depth remaining
-            return -INF;
+            // Prefer getting mated later rather than sooner; a mate closer
+            // to the root is worse than a mate further away
+            return -(MATE_SCORE - ply);
         } else {
             return 0;
         }
     }
 
-    moves.sort_unstable_by_key(|mv| guess_move_score(move_generator, mv));
-    for mv in moves.iter() {
-        move_generator.board.move_piece(mv);
-        let eval = -search(move_generator, depth - 1, -beta, -alpha);
-        move_generator.board.unmake_move(mv).unwrap();
+    context
+        .move_orderer
+        .order_moves(&move_generator.board, moves, ply);
+    // The best move from a previous search of this position is the most likely to cause a cutoff
+    if let Some(tt_move_index) = moves.iter().position(|mv| Some(mv) == tt_move.as_ref()) {
+        moves[..=tt_move_index].rotate_right(1);
+    }
+
+    let original_alpha = alpha;
+    let mut best_move = None;
+    // The deeper plies use lists of their own, so this one stays as it is during the loop
+    for index in 0..moves.len() {
+        let mv = &context.move_lists[ply as usize][index].clone();
+        let previous_state = move_generator.board.make_move(mv);
+        let eval = -search(move_generator, context, depth - 1, ply + 1, -beta, -alpha);
+        move_generator
+            .board
+            .unmake_move(mv, previous_state)
+            .unwrap();
+
+        // Don't let an aborted search pollute the transposition table
+        if context.should_stop() {
+            return 0;
+        }
 
         if eval >= beta {
             // Move too good, opponent will avoid
+            context
+                .move_orderer
+                .record_cutoff(&move_generator.board, mv, depth, ply);
+            context.transposition_table.store(Entry {
+                key,
+                depth,
+                score: score_to_tt(beta, ply),
+                bound: Bound::Lower,
+                best_move: Some(mv.clone()),
+            });
             return beta;
         }
 
-        alpha = std::cmp::max(eval, alpha);
+        if eval > alpha {
+            alpha = eval;
+            best_move = Some(mv.clone());
+        }
     }
 
+    let bound = if alpha > original_alpha {
+        Bound::Exact
+    } else {
+        Bound::Upper
+    };
+    context.transposition_table.store(Entry {
+        key,
+        depth,
+        score: score_to_tt(alpha, ply),
+        bound,
+        best_move,
+    });
+
     alpha
 }
 
-// TODO: Modify move generation to make this more efficient
-fn search_all_captures(move_generator: &mut MoveGenerator, alpha: i32, beta: i32) -> i32 {
-    let eval = evaluate(move_generator);
+fn search_all_captures(
+    move_generator: &mut MoveGenerator,
+    context: &mut SearchContext<impl Evaluator, impl MoveOrderer>,
+    ply: i32,
+    alpha: i32,
+    beta: i32,
+) -> i32 {
+    context.nodes += 1;
+    // No capture can change the outcome, and other evaluators may not know that
+    if is_insufficient_material(&move_generator.board) {
+        return 0;
+    }
+    let eval = context
+        .evaluator
+        .evaluate(&move_generator.board)
+        .to_search();
     if eval >= beta {
         return beta;
     }
 
     let mut alpha = std::cmp::max(alpha, eval);
-    let mut capture_moves: Vec<Move> = move_generator
-        .generate_moves()
-        .into_iter()
-        .filter(|mv| {
-            matches!(
-                mv.flag,
-                Flag::EnPassantCapture | Flag::Capture(_) | Flag::CaptureWithPromotion(_, _)
-            )
-        })
-        .collect();
-    capture_moves.sort_unstable_by_key(|mv| guess_move_score(move_generator, mv));
+    let capture_moves = move_list_at(&mut context.move_lists, ply);
+    move_generator.generate_capture_moves_into(capture_moves);
+    context
+        .move_orderer
+        .order_moves(&move_generator.board, capture_moves, ply);
 
-    for mv in capture_moves.iter() {
-        move_generator.board.move_piece(mv);
-        let eval = -search_all_captures(move_generator, -beta, -alpha);
-        move_generator.board.unmake_move(mv).unwrap();
+    for index in 0..capture_moves.len() {
+        let mv = &context.move_lists[ply as usize][index].clone();
+        let previous_state = move_generator.board.make_move(mv);
+        let eval = -search_all_captures(move_generator, context, ply + 1, -beta, -alpha);
+        move_generator
+            .board
+            .unmake_move(mv, previous_state)
+            .unwrap();
 
         if eval >= beta {
             return beta;
@@ -146,110 +361,511 @@ fn search_all_captures(move_generator: &mut MoveGenerator, alpha: i32, beta: i32
     alpha
 }
 
-pub fn query_tablebase(move_generator: &mut MoveGenerator) -> Result<(Move, i32)> {
-    let base_tb_server_url = "http://tablebase.lichess.ovh/standard";
-    // Make FEN URL friendly
-    let params = [("fen", move_generator.board.to_fen().replace(' ', "_"))];
-    let client = Client::new();
-    let response = client.get(base_tb_server_url).query(&params).send()?;
+fn move_list_at(move_lists: &mut Vec<MoveList>, ply: i32) -> &mut MoveList {
+    let ply = ply as usize;
+    if move_lists.len() <= ply {
+        move_lists.resize_with(ply + 1, MoveList::new);
+    }
+    &mut move_lists[ply]
+}
 
-    let tb_response: TablebaseResponse = if response.status().is_success() {
-        response.json()?
-    } else {
-        bail!("Call to tablebase failed");
-    };
+// Follows the best moves stored in the transposition table from the position after the best
+// move. At least two moves long if possible, so there is always something to ponder on.
+fn principal_variation(
+    move_generator: &mut MoveGenerator,
+    transposition_table: &TranspositionTable,
+    best_move: &Move,
+    depth: u32,
+) -> Vec<Move> {
+    let mut pv = vec![best_move.clone()];
+    let mut undo_stack = vec![move_generator.board.make_move(best_move)];
+    while pv.len() < depth.max(2) as usize {
+        let Some(mv) = transposition_table
+            .probe(move_generator.board.zobrist_key())
+            .and_then(|entry| entry.best_move.clone())
+            // Guard against hash collisions
+            .filter(|mv| move_generator.is_legal(mv))
+        else {
+            break;
+        };
+        undo_stack.push(move_generator.board.make_move(&mv));
+        pv.push(mv);
+    }
 
-    let best_move = tb_response.get_best_move();
-    let eval = match best_move.category {
-        Category::Win => -INF,
-        Category::Draw => 0,
-        Category::Loss => INF,
-    };
+    for (mv, previous_state) in pv.iter().zip(undo_stack).rev() {
+        move_generator
+            .board
+            .unmake_move(mv, previous_state)
+            .unwrap();
+    }
+
+    pv
+}
+
+// A search algorithm, so that the front ends don't depend on a particular one. Whatever the
+// algorithm learns from a search, e.g. in a transposition table, may be kept for the next one.
+pub trait Search {
+    // Searches until one of the limits is reached or stop is set. The position must have at
+    // least one legal move.
+    fn search(
+        &mut self,
+        board: &Board,
+        limits: &SearchLimits,
+        stop: &AtomicBool,
+        on_progress: &mut dyn FnMut(&SearchProgress),
+    ) -> SearchResult;
 
-    Ok((Move::try_from_uci(&best_move.uci, move_generator)?, eval))
+    // Forgets everything learnt from previous searches
+    fn clear(&mut self);
+
+    fn set_hash_size(&mut self, size_mb: usize);
+
+    // Permille of the hash table in use, for the UCI info lines
+    fn hashfull(&self) -> u32;
+}
+
+// The search algorithms that can be picked at runtime, e.g. with the "Search Algorithm" option
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchAlgorithm {
+    #[default]
+    AlphaBeta,
+    // Weaker and experimental
+    Mcts,
+}
+
+impl SearchAlgorithm {
+    pub const ALL: [SearchAlgorithm; 2] = [SearchAlgorithm::AlphaBeta, SearchAlgorithm::Mcts];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SearchAlgorithm::AlphaBeta => "AlphaBeta",
+            SearchAlgorithm::Mcts => "MCTS",
+        }
+    }
+
+    pub fn try_from_name(name: &str) -> Result<Self> {
+        match Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name))
+        {
+            Some(algorithm) => Ok(algorithm),
+            None => bail!("Unknown search algorithm: {name}"),
+        }
+    }
+
+    // With a network, positions are evaluated by it instead of the hand-crafted evaluation with
+    // the given weights
+    pub fn create(
+        self,
+        hash_size_mb: usize,
+        weights: EvalWeights,
+        network: Option<Arc<Network>>,
+    ) -> Box<dyn Search + Send> {
+        match (self, network) {
+            (SearchAlgorithm::AlphaBeta, None) => Box::new(AlphaBeta {
+                transposition_table: TranspositionTable::new(hash_size_mb),
+                evaluator: DefaultEvaluator::with_weights(weights),
+                move_orderer: DefaultMoveOrderer,
+            }),
+            (SearchAlgorithm::AlphaBeta, Some(network)) => Box::new(AlphaBeta {
+                transposition_table: TranspositionTable::new(hash_size_mb),
+                evaluator: NnueEvaluator::new(network),
+                move_orderer: DefaultMoveOrderer,
+            }),
+            (SearchAlgorithm::Mcts, None) => {
+                Box::new(Mcts::with_evaluator(DefaultEvaluator::with_weights(weights)))
+            }
+            (SearchAlgorithm::Mcts, Some(network)) => {
+                Box::new(Mcts::with_evaluator(NnueEvaluator::new(network)))
+            }
+        }
+    }
+}
+
+// Iterative deepening alpha-beta with a transposition table, the default search
+pub struct AlphaBeta<E = DefaultEvaluator, O = DefaultMoveOrderer> {
+    transposition_table: TranspositionTable,
+    evaluator: E,
+    move_orderer: O,
+}
+
+impl AlphaBeta {
+    pub fn new() -> Self {
+        Self::with_evaluator(DefaultEvaluator::default())
+    }
+}
+
+impl<E: Evaluator> AlphaBeta<E> {
+    pub fn with_evaluator(evaluator: E) -> Self {
+        Self {
+            transposition_table: TranspositionTable::default(),
+            evaluator,
+            move_orderer: DefaultMoveOrderer,
+        }
+    }
+}
+
+impl<E: Evaluator, O: MoveOrderer> AlphaBeta<E, O> {
+    pub fn with_move_orderer<P: MoveOrderer>(self, move_orderer: P) -> AlphaBeta<E, P> {
+        AlphaBeta {
+            transposition_table: self.transposition_table,
+            evaluator: self.evaluator,
+            move_orderer,
+        }
+    }
+
+    pub fn evaluator(&self) -> &E {
+        &self.evaluator
+    }
+}
+
+impl Default for AlphaBeta {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: Evaluator, O: MoveOrderer> Search for AlphaBeta<E, O> {
+    fn search(
+        &mut self,
+        board: &Board,
+        limits: &SearchLimits,
+        stop: &AtomicBool,
+        on_progress: &mut dyn FnMut(&SearchProgress),
+    ) -> SearchResult {
+        let mut move_generator = MoveGenerator::new(board.without_history());
+        let mut moves = move_generator.generate_moves();
+        find_best_move_with_info(
+            &mut moves,
+            &mut move_generator,
+            &mut self.transposition_table,
+            &self.evaluator,
+            &mut self.move_orderer,
+            limits,
+            stop,
+            on_progress,
+        )
+    }
+
+    fn clear(&mut self) {
+        self.transposition_table.clear();
+    }
+
+    fn set_hash_size(&mut self, size_mb: usize) {
+        self.transposition_table = TranspositionTable::new(size_mb);
+    }
+
+    fn hashfull(&self) -> u32 {
+        self.transposition_table.hashfull()
+    }
 }
 
 pub fn find_best_move(
     moves: &mut [Move],
     move_generator: &mut MoveGenerator,
     depth: u32,
-) -> (Move, i32) {
-    COUNTER.store(0, Ordering::Relaxed);
+) -> SearchResult {
+    let mut transposition_table = TranspositionTable::default();
+    let stop = AtomicBool::new(false);
+    find_best_move_with_info(
+        moves,
+        move_generator,
+        &mut transposition_table,
+        &DefaultEvaluator::default(),
+        &mut DefaultMoveOrderer,
+        &SearchLimits::depth(depth),
+        &stop,
+        |_| {},
+    )
+}
 
-    let pieces_left = move_generator
-        .board
-        .squares
-        .iter()
-        .filter(|sq| sq.is_some())
-        .count();
-    if pieces_left <= 7 {
-        // TODO: Add logging for when query fails
-        match query_tablebase(move_generator) {
-            Ok(tb_result) => return tb_result,
-            Err(err) => println!("{err}"),
+// Same as find_best_move, but reuses the given transposition table, evaluates positions with the
+// given evaluator and orders moves with the given move orderer, passes its progress to
+// on_progress while searching and can be interrupted by setting the stop flag. When interrupted
+// or out of nodes or time, the best move of the last completed iteration is returned. Positions
+// with few pieces left are looked up in the tablebase while searching, and its move is played if
+// it answers in time.
+#[allow(clippy::too_many_arguments)]
+pub fn find_best_move_with_info(
+    moves: &mut [Move],
+    move_generator: &mut MoveGenerator,
+    transposition_table: &mut TranspositionTable,
+    evaluator: &impl Evaluator,
+    move_orderer: &mut impl MoveOrderer,
+    limits: &SearchLimits,
+    stop: &AtomicBool,
+    mut on_progress: impl FnMut(&SearchProgress),
+) -> SearchResult {
+    let start_time = Instant::now();
+    let time_budget = limits.time_budget(move_generator.board.to_move);
+    let _span = info_span!(
+        "search",
+        max_depth = limits.max_depth(),
+        max_nodes = limits.nodes,
+        time_budget_ms = time_budget.map(|budget| budget.as_millis() as u64)
+    )
+    .entered();
+    evaluator.prepare(&mut move_generator.board);
+    let mut context = SearchContext {
+        start_time,
+        deadline: time_budget.map(|budget| start_time + budget),
+        transposition_table,
+        evaluator,
+        move_orderer,
+        stop,
+        limits,
+        nodes: 0,
+        move_lists: Vec::new(),
+    };
+
+    let use_tablebase = limits.tablebase.enabled
+        && move_generator.board.total_piece_count() <= limits.tablebase.max_pieces;
+    // The answer for a position never changes, so the network is only asked once a game
+    let key = move_generator.board.zobrist_key();
+    let tablebase_probe = if use_tablebase {
+        match context.transposition_table.probe_tablebase(key).cloned() {
+            Some((tb_move, tb_eval)) if limits.allows_root_move(&tb_move) => {
+                return tablebase_result(tb_move, tb_eval, SearchStats::default());
+            }
+            Some(_) => None,
+            None => Some(probe_tablebase(&move_generator.board, &limits.tablebase)),
         }
+    } else {
+        None
+    };
+
+    let result = search_root(moves, move_generator, &mut context, &mut on_progress);
+
+    let Some(tablebase_probe) = tablebase_probe else {
+        return result;
+    };
+    match wait_for_probe(&tablebase_probe, &context) {
+        Some(Ok((tb_move, tb_eval))) => {
+            context
+                .transposition_table
+                .store_tablebase(key, tb_move.clone(), tb_eval);
+            if limits.allows_root_move(&tb_move) {
+                return tablebase_result(tb_move, tb_eval, result.stats);
+            }
+        }
+        Some(Err(err)) => warn!(%err, "tablebase query failed"),
+        None => warn!("tablebase didn't answer in time"),
+    }
+
+    result
+}
+
+fn tablebase_result(best_move: Move, score: i32, stats: SearchStats) -> SearchResult {
+    SearchResult {
+        ponder_move: None,
+        pv: vec![best_move.clone()],
+        best_move,
+        score: Score::from_search(score),
+        stats,
+    }
+}
+
+// Asks the tablebase on another thread, so a slow or unreachable server never holds up the
+// search. The answer arrives on the returned channel.
+fn probe_tablebase(board: &Board, settings: &TablebaseSettings) -> Receiver<Result<(Move, i32)>> {
+    let (sender, receiver) = mpsc::channel();
+    let mut move_generator = MoveGenerator::new(board.without_history());
+    let settings = settings.clone();
+    thread::spawn(move || {
+        // Nobody is listening anymore if the search gave up on the answer
+        let _ = sender.send(query_tablebase(&mut move_generator, &settings));
+    });
+
+    receiver
+}
+
+// The tablebase may take until the search would have run out of time anyway. A stopped search
+// doesn't wait at all, and one without a time limit waits for the request to time out.
+fn wait_for_probe(
+    probe: &Receiver<Result<(Move, i32)>>,
+    context: &SearchContext<impl Evaluator, impl MoveOrderer>,
+) -> Option<Result<(Move, i32)>> {
+    if context.stop.load(Ordering::Relaxed) {
+        return probe.try_recv().ok();
     }
-    moves.sort_unstable_by_key(|mv| guess_move_score(move_generator, mv));
 
-    let mut best_move = moves
-        .get(0)
-        .expect("moves vector must have at least one move");
+    match context.deadline {
+        Some(deadline) => probe
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .ok(),
+        None => probe.recv().ok(),
+    }
+}
+
+// Iterative deepening over the root moves
+fn search_root(
+    moves: &[Move],
+    move_generator: &mut MoveGenerator,
+    context: &mut SearchContext<impl Evaluator, impl MoveOrderer>,
+    mut on_progress: impl FnMut(&SearchProgress),
+) -> SearchResult {
+    let limits = context.limits;
+    let start_time = context.start_time;
+    let time_budget = limits.time_budget(move_generator.board.to_move);
+    let mut moves: Vec<Move> = moves
+        .iter()
+        .filter(|mv| limits.allows_root_move(mv))
+        .cloned()
+        .collect();
+    context.move_orderer.new_search();
+    context
+        .move_orderer
+        .order_moves(&move_generator.board, &mut moves, 0);
+
+    // Without a root move there is nothing to search, the null move stands in for "no move"
+    let Some(mut best_move) = moves.first() else {
+        return SearchResult {
+            best_move: Move::null(),
+            ponder_move: None,
+            score: Score::default(),
+            pv: Vec::new(),
+            stats: SearchStats::default(),
+        };
+    };
+    // An unfinished iteration has only seen some of the moves, in no particular order, so its
+    // best move can be worse than the one of the previous iteration
+    let mut completed_best_move = best_move;
+
+    // Noise stays the same for a move throughout the search, so iterations agree on the scores
+    let mut random = Random::from_clock();
+    let root_noise: Vec<i32> = moves
+        .iter()
+        .map(|_| random.next_noise(limits.max_root_noise))
+        .collect();
+    let root_bonus: Vec<i32> = moves
+        .iter()
+        .map(|mv| {
+            if limits.preferred_moves.contains(mv) {
+                PREFERRED_MOVE_BONUS
+            } else {
+                0
+            }
+        })
+        .collect();
 
     let mut best_eval = -INF;
+    let mut completed_best_eval = -INF;
+    let mut completed_depth = 0;
     // Iterative deepending
     // TODO: Use previous iterations to optimize search
-    for curr_depth in 0..depth {
-        let mut alpha = -INF;
+    for curr_depth in 0..limits.max_depth() {
+        // The next iteration takes longer than all previous ones together, so it would most
+        // likely be cut short anyway
+        let half_time_used = time_budget.is_some_and(|budget| start_time.elapsed() >= budget / 2);
+        if curr_depth > 0 && half_time_used {
+            break;
+        }
+
         let beta = INF;
+        // The best score so far, including noise and bonuses
+        let mut best_score = -INF;
+
+        for (move_number, mv) in moves.iter().enumerate() {
+            if context.start_time.elapsed() >= CURRMOVE_REPORT_DELAY {
+                on_progress(&SearchProgress::CurrentMove {
+                    mv: mv.clone(),
+                    move_number: move_number + 1,
+                });
+            }
+
+            // A move scoring no higher than this can't overtake the best move, whatever its noise
+            let alpha = best_score
+                .saturating_sub(limits.max_root_noise + root_bonus[move_number])
+                .max(-INF);
+            let previous_state = move_generator.board.make_move(mv);
+            let eval = -search(move_generator, context, curr_depth, 1, -beta, -alpha);
+            move_generator
+                .board
+                .unmake_move(mv, previous_state)
+                .unwrap();
+
+            // The move's search was cut short, so its eval can't be trusted
+            if context.should_stop() {
+                debug!(depth = curr_depth + 1, nodes = context.nodes, "search stopped");
+                // Without a completed iteration, the unfinished one is all there is
+                if completed_depth > 0 {
+                    best_move = completed_best_move;
+                    best_eval = completed_best_eval;
+                }
+                return context.result(move_generator, best_move, best_eval, completed_depth);
+            }
 
-        for mv in moves.iter() {
-            move_generator.board.move_piece(mv);
-            let eval = -search(move_generator, curr_depth, -beta, -alpha);
-            move_generator.board.unmake_move(mv).unwrap();
             // If we see mate at the current depth, stop the search, since
             // the current move is guarenteed to be the fastest mate
-            if eval == INF {
-                return (mv.clone(), eval);
+            if mate_in_moves(eval).is_some_and(|moves| moves > 0) {
+                let result = context.result(move_generator, mv, eval, curr_depth + 1);
+                report_iteration(&mut on_progress, &result, context);
+                return result;
             }
 
-            if eval > alpha {
-                alpha = eval;
+            let score = if is_mate_score(eval) {
+                eval
+            } else {
+                eval + root_noise[move_number] + root_bonus[move_number]
+            };
+            if score > best_score {
+                best_score = score;
                 best_move = mv;
                 best_eval = eval;
             }
         }
+
+        completed_depth = curr_depth + 1;
+        completed_best_move = best_move;
+        completed_best_eval = best_eval;
+        let result = context.result(move_generator, best_move, best_eval, completed_depth);
+        report_iteration(&mut on_progress, &result, context);
     }
 
-    (best_move.clone(), best_eval)
+    context.result(move_generator, best_move, best_eval, completed_depth)
 }
 
-pub fn guess_move_score(move_generator: &MoveGenerator, mv: &Move) -> i32 {
-    let mut score_guess: i32 = 0;
+fn report_iteration(
+    on_progress: &mut impl FnMut(&SearchProgress),
+    result: &SearchResult,
+    context: &SearchContext<impl Evaluator, impl MoveOrderer>,
+) {
+    let stats = result.stats;
+    debug!(
+        depth = stats.depth,
+        score = %result.score,
+        nodes = stats.nodes,
+        elapsed_ms = stats.time.as_millis() as u64,
+        "iteration finished"
+    );
 
-    let starting_piece = move_generator.board.squares[mv.starting_square].unwrap();
-    let piece_color = move_generator.board.colors[mv.starting_square].unwrap();
-    let capture_piece_multiplier = 10;
+    on_progress(&SearchProgress::Iteration {
+        depth: stats.depth,
+        score: result.score,
+        pv: result.pv.clone(),
+        nodes: stats.nodes,
+        time: stats.time,
+        hashfull: context.transposition_table.hashfull(),
+    });
+}
 
-    match mv.flag {
-        Flag::PromoteTo(piece) => score_guess += piece.piece_value(),
-        Flag::Capture(piece) => {
-            score_guess +=
-                capture_piece_multiplier * piece.piece_value() - starting_piece.piece_value()
-        }
-        Flag::CaptureWithPromotion(captured_piece, promotion_piece) => {
-            score_guess += promotion_piece.piece_value()
-                + capture_piece_multiplier * captured_piece.piece_value()
-                - starting_piece.piece_value()
-        }
-        _ => (),
-    }
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE_SCORE - MAX_MATE_PLY && score.abs() <= MATE_SCORE
+}
 
-    let position_eval_diff = starting_piece.position_value(mv.target_square, piece_color)
-        - starting_piece.position_value(mv.starting_square, piece_color);
-    score_guess += position_eval_diff;
+// Number of moves until mate from the side to move's perspective: positive if the side to move
+// mates, negative if it gets mated. Returns None if the score is not a mate score.
+pub fn mate_in_moves(score: i32) -> Option<i32> {
+    if !is_mate_score(score) {
+        return None;
+    }
 
-    // Negate score so that the moves with the highest score will be first
-    -score_guess
+    let plies = MATE_SCORE - score.abs();
+    if score > 0 {
+        Some((plies + 1) / 2)
+    } else {
+        Some(-(plies + 1) / 2)
+    }
 }
 
 #[cfg(test)]
@@ -257,14 +873,28 @@ mod tests {
     use crate::{
         board::Board,
         board_builder::BoardBuilder,
+        evaluate::DefaultEvaluator,
         move_generation::{Flag, Move, MoveGenerator},
+        move_ordering::{DefaultMoveOrderer, MoveOrderer},
         piece::{Color, Piece},
-        search::INF,
+        score::Score,
         square::Square,
+        tablebase::TablebaseSettings,
+        transposition_table::TranspositionTable,
+        weights::EvalWeights,
+        zobrist::hash_board,
     };
     use anyhow::Result;
+    use std::{
+        net::TcpListener,
+        sync::atomic::AtomicBool,
+        time::{Duration, Instant},
+    };
 
-    use super::find_best_move;
+    use super::{
+        find_best_move, find_best_move_with_info, mate_in_moves, SearchAlgorithm, SearchLimits,
+        SearchProgress, SearchResult, DEFAULT_SEARCH_DEPTH, MATE_SCORE, MAX_SEARCH_DEPTH,
+    };
 
     #[test]
     fn test_find_best_move_mate_in_one() -> Result<()> {
@@ -277,11 +907,17 @@ mod tests {
 
         let mut move_generator = MoveGenerator::new(board);
         let mut moves = move_generator.generate_moves();
-        let (best_move, eval) = find_best_move(&mut moves, &mut move_generator, 2);
+        let SearchResult {
+            best_move,
+            score: eval,
+            ..
+        } = find_best_move(&mut moves, &mut move_generator, 2);
         let mating_move = Move::from_square(Square::A8, Square::A1, Flag::None);
 
         assert!(best_move == mating_move);
-        assert!(eval == INF);
+        assert!(eval == Score::Mate(1));
+        assert!(eval.to_search() == MATE_SCORE - 1);
+        assert!(mate_in_moves(eval.to_search()) == Some(1));
 
         Ok(())
     }
@@ -293,7 +929,7 @@ mod tests {
         let board: Board = BoardBuilder::try_from_fen("k6r/2p3pp/4p3/4P3/7q/8/5r2/3K4 b - - 1 41")?;
         let mut move_generator = MoveGenerator::new(board);
         let mut moves = move_generator.generate_moves();
-        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 6);
+        let SearchResult { best_move, .. } = find_best_move(&mut moves, &mut move_generator, 6);
         let expected_best_move = Move::from_square(Square::H4, Square::H1, Flag::None);
 
         assert!(best_move == expected_best_move);
@@ -307,7 +943,7 @@ mod tests {
             BoardBuilder::try_from_fen("k6r/2p2ppp/4P3/4P3/8/1r6/4KP1P/2q5 b - - 0 36")?;
         let mut move_generator = MoveGenerator::new(board);
         let mut moves = move_generator.generate_moves();
-        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 6);
+        let SearchResult { best_move, .. } = find_best_move(&mut moves, &mut move_generator, 6);
         // The only mate in two move
         let expected_best_move = Move::from_square(Square::H8, Square::D8, Flag::None);
 
@@ -316,6 +952,384 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_root_noise_keeps_winning_tactics() -> Result<()> {
+        let board: Board = BoardBuilder::new()
+            .piece(Square::H1, Piece::King, Color::White)
+            .piece(Square::A8, Piece::King, Color::Black)
+            .piece(Square::E1, Piece::Rook, Color::White)
+            .piece(Square::E5, Piece::Queen, Color::Black)
+            .piece(Square::B2, Piece::Pawn, Color::White)
+            .piece(Square::B7, Piece::Pawn, Color::Black)
+            .to_move(Color::White)
+            .try_into()?;
+        let mut move_generator = MoveGenerator::new(board);
+        let mut moves = move_generator.generate_moves();
+        let limits = SearchLimits {
+            depth: Some(2),
+            max_root_noise: 100,
+            ..SearchLimits::default()
+        };
+
+        let SearchResult { best_move, .. } = find_best_move_with_info(
+            &mut moves,
+            &mut move_generator,
+            &mut TranspositionTable::new(1),
+            &DefaultEvaluator::default(),
+            &mut DefaultMoveOrderer,
+            &limits,
+            &AtomicBool::new(false),
+            |_| {},
+        );
+
+        // Winning a queen is worth far more than any noise
+        assert!(best_move == Move::from_square(Square::E1, Square::E5, Flag::Capture));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_principal_variation_is_legal() -> Result<()> {
+        let mut move_generator = MoveGenerator::new(Board::starting_position());
+        let mut moves = move_generator.generate_moves();
+        let result = find_best_move_with_info(
+            &mut moves,
+            &mut move_generator,
+            &mut TranspositionTable::new(1),
+            &DefaultEvaluator::default(),
+            &mut DefaultMoveOrderer,
+            &SearchLimits::depth(3),
+            &AtomicBool::new(false),
+            |_| {},
+        );
+
+        assert_eq!(result.stats.depth, 3);
+        assert!(result.pv.len() >= 2);
+        assert!(result.pv[0] == result.best_move);
+        assert!(result.ponder_move.as_ref() == Some(&result.pv[1]));
+        // The search leaves the board as it found it
+        assert!(move_generator.board == Board::starting_position());
+        for mv in &result.pv {
+            assert!(move_generator.generate_moves().contains(mv));
+            move_generator.board.move_piece(mv);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_budget() {
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_secs(60)),
+            btime: Some(Duration::from_secs(30)),
+            winc: Some(Duration::from_secs(2)),
+            movestogo: Some(20),
+            ..SearchLimits::default()
+        };
+        assert_eq!(limits.time_budget(Color::White), Some(Duration::from_secs(4)));
+        assert_eq!(limits.time_budget(Color::Black), Some(Duration::from_millis(1500)));
+
+        // Never more than what's left on the clock
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_millis(100)),
+            winc: Some(Duration::from_secs(10)),
+            ..SearchLimits::default()
+        };
+        assert_eq!(limits.time_budget(Color::White), Some(Duration::from_millis(50)));
+
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_millis(500)),
+            ..SearchLimits::default()
+        };
+        assert_eq!(limits.time_budget(Color::Black), Some(Duration::from_millis(500)));
+        assert_eq!(SearchLimits::default().time_budget(Color::White), None);
+    }
+
+    #[test]
+    fn test_search_without_root_moves() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1")?;
+        let mut move_generator = MoveGenerator::new(board);
+
+        let result = find_best_move(&mut [], &mut move_generator, 2);
+        assert_eq!(result.best_move, Move::null());
+        assert!(result.pv.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_movetime_stops_search() {
+        let mut move_generator = MoveGenerator::new(Board::starting_position());
+        let mut moves = move_generator.generate_moves();
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_millis(200)),
+            ..SearchLimits::default()
+        };
+        let start_time = Instant::now();
+        let result = find_best_move_with_info(
+            &mut moves,
+            &mut move_generator,
+            &mut TranspositionTable::new(1),
+            &DefaultEvaluator::default(),
+            &mut DefaultMoveOrderer,
+            &limits,
+            &AtomicBool::new(false),
+            |_| {},
+        );
+
+        assert!(start_time.elapsed() < Duration::from_secs(1));
+        assert!(moves.contains(&result.best_move));
+    }
+
+    #[test]
+    fn test_stopped_search_returns_legal_move() -> Result<()> {
+        let mut move_generator = MoveGenerator::new(Board::starting_position());
+        let moves = move_generator.generate_moves();
+        let mut transposition_table = TranspositionTable::new(1);
+        let stop = AtomicBool::new(true);
+
+        let SearchResult { best_move, .. } = find_best_move_with_info(
+            &mut moves.clone(),
+            &mut move_generator,
+            &mut transposition_table,
+            &DefaultEvaluator::default(),
+            &mut DefaultMoveOrderer,
+            &SearchLimits::default(),
+            &stop,
+            |_| {},
+        );
+
+        assert!(moves.contains(&best_move));
+        // The board must be left untouched by the interrupted search
+        assert!(move_generator.board == Board::starting_position());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_node_limited_search_stops_early() -> Result<()> {
+        let mut move_generator = MoveGenerator::new(Board::starting_position());
+        let moves = move_generator.generate_moves();
+        let mut transposition_table = TranspositionTable::new(1);
+        let stop = AtomicBool::new(false);
+        let limits = SearchLimits {
+            nodes: Some(1000),
+            ..SearchLimits::default()
+        };
+
+        let mut reported_iterations = Vec::new();
+        let SearchResult { best_move, .. } = find_best_move_with_info(
+            &mut moves.clone(),
+            &mut move_generator,
+            &mut transposition_table,
+            &DefaultEvaluator::default(),
+            &mut DefaultMoveOrderer,
+            &limits,
+            &stop,
+            |info| reported_iterations.push(info.to_owned()),
+        );
+
+        assert!(moves.contains(&best_move));
+        // A thousand nodes is nowhere near enough to reach the maximum depth
+        assert!(!reported_iterations.is_empty() && reported_iterations.len() < 10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_algorithm_by_name() -> Result<()> {
+        for algorithm in SearchAlgorithm::ALL {
+            assert!(SearchAlgorithm::try_from_name(algorithm.name())? == algorithm);
+        }
+        assert!(SearchAlgorithm::try_from_name("alphabeta")? == SearchAlgorithm::AlphaBeta);
+        assert!(SearchAlgorithm::try_from_name("mcts")? == SearchAlgorithm::Mcts);
+        assert!(SearchAlgorithm::try_from_name("minimax").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_trait_object() {
+        let mut searcher = SearchAlgorithm::AlphaBeta.create(1, EvalWeights::default(), None);
+        let result = searcher.search(
+            &Board::starting_position(),
+            &SearchLimits::depth(3),
+            &AtomicBool::new(false),
+            &mut |_| {},
+        );
+
+        let mut move_generator = MoveGenerator::new(Board::starting_position());
+        assert!(move_generator.generate_moves().contains(&result.best_move));
+        assert!(searcher.hashfull() > 0);
+        searcher.clear();
+        assert_eq!(searcher.hashfull(), 0);
+    }
+
+    #[test]
+    fn test_move_orderer_sees_cutoffs() {
+        // Keeps the default order, but remembers what the search told it
+        #[derive(Default)]
+        struct RecordingOrderer {
+            searches: u32,
+            orderings: u32,
+            cutoffs: u32,
+        }
+
+        impl MoveOrderer for RecordingOrderer {
+            fn new_search(&mut self) {
+                self.searches += 1;
+            }
+
+            fn order_moves(&mut self, board: &Board, moves: &mut [Move], ply: i32) {
+                self.orderings += 1;
+                DefaultMoveOrderer.order_moves(board, moves, ply);
+            }
+
+            fn record_cutoff(&mut self, _board: &Board, _mv: &Move, _depth: u32, _ply: i32) {
+                self.cutoffs += 1;
+            }
+        }
+
+        let mut move_generator = MoveGenerator::new(Board::starting_position());
+        let mut moves = move_generator.generate_moves();
+        let mut move_orderer = RecordingOrderer::default();
+        find_best_move_with_info(
+            &mut moves,
+            &mut move_generator,
+            &mut TranspositionTable::new(1),
+            &DefaultEvaluator::default(),
+            &mut move_orderer,
+            &SearchLimits::depth(3),
+            &AtomicBool::new(false),
+            |_| {},
+        );
+
+        assert_eq!(move_orderer.searches, 1);
+        assert!(move_orderer.orderings > 1);
+        assert!(move_orderer.cutoffs > 0);
+    }
+
+    #[test]
+    fn test_interrupted_search_returns_last_completed_iteration() -> Result<()> {
+        let mut move_generator = MoveGenerator::new(Board::starting_position());
+        let mut moves = move_generator.generate_moves();
+        let limits = SearchLimits {
+            nodes: Some(5000),
+            ..SearchLimits::default()
+        };
+
+        let mut last_iteration = None;
+        let result = find_best_move_with_info(
+            &mut moves,
+            &mut move_generator,
+            &mut TranspositionTable::new(1),
+            &DefaultEvaluator::default(),
+            &mut DefaultMoveOrderer,
+            &limits,
+            &AtomicBool::new(false),
+            |progress| {
+                if let SearchProgress::Iteration { depth, pv, .. } = progress {
+                    last_iteration = Some((*depth, pv[0].clone()));
+                }
+            },
+        );
+
+        let (depth, best_move) = last_iteration.expect("at least one iteration should finish");
+        assert_eq!(result.stats.depth, depth);
+        assert!(result.best_move == best_move);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_moves_restrict_root_moves() -> Result<()> {
+        // Capturing the hanging queen is clearly best, but it is not among the allowed moves
+        let board: Board = BoardBuilder::new()
+            .piece(Square::H1, Piece::King, Color::White)
+            .piece(Square::A8, Piece::King, Color::Black)
+            .piece(Square::E1, Piece::Rook, Color::White)
+            .piece(Square::E5, Piece::Queen, Color::Black)
+            .piece(Square::B2, Piece::Pawn, Color::White)
+            .piece(Square::B7, Piece::Pawn, Color::Black)
+            .to_move(Color::White)
+            .try_into()?;
+        let mut move_generator = MoveGenerator::new(board);
+        let mut moves = move_generator.generate_moves();
+        let allowed_moves = vec![
+            Move::from_square(Square::B2, Square::B3, Flag::None),
+            Move::from_square(Square::H1, Square::G1, Flag::None),
+        ];
+        let limits = SearchLimits {
+            depth: Some(2),
+            search_moves: allowed_moves.clone(),
+            ..SearchLimits::default()
+        };
+
+        let SearchResult { best_move, .. } = find_best_move_with_info(
+            &mut moves,
+            &mut move_generator,
+            &mut TranspositionTable::new(1),
+            &DefaultEvaluator::default(),
+            &mut DefaultMoveOrderer,
+            &limits,
+            &AtomicBool::new(false),
+            |_| {},
+        );
+
+        assert!(allowed_moves.contains(&best_move));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_excluded_moves_are_not_played() -> Result<()> {
+        let board: Board = BoardBuilder::new()
+            .piece(Square::H1, Piece::King, Color::White)
+            .piece(Square::A8, Piece::King, Color::Black)
+            .piece(Square::E1, Piece::Rook, Color::White)
+            .piece(Square::E5, Piece::Queen, Color::Black)
+            .piece(Square::B2, Piece::Pawn, Color::White)
+            .piece(Square::B7, Piece::Pawn, Color::Black)
+            .to_move(Color::White)
+            .try_into()?;
+        let mut move_generator = MoveGenerator::new(board);
+        let mut moves = move_generator.generate_moves();
+        let capture_move = Move::from_square(Square::E1, Square::E5, Flag::Capture);
+        let limits = SearchLimits {
+            depth: Some(2),
+            excluded_moves: vec![capture_move.clone()],
+            ..SearchLimits::default()
+        };
+
+        let SearchResult { best_move, .. } = find_best_move_with_info(
+            &mut moves,
+            &mut move_generator,
+            &mut TranspositionTable::new(1),
+            &DefaultEvaluator::default(),
+            &mut DefaultMoveOrderer,
+            &limits,
+            &AtomicBool::new(false),
+            |_| {},
+        );
+
+        assert!(best_move != capture_move);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_depth_of_limits() {
+        assert_eq!(SearchLimits::default().max_depth(), DEFAULT_SEARCH_DEPTH);
+        assert_eq!(SearchLimits::depth(3).max_depth(), 3);
+        assert_eq!(SearchLimits::depth(1000).max_depth(), MAX_SEARCH_DEPTH);
+
+        let node_limited = SearchLimits {
+            nodes: Some(10),
+            ..SearchLimits::default()
+        };
+        assert_eq!(node_limited.max_depth(), MAX_SEARCH_DEPTH);
+    }
+
     #[test]
     fn test_captures_handing_queen() -> Result<()> {
         let board: Board = BoardBuilder::new()
@@ -328,8 +1342,8 @@ mod tests {
 
         let mut move_generator = MoveGenerator::new(board);
         let mut moves = move_generator.generate_moves();
-        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 2);
-        let capture_move = Move::from_square(Square::E1, Square::E5, Flag::Capture(Piece::Queen));
+        let SearchResult { best_move, .. } = find_best_move(&mut moves, &mut move_generator, 2);
+        let capture_move = Move::from_square(Square::E1, Square::E5, Flag::Capture);
 
         assert!(best_move == capture_move);
 
@@ -348,7 +1362,7 @@ mod tests {
 
         let mut move_generator = MoveGenerator::new(board);
         let mut moves = move_generator.generate_moves();
-        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 3);
+        let SearchResult { best_move, .. } = find_best_move(&mut moves, &mut move_generator, 3);
         let capture_move = Move::from_square(Square::A1, Square::E1, Flag::None);
 
         assert!(best_move == capture_move);
@@ -368,7 +1382,7 @@ mod tests {
 
         let mut move_generator = MoveGenerator::new(board);
         let mut moves = move_generator.generate_moves();
-        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 3);
+        let SearchResult { best_move, .. } = find_best_move(&mut moves, &mut move_generator, 3);
         let forking_move = Move::from_square(Square::D1, Square::E3, Flag::None);
 
         assert!(best_move == forking_move);
@@ -387,7 +1401,7 @@ mod tests {
 
         let mut move_generator = MoveGenerator::new(board);
         let mut moves = move_generator.generate_moves();
-        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 3);
+        let SearchResult { best_move, .. } = find_best_move(&mut moves, &mut move_generator, 3);
 
         assert!(
             best_move == Move::from_square(Square::A7, Square::A8, Flag::PromoteTo(Piece::Queen))
@@ -395,4 +1409,111 @@ mod tests {
         println!("{best_move}");
         Ok(())
     }
+
+    #[test]
+    fn test_endgame_tablebase_results_are_cached() -> Result<()> {
+        let board: Board = BoardBuilder::new()
+            .piece(Square::A7, Piece::Pawn, Color::White)
+            .piece(Square::E1, Piece::King, Color::White)
+            .piece(Square::E8, Piece::King, Color::Black)
+            .to_move(Color::White)
+            .try_into()?;
+        // A made up answer, so the test can only pass without asking the network
+        let cached_move = Move::from_square(Square::E1, Square::D1, Flag::None);
+        let mut transposition_table = TranspositionTable::default();
+        transposition_table.store_tablebase(hash_board(&board), cached_move.clone(), 42);
+        let limits = SearchLimits {
+            depth: Some(3),
+            tablebase: TablebaseSettings {
+                enabled: true,
+                ..TablebaseSettings::default()
+            },
+            ..SearchLimits::default()
+        };
+
+        let mut move_generator = MoveGenerator::new(board);
+        let mut moves = move_generator.generate_moves();
+        let result = find_best_move_with_info(
+            &mut moves,
+            &mut move_generator,
+            &mut transposition_table,
+            &DefaultEvaluator::default(),
+            &mut DefaultMoveOrderer,
+            &limits,
+            &AtomicBool::new(false),
+            |_| {},
+        );
+
+        assert_eq!(result.best_move, cached_move);
+        assert_eq!(result.score, Score::from_search(42));
+        assert_eq!(result.stats.nodes, 0);
+
+        // Without the tablebase, the position is searched as usual
+        let limits = SearchLimits {
+            depth: Some(3),
+            tablebase: TablebaseSettings {
+                enabled: false,
+                ..TablebaseSettings::default()
+            },
+            ..SearchLimits::default()
+        };
+        let result = find_best_move_with_info(
+            &mut moves,
+            &mut move_generator,
+            &mut transposition_table,
+            &DefaultEvaluator::default(),
+            &mut DefaultMoveOrderer,
+            &limits,
+            &AtomicBool::new(false),
+            |_| {},
+        );
+        assert!(result.stats.nodes > 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_slow_tablebase_does_not_cost_time() -> Result<()> {
+        let board: Board = BoardBuilder::new()
+            .piece(Square::A7, Piece::Pawn, Color::White)
+            .piece(Square::E1, Piece::King, Color::White)
+            .piece(Square::E8, Piece::King, Color::Black)
+            .to_move(Color::White)
+            .try_into()?;
+        // Takes the connection, but never answers
+        let server = TcpListener::bind("127.0.0.1:0")?;
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_millis(200)),
+            tablebase: TablebaseSettings {
+                enabled: true,
+                url: format!("http://{}/standard", server.local_addr()?),
+                timeout: Duration::from_secs(30),
+                ..TablebaseSettings::default()
+            },
+            ..SearchLimits::default()
+        };
+
+        let mut move_generator = MoveGenerator::new(board);
+        let mut moves = move_generator.generate_moves();
+        let start = Instant::now();
+        let result = find_best_move_with_info(
+            &mut moves,
+            &mut move_generator,
+            &mut TranspositionTable::default(),
+            &DefaultEvaluator::default(),
+            &mut DefaultMoveOrderer,
+            &limits,
+            &AtomicBool::new(false),
+            |_| {},
+        );
+
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(result.stats.nodes > 0);
+        assert_eq!(
+            result.best_move,
+            Move::from_square(Square::A7, Square::A8, Flag::PromoteTo(Piece::Queen))
+        );
+
+        Ok(())
+    }
 }
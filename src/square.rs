@@ -1,7 +1,11 @@
+use std::{fmt, str::FromStr};
+
 use anyhow::{bail, Result};
 
+use crate::piece::Color;
+
 #[derive(Copy, Clone, Debug)]
-pub struct Sq(u8);
+pub struct Sq(#[allow(dead_code)] u8);
 
 // Enum for developer ergonomics, should never exist in runtime (other than for FEN processing).
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -72,78 +76,72 @@ pub enum Square {
     H8,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SquareColor {
+    Light,
+    Dark,
+}
+
 impl Square {
     pub fn as_index(self) -> usize {
         self as usize
     }
 
+    pub fn new(file: File, rank: Rank) -> Self {
+        Self::from_index(square_index(file, rank))
+    }
+
+    pub fn file(self) -> File {
+        File::of(self.as_index())
+    }
+
+    pub fn rank(self) -> Rank {
+        Rank::of(self.as_index())
+    }
+
+    // a1 is dark, h1 is light
+    pub fn color(self) -> SquareColor {
+        if (self.file().index() + self.rank().index()).is_multiple_of(2) {
+            SquareColor::Dark
+        } else {
+            SquareColor::Light
+        }
+    }
+
+    // The number of king moves between the squares
+    pub fn distance(self, other: Square) -> usize {
+        self.file()
+            .distance(other.file())
+            .max(self.rank().distance(other.rank()))
+    }
+
+    // The files and ranks apart added up, the number of king moves without diagonal ones
+    pub fn manhattan_distance(self, other: Square) -> usize {
+        self.file().distance(other.file()) + self.rank().distance(other.rank())
+    }
+
+    // 0 for the four center squares, up to 6 for the corners
+    pub fn center_distance(self) -> usize {
+        let file = self
+            .file()
+            .distance(File::D)
+            .min(self.file().distance(File::E));
+        let rank = self
+            .rank()
+            .distance(Rank::FOURTH)
+            .min(self.rank().distance(Rank::FIFTH));
+        file + rank
+    }
+
     pub fn from_algebraic_notation(s: &str) -> Result<Self> {
-        match s {
-            "a1" => Ok(Square::A1),
-            "b1" => Ok(Square::B1),
-            "c1" => Ok(Square::C1),
-            "d1" => Ok(Square::D1),
-            "e1" => Ok(Square::E1),
-            "f1" => Ok(Square::F1),
-            "g1" => Ok(Square::G1),
-            "h1" => Ok(Square::H1),
-            "a2" => Ok(Square::A2),
-            "b2" => Ok(Square::B2),
-            "c2" => Ok(Square::C2),
-            "d2" => Ok(Square::D2),
-            "e2" => Ok(Square::E2),
-            "f2" => Ok(Square::F2),
-            "g2" => Ok(Square::G2),
-            "h2" => Ok(Square::H2),
-            "a3" => Ok(Square::A3),
-            "b3" => Ok(Square::B3),
-            "c3" => Ok(Square::C3),
-            "d3" => Ok(Square::D3),
-            "e3" => Ok(Square::E3),
-            "f3" => Ok(Square::F3),
-            "g3" => Ok(Square::G3),
-            "h3" => Ok(Square::H3),
-            "a4" => Ok(Square::A4),
-            "b4" => Ok(Square::B4),
-            "c4" => Ok(Square::C4),
-            "d4" => Ok(Square::D4),
-            "e4" => Ok(Square::E4),
-            "f4" => Ok(Square::F4),
-            "g4" => Ok(Square::G4),
-            "h4" => Ok(Square::H4),
-            "a5" => Ok(Square::A5),
-            "b5" => Ok(Square::B5),
-            "c5" => Ok(Square::C5),
-            "d5" => Ok(Square::D5),
-            "e5" => Ok(Square::E5),
-            "f5" => Ok(Square::F5),
-            "g5" => Ok(Square::G5),
-            "h5" => Ok(Square::H5),
-            "a6" => Ok(Square::A6),
-            "b6" => Ok(Square::B6),
-            "c6" => Ok(Square::C6),
-            "d6" => Ok(Square::D6),
-            "e6" => Ok(Square::E6),
-            "f6" => Ok(Square::F6),
-            "g6" => Ok(Square::G6),
-            "h6" => Ok(Square::H6),
-            "a7" => Ok(Square::A7),
-            "b7" => Ok(Square::B7),
-            "c7" => Ok(Square::C7),
-            "d7" => Ok(Square::D7),
-            "e7" => Ok(Square::E7),
-            "f7" => Ok(Square::F7),
-            "g7" => Ok(Square::G7),
-            "h7" => Ok(Square::H7),
-            "a8" => Ok(Square::A8),
-            "b8" => Ok(Square::B8),
-            "c8" => Ok(Square::C8),
-            "d8" => Ok(Square::D8),
-            "e8" => Ok(Square::E8),
-            "f8" => Ok(Square::F8),
-            "g8" => Ok(Square::G8),
-            "h8" => Ok(Square::H8),
-            square_string => bail!("Invalid square string: {square_string}"),
+        let mut chars = s.chars();
+        match (
+            chars.next().and_then(File::from_char),
+            chars.next().and_then(Rank::from_char),
+            chars.next(),
+        ) {
+            (Some(file), Some(rank), None) => Ok(Square::new(file, rank)),
+            _ => bail!("Invalid square string: {s}"),
         }
     }
 
@@ -217,3 +215,186 @@ impl Square {
         }
     }
 }
+
+// Lowercase, like "e4"
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.file().to_char(), self.rank().to_char())
+    }
+}
+
+impl FromStr for Square {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::from_algebraic_notation(s)
+    }
+}
+
+// The index of the square on the file and rank, as the board's arrays are indexed
+pub fn square_index(file: File, rank: Rank) -> usize {
+    rank.index() * 8 + file.index()
+}
+
+// A file of the board, from the a-file to the h-file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct File(u8);
+
+impl File {
+    pub const A: File = File(0);
+    pub const B: File = File(1);
+    pub const C: File = File(2);
+    pub const D: File = File(3);
+    pub const E: File = File(4);
+    pub const F: File = File(5);
+    pub const G: File = File(6);
+    pub const H: File = File(7);
+
+    // None past the h-file
+    pub fn new(index: usize) -> Option<Self> {
+        (index < 8).then_some(File(index as u8))
+    }
+
+    // The file the square with the index is on
+    pub fn of(square: usize) -> Self {
+        File((square % 8) as u8)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    // The file that many files towards the h-file, None if that's off the board
+    pub fn offset(self, files: isize) -> Option<Self> {
+        let index = self.0 as isize + files;
+        (0..8).contains(&index).then_some(File(index as u8))
+    }
+
+    pub fn distance(self, other: File) -> usize {
+        self.0.abs_diff(other.0) as usize
+    }
+
+    // From the a-file to the h-file
+    pub fn all() -> impl DoubleEndedIterator<Item = File> {
+        (0..8).map(File)
+    }
+
+    pub fn to_char(self) -> char {
+        (b'a' + self.0) as char
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        ('a'..='h').contains(&c).then(|| File(c as u8 - b'a'))
+    }
+}
+
+// A rank of the board, from the first rank, white's back rank, to the eighth
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rank(u8);
+
+impl Rank {
+    pub const FIRST: Rank = Rank(0);
+    pub const SECOND: Rank = Rank(1);
+    pub const THIRD: Rank = Rank(2);
+    pub const FOURTH: Rank = Rank(3);
+    pub const FIFTH: Rank = Rank(4);
+    pub const SIXTH: Rank = Rank(5);
+    pub const SEVENTH: Rank = Rank(6);
+    pub const EIGHTH: Rank = Rank(7);
+
+    // None past the eighth rank
+    pub fn new(index: usize) -> Option<Self> {
+        (index < 8).then_some(Rank(index as u8))
+    }
+
+    // The rank the square with the index is on
+    pub fn of(square: usize) -> Self {
+        Rank((square / 8) as u8)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    // The rank that many ranks towards the eighth, None if that's off the board
+    pub fn offset(self, ranks: isize) -> Option<Self> {
+        let index = self.0 as isize + ranks;
+        (0..8).contains(&index).then_some(Rank(index as u8))
+    }
+
+    pub fn distance(self, other: Rank) -> usize {
+        self.0.abs_diff(other.0) as usize
+    }
+
+    // From the first rank to the eighth
+    pub fn all() -> impl DoubleEndedIterator<Item = Rank> {
+        (0..8).map(Rank)
+    }
+
+    // The rank as the side sees it, so black's back rank is its FIRST
+    pub fn relative_to(self, color: Color) -> Self {
+        match color {
+            Color::White => self,
+            Color::Black => Rank(7 - self.0),
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        (b'1' + self.0) as char
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        ('1'..='8').contains(&c).then(|| Rank(c as u8 - b'1'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::piece::Color;
+
+    use super::{square_index, File, Rank, Square, SquareColor};
+
+    #[test]
+    fn test_file_and_rank() {
+        assert_eq!(File::of(Square::E4 as usize), File::E);
+        assert_eq!(Rank::of(Square::E4 as usize), Rank::FOURTH);
+        assert_eq!(Square::new(File::E, Rank::FOURTH), Square::E4);
+        assert_eq!(square_index(File::H, Rank::EIGHTH), 63);
+
+        assert_eq!(File::A.offset(-1), None);
+        assert_eq!(File::G.offset(1), Some(File::H));
+        assert_eq!(Rank::EIGHTH.offset(1), None);
+        assert_eq!(File::A.distance(File::H), 7);
+        assert_eq!(Rank::SECOND.relative_to(Color::Black), Rank::SEVENTH);
+        assert_eq!(File::all().map(File::to_char).collect::<String>(), "abcdefgh");
+        assert_eq!(File::new(8), None);
+    }
+
+    #[test]
+    fn test_square_names() {
+        assert_eq!(Square::E4.to_string(), "e4");
+        assert_eq!("h8".parse::<Square>().unwrap(), Square::H8);
+        assert_eq!(Square::from_algebraic_notation("a1").unwrap(), Square::A1);
+        assert!("i1".parse::<Square>().is_err());
+        assert!("e".parse::<Square>().is_err());
+        assert!("e44".parse::<Square>().is_err());
+        assert!("E4".parse::<Square>().is_err());
+    }
+
+    #[test]
+    fn test_square_color_and_distances() {
+        assert_eq!(Square::A1.color(), SquareColor::Dark);
+        assert_eq!(Square::H1.color(), SquareColor::Light);
+        assert_eq!(Square::E4.color(), SquareColor::Light);
+
+        assert_eq!(Square::A1.distance(Square::H8), 7);
+        assert_eq!(Square::E4.distance(Square::F6), 2);
+        assert_eq!(Square::A1.manhattan_distance(Square::H8), 14);
+        assert_eq!(Square::E4.manhattan_distance(Square::F6), 3);
+
+        assert_eq!(Square::D4.center_distance(), 0);
+        assert_eq!(Square::E5.center_distance(), 0);
+        assert_eq!(Square::A1.center_distance(), 6);
+        assert_eq!(Square::H4.center_distance(), 3);
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    move_generation::Move,
+    search::{SearchLimits, SearchResult},
+};
+
+pub const MIN_ELO: u32 = 1200;
+pub const MAX_ELO: u32 = 2200;
+pub const DEFAULT_ELO: u32 = 1500;
+
+// Every step of this many elo allows one more ply of search
+const ELO_PER_DEPTH: u32 = 200;
+const MIN_NODES: u64 = 5_000;
+const NODES_PER_ELO: u64 = 200;
+// How often the weakest setting deliberately plays a worse move, and how many centipawns it
+// is willing to give away when it does
+const MAX_SUBOPTIMAL_MOVE_PROBABILITY: f64 = 0.3;
+const MAX_SCORE_LOSS: i32 = 300;
+
+pub const MAX_SKILL_LEVEL: u32 = 20;
+const NOISE_PER_SKILL_LEVEL: i32 = 20;
+
+// Weakens the engine for UCI_LimitStrength by capping the search and sometimes playing the
+// second best move instead of the best one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrengthLimit {
+    elo: u32,
+}
+
+impl StrengthLimit {
+    pub fn new(elo: u32) -> Self {
+        Self {
+            elo: elo.clamp(MIN_ELO, MAX_ELO),
+        }
+    }
+
+    pub fn max_depth(&self) -> u32 {
+        1 + (self.elo - MIN_ELO) / ELO_PER_DEPTH
+    }
+
+    pub fn max_nodes(&self) -> u64 {
+        MIN_NODES + (self.elo - MIN_ELO) as u64 * NODES_PER_ELO
+    }
+
+    // The caller's own limits still apply if they are stricter
+    pub fn apply(&self, limits: &mut SearchLimits) {
+        limits.depth = Some(
+            limits
+                .depth
+                .map_or(self.max_depth(), |d| d.min(self.max_depth())),
+        );
+        limits.nodes = Some(
+            limits
+                .nodes
+                .map_or(self.max_nodes(), |n| n.min(self.max_nodes())),
+        );
+    }
+
+    // Scales linearly from the maximum at MIN_ELO down to never at MAX_ELO
+    pub fn suboptimal_move_probability(&self) -> f64 {
+        let weakness = (MAX_ELO - self.elo) as f64 / (MAX_ELO - MIN_ELO) as f64;
+        weakness * MAX_SUBOPTIMAL_MOVE_PROBABILITY
+    }
+
+    pub fn max_score_loss(&self) -> i32 {
+        let weakness = (MAX_ELO - self.elo) as i32;
+        weakness * MAX_SCORE_LOSS / (MAX_ELO - MIN_ELO) as i32
+    }
+
+    // Given the result of the full strength search, decides which move to actually play. With
+    // the calibrated probability, the best move is excluded and the position searched again.
+    // The runner-up is only played if it doesn't lose more than the allowed score. search runs
+    // the same search as before with the given limits.
+    pub fn choose_move(
+        &self,
+        best: SearchResult,
+        moves: &[Move],
+        limits: &SearchLimits,
+        stop: &AtomicBool,
+        search: impl FnOnce(&SearchLimits) -> SearchResult,
+    ) -> SearchResult {
+        let mut alternative_limits = limits.clone();
+        alternative_limits
+            .excluded_moves
+            .push(best.best_move.clone());
+        let has_alternative = moves
+            .iter()
+            .any(|mv| alternative_limits.allows_root_move(mv));
+        // A stopped search has no time left to look for an alternative
+        if !has_alternative
+            || stop.load(Ordering::Relaxed)
+            || Random::from_clock().next_fraction() >= self.suboptimal_move_probability()
+        {
+            return best;
+        }
+
+        let alternative = search(&alternative_limits);
+
+        let max_score_loss = self.max_score_loss();
+        if alternative.score.to_search() >= best.score.to_search().saturating_sub(max_score_loss) {
+            alternative
+        } else {
+            best
+        }
+    }
+}
+
+impl Default for StrengthLimit {
+    fn default() -> Self {
+        Self::new(DEFAULT_ELO)
+    }
+}
+
+// Noise only needs to be different from move to move, not good randomness, so the clock is
+// scrambled with splitmix64 instead of pulling in a crate for it
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn from_clock() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64);
+
+        Self { state: nanos }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform number in [0, 1)
+    pub fn next_fraction(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Uniform number in [-max, max]
+    pub fn next_noise(&mut self, max: i32) -> i32 {
+        (self.next_u64() % (2 * max as u64 + 1)) as i32 - max
+    }
+}
+
+// Skill Level scales the noise added to root move scores, from none at the maximum level to
+// several pawns at level 0. Mate scores are never disturbed, so forced mates are still found.
+pub fn root_noise_for_skill_level(skill_level: u32) -> i32 {
+    (MAX_SKILL_LEVEL - skill_level.min(MAX_SKILL_LEVEL)) as i32 * NOISE_PER_SKILL_LEVEL
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::search::SearchLimits;
+
+    use super::{
+        root_noise_for_skill_level, Random, StrengthLimit, MAX_ELO, MAX_SKILL_LEVEL, MIN_ELO,
+    };
+
+    #[test]
+    fn test_elo_is_clamped() {
+        assert_eq!(StrengthLimit::new(100), StrengthLimit::new(MIN_ELO));
+        assert_eq!(StrengthLimit::new(3000), StrengthLimit::new(MAX_ELO));
+    }
+
+    #[test]
+    fn test_weaker_settings_search_less_and_blunder_more() {
+        let weakest = StrengthLimit::new(MIN_ELO);
+        let strongest = StrengthLimit::new(MAX_ELO);
+
+        assert_eq!(weakest.max_depth(), 1);
+        assert_eq!(strongest.max_depth(), 6);
+        assert!(weakest.max_nodes() < strongest.max_nodes());
+        assert!(weakest.suboptimal_move_probability() > 0.0);
+        assert_eq!(strongest.suboptimal_move_probability(), 0.0);
+        assert_eq!(strongest.max_score_loss(), 0);
+    }
+
+    #[test]
+    fn test_apply_keeps_stricter_limits() {
+        let strength_limit = StrengthLimit::new(1600);
+
+        let mut limits = SearchLimits::default();
+        strength_limit.apply(&mut limits);
+        assert_eq!(limits.depth, Some(3));
+        assert_eq!(limits.nodes, Some(strength_limit.max_nodes()));
+
+        let mut limits = SearchLimits {
+            depth: Some(1),
+            nodes: Some(100),
+            ..SearchLimits::default()
+        };
+        strength_limit.apply(&mut limits);
+        assert_eq!(limits.depth, Some(1));
+        assert_eq!(limits.nodes, Some(100));
+    }
+
+    #[test]
+    fn test_root_noise_for_skill_level() {
+        assert_eq!(root_noise_for_skill_level(MAX_SKILL_LEVEL), 0);
+        assert_eq!(root_noise_for_skill_level(0), 400);
+        assert!(root_noise_for_skill_level(10) < root_noise_for_skill_level(5));
+    }
+
+    #[test]
+    fn test_random_noise_is_bounded() {
+        let mut random = Random::from_clock();
+        for _ in 0..1000 {
+            assert!((-50..=50).contains(&random.next_noise(50)));
+            assert!((0.0..1.0).contains(&random.next_fraction()));
+        }
+        assert_eq!(random.next_noise(0), 0);
+    }
+}
@@ -0,0 +1,302 @@
+use anyhow::{bail, Result};
+#[cfg(feature = "online-tablebase")]
+use reqwest::{self, blocking::Client};
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::{debug, info_span};
+
+use crate::{
+    move_generation::{Move, MoveGenerator},
+    search::{MATE_SCORE, MAX_MATE_PLY},
+};
+
+// A tablebase win without a known distance to mate is treated as a huge non-mate score
+const TABLEBASE_WIN_SCORE: i32 = MATE_SCORE - MAX_MATE_PLY - 1;
+pub const DEFAULT_TABLEBASE_URL: &str = "http://tablebase.lichess.ovh/standard";
+// Long enough for a round trip to the server, short enough to not lose much time when it's down
+pub const DEFAULT_TABLEBASE_TIMEOUT: Duration = Duration::from_secs(2);
+pub const MAX_TABLEBASE_TIMEOUT: Duration = Duration::from_secs(60);
+// The online tablebases cover positions with up to seven pieces, kings included
+pub const MAX_TABLEBASE_PIECES: u32 = 7;
+pub const MIN_TABLEBASE_PIECES: u32 = 3;
+
+// How the search uses the online tablebase. Turn it off where there's no network, or where
+// outside help isn't allowed. Off by default when built without the online-tablebase feature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TablebaseSettings {
+    pub enabled: bool,
+    pub url: String,
+    pub timeout: Duration,
+    // Positions with more pieces than this are searched as usual
+    pub max_pieces: u32,
+}
+
+impl Default for TablebaseSettings {
+    fn default() -> Self {
+        Self {
+            enabled: cfg!(feature = "online-tablebase"),
+            url: DEFAULT_TABLEBASE_URL.to_string(),
+            timeout: DEFAULT_TABLEBASE_TIMEOUT,
+            max_pieces: MAX_TABLEBASE_PIECES,
+        }
+    }
+}
+
+#[allow(unused)]
+#[derive(Debug, Deserialize)]
+pub struct TablebaseResponse {
+    pub dtz: Option<i32>,
+    pub precise_dtz: Option<i32>,
+    pub dtm: Option<i32>,
+    pub checkmate: bool,
+    pub stalemate: bool,
+    pub insufficient_material: bool,
+    pub category: Category,
+    pub moves: Vec<TablebaseMove>,
+}
+
+#[allow(unused)]
+#[derive(Debug, Deserialize)]
+pub struct TablebaseMove {
+    uci: String,
+    san: String,
+    dtz: Option<i32>,
+    precise_dtz: Option<i32>,
+    dtm: Option<i32>,
+    zeroing: bool,
+    checkmate: bool,
+    stalemate: bool,
+    insufficient_material: bool,
+    category: Category,
+}
+
+// A cursed win is a win that the fifty-move rule turns into a draw, a blessed loss a loss that
+// it saves. The maybe and syzygy categories are wins and losses whose exact distance to zeroing
+// isn't known.
+#[derive(Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Category {
+    Win,
+    Loss,
+    Draw,
+    CursedWin,
+    BlessedLoss,
+    MaybeWin,
+    MaybeLoss,
+    SyzygyWin,
+    SyzygyLoss,
+    #[serde(other)]
+    Unknown,
+}
+
+// What a move leads to for the side playing it, from worst to best
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Outcome {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl TablebaseResponse {
+    // Wins that hold under the fifty-move rule come first: a mate, then a move that resets the
+    // half-move clock, then the one closest to the next reset, then the fastest mate. Losing
+    // moves put the reset and the mate off for as long as possible.
+    fn get_best_move(&self, half_move_clock: u32) -> (&TablebaseMove, Outcome) {
+        self.moves
+            .iter()
+            // Among equally good moves, the first one the server listed is played
+            .rev()
+            .map(|mv| (mv, mv.outcome(half_move_clock)))
+            .max_by_key(|(mv, outcome)| {
+                let dtz = mv.precise_dtz.or(mv.dtz).unwrap_or_default().abs();
+                let dtm = mv.dtm.unwrap_or_default().abs();
+                match outcome {
+                    Outcome::Win => (*outcome, mv.checkmate, mv.zeroing, -dtz, -dtm),
+                    Outcome::Loss | Outcome::BlessedLoss => (*outcome, false, false, dtz, dtm),
+                    Outcome::Draw | Outcome::CursedWin => (*outcome, false, false, 0, 0),
+                }
+            })
+            .expect("tablebase positions have at least one move")
+    }
+}
+
+impl TablebaseMove {
+    // The category is from the opponent's perspective, so a loss is good
+    fn outcome(&self, half_move_clock: u32) -> Outcome {
+        match self.category {
+            Category::Loss | Category::MaybeLoss | Category::SyzygyLoss => {
+                if self.zeroes_in_time(half_move_clock) {
+                    Outcome::Win
+                } else {
+                    Outcome::CursedWin
+                }
+            }
+            Category::BlessedLoss => Outcome::CursedWin,
+            Category::Draw | Category::Unknown => Outcome::Draw,
+            Category::CursedWin => Outcome::BlessedLoss,
+            Category::Win | Category::MaybeWin | Category::SyzygyWin => {
+                if self.zeroes_in_time(half_move_clock) {
+                    Outcome::Loss
+                } else {
+                    Outcome::BlessedLoss
+                }
+            }
+        }
+    }
+
+    // Whether the winning side gets to reset the half-move clock, with a capture or a pawn move,
+    // before a draw can be claimed. The distance to zeroing is counted from after this move.
+    fn zeroes_in_time(&self, half_move_clock: u32) -> bool {
+        let clock_after_move = if self.zeroing { 0 } else { half_move_clock + 1 };
+
+        self.precise_dtz
+            .or(self.dtz)
+            .is_none_or(|dtz| clock_after_move + dtz.unsigned_abs() <= 100)
+    }
+}
+
+// Asks the tablebase server for the best move and its score in the current position
+pub fn query_tablebase(
+    move_generator: &mut MoveGenerator,
+    settings: &TablebaseSettings,
+) -> Result<(Move, i32)> {
+    let _span = info_span!("tablebase").entered();
+    let tb_response = fetch_tablebase(&move_generator.board.to_fen(), settings)?;
+
+    let half_move_clock = move_generator.board.board_state.half_move_clock;
+    let (best_move, outcome) = tb_response.get_best_move(half_move_clock);
+    debug!(
+        best_move = best_move.uci,
+        dtm = best_move.dtm,
+        dtz = best_move.dtz,
+        ?outcome,
+        "tablebase answered"
+    );
+    // The move's dtm is counted from the position after the move, so add the move itself
+    let eval = match outcome {
+        Outcome::Loss => best_move
+            .dtm
+            .map_or(-TABLEBASE_WIN_SCORE, |dtm| -(MATE_SCORE - dtm.abs() - 1)),
+        Outcome::BlessedLoss | Outcome::Draw | Outcome::CursedWin => 0,
+        Outcome::Win => best_move
+            .dtm
+            .map_or(TABLEBASE_WIN_SCORE, |dtm| MATE_SCORE - dtm.abs() - 1),
+    };
+
+    Ok((Move::try_from_uci(&best_move.uci, &move_generator.board)?, eval))
+}
+
+#[cfg(feature = "online-tablebase")]
+fn fetch_tablebase(fen: &str, settings: &TablebaseSettings) -> Result<TablebaseResponse> {
+    // Make FEN URL friendly
+    let params = [("fen", fen.replace(' ', "_"))];
+    let client = Client::builder().timeout(settings.timeout).build()?;
+    let response = client.get(&settings.url).query(&params).send()?;
+
+    if response.status().is_success() {
+        Ok(response.json()?)
+    } else {
+        bail!("Call to tablebase failed");
+    }
+}
+
+#[cfg(not(feature = "online-tablebase"))]
+fn fetch_tablebase(_fen: &str, _settings: &TablebaseSettings) -> Result<TablebaseResponse> {
+    bail!("Talia was built without the online-tablebase feature");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{board_builder::BoardBuilder, move_generation::MoveGenerator};
+
+    use super::{query_tablebase, Outcome, TablebaseResponse, TablebaseSettings};
+
+    // A response with the given moves as (uci, category, dtz, dtm, zeroing)
+    fn response_with(moves: &[(&str, &str, i32, Option<i32>, bool)]) -> TablebaseResponse {
+        let moves: Vec<serde_json::Value> = moves
+            .iter()
+            .map(|&(uci, category, dtz, dtm, zeroing)| {
+                serde_json::json!({
+                    "uci": uci, "san": uci, "dtz": dtz, "precise_dtz": dtz, "dtm": dtm,
+                    "zeroing": zeroing, "checkmate": false, "stalemate": false,
+                    "insufficient_material": false, "category": category,
+                })
+            })
+            .collect();
+
+        serde_json::from_value(serde_json::json!({
+            "dtz": null, "precise_dtz": null, "dtm": null, "checkmate": false,
+            "stalemate": false, "insufficient_material": false, "category": "win",
+            "moves": moves,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_best_move_prefers_zeroing_and_short_dtz() {
+        let response = response_with(&[
+            ("a1a2", "draw", 0, None, false),
+            ("b1b2", "loss", -30, Some(-40), false),
+            ("c1c2", "loss", -12, Some(-50), false),
+            ("d1d2", "loss", -40, Some(-20), true),
+        ]);
+
+        let (best_move, outcome) = response.get_best_move(0);
+        assert_eq!((best_move.uci.as_str(), outcome), ("d1d2", Outcome::Win));
+
+        let response = response_with(&[
+            ("b1b2", "loss", -30, Some(-40), false),
+            ("c1c2", "loss", -12, Some(-50), false),
+        ]);
+        assert_eq!(response.get_best_move(0).0.uci, "c1c2");
+    }
+
+    #[test]
+    fn test_best_move_respects_fifty_move_rule() {
+        let response = response_with(&[
+            ("a1a2", "loss", -15, Some(-8), false),
+            ("b1b2", "loss", -20, Some(-30), true),
+        ]);
+        // 91 plies without a reset plus 15 more is too late to win, the pawn move is in time
+        let (best_move, outcome) = response.get_best_move(90);
+        assert_eq!((best_move.uci.as_str(), outcome), ("b1b2", Outcome::Win));
+
+        let response = response_with(&[("a1a2", "loss", -15, Some(-8), false)]);
+        assert_eq!(response.get_best_move(90).1, Outcome::CursedWin);
+        assert_eq!(response.get_best_move(84).1, Outcome::Win);
+
+        // When losing, the opponent's win may run into the fifty-move rule
+        let response = response_with(&[
+            ("a1a2", "win", 5, Some(9), false),
+            ("b1b2", "win", 20, Some(25), false),
+            ("c1c2", "maybe-win", 7, None, false),
+        ]);
+        let (best_move, outcome) = response.get_best_move(85);
+        assert_eq!((best_move.uci.as_str(), outcome), ("b1b2", Outcome::BlessedLoss));
+    }
+
+    #[test]
+    fn test_unknown_categories_are_draws() {
+        let response = response_with(&[("a1a2", "some-new-category", 0, None, false)]);
+        assert_eq!(response.get_best_move(0).1, Outcome::Draw);
+    }
+
+    #[test]
+    fn test_unreachable_tablebase_fails_within_timeout() {
+        let board = BoardBuilder::try_from_fen("8/P7/8/8/8/8/8/4K2k w - - 0 1").unwrap();
+        let settings = TablebaseSettings {
+            // Nothing listens on the discard port
+            url: "http://127.0.0.1:9/standard".to_string(),
+            timeout: Duration::from_millis(500),
+            ..TablebaseSettings::default()
+        };
+
+        let start = Instant::now();
+        assert!(query_tablebase(&mut MoveGenerator::new(board), &settings).is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{
+    board::Board,
+    direction::Direction,
+    piece::{Color, Piece},
+    weights::{Features, Term},
+};
+
+// A piece attacked by a cheaper one is lost unless it moves, and one nobody defends can simply be
+// taken. The search would see it one move later, which is too late at the leaves.
+pub const THREAT_BY_LESSER_PIECE_PENALTY: i32 = 30;
+pub const HANGING_PIECE_PENALTY: i32 = 25;
+// Kings have no material value but are the last piece anyone wants to capture with
+const KING_ATTACKER_VALUE: i32 = 10_000;
+const NO_ATTACKER: i32 = i32::MAX;
+
+// Hanging pieces and pieces attacked by cheaper ones, for both sides. From white's perspective.
+pub fn threats(board: &Board) -> Features {
+    let white_attacks = least_valuable_attackers(board, Color::White);
+    let black_attacks = least_valuable_attackers(board, Color::Black);
+
+    threatened(board, Color::Black, &white_attacks, &black_attacks)
+        - threatened(board, Color::White, &black_attacks, &white_attacks)
+}
+
+// The threats against the pieces of the given color, counted negatively like in threats
+pub fn threats_against(board: &Board, color: Color) -> Features {
+    let own_attacks = least_valuable_attackers(board, color);
+    let enemy_attacks = least_valuable_attackers(board, color.opposite_color());
+
+    Features::default() - threatened(board, color, &enemy_attacks, &own_attacks)
+}
+
+// How much the pieces of the given color are threatened
+fn threatened(
+    board: &Board,
+    color: Color,
+    enemy_attacks: &[i32; 64],
+    own_attacks: &[i32; 64],
+) -> Features {
+    let mut features = Features::default();
+    for square in 0..64 {
+        let piece = match board.squares[square] {
+            Some(piece) if piece != Piece::King && board.colors[square] == Some(color) => piece,
+            _ => continue,
+        };
+        if enemy_attacks[square] == NO_ATTACKER {
+            continue;
+        }
+
+        if enemy_attacks[square] < piece.piece_value() {
+            features[Term::ThreatByLesserPiece] += 1;
+        }
+        if own_attacks[square] == NO_ATTACKER {
+            features[Term::HangingPiece] += 1;
+        }
+    }
+
+    features
+}
+
+// For every square, the value of the cheapest piece of the given color attacking it
+fn least_valuable_attackers(board: &Board, color: Color) -> [i32; 64] {
+    let mut attackers = [NO_ATTACKER; 64];
+    let mut attack = |square: usize, value: i32| {
+        attackers[square] = attackers[square].min(value);
+    };
+
+    for square in 0..64 {
+        let piece = match board.squares[square] {
+            Some(piece) if board.colors[square] == Some(color) => piece,
+            _ => continue,
+        };
+        let value = match piece {
+            Piece::King => KING_ATTACKER_VALUE,
+            _ => piece.piece_value(),
+        };
+
+        match piece {
+            Piece::Pawn => {
+                for direction in Direction::pawn_captures(color) {
+                    if let Some(target) = direction.from(square) {
+                        attack(target, value);
+                    }
+                }
+            }
+            Piece::Knight | Piece::King => {
+                let directions = if piece == Piece::Knight {
+                    Direction::KNIGHT_JUMPS
+                } else {
+                    Direction::RAYS
+                };
+                for direction in directions {
+                    if let Some(target) = direction.from(square) {
+                        attack(target, value);
+                    }
+                }
+            }
+            Piece::Bishop | Piece::Rook | Piece::Queen => {
+                let directions: &[Direction] = match piece {
+                    Piece::Bishop => &Direction::DIAGONALS,
+                    Piece::Rook => &Direction::ORTHOGONALS,
+                    _ => &Direction::RAYS,
+                };
+                for &direction in directions {
+                    let mut target = direction.from(square);
+                    while let Some(target_square) = target {
+                        attack(target_square, value);
+                        // Sliders stop at the first piece in the way, which they do attack
+                        if !board.is_square_empty(target_square) {
+                            break;
+                        }
+                        target = direction.from(target_square);
+                    }
+                }
+            }
+        }
+    }
+
+    attackers
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Board,
+        board_builder::BoardBuilder,
+        piece::Color,
+        square::Square,
+        weights::{EvalWeights, Features},
+    };
+
+    use super::{
+        least_valuable_attackers, threats, HANGING_PIECE_PENALTY, NO_ATTACKER,
+        THREAT_BY_LESSER_PIECE_PENALTY,
+    };
+
+    fn eval(fen: &str) -> i32 {
+        threats(&BoardBuilder::try_from_fen(fen).unwrap()).evaluate(&EvalWeights::default())
+    }
+
+    #[test]
+    fn test_no_threats_in_starting_position() {
+        assert_eq!(threats(&Board::starting_position()), Features::default());
+    }
+
+    #[test]
+    fn test_piece_attacked_by_pawn() {
+        // The knight on d4 is defended, but the pawn on e5 still wins it
+        assert_eq!(
+            eval("4k3/8/8/4p3/3N4/2P5/8/4K3 w - - 0 1"),
+            -THREAT_BY_LESSER_PIECE_PENALTY
+        );
+    }
+
+    #[test]
+    fn test_hanging_piece() {
+        // Nothing defends the bishop on b5 from the rook on b8
+        assert_eq!(eval("1r4k1/8/8/1B6/8/8/8/4K3 w - - 0 1"), -HANGING_PIECE_PENALTY);
+        // Defended by the pawn, and attacked by a more valuable piece
+        assert_eq!(eval("1r4k1/8/8/1B6/2P5/8/8/4K3 w - - 0 1"), 0);
+        // A queen attacked by a knight and undefended is both
+        assert_eq!(
+            eval("4k3/8/8/8/2n5/8/3Q4/7K w - - 0 1"),
+            -THREAT_BY_LESSER_PIECE_PENALTY - HANGING_PIECE_PENALTY
+        );
+    }
+
+    #[test]
+    fn test_sliders_stop_at_blockers() {
+        let board = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/R1N1K3 w - - 0 1").unwrap();
+        let attackers = least_valuable_attackers(&board, Color::White);
+
+        assert_eq!(attackers[Square::B1.as_index()], 500);
+        assert_eq!(attackers[Square::C1.as_index()], 500);
+        assert_eq!(attackers[Square::A8.as_index()], 500);
+        assert_eq!(attackers[Square::D1.as_index()], 10_000);
+        assert_eq!(attackers[Square::H8.as_index()], NO_ATTACKER);
+    }
+}
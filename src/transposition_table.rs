@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use crate::{move_generation::Move, search::is_mate_score};
+
+pub const DEFAULT_HASH_SIZE_MB: usize = 16;
+pub const MIN_HASH_SIZE_MB: usize = 1;
+pub const MAX_HASH_SIZE_MB: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    // The score is the exact minimax value of the position
+    Exact,
+    // The search failed high, the real score is at least this high
+    Lower,
+    // The search failed low, the real score is at most this high
+    Upper,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub key: u64,
+    pub depth: u32,
+    pub score: i32,
+    pub bound: Bound,
+    pub best_move: Option<Move>,
+}
+
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+    // Best move and score the online tablebase gave for a position. Kept apart from the search
+    // entries, so they're never replaced and the tablebase is asked only once per game.
+    tablebase: HashMap<u64, (Move, i32)>,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let num_entries = (size_mb * 1024 * 1024 / std::mem::size_of::<Option<Entry>>()).max(1);
+
+        Self {
+            entries: vec![None; num_entries],
+            tablebase: HashMap::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+        self.tablebase.clear();
+    }
+
+    pub fn probe(&self, key: u64) -> Option<&Entry> {
+        self.entries[self.index(key)]
+            .as_ref()
+            .filter(|entry| entry.key == key)
+    }
+
+    // Always replace: the most recent search results are the most relevant ones
+    pub fn store(&mut self, entry: Entry) {
+        let index = self.index(entry.key);
+        self.entries[index] = Some(entry);
+    }
+
+    pub fn probe_tablebase(&self, key: u64) -> Option<&(Move, i32)> {
+        self.tablebase.get(&key)
+    }
+
+    pub fn store_tablebase(&mut self, key: u64, mv: Move, score: i32) {
+        self.tablebase.insert(key, (mv, score));
+    }
+
+    // Occupancy of the table in permille, sampled from the first thousand entries as the UCI
+    // hashfull convention suggests
+    pub fn hashfull(&self) -> u32 {
+        let sample_size = self.entries.len().min(1000);
+        let used = self.entries[..sample_size]
+            .iter()
+            .filter(|entry| entry.is_some())
+            .count();
+
+        (used * 1000 / sample_size) as u32
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_SIZE_MB)
+    }
+}
+
+// Mate scores are relative to the root, but a position can be reached at different plies. Store
+// them relative to the position itself and convert back when probing.
+pub fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if !is_mate_score(score) {
+        score
+    } else if score > 0 {
+        score + ply
+    } else {
+        score - ply
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: i32) -> i32 {
+    if !is_mate_score(score) {
+        score
+    } else if score > 0 {
+        score - ply
+    } else {
+        score + ply
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        move_generation::{Flag, Move},
+        piece::Piece,
+        search::MATE_SCORE,
+        square::Square,
+    };
+
+    use super::{score_from_tt, score_to_tt, Bound, Entry, TranspositionTable};
+
+    fn entry(key: u64) -> Entry {
+        Entry {
+            key,
+            depth: 3,
+            score: 42,
+            bound: Bound::Exact,
+            best_move: None,
+        }
+    }
+
+    #[test]
+    fn test_probe_returns_stored_entry() {
+        let mut tt = TranspositionTable::new(1);
+        tt.store(entry(12345));
+
+        assert_eq!(tt.probe(12345).map(|entry| entry.score), Some(42));
+        assert!(tt.probe(54321).is_none());
+    }
+
+    #[test]
+    fn test_hashfull() {
+        let mut tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+
+        for key in 0..500 {
+            tt.store(entry(key));
+        }
+        assert_eq!(tt.hashfull(), 500);
+
+        tt.clear();
+        assert_eq!(tt.hashfull(), 0);
+    }
+
+    #[test]
+    fn test_tablebase_results_last_until_cleared() {
+        let mut tt = TranspositionTable::new(1);
+        let mv = Move::from_square(Square::A7, Square::A8, Flag::PromoteTo(Piece::Queen));
+        tt.store_tablebase(12345, mv.clone(), 500);
+        // Filling the table doesn't push out tablebase results
+        for key in 0..100_000 {
+            tt.store(entry(key));
+        }
+
+        assert_eq!(tt.probe_tablebase(12345), Some(&(mv, 500)));
+        assert!(tt.probe_tablebase(54321).is_none());
+        tt.clear();
+        assert!(tt.probe_tablebase(12345).is_none());
+    }
+
+    #[test]
+    fn test_mate_scores_round_trip_through_table() {
+        let mate_in_three_plies = MATE_SCORE - 3;
+        let stored = score_to_tt(mate_in_three_plies, 2);
+
+        // Mate is one ply away from the stored position
+        assert_eq!(stored, MATE_SCORE - 1);
+        assert_eq!(score_from_tt(stored, 2), mate_in_three_plies);
+        assert_eq!(score_from_tt(stored, 4), MATE_SCORE - 5);
+        assert_eq!(score_to_tt(150, 7), 150);
+    }
+}
@@ -0,0 +1,284 @@
+use std::{io::BufRead, thread};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    board_builder::BoardBuilder,
+    endgame::{drawishness_scale, evaluate_endgame, is_insufficient_material, FULL_SCALE},
+    evaluate::{features, fixed_terms},
+    weights::{EvalWeights, Features, Term},
+};
+
+pub const DEFAULT_TUNING_ITERATIONS: u32 = 500;
+// Adam's step size, in centipawns
+pub const DEFAULT_LEARNING_RATE: f64 = 1.0;
+const ADAM_BETA1: f64 = 0.9;
+const ADAM_BETA2: f64 = 0.999;
+const ADAM_EPSILON: f64 = 1e-8;
+// Range searched for the scaling constant that maps evaluations to expected results
+const MIN_SCALING_CONSTANT: f64 = 0.01;
+const MAX_SCALING_CONSTANT: f64 = 10.0;
+
+// A position from the training data, reduced to what its evaluation needs as a function of the
+// weights: the scale times the fixed terms plus the weighted features, from white's perspective
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainingPosition {
+    features: Features,
+    fixed: f64,
+    scale: f64,
+    // 1 for a white win, 0.5 for a draw and 0 for a black win
+    result: f64,
+}
+
+impl TrainingPosition {
+    fn eval(&self, weights: &[f64; Term::COUNT]) -> f64 {
+        let weighted: f64 = self
+            .features
+            .counts()
+            .iter()
+            .zip(weights)
+            .map(|(&count, weight)| count as f64 * weight)
+            .sum();
+
+        self.scale * (self.fixed + weighted)
+    }
+}
+
+// One position per line, a FEN followed by the game's result: "1-0", "0-1" or "1/2-1/2", or the
+// score for white as a number like "[0.5]", optionally in quotes and after a "c9" EPD opcode.
+// Positions the weights can't change the evaluation of, like known endings, are left out.
+pub fn load_positions(input: impl BufRead) -> Result<Vec<TrainingPosition>> {
+    let mut positions = Vec::new();
+    for (line_number, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(position) =
+            parse_position(&line).map_err(|err| anyhow!("line {}: {err}", line_number + 1))?
+        {
+            positions.push(position);
+        }
+    }
+
+    Ok(positions)
+}
+
+fn parse_position(line: &str) -> Result<Option<TrainingPosition>> {
+    let mut fields: Vec<&str> = line.split_whitespace().collect();
+    let result = match fields
+        .pop()
+        .unwrap()
+        .trim_matches(|c| matches!(c, '[' | ']' | '"' | ';'))
+    {
+        "1-0" => 1.0,
+        "0-1" => 0.0,
+        "1/2-1/2" => 0.5,
+        score => match score.parse::<f64>() {
+            Ok(score) if (0.0..=1.0).contains(&score) => score,
+            _ => bail!("no result at the end of the line"),
+        },
+    };
+    if fields.last() == Some(&"c9") {
+        fields.pop();
+    }
+    // EPD leaves out the move counters
+    if fields.len() == 4 {
+        fields.extend(["0", "1"]);
+    }
+    if fields.len() != 6 {
+        bail!("not a FEN");
+    }
+
+    let board = BoardBuilder::try_from_fen(&fields.join(" "))?;
+    if is_insufficient_material(&board) || evaluate_endgame(&board).is_some() {
+        return Ok(None);
+    }
+
+    Ok(Some(TrainingPosition {
+        features: features(&board),
+        fixed: fixed_terms(&board) as f64,
+        scale: drawishness_scale(&board) as f64 / FULL_SCALE as f64,
+        result,
+    }))
+}
+
+// Texel's method: the weights are tuned so that the evaluation, mapped to an expected result,
+// predicts the results of the games the positions come from as well as possible. The mapping is
+// fitted to the initial weights first. on_iteration gets the mean squared error every iteration.
+pub fn tune(
+    positions: &[TrainingPosition],
+    initial: &EvalWeights,
+    iterations: u32,
+    learning_rate: f64,
+    mut on_iteration: impl FnMut(u32, f64),
+) -> EvalWeights {
+    let mut weights = Term::ALL.map(|term| initial[term] as f64);
+    if positions.is_empty() {
+        return *initial;
+    }
+    let k = scaling_constant(positions, &weights);
+
+    // Adam, since the terms differ a lot in how often they apply
+    let mut first_moment = [0.0; Term::COUNT];
+    let mut second_moment = [0.0; Term::COUNT];
+    for iteration in 1..=iterations {
+        let gradient = gradient(positions, &weights, k);
+        for term in 0..Term::COUNT {
+            first_moment[term] =
+                ADAM_BETA1 * first_moment[term] + (1.0 - ADAM_BETA1) * gradient[term];
+            second_moment[term] = ADAM_BETA2 * second_moment[term]
+                + (1.0 - ADAM_BETA2) * gradient[term] * gradient[term];
+            let first = first_moment[term] / (1.0 - ADAM_BETA1.powi(iteration as i32));
+            let second = second_moment[term] / (1.0 - ADAM_BETA2.powi(iteration as i32));
+            weights[term] -= learning_rate * first / (second.sqrt() + ADAM_EPSILON);
+        }
+        on_iteration(iteration, mean_squared_error(positions, &weights, k));
+    }
+
+    let mut tuned = EvalWeights::default();
+    for term in Term::ALL {
+        tuned[term] = weights[term as usize].round() as i32;
+    }
+
+    tuned
+}
+
+// Expected result for white, from 0 to 1
+fn sigmoid(eval: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+}
+
+fn mean_squared_error(positions: &[TrainingPosition], weights: &[f64; Term::COUNT], k: f64) -> f64 {
+    let sum: f64 = in_parallel(positions, |chunk| {
+        chunk
+            .iter()
+            .map(|position| (position.result - sigmoid(position.eval(weights), k)).powi(2))
+            .sum::<f64>()
+    })
+    .into_iter()
+    .sum();
+
+    sum / positions.len() as f64
+}
+
+// The constant the error is lowest with for the given weights, by ternary search
+fn scaling_constant(positions: &[TrainingPosition], weights: &[f64; Term::COUNT]) -> f64 {
+    let (mut low, mut high) = (MIN_SCALING_CONSTANT, MAX_SCALING_CONSTANT);
+    while high - low > 1e-4 {
+        let third = (high - low) / 3.0;
+        if mean_squared_error(positions, weights, low + third)
+            < mean_squared_error(positions, weights, high - third)
+        {
+            high -= third;
+        } else {
+            low += third;
+        }
+    }
+
+    (low + high) / 2.0
+}
+
+// Of the mean squared error, by the weights
+fn gradient(
+    positions: &[TrainingPosition],
+    weights: &[f64; Term::COUNT],
+    k: f64,
+) -> [f64; Term::COUNT] {
+    let mut gradient = [0.0; Term::COUNT];
+    let chunk_gradients = in_parallel(positions, |chunk| {
+        let mut gradient = [0.0; Term::COUNT];
+        for position in chunk {
+            let expected = sigmoid(position.eval(weights), k);
+            let slope = -2.0 * (position.result - expected) * expected * (1.0 - expected) * k
+                / 400.0
+                * std::f64::consts::LN_10
+                * position.scale;
+            for (term, &count) in position.features.counts().iter().enumerate() {
+                gradient[term] += slope * count as f64;
+            }
+        }
+        gradient
+    });
+    for chunk_gradient in chunk_gradients {
+        for term in 0..Term::COUNT {
+            gradient[term] += chunk_gradient[term] / positions.len() as f64;
+        }
+    }
+
+    gradient
+}
+
+// Splits the positions over all cores
+fn in_parallel<T: Send>(
+    positions: &[TrainingPosition],
+    work: impl Fn(&[TrainingPosition]) -> T + Sync,
+) -> Vec<T> {
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = positions.len().div_ceil(threads).max(1);
+    thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| work(chunk)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::weights::{EvalWeights, Term};
+
+    use super::{load_positions, parse_position, tune};
+
+    #[test]
+    fn test_parse_position() {
+        let starting_position = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        for (line, result) in [
+            (format!("{starting_position} [1.0]"), 1.0),
+            (format!("{starting_position} 0-1"), 0.0),
+            (format!("{starting_position} \"1/2-1/2\";"), 0.5),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - c9 \"1-0\";".to_string(),
+                1.0,
+            ),
+        ] {
+            assert_eq!(parse_position(&line).unwrap().unwrap().result, result);
+        }
+
+        assert!(parse_position(starting_position).is_err());
+        assert!(parse_position(&format!("{starting_position} [2.0]")).is_err());
+        // Known endings are left out
+        assert_eq!(
+            parse_position("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1 1/2-1/2").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tuning_lowers_the_error() {
+        // White wins the positions where the knight is better placed, draws the others
+        let positions = load_positions(Cursor::new(
+            "4k3/pp6/8/8/8/8/PPP5/1N2K3 w - - 0 1 1-0\n\
+             \n\
+             4k3/pp6/8/8/8/8/PPP5/1N2K3 b - - 0 1 1-0\n\
+             4k3/pp3ppp/8/8/8/8/PP3PPP/4K3 w - - 0 1 1/2-1/2\n\
+             4k3/pp3pp1/8/8/8/8/PP3PPP/4K3 w - - 0 1 1/2-1/2\n",
+        ))
+        .unwrap();
+        assert_eq!(positions.len(), 4);
+
+        let initial = EvalWeights::default();
+        let mut errors = Vec::new();
+        let tuned = tune(&positions, &initial, 50, 1.0, |_, error| errors.push(error));
+
+        assert_eq!(errors.len(), 50);
+        assert!(errors.last() < errors.first());
+        assert!(tuned[Term::KnightValue] > initial[Term::KnightValue]);
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    fmt, fs,
+    ops::{Add, Index, IndexMut, Sub},
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    evaluate::{
+        BISHOP_PAIR_BONUS, CONNECTED_ROOKS_BONUS, ROOK_ON_SEVENTH_RANK_BONUS, ROOK_OPEN_FILE_BONUS,
+        ROOK_SEMI_OPEN_FILE_BONUS,
+    },
+    pawn_structure::{
+        BACKWARD_PAWN_PENALTY, DOUBLED_PAWN_PENALTY, ISOLATED_PAWN_PENALTY, PASSED_PAWN_BONUS,
+    },
+    piece::Piece,
+    threats::{HANGING_PIECE_PENALTY, THREAT_BY_LESSER_PIECE_PENALTY},
+};
+
+// Range of the weight options, wide enough for any sensible piece value
+pub const MIN_WEIGHT: i32 = -1000;
+pub const MAX_WEIGHT: i32 = 3000;
+
+// The evaluation terms whose weights can be changed without recompiling, e.g. by the tuner.
+// Everything else the evaluation looks at, like the piece square tables, stays fixed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Term {
+    PawnValue,
+    KnightValue,
+    BishopValue,
+    RookValue,
+    QueenValue,
+    BishopPair,
+    RookOpenFile,
+    RookSemiOpenFile,
+    RookOnSeventhRank,
+    ConnectedRooks,
+    DoubledPawn,
+    IsolatedPawn,
+    BackwardPawn,
+    // By rank, counted from the pawn's own side. Passed pawns can't be on the first or last rank.
+    PassedPawnRank2,
+    PassedPawnRank3,
+    PassedPawnRank4,
+    PassedPawnRank5,
+    PassedPawnRank6,
+    PassedPawnRank7,
+    ThreatByLesserPiece,
+    HangingPiece,
+}
+
+impl Term {
+    pub const COUNT: usize = 21;
+    pub const ALL: [Term; Term::COUNT] = [
+        Term::PawnValue,
+        Term::KnightValue,
+        Term::BishopValue,
+        Term::RookValue,
+        Term::QueenValue,
+        Term::BishopPair,
+        Term::RookOpenFile,
+        Term::RookSemiOpenFile,
+        Term::RookOnSeventhRank,
+        Term::ConnectedRooks,
+        Term::DoubledPawn,
+        Term::IsolatedPawn,
+        Term::BackwardPawn,
+        Term::PassedPawnRank2,
+        Term::PassedPawnRank3,
+        Term::PassedPawnRank4,
+        Term::PassedPawnRank5,
+        Term::PassedPawnRank6,
+        Term::PassedPawnRank7,
+        Term::ThreatByLesserPiece,
+        Term::HangingPiece,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Term::PawnValue => "pawn_value",
+            Term::KnightValue => "knight_value",
+            Term::BishopValue => "bishop_value",
+            Term::RookValue => "rook_value",
+            Term::QueenValue => "queen_value",
+            Term::BishopPair => "bishop_pair_bonus",
+            Term::RookOpenFile => "rook_open_file_bonus",
+            Term::RookSemiOpenFile => "rook_semi_open_file_bonus",
+            Term::RookOnSeventhRank => "rook_on_seventh_rank_bonus",
+            Term::ConnectedRooks => "connected_rooks_bonus",
+            Term::DoubledPawn => "doubled_pawn_penalty",
+            Term::IsolatedPawn => "isolated_pawn_penalty",
+            Term::BackwardPawn => "backward_pawn_penalty",
+            Term::PassedPawnRank2 => "passed_pawn_rank_2_bonus",
+            Term::PassedPawnRank3 => "passed_pawn_rank_3_bonus",
+            Term::PassedPawnRank4 => "passed_pawn_rank_4_bonus",
+            Term::PassedPawnRank5 => "passed_pawn_rank_5_bonus",
+            Term::PassedPawnRank6 => "passed_pawn_rank_6_bonus",
+            Term::PassedPawnRank7 => "passed_pawn_rank_7_bonus",
+            Term::ThreatByLesserPiece => "threat_by_lesser_piece_penalty",
+            Term::HangingPiece => "hanging_piece_penalty",
+        }
+    }
+
+    pub fn try_from_name(name: &str) -> Result<Self> {
+        match Self::ALL
+            .into_iter()
+            .find(|term| term.name().eq_ignore_ascii_case(name))
+        {
+            Some(term) => Ok(term),
+            None => bail!("Unknown evaluation term: {name}"),
+        }
+    }
+
+    pub fn piece_value(piece: Piece) -> Option<Self> {
+        match piece {
+            Piece::Pawn => Some(Term::PawnValue),
+            Piece::Knight => Some(Term::KnightValue),
+            Piece::Bishop => Some(Term::BishopValue),
+            Piece::Rook => Some(Term::RookValue),
+            Piece::Queen => Some(Term::QueenValue),
+            Piece::King => None,
+        }
+    }
+
+    // For a passed pawn on the given rank, 0 being the pawn's own back rank
+    pub fn passed_pawn(rank: usize) -> Self {
+        Term::ALL[Term::PassedPawnRank2 as usize + rank - 1]
+    }
+}
+
+// How much each term is worth, in centipawns. Penalties are subtracted, so they are positive
+// like the bonuses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvalWeights([i32; Term::COUNT]);
+
+impl Default for EvalWeights {
+    // The hand-tuned values the evaluation was written with
+    fn default() -> Self {
+        let mut weights = Self([0; Term::COUNT]);
+        for piece in [
+            Piece::Pawn,
+            Piece::Knight,
+            Piece::Bishop,
+            Piece::Rook,
+            Piece::Queen,
+        ] {
+            weights[Term::piece_value(piece).unwrap()] = piece.piece_value();
+        }
+        weights[Term::BishopPair] = BISHOP_PAIR_BONUS;
+        weights[Term::RookOpenFile] = ROOK_OPEN_FILE_BONUS;
+        weights[Term::RookSemiOpenFile] = ROOK_SEMI_OPEN_FILE_BONUS;
+        weights[Term::RookOnSeventhRank] = ROOK_ON_SEVENTH_RANK_BONUS;
+        weights[Term::ConnectedRooks] = CONNECTED_ROOKS_BONUS;
+        weights[Term::DoubledPawn] = DOUBLED_PAWN_PENALTY;
+        weights[Term::IsolatedPawn] = ISOLATED_PAWN_PENALTY;
+        weights[Term::BackwardPawn] = BACKWARD_PAWN_PENALTY;
+        for rank in 1..7 {
+            weights[Term::passed_pawn(rank)] = PASSED_PAWN_BONUS[rank];
+        }
+        weights[Term::ThreatByLesserPiece] = THREAT_BY_LESSER_PIECE_PENALTY;
+        weights[Term::HangingPiece] = HANGING_PIECE_PENALTY;
+
+        weights
+    }
+}
+
+impl Index<Term> for EvalWeights {
+    type Output = i32;
+
+    fn index(&self, term: Term) -> &i32 {
+        &self.0[term as usize]
+    }
+}
+
+impl IndexMut<Term> for EvalWeights {
+    fn index_mut(&mut self, term: Term) -> &mut i32 {
+        &mut self.0[term as usize]
+    }
+}
+
+// One "name = value" line per term, which is also valid TOML
+impl fmt::Display for EvalWeights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for term in Term::ALL {
+            writeln!(f, "{} = {}", term.name(), self[term])?;
+        }
+        Ok(())
+    }
+}
+
+impl EvalWeights {
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    // Terms that aren't mentioned keep their default weight. Blank lines and '#' comments are
+    // skipped.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut weights = Self::default();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("line {}: expected 'name = value'", line_number + 1))?;
+            let term = Term::try_from_name(name.trim())?;
+            weights[term] = value.trim().parse().map_err(|_| {
+                anyhow!("line {}: {} must be a number", line_number + 1, term.name())
+            })?;
+        }
+
+        Ok(weights)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+// How often each term applies in a position, white's count minus black's. Penalties count
+// negatively, so the evaluation is always the sum of every count times its weight.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Features([i32; Term::COUNT]);
+
+impl Index<Term> for Features {
+    type Output = i32;
+
+    fn index(&self, term: Term) -> &i32 {
+        &self.0[term as usize]
+    }
+}
+
+impl IndexMut<Term> for Features {
+    fn index_mut(&mut self, term: Term) -> &mut i32 {
+        &mut self.0[term as usize]
+    }
+}
+
+impl Add for Features {
+    type Output = Features;
+
+    fn add(mut self, other: Features) -> Features {
+        for (count, other) in self.0.iter_mut().zip(other.0) {
+            *count += other;
+        }
+        self
+    }
+}
+
+impl Sub for Features {
+    type Output = Features;
+
+    fn sub(mut self, other: Features) -> Features {
+        for (count, other) in self.0.iter_mut().zip(other.0) {
+            *count -= other;
+        }
+        self
+    }
+}
+
+impl Features {
+    // From white's perspective
+    pub fn evaluate(&self, weights: &EvalWeights) -> i32 {
+        self.0
+            .iter()
+            .zip(weights.0)
+            .map(|(count, weight)| count * weight)
+            .sum()
+    }
+
+    pub fn counts(&self) -> &[i32; Term::COUNT] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EvalWeights, Term};
+
+    #[test]
+    fn test_weights_file_round_trip() {
+        let mut weights = EvalWeights::default();
+        weights[Term::KnightValue] = 310;
+        weights[Term::PassedPawnRank7] = -3;
+
+        assert_eq!(EvalWeights::parse(&weights.to_string()).unwrap(), weights);
+    }
+
+    #[test]
+    fn test_parse_weights() {
+        let weights =
+            EvalWeights::parse("# Tuned\n\nknight_value = 310\nBISHOP_PAIR_BONUS=40 # more\n")
+                .unwrap();
+        assert_eq!(weights[Term::KnightValue], 310);
+        assert_eq!(weights[Term::BishopPair], 40);
+        assert_eq!(weights[Term::RookValue], 500);
+
+        assert!(EvalWeights::parse("knight_value 310").is_err());
+        assert!(EvalWeights::parse("king_value = 0").is_err());
+        assert!(EvalWeights::parse("knight_value = three hundred").is_err());
+    }
+}
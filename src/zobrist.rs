@@ -0,0 +1,190 @@
+use crate::{
+    board::Board,
+    piece::{Color, Piece},
+};
+
+// Random keys are generated at compile time with splitmix64 so hashes are stable across runs.
+// Board::position_key promises as much to its callers, so the seed and the layout must stay.
+const SEED: u64 = 0x5441_4c49_4143_4845;
+const NUM_PIECE_SQUARE_KEYS: usize = 12 * 64;
+const BLACK_TO_MOVE_INDEX: usize = NUM_PIECE_SQUARE_KEYS;
+const CASTLING_INDEX: usize = BLACK_TO_MOVE_INDEX + 1;
+const EN_PASSANT_FILE_INDEX: usize = CASTLING_INDEX + 4;
+const NUM_KEYS: usize = EN_PASSANT_FILE_INDEX + 8;
+
+const KEYS: [u64; NUM_KEYS] = generate_keys();
+
+const fn generate_keys() -> [u64; NUM_KEYS] {
+    let mut keys = [0; NUM_KEYS];
+    let mut state = SEED;
+    let mut i = 0;
+    while i < NUM_KEYS {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        keys[i] = z ^ (z >> 31);
+        i += 1;
+    }
+
+    keys
+}
+
+pub(crate) fn piece_square_key(piece: Piece, color: Color, square: usize) -> u64 {
+    KEYS[(color as usize * 6 + piece as usize) * 64 + square]
+}
+
+// Computes the key from scratch. Boards keep the key of their pieces up to date as they move,
+// see Board::zobrist_key, so this is for checking that one.
+pub fn hash_board(board: &Board) -> u64 {
+    let mut hash = 0;
+
+    for square in 0..64 {
+        if let (Some(piece), Some(color)) = (board.squares[square], board.colors[square]) {
+            hash ^= piece_square_key(piece, color, square);
+        }
+    }
+
+    hash ^ state_key(board)
+}
+
+// The part of the key for everything besides the pieces: the side to move, castling and en
+// passant rights
+pub(crate) fn state_key(board: &Board) -> u64 {
+    let mut hash = 0;
+
+    if board.to_move == Color::Black {
+        hash ^= KEYS[BLACK_TO_MOVE_INDEX];
+    }
+
+    let castling_priviledges = [
+        board.board_state.white_kingside_castling_priviledge,
+        board.board_state.white_queenside_castling_priviledge,
+        board.board_state.black_kingside_castling_priviledge,
+        board.board_state.black_queenside_castling_priviledge,
+    ];
+    for (i, can_castle) in castling_priviledges.into_iter().enumerate() {
+        if can_castle {
+            hash ^= KEYS[CASTLING_INDEX + i];
+        }
+    }
+
+    if let Some(square) = board.board_state.en_passant_square {
+        hash ^= KEYS[EN_PASSANT_FILE_INDEX + square % 8];
+    }
+
+    hash
+}
+
+// Only the pawns, e.g. to cache the evaluation of a pawn structure. Like hash_board, computed
+// from scratch, Board::pawn_key is kept up to date instead.
+pub fn hash_pawns(board: &Board) -> u64 {
+    let mut hash = 0;
+
+    for square in 0..64 {
+        if let (Some(Piece::Pawn), Some(color)) = (board.squares[square], board.colors[square]) {
+            hash ^= piece_square_key(Piece::Pawn, color, square);
+        }
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Board,
+        board_builder::BoardBuilder,
+        move_generation::{Flag, Move, MoveGenerator},
+        square::Square::*,
+    };
+    use anyhow::Result;
+
+    use super::{hash_board, hash_pawns};
+
+    #[test]
+    fn test_same_position_by_transposition_has_same_hash() -> Result<()> {
+        let board: Board = BoardBuilder::from_starting_position()
+            .make_move(Move::from_square(G1, F3, Flag::None))
+            .make_move(Move::from_square(G8, F6, Flag::None))
+            .make_move(Move::from_square(B1, C3, Flag::None))
+            .try_into()?;
+        let transposed_board: Board = BoardBuilder::from_starting_position()
+            .make_move(Move::from_square(B1, C3, Flag::None))
+            .make_move(Move::from_square(G8, F6, Flag::None))
+            .make_move(Move::from_square(G1, F3, Flag::None))
+            .try_into()?;
+
+        assert_eq!(hash_board(&board), hash_board(&transposed_board));
+
+        Ok(())
+    }
+
+    // Checks the board's own keys against ones computed from scratch after every move played
+    // and taken back, down to the given depth
+    fn check_incremental_keys(move_generator: &mut MoveGenerator, depth: u32) {
+        let check = |board: &Board| {
+            assert_eq!(board.zobrist_key(), hash_board(board));
+            assert_eq!(board.pawn_key(), hash_pawns(board));
+        };
+        for mv in move_generator.generate_moves() {
+            let previous_state = move_generator.board.make_move(&mv);
+            check(&move_generator.board);
+            if depth > 1 {
+                check_incremental_keys(move_generator, depth - 1);
+            }
+            move_generator
+                .board
+                .unmake_move(&mv, previous_state)
+                .unwrap();
+            check(&move_generator.board);
+        }
+    }
+
+    #[test]
+    fn test_incremental_key_matches_full_hash() -> Result<()> {
+        // Captures, promotions with and without capture, en passant and castling for both sides
+        let mut move_generator = MoveGenerator::new(BoardBuilder::try_from_fen(
+            "r3k2r/pPppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q2/1PPBBPpP/R3K2R b KQkq a3 0 1",
+        )?);
+        check_incremental_keys(&mut move_generator, 2);
+
+        let (board, _) = Board::starting_position().play_uci_moves(&["e2e4", "d7d5", "e4d5"])?;
+        assert_eq!(board.zobrist_key(), hash_board(&board));
+        assert_eq!(board.pawn_key(), hash_pawns(&board));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_side_to_move_changes_hash() -> Result<()> {
+        let white_to_move = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1")?;
+        let black_to_move = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 1")?;
+
+        assert_ne!(hash_board(&white_to_move), hash_board(&black_to_move));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_castling_rights_change_hash() -> Result<()> {
+        let can_castle = BoardBuilder::try_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1")?;
+        let cannot_castle = BoardBuilder::try_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w Qkq - 0 1")?;
+
+        assert_ne!(hash_board(&can_castle), hash_board(&cannot_castle));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pawn_hash_only_sees_pawns() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("4k3/pp6/8/8/8/8/5PP1/R3K3 w - - 0 1")?;
+        let other_pieces = BoardBuilder::try_from_fen("3qk3/pp6/8/8/8/8/5PP1/4K2R b - - 0 1")?;
+        let other_pawns = BoardBuilder::try_from_fen("4k3/pp6/8/8/8/6P1/5P2/R3K3 w - - 0 1")?;
+
+        assert_eq!(hash_pawns(&board), hash_pawns(&other_pieces));
+        assert_ne!(hash_pawns(&board), hash_pawns(&other_pawns));
+
+        Ok(())
+    }
+}